@@ -0,0 +1,27 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBuffer {
+    mat4 mtx_obj_to_clip;
+    mat4 mtx_norm_obj_to_world;
+    float elapsed_seconds;
+    float viewport_w;
+    float viewport_h;
+} ubo;
+layout(set = 0, binding = 1) uniform sampler2D gbuffer_albedo;
+layout(set = 0, binding = 2) uniform sampler2D gbuffer_normal;
+layout(location = 0) in vec3 frag_norm_world;
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / vec2(ubo.viewport_w, ubo.viewport_h);
+    vec4 albedo = texture(gbuffer_albedo, uv);
+    vec4 packed_normal = texture(gbuffer_normal, uv);
+    if (packed_normal.a == 0.0) {
+        // Nothing was rasterized here
+        out_color = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+    vec3 n = normalize(packed_normal.xyz * 2.0 - 1.0);
+    float n_dot_l = max(dot(vec3(0, -1, 0), n), 0.0);
+    out_color = vec4(albedo.rgb * (n_dot_l + 0.05), 1.0);
+}
@@ -0,0 +1,18 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform UniformBuffer {
+    mat4 mtx_obj_to_clip;
+    mat4 mtx_norm_obj_to_world;
+    float elapsed_seconds;
+    float viewport_w;
+    float viewport_h;
+} ubo;
+layout(location = 0) in vec3 frag_norm_world;
+layout(location = 0) out vec4 out_albedo;
+layout(location = 1) out vec4 out_normal;
+
+void main() {
+    out_albedo = vec4(0.8, 0.8, 0.8, 1.0);
+    // Pack the world space normal into the [0, 1] range
+    out_normal = vec4(normalize(frag_norm_world) * 0.5 + 0.5, 1.0);
+}
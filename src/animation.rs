@@ -0,0 +1,510 @@
+use crate::*;
+use glam::*;
+
+/// A glTF skin: the nodes that act as joints, and the matrices that bring
+/// the vertices of the mesh into the space of each joint.
+pub struct Skeleton {
+    pub name: String,
+    pub joints: Vec<usize>,               // Indices into Scene::nodes
+    pub inverse_bind_matrices: Vec<Mat4>, // One per joint
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear, // Slerp for rotations
+    Step,
+    CubicSpline,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AnimationProperty {
+    Translation,
+    Rotation,
+    Scale,
+    MorphWeights,
+}
+
+/// The keyframes of one property of one node
+pub struct AnimationChannel {
+    pub node_index: usize, // Index into Scene::nodes
+    pub property: AnimationProperty,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>, // In seconds, increasing
+    // One value per keyframe, or three for cubic splines: the in-tangent, the
+    // value and the out-tangent. Each value has `component_count` floats:
+    // three for translations and scales, an xyzw quaternion for rotations,
+    // and one per morph target for morph weights.
+    pub values: Vec<f32>,
+    pub component_count: usize,
+}
+
+pub struct Animation {
+    pub name: String,
+    pub channels: Vec<AnimationChannel>,
+    pub duration: f32, // In seconds, the time of the last keyframe
+}
+
+impl Skeleton {
+    pub fn load_all(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Skeleton> {
+        gltf.skins()
+            .map(|skin| {
+                let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+                let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+                let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+                    Some(iter) => iter.map(|m| Mat4::from_cols_array_2d(&m)).collect(),
+                    None => vec![Mat4::identity(); joints.len()],
+                };
+                Skeleton {
+                    name: skin.name().unwrap_or("").to_string(),
+                    joints,
+                    inverse_bind_matrices,
+                }
+            })
+            .collect()
+    }
+
+    /// The matrices that take skinned vertices from the space of the mesh to
+    /// their posed position, still in the space of the mesh. Upload them to
+    /// a uniform or storage buffer every frame, indexed by JOINTS_0.
+    pub fn joint_matrices(&self, nodes: &[SceneNode], mesh_world_transform: Mat4) -> Vec<Mat4> {
+        let world_to_mesh = mesh_world_transform.inverse();
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(joint_idx, inverse_bind_matrix)| {
+                world_to_mesh * nodes[*joint_idx].world_transform * *inverse_bind_matrix
+            })
+            .collect()
+    }
+}
+
+impl AnimationChannel {
+    /// A channel with the values of all its keyframes, which have to match
+    /// the number of keyframes
+    pub fn new(
+        node_index: usize,
+        property: AnimationProperty,
+        interpolation: Interpolation,
+        times: Vec<f32>,
+        values: Vec<f32>,
+    ) -> Result<AnimationChannel, String> {
+        if !times.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(String::from(
+                "The keyframe times of an animation channel aren't increasing.",
+            ));
+        }
+        let value_count = match interpolation {
+            Interpolation::CubicSpline => times.len() * 3,
+            _ => times.len(),
+        };
+        let component_count = match property {
+            AnimationProperty::Translation | AnimationProperty::Scale => 3,
+            AnimationProperty::Rotation => 4,
+            AnimationProperty::MorphWeights => values.len().checked_div(value_count).unwrap_or(0),
+        };
+        if values.len() != value_count * component_count {
+            return Err(format!(
+                "An animation channel has {} floats of values for {} keyframes.",
+                values.len(),
+                times.len()
+            ));
+        }
+
+        Ok(AnimationChannel {
+            node_index,
+            property,
+            interpolation,
+            times,
+            values,
+            component_count,
+        })
+    }
+
+    /// The value of the property at the given time. Times outside the
+    /// keyframes are clamped.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let keyframe_count = self.times.len();
+        // The nth value; cubic splines have three values per keyframe
+        let get_element = |element_idx: usize| {
+            let start = element_idx * self.component_count;
+            &self.values[start..start + self.component_count]
+        };
+        // Cubic splines store the value in the middle of each triplet
+        let get_value = |keyframe_idx: usize| match self.interpolation {
+            Interpolation::CubicSpline => get_element(keyframe_idx * 3 + 1),
+            _ => get_element(keyframe_idx),
+        };
+
+        if keyframe_count == 0 {
+            return vec![0.0; self.component_count];
+        }
+        if time <= self.times[0] {
+            return get_value(0).to_vec();
+        }
+        if time >= self.times[keyframe_count - 1] {
+            return get_value(keyframe_count - 1).to_vec();
+        }
+
+        // The keyframe before the time
+        let idx = self.times.iter().rposition(|t| *t <= time).unwrap_or(0);
+        let dt = self.times[idx + 1] - self.times[idx];
+        let s = (time - self.times[idx]) / dt;
+
+        match self.interpolation {
+            Interpolation::Step => get_value(idx).to_vec(),
+            Interpolation::Linear => {
+                let (start, end) = (get_value(idx), get_value(idx + 1));
+                match self.property {
+                    AnimationProperty::Rotation => {
+                        let start = Quat::from_slice_unaligned(start);
+                        let mut end = Quat::from_slice_unaligned(end);
+                        // A quaternion and its negation are the same rotation.
+                        // Take the closer one, to rotate the short way around.
+                        if start.dot(end) < 0.0 {
+                            end = -end;
+                        }
+                        // glam lerps close rotations without normalizing them
+                        <[f32; 4]>::from(start.slerp(end, s).normalize()).to_vec()
+                    }
+                    _ => start
+                        .iter()
+                        .zip(end)
+                        .map(|(start, end)| start + (end - start) * s)
+                        .collect(),
+                }
+            }
+            Interpolation::CubicSpline => {
+                // Hermite spline, with tangents scaled by the keyframe spacing
+                let start = get_element(idx * 3 + 1);
+                let start_out_tangent = get_element(idx * 3 + 2);
+                let end_in_tangent = get_element((idx + 1) * 3);
+                let end = get_element((idx + 1) * 3 + 1);
+                let s2 = s * s;
+                let s3 = s2 * s;
+                let value: Vec<f32> = (0..self.component_count)
+                    .map(|i| {
+                        start[i] * (2.0 * s3 - 3.0 * s2 + 1.0)
+                            + start_out_tangent[i] * dt * (s3 - 2.0 * s2 + s)
+                            + end[i] * (-2.0 * s3 + 3.0 * s2)
+                            + end_in_tangent[i] * dt * (s3 - s2)
+                    })
+                    .collect();
+                match self.property {
+                    AnimationProperty::Rotation => {
+                        <[f32; 4]>::from(Quat::from_slice_unaligned(&value).normalize()).to_vec()
+                    }
+                    _ => value,
+                }
+            }
+        }
+    }
+}
+
+impl Animation {
+    pub fn load_all(
+        gltf: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Vec<Animation>, String> {
+        gltf.animations()
+            .map(|animation| {
+                let mut channels = Vec::new();
+                for channel in animation.channels() {
+                    let property = match channel.target().property() {
+                        gltf::animation::Property::Translation => AnimationProperty::Translation,
+                        gltf::animation::Property::Rotation => AnimationProperty::Rotation,
+                        gltf::animation::Property::Scale => AnimationProperty::Scale,
+                        gltf::animation::Property::MorphTargetWeights => {
+                            AnimationProperty::MorphWeights
+                        }
+                    };
+                    let interpolation = match channel.sampler().interpolation() {
+                        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                        gltf::animation::Interpolation::Step => Interpolation::Step,
+                        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                    };
+                    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                    let times: Vec<f32> = match reader.read_inputs() {
+                        Some(iter) => iter.collect(),
+                        None => continue,
+                    };
+                    let values: Vec<f32> = match reader.read_outputs() {
+                        Some(gltf::animation::util::ReadOutputs::Translations(iter))
+                        | Some(gltf::animation::util::ReadOutputs::Scales(iter)) => {
+                            iter.flatten().collect()
+                        }
+                        Some(gltf::animation::util::ReadOutputs::Rotations(iter)) => {
+                            iter.into_f32().flatten().collect()
+                        }
+                        Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(iter)) => {
+                            iter.into_f32().collect()
+                        }
+                        None => continue,
+                    };
+                    channels.push(
+                        AnimationChannel::new(
+                            channel.target().node().index(),
+                            property,
+                            interpolation,
+                            times,
+                            values,
+                        )
+                        .map_err(|err| {
+                            format!("Animation `{}`: {}", animation.name().unwrap_or(""), err)
+                        })?,
+                    );
+                }
+
+                let duration = channels
+                    .iter()
+                    .filter_map(|channel| channel.times.last())
+                    .fold(0.0, |duration: f32, time| duration.max(*time));
+                Ok(Animation {
+                    name: animation.name().unwrap_or("").to_string(),
+                    channels,
+                    duration,
+                })
+            })
+            .collect()
+    }
+
+    /// Pose the nodes at the given time, in seconds. The world transforms of
+    /// the nodes need to be updated afterwards.
+    pub fn apply(&self, time: f32, nodes: &mut [SceneNode]) {
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let node = &mut nodes[channel.node_index];
+            match channel.property {
+                AnimationProperty::Translation => {
+                    node.translation = Vec3::from_slice_unaligned(&value)
+                }
+                AnimationProperty::Rotation => node.rotation = Quat::from_slice_unaligned(&value),
+                AnimationProperty::Scale => node.scale = Vec3::from_slice_unaligned(&value),
+                AnimationProperty::MorphWeights => {
+                    node.morph_weights = value;
+                    continue;
+                }
+            }
+            node.local_transform =
+                Mat4::from_scale_rotation_translation(node.scale, node.rotation, node.translation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_channel(
+        property: AnimationProperty,
+        interpolation: Interpolation,
+        times: &[f32],
+        values: &[f32],
+    ) -> AnimationChannel {
+        AnimationChannel::new(0, property, interpolation, times.to_vec(), values.to_vec()).unwrap()
+    }
+
+    fn assert_near(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn step() {
+        let channel = new_channel(
+            AnimationProperty::Translation,
+            Interpolation::Step,
+            &[1.0, 2.0],
+            &[0.0, 0.0, 0.0, 1.0, 2.0, 3.0],
+        );
+        assert_near(&channel.sample(1.0), &[0.0, 0.0, 0.0]);
+        assert_near(&channel.sample(1.99), &[0.0, 0.0, 0.0]);
+        assert_near(&channel.sample(2.0), &[1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn linear() {
+        let channel = new_channel(
+            AnimationProperty::Scale,
+            Interpolation::Linear,
+            &[0.0, 1.0, 3.0],
+            &[0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 3.0, 2.0, 0.0],
+        );
+        assert_near(&channel.sample(0.5), &[0.5, 1.0, 2.0]);
+        assert_near(&channel.sample(1.0), &[1.0, 2.0, 4.0]);
+        assert_near(&channel.sample(2.0), &[2.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn times_are_clamped() {
+        for interpolation in &[
+            Interpolation::Step,
+            Interpolation::Linear,
+            Interpolation::CubicSpline,
+        ] {
+            let values: &[f32] = match interpolation {
+                // Tangents that would move the value if they were used
+                Interpolation::CubicSpline => &[9.0, 1.0, 9.0, 9.0, 2.0, 9.0],
+                _ => &[1.0, 2.0],
+            };
+            let channel = new_channel(
+                AnimationProperty::MorphWeights,
+                *interpolation,
+                &[1.0, 2.0],
+                values,
+            );
+            assert_near(&channel.sample(-1.0), &[1.0]);
+            assert_near(&channel.sample(1.0), &[1.0]);
+            assert_near(&channel.sample(2.0), &[2.0]);
+            assert_near(&channel.sample(10.0), &[2.0]);
+        }
+    }
+
+    #[test]
+    fn cubic_spline() {
+        // With tangents of 0, the spline eases in and out
+        let channel = new_channel(
+            AnimationProperty::MorphWeights,
+            Interpolation::CubicSpline,
+            &[0.0, 2.0],
+            &[0.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        );
+        assert_near(&channel.sample(0.5), &[0.15625]);
+        assert_near(&channel.sample(1.0), &[0.5]);
+        assert_near(&channel.sample(1.5), &[0.84375]);
+
+        // With tangents along the line between the values, it is the line.
+        // The tangents are per second, and the keyframes 2 seconds apart.
+        let channel = new_channel(
+            AnimationProperty::MorphWeights,
+            Interpolation::CubicSpline,
+            &[0.0, 2.0],
+            &[0.5, 0.0, 0.5, 0.5, 1.0, 0.5],
+        );
+        assert_near(&channel.sample(0.5), &[0.25]);
+        assert_near(&channel.sample(1.5), &[0.75]);
+
+        // Rotations are normalized
+        let channel = new_channel(
+            AnimationProperty::Rotation,
+            Interpolation::CubicSpline,
+            &[0.0, 1.0],
+            &[
+                0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, //
+                0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+            ],
+        );
+        let rotation = Quat::from_slice_unaligned(&channel.sample(0.5));
+        assert!(rotation.is_normalized());
+        assert!(rotation.dot(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)) > 0.9999);
+    }
+
+    #[test]
+    fn rotations_take_the_shortest_path() {
+        let start = Quat::from_rotation_y(0.1);
+        // The same rotation as 0.3 radians, on the other side of the sphere
+        let end = -Quat::from_rotation_y(0.3);
+        let values: Vec<f32> = <[f32; 4]>::from(start)
+            .iter()
+            .chain(<[f32; 4]>::from(end).iter())
+            .copied()
+            .collect();
+        let channel = new_channel(
+            AnimationProperty::Rotation,
+            Interpolation::Linear,
+            &[0.0, 1.0],
+            &values,
+        );
+        let rotation = Quat::from_slice_unaligned(&channel.sample(0.5));
+        assert!(rotation.is_normalized());
+        assert!(rotation.dot(Quat::from_rotation_y(0.2)).abs() > 0.9999);
+    }
+
+    #[test]
+    fn mismatched_values_are_rejected() {
+        let try_new = |interpolation, times: &[f32], values: &[f32]| {
+            AnimationChannel::new(
+                0,
+                AnimationProperty::Translation,
+                interpolation,
+                times.to_vec(),
+                values.to_vec(),
+            )
+        };
+        assert!(try_new(Interpolation::Linear, &[0.0, 1.0], &[0.0; 3]).is_err());
+        assert!(try_new(Interpolation::Linear, &[0.0, 1.0], &[0.0; 9]).is_err());
+        assert!(try_new(Interpolation::CubicSpline, &[0.0, 1.0], &[0.0; 6]).is_err());
+        assert!(try_new(Interpolation::CubicSpline, &[0.0, 1.0], &[0.0; 18]).is_ok());
+        // Times have to increase
+        assert!(try_new(Interpolation::Linear, &[1.0, 1.0], &[0.0; 6]).is_err());
+        assert!(try_new(Interpolation::Linear, &[0.0, f32::NAN], &[0.0; 6]).is_err());
+
+        // Morph weights have as many components as fit
+        let new_weights = |times: &[f32], values: &[f32]| {
+            AnimationChannel::new(
+                0,
+                AnimationProperty::MorphWeights,
+                Interpolation::Linear,
+                times.to_vec(),
+                values.to_vec(),
+            )
+        };
+        assert_eq!(
+            new_weights(&[0.0, 1.0], &[0.0; 6]).unwrap().component_count,
+            3
+        );
+        assert!(new_weights(&[0.0, 1.0], &[0.0; 5]).is_err());
+    }
+
+    fn node() -> SceneNode {
+        SceneNode {
+            name: String::new(),
+            opt_parent: None,
+            children: Vec::new(),
+            translation: Vec3::zero(),
+            rotation: Quat::identity(),
+            scale: Vec3::one(),
+            local_transform: Mat4::identity(),
+            world_transform: Mat4::identity(),
+            morph_weights: vec![0.0; 2],
+        }
+    }
+
+    #[test]
+    fn morph_weights_are_applied_to_their_node() {
+        let weights = AnimationChannel::new(
+            1,
+            AnimationProperty::MorphWeights,
+            Interpolation::Linear,
+            vec![0.0, 1.0],
+            vec![0.0, 1.0, 1.0, 0.0],
+        )
+        .unwrap();
+        let translation = AnimationChannel::new(
+            0,
+            AnimationProperty::Translation,
+            Interpolation::Step,
+            vec![0.0],
+            vec![1.0, 2.0, 3.0],
+        )
+        .unwrap();
+        let animation = Animation {
+            name: String::new(),
+            channels: vec![weights, translation],
+            duration: 1.0,
+        };
+
+        let mut nodes = vec![node(), node()];
+        animation.apply(0.25, &mut nodes);
+        // Every weight is interpolated on its own
+        assert_near(&nodes[1].morph_weights, &[0.25, 0.75]);
+        // Weights don't change the transform of the node
+        assert_eq!(nodes[1].local_transform, Mat4::identity());
+        assert_eq!(nodes[0].morph_weights, vec![0.0; 2]);
+        assert_eq!(
+            nodes[0].local_transform,
+            Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0))
+        );
+    }
+}
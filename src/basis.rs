@@ -6,7 +6,7 @@ use winit::window::Window;
 pub struct Basis {
     pub entry: ash::Entry,
     pub instance: ash::Instance,
-    pub surface: vk::SurfaceKHR,
+    pub opt_surface: Option<vk::SurfaceKHR>, // None if the context is headless
     pub validation_layers: Vec<String>,
 
     // - Extensions
@@ -16,14 +16,18 @@ pub struct Basis {
 impl Drop for Basis {
     fn drop(&mut self) {
         unsafe {
-            self.ext_surface.destroy_surface(self.surface, None);
+            if let Some(surface) = self.opt_surface {
+                self.ext_surface.destroy_surface(surface, None);
+            }
             self.instance.destroy_instance(None);
         }
     }
 }
 
 impl Basis {
-    pub fn new(app_name: &str, window: &Window) -> Basis {
+    /// Pass `None` as the window to create a headless basis, i.e. one without
+    /// a surface.
+    pub fn new(app_name: &str, opt_window: Option<&Window>) -> Basis {
         let validation_layers = vec![String::from("VK_LAYER_KHRONOS_validation")];
 
         // # Init Ash
@@ -71,7 +75,11 @@ impl Basis {
                 .map(|layer_name| layer_name.as_ptr())
                 .collect();
 
-            let extension_names = platforms::required_extension_names();
+            let extension_names = if opt_window.is_some() {
+                platforms::required_extension_names()
+            } else {
+                platforms::required_headless_extension_names()
+            };
 
             let create_info = vk::InstanceCreateInfo::builder()
                 .enabled_layer_names(&layer_names)
@@ -89,14 +97,13 @@ impl Basis {
 
         // # Create surface
         let ext_surface = ash::extensions::khr::Surface::new(&entry, &instance);
-        let surface = unsafe {
-            platforms::create_surface(&entry, &instance, &window)
-                .expect("Failed to create surface.")
-        };
+        let opt_surface = opt_window.map(|window| unsafe {
+            platforms::create_surface(&entry, &instance, window).expect("Failed to create surface.")
+        });
 
         Basis {
             instance,
-            surface,
+            opt_surface,
             validation_layers,
             entry,
             ext_surface,
@@ -0,0 +1,317 @@
+use glam::*;
+
+/// An axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+/// The six planes of a view frustum, with their normals pointing inside: a
+/// point p is inside a plane if `plane.truncate().dot(p) + plane.w() >= 0`.
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    pub planes: [Vec4; 6], // Left, right, bottom, top, near, far
+}
+
+impl Aabb {
+    /// The smallest box around the points, or an empty box at the origin if
+    /// there are none
+    pub fn from_points(points: impl Iterator<Item = Vec3>) -> Aabb {
+        let (min, max) = points.fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), point| (min.min(point), max.max(point)),
+        );
+        if min.cmple(max).all() {
+            Aabb { min, max }
+        } else {
+            Aabb {
+                min: Vec3::zero(),
+                max: Vec3::zero(),
+            }
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The box around this one once transformed, which is larger than it
+    /// unless the transform only scales and translates
+    pub fn transform(&self, transform: Mat4) -> Aabb {
+        // Arvo's method: sum the extremes of each column along each axis
+        let mut min = transform.w_axis().truncate();
+        let mut max = min;
+        for (axis, (axis_min, axis_max)) in [
+            transform.x_axis().truncate(),
+            transform.y_axis().truncate(),
+            transform.z_axis().truncate(),
+        ]
+        .iter()
+        .zip(self.min.as_ref().iter().zip(self.max.as_ref()))
+        {
+            let a = *axis * *axis_min;
+            let b = *axis * *axis_max;
+            min += a.min(b);
+            max += a.max(b);
+        }
+        Aabb { min, max }
+    }
+}
+
+impl BoundingSphere {
+    /// A sphere centered on the box around the points. Not the smallest one,
+    /// but close enough for culling.
+    pub fn from_points(points: impl Iterator<Item = Vec3>, aabb: &Aabb) -> BoundingSphere {
+        let center = aabb.center();
+        let radius = points
+            .map(|point| (point - center).length())
+            .fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    /// A sphere around both spheres
+    pub fn union(&self, other: &BoundingSphere, center: Vec3) -> BoundingSphere {
+        BoundingSphere {
+            center,
+            radius: f32::max(
+                (self.center - center).length() + self.radius,
+                (other.center - center).length() + other.radius,
+            ),
+        }
+    }
+
+    /// The sphere around this one once transformed. Non-uniform scales grow
+    /// it along every axis.
+    pub fn transform(&self, transform: Mat4) -> BoundingSphere {
+        let scale = transform
+            .x_axis()
+            .truncate()
+            .length()
+            .max(transform.y_axis().truncate().length())
+            .max(transform.z_axis().truncate().length());
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+impl Frustum {
+    /// Extract the planes of a view-projection matrix, with depth in [0, 1].
+    /// Multiply in a model matrix to get the planes in the space of a mesh,
+    /// so that its bounds can be tested without transforming them.
+    pub fn from_matrix(view_projection: Mat4) -> Frustum {
+        // Gribb and Hartmann's method: each clip plane is a sum of the rows
+        let m = view_projection.to_cols_array_2d();
+        let row = |idx: usize| Vec4::new(m[0][idx], m[1][idx], m[2][idx], m[3][idx]);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        let mut normalized_planes = [Vec4::zero(); 6];
+        for (normalized_plane, plane) in normalized_planes.iter_mut().zip(&planes) {
+            let length = plane.truncate().length();
+            *normalized_plane = if length > f32::EPSILON {
+                *plane / length
+            } else {
+                // An infinite far plane, which everything is inside of
+                Vec4::new(0.0, 0.0, 0.0, 1.0)
+            };
+        }
+        Frustum {
+            planes: normalized_planes,
+        }
+    }
+
+    /// False only if the sphere is entirely outside the frustum
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w() >= -sphere.radius)
+    }
+
+    /// False only if the box is entirely outside one of the planes. Boxes
+    /// near the corners of the frustum can pass without being visible.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let normal = plane.truncate();
+            let pick = |n: f32, min: f32, max: f32| if n >= 0.0 { max } else { min };
+            let corner = Vec3::new(
+                pick(normal.x(), aabb.min.x(), aabb.max.x()),
+                pick(normal.y(), aabb.min.y(), aabb.max.y()),
+                pick(normal.z(), aabb.min.z(), aabb.max.z()),
+            );
+            normal.dot(corner) + plane.w() >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Looking down +Z from the origin, like the demos, with a 90 degree field
+    // of view: at a distance z, x and y go from -z to z
+    fn frustum() -> Frustum {
+        Frustum::from_matrix(Mat4::perspective_lh(
+            std::f32::consts::FRAC_PI_2,
+            1.0,
+            0.1,
+            100.0,
+        ))
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Vec3::new(x, y, z),
+            radius,
+        }
+    }
+
+    fn aabb(center: Vec3, half_extent: f32) -> Aabb {
+        Aabb {
+            min: center - Vec3::splat(half_extent),
+            max: center + Vec3::splat(half_extent),
+        }
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        for plane in &frustum().planes {
+            assert!((plane.truncate().length() - 1.0).abs() < 1e-5);
+        }
+        // The near plane is at z = 0.1, facing +Z
+        let near = frustum().planes[4];
+        assert!((near.truncate() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+        assert!((near.w() + 0.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn spheres() {
+        let frustum = frustum();
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 10.0, 1.0)));
+        // Behind the camera, beyond the far plane, and before the near plane
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, 102.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, 0.05, 0.01)));
+        // Straddling the left plane at x = -10, and just outside it, at a
+        // distance of 2 / sqrt(2) from it
+        assert!(frustum.intersects_sphere(&sphere(-10.5, 0.0, 10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(-12.0, 0.0, 10.0, 1.0)));
+        assert!(frustum.intersects_sphere(&sphere(-12.0, 0.0, 10.0, 1.5)));
+        // The same for the top plane
+        assert!(frustum.intersects_sphere(&sphere(0.0, 10.5, 10.0, 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 12.0, 10.0, 1.0)));
+    }
+
+    #[test]
+    fn boxes() {
+        let frustum = frustum();
+        assert!(frustum.intersects_aabb(&aabb(Vec3::new(0.0, 0.0, 10.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&aabb(Vec3::new(0.0, 0.0, -10.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&aabb(Vec3::new(0.0, 0.0, 102.0), 1.0)));
+        assert!(frustum.intersects_aabb(&aabb(Vec3::new(-10.5, 0.0, 10.0), 1.0)));
+        assert!(!frustum.intersects_aabb(&aabb(Vec3::new(-12.0, 0.0, 10.0), 0.9)));
+        // A box around the camera
+        assert!(frustum.intersects_aabb(&aabb(Vec3::zero(), 1.0)));
+        // Past the edge between the left and far planes, the box is outside
+        // the frustum but not entirely outside either plane, so the test is
+        // conservative
+        assert!(frustum.intersects_aabb(&Aabb {
+            min: Vec3::new(-115.0, -1.0, 95.0),
+            max: Vec3::new(-101.0, 1.0, 110.0),
+        }));
+    }
+
+    #[test]
+    fn planes_in_mesh_space() {
+        // A mesh at the origin, moved in front of or behind the camera
+        let view_projection = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        let local_sphere = sphere(0.0, 0.0, 0.0, 1.0);
+        let local_aabb = aabb(Vec3::zero(), 1.0);
+
+        let in_front = Frustum::from_matrix(
+            view_projection * Mat4::from_translation(Vec3::new(0.0, 0.0, 10.0)),
+        );
+        assert!(in_front.intersects_sphere(&local_sphere));
+        assert!(in_front.intersects_aabb(&local_aabb));
+
+        let behind = Frustum::from_matrix(
+            view_projection * Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0)),
+        );
+        assert!(!behind.intersects_sphere(&local_sphere));
+        assert!(!behind.intersects_aabb(&local_aabb));
+    }
+
+    #[test]
+    fn infinite_far_plane() {
+        let frustum = Frustum::from_matrix(Mat4::perspective_infinite_lh(
+            std::f32::consts::FRAC_PI_2,
+            1.0,
+            0.1,
+        ));
+        assert_eq!(frustum.planes[5], Vec4::new(0.0, 0.0, 0.0, 1.0));
+        assert!(frustum.intersects_sphere(&sphere(0.0, 0.0, 1.0e6, 1.0)));
+        assert!(frustum.intersects_aabb(&aabb(Vec3::new(0.0, 0.0, 1.0e6), 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere(0.0, 0.0, -10.0, 1.0)));
+    }
+
+    #[test]
+    fn transformed_bounds() {
+        let unit = aabb(Vec3::zero(), 1.0);
+        let transform = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0))
+            * Mat4::from_rotation_z(std::f32::consts::FRAC_PI_4)
+            * Mat4::from_scale(Vec3::new(2.0, 1.0, 1.0));
+        let transformed = unit.transform(transform);
+        // The corners of the box once transformed are all inside it
+        for corner in 0..8 {
+            let point = Vec3::new(
+                if corner & 1 == 0 { -1.0 } else { 1.0 },
+                if corner & 2 == 0 { -1.0 } else { 1.0 },
+                if corner & 4 == 0 { -1.0 } else { 1.0 },
+            );
+            let point = transform.transform_point3(point);
+            assert!(point.cmpge(transformed.min - Vec3::splat(1e-5)).all());
+            assert!(point.cmple(transformed.max + Vec3::splat(1e-5)).all());
+        }
+        let expected_half_extent = 3.0 / std::f32::consts::SQRT_2;
+        assert!((transformed.extent().x() - 2.0 * expected_half_extent).abs() < 1e-5);
+        assert!((transformed.center() - Vec3::new(1.0, 2.0, 3.0)).length() < 1e-5);
+
+        let transformed_sphere = sphere(1.0, 0.0, 0.0, 1.0).transform(transform);
+        assert!((transformed_sphere.radius - 2.0).abs() < 1e-5);
+        assert!(
+            (transformed_sphere.center
+                - Vec3::new(
+                    1.0 + std::f32::consts::SQRT_2,
+                    2.0 + std::f32::consts::SQRT_2,
+                    3.0
+                ))
+            .length()
+                < 1e-5
+        );
+    }
+}
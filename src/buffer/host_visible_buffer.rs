@@ -3,7 +3,7 @@ use crate::*;
 pub struct HostVisibleBuffer {
     pub name: String,
     pub vk_buffer: vk::Buffer,
-    pub memory: vk::DeviceMemory,
+    pub allocation: Allocation,
     pub size: usize,
     device: ash::Device,
 }
@@ -12,7 +12,6 @@ impl Drop for HostVisibleBuffer {
     fn drop(&mut self) {
         unsafe {
             self.device.destroy_buffer(self.vk_buffer, None);
-            self.device.free_memory(self.memory, None);
         }
     }
 }
@@ -25,7 +24,7 @@ impl HostVisibleBuffer {
         gpu: &Gpu,
         debug_utils: &DebugUtils,
     ) -> HostVisibleBuffer {
-        let (vk_buffer, memory) = super::new_raw_buffer(
+        let (vk_buffer, allocation) = super::new_raw_buffer(
             size,
             usage,
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
@@ -37,7 +36,7 @@ impl HostVisibleBuffer {
         HostVisibleBuffer {
             name: String::from(name),
             vk_buffer,
-            memory,
+            allocation,
             size,
             device: gpu.device.clone(),
         }
@@ -47,19 +46,27 @@ impl HostVisibleBuffer {
         let data_size = std::mem::size_of_val(data);
         debug_assert!(self.size >= offset + data_size);
 
+        // The allocator keeps host-visible memory mapped
+        let mapped_ptr = self
+            .allocation
+            .opt_mapped_ptr
+            .expect("Host-visible buffer memory is not mapped.");
         unsafe {
-            let data_ptr = self
-                .device
-                .map_memory(
-                    self.memory,
-                    offset as u64,
-                    data_size as u64,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .expect("Failed to map memory.") as *mut T;
-
+            let data_ptr = mapped_ptr.add(offset) as *mut T;
             data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
-            self.device.unmap_memory(self.memory);
         }
     }
+
+    /// A copy of the contents of the buffer
+    pub fn read_data(&self) -> Vec<u8> {
+        let mapped_ptr = self
+            .allocation
+            .opt_mapped_ptr
+            .expect("Host-visible buffer memory is not mapped.");
+        let mut data = vec![0_u8; self.size];
+        unsafe {
+            mapped_ptr.copy_to_nonoverlapping(data.as_mut_ptr(), self.size);
+        }
+        data
+    }
 }
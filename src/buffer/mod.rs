@@ -4,13 +4,17 @@ pub mod device_local_buffer;
 pub use device_local_buffer::*;
 pub mod host_visible_buffer;
 pub use host_visible_buffer::*;
+pub mod staging_ring;
+pub use staging_ring::*;
+pub mod uniform_ring;
+pub use uniform_ring::*;
 
 fn new_raw_buffer(
     size: usize,
     usage: vk::BufferUsageFlags,
     required_memory_properties: vk::MemoryPropertyFlags,
     gpu: &Gpu,
-) -> (vk::Buffer, vk::DeviceMemory) {
+) -> (vk::Buffer, Allocation) {
     // Create buffer
     let buffer_create_info = vk::BufferCreateInfo::builder()
         .size(size as vk::DeviceSize)
@@ -22,35 +26,22 @@ fn new_raw_buffer(
             .create_buffer(&buffer_create_info, None)
             .expect("Failed to create buffer.")
     };
-    // Locate memory type
+    // Find room for it in one of the allocator's blocks
     let mem_requirements = unsafe { gpu.device.get_buffer_memory_requirements(vk_buffer) };
-    let memory_type_index = gpu
-        .memory_properties
-        .memory_types
-        .iter()
-        .enumerate()
-        .position(|(i, &m)| {
-            (mem_requirements.memory_type_bits & (1 << i)) > 0
-                && m.property_flags.contains(required_memory_properties)
-        })
-        .expect("Failed to find suitable memory type.") as u32;
-    // Allocate memory
-    // TODO: Replace with allocator library?
-    let allocate_info = vk::MemoryAllocateInfo::builder()
-        .allocation_size(mem_requirements.size)
-        .memory_type_index(memory_type_index);
-
-    let device_memory = unsafe {
-        gpu.device
-            .allocate_memory(&allocate_info, None)
-            .expect("Failed to allocate buffer memory.")
-    };
+    let allocation = gpu
+        .allocator
+        .allocate(
+            mem_requirements,
+            required_memory_properties,
+            AllocationKind::Buffer,
+        )
+        .unwrap_or_else(|err| panic!("Failed to allocate buffer memory: {}", err));
     // Bind memory to buffer
     unsafe {
         gpu.device
-            .bind_buffer_memory(vk_buffer, device_memory, 0)
+            .bind_buffer_memory(vk_buffer, allocation.device_memory, allocation.offset)
             .expect("Failed to bind buffer.");
     }
 
-    (vk_buffer, device_memory)
+    (vk_buffer, allocation)
 }
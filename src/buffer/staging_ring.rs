@@ -0,0 +1,171 @@
+use crate::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+// The part of an upload that hasn't been copied to its buffer yet
+struct PendingUpload {
+    dst_buffer: BufferHandle,
+    dst_offset: usize,
+    data: Vec<u8>,
+}
+
+/// A persistently mapped buffer that uploads to device-local buffers are
+/// staged in. Uploads are queued, and copied by the command buffer of the
+/// next frame from the segment of the buffer owned by that frame. Whatever
+/// doesn't fit in the segment is left for the frames after it, so uploads of
+/// any size are split across frames instead of stalling. Uploads are queued
+/// by buffer handle, and dropped if the buffer is gone by the time they are
+/// copied.
+pub struct StagingRing {
+    pub buffer: HostVisibleBuffer,
+    pub frame_size: usize, // Size of the segment of each frame
+    pending_uploads: RefCell<VecDeque<PendingUpload>>,
+}
+
+impl StagingRing {
+    pub fn new(
+        name: &str,
+        frame_size: usize,
+        num_frames: usize,
+        gpu: &Gpu,
+        debug_utils: &DebugUtils,
+    ) -> StagingRing {
+        let buffer = HostVisibleBuffer::new(
+            name,
+            frame_size * num_frames,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            gpu,
+            debug_utils,
+        );
+
+        StagingRing {
+            buffer,
+            frame_size,
+            pending_uploads: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    pub fn queue_upload<T>(&self, dst_buffer: BufferHandle, data: &[T]) {
+        let data_size = std::mem::size_of_val(data);
+        if data_size == 0 {
+            return;
+        }
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, data_size) };
+        self.pending_uploads.borrow_mut().push_back(PendingUpload {
+            dst_buffer,
+            dst_offset: 0,
+            data: bytes.to_vec(),
+        });
+    }
+
+    /// Whether part of an upload to the buffer is still waiting for a frame
+    /// to copy it
+    pub fn is_pending(&self, buffer_handle: BufferHandle) -> bool {
+        self.pending_uploads
+            .borrow()
+            .iter()
+            .any(|upload| upload.dst_buffer == buffer_handle)
+    }
+
+    /// Record the copies of the pending uploads that fit in the segment of the
+    /// frame, whose previous copies must no longer be in use by the GPU. The
+    /// buffers are resolved with `get_vk_buffer`.
+    pub fn flush(
+        &self,
+        frame_idx: usize,
+        command_buffer: vk::CommandBuffer,
+        device: &ash::Device,
+        get_vk_buffer: impl Fn(BufferHandle) -> Option<vk::Buffer>,
+    ) {
+        let mut pending_uploads = self.pending_uploads.borrow_mut();
+        if pending_uploads.is_empty() {
+            return;
+        }
+
+        // Stage as much as fits in the segment
+        let segment_offset = frame_idx * self.frame_size;
+        let mut offset = 0;
+        let mut copies: Vec<(vk::Buffer, vk::BufferCopy)> = Vec::new();
+        while offset < self.frame_size {
+            let upload = match pending_uploads.front_mut() {
+                Some(upload) => upload,
+                None => break,
+            };
+            let dst_buffer = match get_vk_buffer(upload.dst_buffer) {
+                Some(dst_buffer) => dst_buffer,
+                None => {
+                    // The buffer was destroyed before the upload was copied
+                    pending_uploads.pop_front();
+                    continue;
+                }
+            };
+            let size = upload.data.len().min(self.frame_size - offset);
+            self.buffer
+                .upload_data(&upload.data[..size], segment_offset + offset);
+            copies.push((
+                dst_buffer,
+                vk::BufferCopy {
+                    src_offset: (segment_offset + offset) as u64,
+                    dst_offset: upload.dst_offset as u64,
+                    size: size as u64,
+                },
+            ));
+            offset += size;
+
+            if size == upload.data.len() {
+                pending_uploads.pop_front();
+            } else {
+                upload.data.drain(..size);
+                upload.dst_offset += size;
+            }
+        }
+
+        if copies.is_empty() {
+            return;
+        }
+
+        unsafe {
+            // Wait for the uses of the buffers by earlier frames before
+            // overwriting them
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[],
+            );
+            for (dst_buffer, copy) in &copies {
+                device.cmd_copy_buffer(
+                    command_buffer,
+                    self.buffer.vk_buffer,
+                    *dst_buffer,
+                    &[*copy],
+                );
+            }
+            // Make the copies visible to every way passes can use the buffers
+            let memory_barriers = [vk::MemoryBarrier {
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::VERTEX_ATTRIBUTE_READ
+                    | vk::AccessFlags::INDEX_READ
+                    | vk::AccessFlags::UNIFORM_READ
+                    | vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::SHADER_WRITE,
+                ..Default::default()
+            }];
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::VERTEX_INPUT
+                    | vk::PipelineStageFlags::VERTEX_SHADER
+                    | vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &[],
+                &[],
+            );
+        }
+    }
+}
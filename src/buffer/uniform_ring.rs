@@ -0,0 +1,170 @@
+use crate::*;
+
+/// A persistently mapped uniform buffer that many small uniforms, e.g. one per
+/// draw, are linearly allocated from. Each frame in flight owns a segment of
+/// the buffer that is reset when the frame begins again. Passes bind it as a
+/// dynamic uniform buffer, offset to each uniform with the offset returned by
+/// `push()`.
+pub struct UniformRing {
+    pub buffer: HostVisibleBuffer,
+    allocator: RingAllocator,
+}
+
+// Where the uniforms go in the buffer, apart from the buffer itself
+struct RingAllocator {
+    frame_size: usize,       // Size of the segment of each frame
+    max_uniform_size: usize, // The range of the descriptors it is bound to
+    alignment: usize,
+    frame_idx: usize,
+    offset: usize, // Next free offset in the segment of the current frame
+}
+
+impl UniformRing {
+    pub fn new(
+        name: &str,
+        frame_size: usize,
+        max_uniform_size: usize,
+        num_frames: usize,
+        gpu: &Gpu,
+        debug_utils: &DebugUtils,
+    ) -> Result<UniformRing, String> {
+        let limits = &gpu.properties.limits;
+        if max_uniform_size == 0 || max_uniform_size > frame_size {
+            return Err(format!(
+                "Uniform ring `{}`: the max uniform size {} must be between 1 and the frame size {}.",
+                name, max_uniform_size, frame_size
+            ));
+        }
+        if max_uniform_size > limits.max_uniform_buffer_range as usize {
+            return Err(format!(
+                "Uniform ring `{}`: the max uniform size {} exceeds the device limit of {}.",
+                name, max_uniform_size, limits.max_uniform_buffer_range
+            ));
+        }
+        // Start the segment of each frame at a valid dynamic offset
+        let alignment = (limits.min_uniform_buffer_offset_alignment as usize).max(1);
+        let frame_size = align_up(frame_size as u64, alignment as u64) as usize;
+        let buffer = HostVisibleBuffer::new(
+            name,
+            frame_size * num_frames,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            gpu,
+            debug_utils,
+        );
+
+        Ok(UniformRing {
+            buffer,
+            allocator: RingAllocator::new(frame_size, max_uniform_size, alignment),
+        })
+    }
+
+    /// The range of the descriptors the ring is bound to
+    pub fn get_max_uniform_size(&self) -> usize {
+        self.allocator.max_uniform_size
+    }
+
+    /// Start allocating from the segment of the frame, whose previous uniforms
+    /// must no longer be in use by the GPU
+    pub fn begin_frame(&mut self, frame_idx: usize) {
+        self.allocator.begin_frame(frame_idx);
+    }
+
+    /// Copy the data into the segment of the current frame and return its
+    /// dynamic offset
+    pub fn push<T>(&mut self, data: &[T]) -> Result<u32, String> {
+        let dynamic_offset = self
+            .allocator
+            .allocate(std::mem::size_of_val(data))
+            .map_err(|err| format!("Uniform ring `{}`: {}", self.buffer.name, err))?;
+        self.buffer.upload_data(data, dynamic_offset);
+
+        Ok(dynamic_offset as u32)
+    }
+}
+
+impl RingAllocator {
+    fn new(frame_size: usize, max_uniform_size: usize, alignment: usize) -> RingAllocator {
+        RingAllocator {
+            frame_size,
+            max_uniform_size,
+            alignment,
+            frame_idx: 0,
+            offset: 0,
+        }
+    }
+
+    fn begin_frame(&mut self, frame_idx: usize) {
+        self.frame_idx = frame_idx;
+        self.offset = 0;
+    }
+
+    /// The offset of the next uniform from the start of the buffer
+    fn allocate(&mut self, data_size: usize) -> Result<usize, String> {
+        if data_size > self.max_uniform_size {
+            return Err(format!(
+                "{} bytes exceed the max uniform size of {}.",
+                data_size, self.max_uniform_size
+            ));
+        }
+        // The whole descriptor range has to fit in the segment
+        let offset = align_up(self.offset as u64, self.alignment as u64) as usize;
+        if offset + self.max_uniform_size > self.frame_size {
+            return Err(format!(
+                "Out of space for the current frame ({} bytes per frame).",
+                self.frame_size
+            ));
+        }
+        self.offset = offset + data_size;
+
+        Ok(self.frame_idx * self.frame_size + offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_are_aligned() {
+        let mut allocator = RingAllocator::new(1024, 64, 256);
+        assert_eq!(allocator.allocate(4), Ok(0));
+        assert_eq!(allocator.allocate(64), Ok(256));
+        assert_eq!(allocator.allocate(1), Ok(512));
+    }
+
+    #[test]
+    fn frames_have_their_own_segment() {
+        let mut allocator = RingAllocator::new(1024, 64, 256);
+        allocator.begin_frame(2);
+        assert_eq!(allocator.allocate(16), Ok(2 * 1024));
+        assert_eq!(allocator.allocate(16), Ok(2 * 1024 + 256));
+    }
+
+    #[test]
+    fn the_descriptor_range_has_to_fit() {
+        // A fourth uniform would start at 768, where its data would fit but
+        // its descriptor range wouldn't
+        let mut allocator = RingAllocator::new(1024, 300, 256);
+        assert_eq!(allocator.allocate(16), Ok(0));
+        assert_eq!(allocator.allocate(16), Ok(256));
+        assert_eq!(allocator.allocate(16), Ok(512));
+        let err = allocator.allocate(16).unwrap_err();
+        assert!(err.contains("Out of space"), "{}", err);
+
+        let err = allocator.allocate(301).unwrap_err();
+        assert!(err.contains("max uniform size"), "{}", err);
+    }
+
+    #[test]
+    fn begin_frame_resets_the_offset() {
+        let mut allocator = RingAllocator::new(512, 64, 256);
+        assert_eq!(allocator.allocate(16), Ok(0));
+        assert_eq!(allocator.allocate(16), Ok(256));
+        assert!(allocator.allocate(16).is_err());
+
+        allocator.begin_frame(1);
+        assert_eq!(allocator.allocate(16), Ok(512));
+        allocator.begin_frame(0);
+        assert_eq!(allocator.allocate(16), Ok(0));
+    }
+}
@@ -1,16 +1,45 @@
 use crate::*;
 
-// TODO: Add support for buffer aliases, so that intermediate buffers with
-// different handles can use the same underlying memory, as long as they
-// don't overlap.
+/// A device-local buffer that only lives within a frame's graph: written by
+/// one of its passes before any other pass reads it. Like transient images,
+/// each graph creates its own buffer for it in memory shared with the
+/// transient buffers that are never alive at the same time.
+#[derive(Clone)]
+pub struct TransientBuffer {
+    pub name: String,
+    pub size: usize,
+    pub usage: vk::BufferUsageFlags,
+}
+
+// Enough for a few meshes per frame. Larger uploads are spread over frames.
+const STAGING_RING_FRAME_SIZE: usize = 16 * 1024 * 1024;
 
 pub struct BufferList {
-    pub list: Vec<(BufferHandle, HostVisibleBuffer)>, // TODO: Support device local buffers too
+    pub list: Vec<(BufferHandle, HostVisibleBuffer)>,
+    pub device_local_list: Vec<(BufferHandle, DeviceLocalBuffer)>,
+    pub transient_list: Vec<(BufferHandle, TransientBuffer)>,
+    // A copy per frame in flight, the handle resolves to that of the current frame
+    pub per_frame_list: Vec<(BufferHandle, Vec<HostVisibleBuffer>)>,
+    pub uniform_ring_list: Vec<(BufferHandle, UniformRing)>,
+    pub staging_ring: StagingRing, // Uploads to the device-local buffers
 }
 
 impl BufferList {
-    pub fn new() -> BufferList {
-        BufferList { list: Vec::new() }
+    pub fn new(num_frames: usize, gpu: &Gpu, debug_utils: &DebugUtils) -> BufferList {
+        BufferList {
+            list: Vec::new(),
+            device_local_list: Vec::new(),
+            transient_list: Vec::new(),
+            per_frame_list: Vec::new(),
+            uniform_ring_list: Vec::new(),
+            staging_ring: StagingRing::new(
+                "buffer_staging_ring",
+                STAGING_RING_FRAME_SIZE,
+                num_frames,
+                gpu,
+                debug_utils,
+            ),
+        }
     }
 
     pub fn new_buffer(
@@ -28,7 +57,7 @@ impl BufferList {
             BufferHandle(hasher.finish())
         };
         // Error if name already exists
-        if self.get_buffer_from_handle(handle).is_some() {
+        if self.contains(handle) {
             return Err(format!(
                 "A buffer with the same name `{}` already exists in the context.",
                 name
@@ -41,6 +70,134 @@ impl BufferList {
         Ok(handle)
     }
 
+    /// A buffer in device-local memory, uploaded to through the staging ring
+    pub fn new_device_local_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+        gpu: &Gpu,
+        debug_utils: &DebugUtils,
+    ) -> Result<BufferHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            BufferHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.contains(handle) {
+            return Err(format!(
+                "A buffer with the same name `{}` already exists in the context.",
+                name
+            ));
+        }
+        let buffer = DeviceLocalBuffer::new_empty(name, size, usage, gpu, debug_utils);
+        self.device_local_list.push((handle, buffer));
+
+        Ok(handle)
+    }
+
+    pub fn new_transient_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<BufferHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            BufferHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.contains(handle) {
+            return Err(format!(
+                "A buffer with the same name `{}` already exists in the context.",
+                name
+            ));
+        }
+        // The buffer itself is created by the graphs that use it
+        self.transient_list.push((
+            handle,
+            TransientBuffer {
+                name: String::from(name),
+                size,
+                usage,
+            },
+        ));
+
+        Ok(handle)
+    }
+
+    pub fn new_per_frame_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+        num_frames: usize,
+        gpu: &Gpu,
+        debug_utils: &DebugUtils,
+    ) -> Result<BufferHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            BufferHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.contains(handle) {
+            return Err(format!(
+                "A buffer with the same name `{}` already exists in the context.",
+                name
+            ));
+        }
+        // Create and insert a copy for each frame
+        let buffers = (0..num_frames)
+            .map(|i| {
+                HostVisibleBuffer::new(&format!("{}_{}", name, i), size, usage, gpu, debug_utils)
+            })
+            .collect();
+        self.per_frame_list.push((handle, buffers));
+
+        Ok(handle)
+    }
+
+    pub fn new_uniform_ring(
+        &mut self,
+        name: &str,
+        frame_size: usize,
+        max_uniform_size: usize,
+        num_frames: usize,
+        gpu: &Gpu,
+        debug_utils: &DebugUtils,
+    ) -> Result<BufferHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            BufferHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.contains(handle) {
+            return Err(format!(
+                "A buffer with the same name `{}` already exists in the context.",
+                name
+            ));
+        }
+        let uniform_ring = UniformRing::new(
+            name,
+            frame_size,
+            max_uniform_size,
+            num_frames,
+            gpu,
+            debug_utils,
+        )?;
+        self.uniform_ring_list.push((handle, uniform_ring));
+
+        Ok(handle)
+    }
+
     pub fn get_buffer_from_handle(
         &self,
         buffer_handle: BufferHandle,
@@ -53,11 +210,166 @@ impl BufferList {
         None
     }
 
-    pub fn upload_data<T>(&self, buffer_handle: BufferHandle, data: &[T]) {
-        let internal_buffer = self.get_buffer_from_handle(buffer_handle).unwrap_or_else(||panic!(
-            "A buffer with the hash `{}` not found in the context.",
-            buffer_handle.0
-        ));
+    pub fn get_device_local_buffer_from_handle(
+        &self,
+        buffer_handle: BufferHandle,
+    ) -> Option<&DeviceLocalBuffer> {
+        for (handle, buffer) in &self.device_local_list {
+            if *handle == buffer_handle {
+                return Some(buffer);
+            }
+        }
+        None
+    }
+
+    pub fn get_transient_buffer_from_handle(
+        &self,
+        buffer_handle: BufferHandle,
+    ) -> Option<&TransientBuffer> {
+        for (handle, transient_buffer) in &self.transient_list {
+            if *handle == buffer_handle {
+                return Some(transient_buffer);
+            }
+        }
+        None
+    }
+
+    /// The copies of a per-frame buffer, indexed by frame
+    pub fn get_per_frame_buffers_from_handle(
+        &self,
+        buffer_handle: BufferHandle,
+    ) -> Option<&[HostVisibleBuffer]> {
+        for (handle, buffers) in &self.per_frame_list {
+            if *handle == buffer_handle {
+                return Some(buffers);
+            }
+        }
+        None
+    }
+
+    pub fn get_uniform_ring_from_handle(
+        &self,
+        buffer_handle: BufferHandle,
+    ) -> Option<&UniformRing> {
+        for (handle, uniform_ring) in &self.uniform_ring_list {
+            if *handle == buffer_handle {
+                return Some(uniform_ring);
+            }
+        }
+        None
+    }
+
+    /// The buffer a handle refers to in the frame, whether it is a single or
+    /// a per-frame buffer
+    pub fn get_frame_buffer_from_handle(
+        &self,
+        buffer_handle: BufferHandle,
+        frame_idx: usize,
+    ) -> Option<&HostVisibleBuffer> {
+        self.get_buffer_from_handle(buffer_handle).or_else(|| {
+            self.get_per_frame_buffers_from_handle(buffer_handle)
+                .map(|buffers| &buffers[frame_idx])
+        })
+    }
+
+    /// The buffer and size a handle refers to in the frame, for any kind of
+    /// buffer except transient ones, which only exist in graphs
+    pub fn get_vk_buffer(
+        &self,
+        buffer_handle: BufferHandle,
+        frame_idx: usize,
+    ) -> Option<(vk::Buffer, usize)> {
+        self.get_frame_buffer_from_handle(buffer_handle, frame_idx)
+            .or_else(|| {
+                self.get_uniform_ring_from_handle(buffer_handle)
+                    .map(|uniform_ring| &uniform_ring.buffer)
+            })
+            .map(|buffer| (buffer.vk_buffer, buffer.size))
+            .or_else(|| {
+                self.get_device_local_buffer_from_handle(buffer_handle)
+                    .map(|buffer| (buffer.vk_buffer, buffer.size))
+            })
+    }
+
+    pub fn contains(&self, buffer_handle: BufferHandle) -> bool {
+        self.get_buffer_from_handle(buffer_handle).is_some()
+            || self
+                .get_device_local_buffer_from_handle(buffer_handle)
+                .is_some()
+            || self
+                .get_transient_buffer_from_handle(buffer_handle)
+                .is_some()
+            || self
+                .get_per_frame_buffers_from_handle(buffer_handle)
+                .is_some()
+            || self.get_uniform_ring_from_handle(buffer_handle).is_some()
+    }
+
+    /// Reset the uniform rings to the segments of the frame, and record the
+    /// copies of the queued uploads into its command buffer
+    pub fn begin_frame(
+        &mut self,
+        frame_idx: usize,
+        command_buffer: vk::CommandBuffer,
+        device: &ash::Device,
+    ) {
+        for (_, uniform_ring) in &mut self.uniform_ring_list {
+            uniform_ring.begin_frame(frame_idx);
+        }
+        self.staging_ring
+            .flush(frame_idx, command_buffer, device, |buffer_handle| {
+                self.get_device_local_buffer_from_handle(buffer_handle)
+                    .map(|buffer| buffer.vk_buffer)
+            });
+    }
+
+    /// Host-visible buffers are written immediately. Uploads to device-local
+    /// buffers are queued, and copied at the start of the next frames. See
+    /// `is_uploaded()`.
+    pub fn upload_data<T>(&self, buffer_handle: BufferHandle, frame_idx: usize, data: &[T]) {
+        if let Some(buffer) = self.get_device_local_buffer_from_handle(buffer_handle) {
+            assert!(
+                std::mem::size_of_val(data) <= buffer.size,
+                "Uploading {} bytes to buffer `{}` of {} bytes.",
+                std::mem::size_of_val(data),
+                buffer.name,
+                buffer.size
+            );
+            self.staging_ring.queue_upload(buffer_handle, data);
+            return;
+        }
+        let internal_buffer = self
+            .get_frame_buffer_from_handle(buffer_handle, frame_idx)
+            .unwrap_or_else(|| {
+                panic!(
+                    "A buffer with the hash `{}` not found in the context.",
+                    buffer_handle.0
+                )
+            });
         internal_buffer.upload_data(data, 0);
     }
+
+    /// Whether every upload to the buffer is copied by the start of the
+    /// current frame, so that its passes can read the data. Large uploads
+    /// to device-local buffers take several frames.
+    pub fn is_uploaded(&self, buffer_handle: BufferHandle) -> bool {
+        !self.staging_ring.is_pending(buffer_handle)
+    }
+
+    /// Copy the data into a uniform ring, returning its dynamic offset
+    pub fn push_uniform<T>(&mut self, buffer_handle: BufferHandle, data: &[T]) -> u32 {
+        let (_, uniform_ring) = self
+            .uniform_ring_list
+            .iter_mut()
+            .find(|(handle, _)| *handle == buffer_handle)
+            .unwrap_or_else(|| {
+                panic!(
+                    "A uniform ring with the hash `{}` not found in the context.",
+                    buffer_handle.0
+                )
+            });
+        uniform_ring
+            .push(data)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
 }
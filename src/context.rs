@@ -5,6 +5,7 @@ use winit::event_loop::{ControlFlow, EventLoop};
 use winit::platform::desktop::EventLoopExtDesktop;
 
 const ENABLE_DEBUG_MESSENGER_CALLBACK: bool = true;
+const APP_NAME: &str = "";
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq)]
 pub struct BufferHandle(pub u64);
@@ -18,18 +19,19 @@ pub struct ImageHandle(pub u64);
 pub struct ShaderHandle(pub u64);
 
 pub struct Context {
-    window: winit::window::Window,
-    event_loop: winit::event_loop::EventLoop<()>,
+    opt_window: Option<winit::window::Window>, // None if headless
+    opt_event_loop: Option<winit::event_loop::EventLoop<()>>, // None if headless
 
     // Graph being built in the current frame
-    pub builder_passes: Vec<(PassHandle, BuilderPass)>,
+    pub graph_builder: GraphBuilder,
 
     pub shader_list: ShaderList,
     // TODO: Move these to the graph builder instead?
     pub image_list: ImageList,
     pub buffer_list: BufferList,
 
-    graph_cache: Vec<(Graph, GraphHandle)>, // (graph, hash) // TODO: Make this a proper LRU and move it to its own file
+    graph_cache: GraphCache,
+    resource_pool: ResourcePool, // Resources of the graphs evicted from the cache
     pub command_pool: vk::CommandPool,
 
     pub sync_idx: usize,      // Index of the synchronization primitives
@@ -73,15 +75,28 @@ impl Context {
                 .device_wait_idle()
                 .expect("Failed to wait device idle.")
         };
+        // The graphs use the swapchain images and the transient resources
+        // sized after them
+        self.graph_cache.clear(&mut self.resource_pool);
         // Recreate swapchain
         self.facade.destroy(&mut self.image_list);
-        self.facade = Facade::new(
-            &self.basis,
-            &self.gpu,
-            &self.window,
-            &mut self.image_list,
-            &self.debug_utils,
-        );
+        self.facade = match &self.opt_window {
+            Some(window) => Facade::new(
+                &self.basis,
+                &self.gpu,
+                window,
+                &mut self.image_list,
+                &self.debug_utils,
+            ),
+            None => Facade::new_headless(
+                self.facade.swapchain_width,
+                self.facade.swapchain_height,
+                self.facade.num_frames,
+                &self.gpu,
+                &mut self.image_list,
+                &self.debug_utils,
+            ),
+        };
         // Recreate the images which depend on the resolution of the swapchain
         for i in 0..self.image_list.list.len() {
             let (_, internal_image) = &mut self.image_list.list[i];
@@ -103,8 +118,6 @@ impl Context {
     }
 
     pub fn new() -> Context {
-        const APP_NAME: &str = "";
-
         // # Init window
         let event_loop = EventLoop::new();
         let window = {
@@ -116,10 +129,55 @@ impl Context {
                 .expect("Failed to create window.")
         };
 
-        let basis = Basis::new(APP_NAME, &window);
+        let basis = Basis::new(APP_NAME, Some(&window));
         let gpu = Gpu::new(&basis);
         let debug_utils = DebugUtils::new(&basis, &gpu, ENABLE_DEBUG_MESSENGER_CALLBACK);
 
+        // TODO: Move this up?
+        let mut image_list = ImageList::new();
+        let facade = Facade::new(&basis, &gpu, &window, &mut image_list, &debug_utils);
+
+        Context::new_from_parts(
+            Some(window),
+            Some(event_loop),
+            image_list,
+            facade,
+            debug_utils,
+            gpu,
+            basis,
+        )
+    }
+
+    /// Creates a context without a window or a swapchain. Frames are rendered
+    /// into offscreen images of the given size, which take the place of the
+    /// swapchain images in `facade.swapchain_images`.
+    pub fn new_headless(width: u32, height: u32, frames_in_flight: usize) -> Context {
+        let basis = Basis::new(APP_NAME, None);
+        let gpu = Gpu::new(&basis);
+        let debug_utils = DebugUtils::new(&basis, &gpu, ENABLE_DEBUG_MESSENGER_CALLBACK);
+
+        let mut image_list = ImageList::new();
+        let facade = Facade::new_headless(
+            width,
+            height,
+            frames_in_flight,
+            &gpu,
+            &mut image_list,
+            &debug_utils,
+        );
+
+        Context::new_from_parts(None, None, image_list, facade, debug_utils, gpu, basis)
+    }
+
+    fn new_from_parts(
+        opt_window: Option<winit::window::Window>,
+        opt_event_loop: Option<winit::event_loop::EventLoop<()>>,
+        image_list: ImageList,
+        facade: Facade,
+        debug_utils: DebugUtils,
+        gpu: Gpu,
+        basis: Basis,
+    ) -> Context {
         // # Create command pool
         let command_pool = {
             let info = vk::CommandPoolCreateInfo::builder()
@@ -134,11 +192,7 @@ impl Context {
         };
 
         let shader_list = ShaderList::new(gpu.device.clone());
-
-        // TODO: Move this up?
-        let mut image_list = ImageList::new();
-        let facade = Facade::new(&basis, &gpu, &window, &mut image_list, &debug_utils);
-        let buffer_list = BufferList::new();
+        let buffer_list = BufferList::new(facade.num_frames, &gpu, &debug_utils);
 
         // # Allocate command buffers
         let command_buffers = {
@@ -167,15 +221,16 @@ impl Context {
         };
 
         Context {
-            window,
-            event_loop,
+            opt_window,
+            opt_event_loop,
 
-            builder_passes: Vec::new(),
+            graph_builder: GraphBuilder::new(),
             shader_list,
             image_list,
             buffer_list,
 
-            graph_cache: Vec::new(),
+            graph_cache: GraphCache::new(),
+            resource_pool: ResourcePool::new(),
             command_pool,
 
             sync_idx: 0,
@@ -192,40 +247,62 @@ impl Context {
         }
     }
 
-    pub fn build_graph(&mut self) -> GraphHandle {
+    pub fn build_graph(&mut self) -> Result<GraphHandle, String> {
+        // Graphs bind the copies of per-frame buffers of the frame they were
+        // built for, so those are cached once per frame. So are graphs that
+        // declare buffers, to give each frame in flight its own copy to upload
+        // to.
+        let is_cached_per_frame = !self.graph_builder.buffers.is_empty()
+            || self.graph_builder.passes.iter().any(|(_, pass)| {
+                pass.uniform_buffers
+                    .iter()
+                    .map(|u| u.buffer)
+                    .chain(pass.storage_buffers.iter().map(|s| s.buffer))
+                    .any(|buffer_handle| {
+                        self.buffer_list
+                            .get_per_frame_buffers_from_handle(buffer_handle)
+                            .is_some()
+                    })
+            });
+        let frame_idx = if is_cached_per_frame {
+            self.swapchain_idx
+        } else {
+            0
+        };
         // Get the hash of the graph builder
         let req_hash: u64 = {
             let mut hasher = DefaultHasher::new();
-            self.builder_passes.hash(&mut hasher);
+            self.graph_builder.hash(&mut hasher);
+            if is_cached_per_frame {
+                frame_idx.hash(&mut hasher);
+            }
             hasher.finish()
         };
-        // Try finding the requested graph in the cache
-        let opt_idx = self
-            .graph_cache
-            .iter()
-            .position(|(_, cached_hash)| cached_hash.0 == req_hash);
 
-        if opt_idx.is_none() {
+        // Try finding the requested graph in the cache
+        if !self.graph_cache.touch(GraphHandle(req_hash)) {
             // The requested graph doesn't exist. Build it and add it to the cache.
             println!("Adding graph to cache");
-            self.graph_cache.push((
-                Graph::new(
-                    &self.gpu,
-                    &self.builder_passes,
-                    &self.shader_list,
-                    &self.buffer_list,
-                    &self.image_list,
-                ),
-                GraphHandle(req_hash),
-            ));
+            let graph = Graph::new(
+                &self.gpu,
+                &self.graph_builder,
+                &self.shader_list,
+                &self.buffer_list,
+                &self.image_list,
+                &self.facade,
+                frame_idx,
+                &mut self.resource_pool,
+                &self.debug_utils,
+            )?;
+            self.graph_cache.insert(graph, GraphHandle(req_hash));
         }
 
-        GraphHandle(req_hash)
+        Ok(GraphHandle(req_hash))
     }
 
     pub fn begin_frame(&mut self) -> bool {
-        // Clear the passes of the current graph
-        self.builder_passes.clear();
+        // Clear the passes and resources of the current graph
+        self.graph_builder.clear();
 
         // Execute the event loop
         let mut is_running = true;
@@ -233,41 +310,43 @@ impl Context {
         let swapchain_width = self.facade.swapchain_width;
         let swapchain_height = self.facade.swapchain_height;
 
-        self.event_loop.run_return(|event, _, control_flow| {
-            *control_flow = ControlFlow::Wait;
-
-            match event {
-                Event::WindowEvent { event, .. } => match event {
-                    WindowEvent::CloseRequested => is_running = false,
-                    #[allow(clippy::match_single_binding)] // TODO: Simplify  this
-                    WindowEvent::KeyboardInput { input, .. } => match input {
-                        KeyboardInput {
-                            virtual_keycode,
-                            state,
-                            ..
-                        } => match (virtual_keycode, state) {
-                            (Some(VirtualKeyCode::Escape), ElementState::Pressed)
-                            | (Some(VirtualKeyCode::Return), ElementState::Pressed) => {
-                                is_running = false;
-                            }
-                            _ => {}
+        // Headless contexts have no events to process
+        if let Some(event_loop) = &mut self.opt_event_loop {
+            event_loop.run_return(|event, _, control_flow| {
+                *control_flow = ControlFlow::Wait;
+
+                match event {
+                    Event::WindowEvent { event, .. } => match event {
+                        WindowEvent::CloseRequested => is_running = false,
+                        #[allow(clippy::match_single_binding)] // TODO: Simplify  this
+                        WindowEvent::KeyboardInput { input, .. } => match input {
+                            KeyboardInput {
+                                virtual_keycode,
+                                state,
+                                ..
+                            } => match (virtual_keycode, state) {
+                                (Some(VirtualKeyCode::Escape), ElementState::Pressed)
+                                | (Some(VirtualKeyCode::Return), ElementState::Pressed) => {
+                                    is_running = false;
+                                }
+                                _ => {}
+                            },
                         },
-                    },
-                    WindowEvent::Resized(physical_size) => {
-                        if swapchain_width != physical_size.width
-                            || swapchain_height != physical_size.height
+                        WindowEvent::Resized(physical_size)
+                            if swapchain_width != physical_size.width
+                                || swapchain_height != physical_size.height =>
                         {
                             resize_needed = true;
                         }
+                        _ => {}
+                    },
+                    Event::MainEventsCleared => {
+                        *control_flow = ControlFlow::Exit;
                     }
-                    _ => {}
-                },
-                Event::MainEventsCleared => {
-                    *control_flow = ControlFlow::Exit;
+                    _ => (),
                 }
-                _ => (),
-            }
-        });
+            });
+        }
 
         // This mechanism is need on Windows:
         if resize_needed {
@@ -284,12 +363,21 @@ impl Context {
             unsafe {
                 self.gpu
                     .device
-                    .wait_for_fences(&wait_fences, true, std::u64::MAX)
+                    .wait_for_fences(&wait_fences, true, u64::MAX)
                     .expect("Failed to wait for Fence.");
 
-                let result = self.facade.ext_swapchain.acquire_next_image(
+                let ext_swapchain = match &self.facade.opt_ext_swapchain {
+                    Some(ext_swapchain) => ext_swapchain,
+                    None => {
+                        // Headless. There is nothing to acquire, so the frames
+                        // are simply used in order.
+                        opt_frame_idx = Some(self.sync_idx);
+                        break;
+                    }
+                };
+                let result = ext_swapchain.acquire_next_image(
                     self.facade.swapchain,
-                    std::u64::MAX,
+                    u64::MAX,
                     self.facade.image_available_semaphores[self.sync_idx],
                     vk::Fence::null(),
                 );
@@ -340,6 +428,17 @@ impl Context {
         self.debug_utils
             .set_command_buffer_name(cmd_buf, &format!("command_buffer_{}", self.swapchain_idx));
 
+        // The frames that used the graphs which weren't built for a while are
+        // done with them now
+        self.graph_cache
+            .begin_frame(2 * self.facade.num_frames, &mut self.resource_pool);
+        // ...and the pooled resources no graph took back in that time are freed
+        self.resource_pool.begin_frame(2 * self.facade.num_frames);
+
+        // Copy the queued uploads before any pass uses the buffers
+        self.buffer_list
+            .begin_frame(self.swapchain_idx, cmd_buf, &self.gpu.device);
+
         is_running
     }
 
@@ -352,10 +451,18 @@ impl Context {
                 .expect("Failed to end recording command buffer.");
         }
 
+        // Headless contexts don't acquire or present, so there is nothing to
+        // wait on or signal.
+        let (wait_semaphores, signal_semaphores) = if self.gpu.is_headless {
+            (Vec::new(), Vec::new())
+        } else {
+            (
+                vec![self.facade.image_available_semaphores[self.sync_idx]],
+                vec![self.facade.render_finished_semaphores[self.sync_idx]],
+            )
+        };
         let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let wait_semaphores = [self.facade.image_available_semaphores[self.sync_idx]];
-        let signal_semaphores = [self.facade.render_finished_semaphores[self.sync_idx]];
-        let command_buffers = [self.command_buffers[self.swapchain_idx as usize]];
+        let command_buffers = [self.command_buffers[self.swapchain_idx]];
 
         let submit_infos = [vk::SubmitInfo {
             wait_semaphore_count: wait_semaphores.len() as u32,
@@ -400,11 +507,9 @@ impl Context {
         // if it does happen. This works fine, when tested on Windows and on Linux on an
         // integrated GPU. If this fails on some other platform, consider calling
         // recreate_resolution_dependent_state() on error.
-        let _ = unsafe {
-            self.facade
-                .ext_swapchain
-                .queue_present(self.gpu.present_queue, &present_info)
-        };
+        if let Some(ext_swapchain) = &self.facade.opt_ext_swapchain {
+            let _ = unsafe { ext_swapchain.queue_present(self.gpu.present_queue, &present_info) };
+        }
 
         for event in self.watch_rx.try_iter() {
             use notify::DebouncedEvent::*;
@@ -416,65 +521,314 @@ impl Context {
                             .device_wait_idle()
                             .expect("Failed to wait device idle!");
                     }
-                    self.shader_list.hot_reload(&mut self.graph_cache);
+                    self.shader_list
+                        .hot_reload(&mut self.graph_cache, &mut self.resource_pool);
                 }
                 _ => (),
             }
         }
     }
 
+    /// Wait for the frame submitted by the last `end_frame()` of a headless
+    /// context, and copy its image back. The pixels are tightly packed
+    /// B8G8R8A8 rows, top row first.
+    pub fn read_back_frame(&self) -> Result<Vec<u8>, String> {
+        if !self.gpu.is_headless {
+            return Err(String::from(
+                "Only the frames of headless contexts can be read back.",
+            ));
+        }
+        let image = &self
+            .image_list
+            .get_image_from_handle(self.facade.swapchain_images[self.swapchain_idx])
+            .ok_or_else(|| String::from("The image of the frame is missing."))?
+            .image;
+        let size = image.width as usize * image.height as usize * 4;
+        let buffer = HostVisibleBuffer::new(
+            "buffer_frame_read_back",
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            &self.gpu,
+            &self.debug_utils,
+        );
+
+        let command_buffer = begin_single_use_command_buffer(&self.gpu.device, self.command_pool);
+        unsafe {
+            // The graph leaves the image of a headless frame ready to be copied
+            let image_barriers = [vk::ImageMemoryBarrier {
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image: image.vk_image,
+                subresource_range: vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                ..Default::default()
+            }];
+            self.gpu.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &image_barriers,
+            );
+
+            let regions = [vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: image.width,
+                    height: image.height,
+                    depth: 1,
+                },
+            }];
+            self.gpu.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image.vk_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer.vk_buffer,
+                &regions,
+            );
+
+            let memory_barriers = [vk::MemoryBarrier {
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::HOST_READ,
+                ..Default::default()
+            }];
+            self.gpu.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &memory_barriers,
+                &[],
+                &[],
+            );
+        }
+        // Waits for the queue to be idle, including the frame itself
+        end_single_use_command_buffer(command_buffer, self.command_pool, &self.gpu);
+
+        Ok(buffer.read_data())
+    }
+
     pub fn begin_pass(&self, graph_handle: GraphHandle, pass_handle: PassHandle) {
-        let (graph, _) = self
+        let graph = self
             .graph_cache
-            .iter()
-            .find(|(_, cached_hash)| cached_hash.0 == graph_handle.0)
+            .get(graph_handle)
             .expect("Graph not found in cache. Have you called build_graph()?");
         graph.begin_pass(pass_handle, self.command_buffers[self.swapchain_idx])
     }
 
     pub fn end_pass(&self, graph_handle: GraphHandle) {
-        let (graph, _) = self
+        let graph = self
             .graph_cache
-            .iter()
-            .find(|(_, cached_hash)| cached_hash.0 == graph_handle.0)
+            .get(graph_handle)
             .expect("Graph not found in cache. Have you called build_graph()?");
         graph.end_pass(self.command_buffers[self.swapchain_idx]);
     }
 
+    pub fn push_constants<T>(&self, graph_handle: GraphHandle, data: &[T]) {
+        let graph = self
+            .graph_cache
+            .get(graph_handle)
+            .expect("Graph not found in cache. Have you called build_graph()?");
+        graph.push_constants(self.command_buffers[self.swapchain_idx], data);
+    }
+
+    /// Bind a vertex buffer to the first binding, e.g. for a pass whose vertex
+    /// layout was declared with `add_pass()`
+    pub fn bind_vertex_buffer(&self, buffer_handle: BufferHandle) {
+        let (vk_buffer, _) = self
+            .buffer_list
+            .get_vk_buffer(buffer_handle, self.swapchain_idx)
+            .unwrap_or_else(|| {
+                panic!(
+                    "A buffer with the hash `{}` not found in the context.",
+                    buffer_handle.0
+                )
+            });
+        unsafe {
+            self.gpu.device.cmd_bind_vertex_buffers(
+                self.command_buffers[self.swapchain_idx],
+                0,
+                &[vk_buffer],
+                &[0],
+            );
+        }
+    }
+
+    pub fn bind_index_buffer(&self, buffer_handle: BufferHandle, index_type: vk::IndexType) {
+        let (vk_buffer, _) = self
+            .buffer_list
+            .get_vk_buffer(buffer_handle, self.swapchain_idx)
+            .unwrap_or_else(|| {
+                panic!(
+                    "A buffer with the hash `{}` not found in the context.",
+                    buffer_handle.0
+                )
+            });
+        unsafe {
+            self.gpu.device.cmd_bind_index_buffer(
+                self.command_buffers[self.swapchain_idx],
+                vk_buffer,
+                0,
+                index_type,
+            );
+        }
+    }
+
+    /// Offset the uniform rings bound by the active pass, e.g. before each draw
+    pub fn bind_dynamic_offsets(&self, graph_handle: GraphHandle, offsets: &[u32]) {
+        let graph = self
+            .graph_cache
+            .get(graph_handle)
+            .expect("Graph not found in cache. Have you called build_graph()?");
+        graph.bind_dynamic_offsets(self.command_buffers[self.swapchain_idx], offsets);
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_pass(
         &mut self,
         name: &str,
         vertex_shader: ShaderHandle,
         fragment_shader: ShaderHandle,
-        output_images: &[ImageHandle],
+        pipeline_state: PipelineState,
+        opt_vertex_layout: Option<&VertexLayout>,
+        output_images: &[ColorOutput],
         opt_depth_image: Option<ImageHandle>,
-        uniform_buffer: BufferHandle,
-        image_handle: ImageHandle,
-        environment_sampler: &Sampler,
+        sampled_images: &[SampledImage],
+        uniform_buffers: &[UniformBuffer],
+        storage_images: &[StorageImage],
+        storage_buffers: &[StorageBuffer],
     ) -> Result<PassHandle, String> {
         // TODO: Assert that color and depth images have the same resolution
-        let img = self
-            .image_list
-            .get_image_from_handle(image_handle)
-            .unwrap_or_else(|| {
-                panic!(
-                    "Image with handle `{:?}` not found in the context.",
-                    image_handle
-                )
-            });
-
-        let pass = BuilderPass {
+        pipeline_state.validate(&self.gpu)?;
+        self.push_builder_pass(BuilderPass {
             name: String::from(name),
-            vertex_shader,
-            fragment_shader,
+            kind: PassKind::Graphics {
+                vertex_shader,
+                fragment_shader,
+                pipeline_state,
+                opt_vertex_layout: opt_vertex_layout.cloned(),
+            },
             output_images: output_images.to_owned(),
-            input_image: (img.image.image_view, environment_sampler.vk_sampler),
             opt_depth_image,
+            sampled_images: sampled_images.to_owned(),
+            uniform_buffers: uniform_buffers.to_owned(),
+            storage_images: storage_images.to_owned(),
+            storage_buffers: storage_buffers.to_owned(),
             viewport_width: self.facade.swapchain_width,
             viewport_height: self.facade.swapchain_height,
-            uniform_buffer,
-        };
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_compute_pass(
+        &mut self,
+        name: &str,
+        compute_shader: ShaderHandle,
+        sampled_images: &[SampledImage],
+        uniform_buffers: &[UniformBuffer],
+        storage_images: &[StorageImage],
+        storage_buffers: &[StorageBuffer],
+        group_count: [u32; 3],
+    ) -> Result<PassHandle, String> {
+        self.push_builder_pass(BuilderPass {
+            name: String::from(name),
+            kind: PassKind::Compute {
+                compute_shader,
+                group_count,
+            },
+            output_images: Vec::new(),
+            opt_depth_image: None,
+            sampled_images: sampled_images.to_owned(),
+            uniform_buffers: uniform_buffers.to_owned(),
+            storage_images: storage_images.to_owned(),
+            storage_buffers: storage_buffers.to_owned(),
+            viewport_width: self.facade.swapchain_width,
+            viewport_height: self.facade.swapchain_height,
+        })
+    }
+
+    fn push_builder_pass(&mut self, mut pass: BuilderPass) -> Result<PassHandle, String> {
+        // Error if any of the resources don't exist
+        let mut image_handles: Vec<ImageHandle> =
+            pass.output_images.iter().map(|o| o.image).collect();
+        image_handles.extend(pass.opt_depth_image);
+        image_handles.extend(pass.sampled_images.iter().map(|s| s.image));
+        image_handles.extend(pass.storage_images.iter().map(|s| s.image));
+        for image_handle in image_handles {
+            let is_in_context = self.image_list.contains(image_handle);
+            let is_in_graph = self.graph_builder.get_image(image_handle).is_some();
+            if !is_in_context && !is_in_graph {
+                return Err(format!(
+                    "Image with handle `{:?}` not found in the context.",
+                    image_handle
+                ));
+            }
+            if is_in_context && is_in_graph {
+                return Err(format!(
+                    "Image with handle `{:?}` is declared in both the context and the graph.",
+                    image_handle
+                ));
+            }
+        }
+        let mut buffer_handles: Vec<BufferHandle> =
+            pass.uniform_buffers.iter().map(|u| u.buffer).collect();
+        buffer_handles.extend(pass.storage_buffers.iter().map(|s| s.buffer));
+        for buffer_handle in buffer_handles {
+            let is_in_context = self.buffer_list.contains(buffer_handle);
+            let is_in_graph = self.graph_builder.get_buffer(buffer_handle).is_some();
+            if !is_in_context && !is_in_graph {
+                return Err(format!(
+                    "Buffer with handle `{:?}` not found in the context.",
+                    buffer_handle
+                ));
+            }
+            if is_in_context && is_in_graph {
+                return Err(format!(
+                    "Buffer with handle `{:?}` is declared in both the context and the graph.",
+                    buffer_handle
+                ));
+            }
+        }
+
+        if let Some(storage_buffer) = pass.storage_buffers.iter().find(|s| {
+            self.buffer_list
+                .get_uniform_ring_from_handle(s.buffer)
+                .is_some()
+        }) {
+            return Err(format!(
+                "Uniform ring with handle `{:?}` can only be bound as a uniform buffer.",
+                storage_buffer.buffer
+            ));
+        }
+
+        // Keep the hash independent of the order resources were declared in
+        pass.sampled_images.sort_by_key(|s| s.binding);
+        pass.uniform_buffers.sort_by_key(|u| u.binding);
+        pass.storage_images.sort_by_key(|s| s.binding);
+        pass.storage_buffers.sort_by_key(|s| s.binding);
+
+        // Error if the pass doesn't match the interface its shaders expect
+        PassLayout::new(&pass, &self.shader_list, &self.buffer_list)?;
 
         let pass_handle = {
             let mut hasher = DefaultHasher::new();
@@ -482,7 +836,7 @@ impl Context {
             PassHandle(hasher.finish())
         };
 
-        self.builder_passes.push((pass_handle, pass));
+        self.graph_builder.passes.push((pass_handle, pass));
 
         Ok(pass_handle)
     }
@@ -508,8 +862,114 @@ impl Context {
             .new_buffer(name, size, usage, &self.gpu, &self.debug_utils)
     }
 
+    /// A buffer in device-local memory, e.g. for vertices, indices or storage
+    /// that passes bind by handle. Uploads to it are staged, and copied at the
+    /// start of the next frame.
+    pub fn new_device_local_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<BufferHandle, String> {
+        self.buffer_list
+            .new_device_local_buffer(name, size, usage, &self.gpu, &self.debug_utils)
+    }
+
+    /// A buffer with a copy per frame in flight. Uploads and the passes of a
+    /// frame use the copy of the current frame.
+    pub fn new_per_frame_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<BufferHandle, String> {
+        self.buffer_list.new_per_frame_buffer(
+            name,
+            size,
+            usage,
+            self.facade.num_frames,
+            &self.gpu,
+            &self.debug_utils,
+        )
+    }
+
+    /// A uniform buffer for many small uniforms that are pushed every frame.
+    /// See `UniformRing`.
+    pub fn new_uniform_ring(
+        &mut self,
+        name: &str,
+        frame_size: usize,
+        max_uniform_size: usize,
+    ) -> Result<BufferHandle, String> {
+        self.buffer_list.new_uniform_ring(
+            name,
+            frame_size,
+            max_uniform_size,
+            self.facade.num_frames,
+            &self.gpu,
+            &self.debug_utils,
+        )
+    }
+
+    /// A device-local buffer for results passed between the passes of a
+    /// frame. See `TransientBuffer`.
+    pub fn new_transient_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<BufferHandle, String> {
+        self.buffer_list.new_transient_buffer(name, size, usage)
+    }
+
     pub fn upload_data<T>(&self, buffer_handle: BufferHandle, data: &[T]) {
-        self.buffer_list.upload_data(buffer_handle, data);
+        self.buffer_list
+            .upload_data(buffer_handle, self.swapchain_idx, data);
+    }
+
+    /// Whether the passes of the current frame can read everything uploaded
+    /// to the buffer. See `BufferList::is_uploaded()`.
+    pub fn is_uploaded(&self, buffer_handle: BufferHandle) -> bool {
+        self.buffer_list.is_uploaded(buffer_handle)
+    }
+
+    /// Copy a uniform into a uniform ring, returning the dynamic offset to bind
+    /// it with
+    pub fn push_uniform<T>(&mut self, buffer_handle: BufferHandle, data: &[T]) -> u32 {
+        self.buffer_list.push_uniform(buffer_handle, data)
+    }
+
+    /// Where the transient resources of a built graph are placed in memory,
+    /// and how much sharing it saves. See `TransientResources::report()`.
+    pub fn get_transient_resources_report(&self, graph_handle: GraphHandle) -> String {
+        self.graph_cache
+            .get(graph_handle)
+            .expect("Graph not found in cache. Have you called build_graph()?")
+            .transient_resources
+            .report()
+    }
+
+    /// Upload to a buffer declared with `GraphBuilder::new_buffer()`, which is
+    /// resolved to the copy the built graph has for the current frame
+    pub fn upload_to_buffer<T>(
+        &self,
+        graph_handle: GraphHandle,
+        buffer_handle: BufferHandle,
+        data: &[T],
+    ) {
+        let graph = self
+            .graph_cache
+            .get(graph_handle)
+            .expect("Graph not found in cache. Have you called build_graph()?");
+        graph
+            .get_buffer(buffer_handle)
+            .unwrap_or_else(|| {
+                panic!(
+                    "A buffer with the hash `{}` not found in the graph.",
+                    buffer_handle.0
+                )
+            })
+            .upload_data(data, 0);
     }
 
     /* Images */
@@ -532,6 +992,38 @@ impl Context {
             &self.debug_utils,
         )
     }
+    /// An intermediate render target of a frame, e.g. in a post-processing
+    /// chain. See `TransientImage`.
+    pub fn new_transient_image_relative_size(
+        &mut self,
+        name: &str,
+        scale: f32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_flags: vk::ImageAspectFlags,
+    ) -> Result<ImageHandle, String> {
+        self.image_list
+            .new_transient_image_relative_size(name, scale, format, usage, aspect_flags)
+    }
+    pub fn new_image_from_rgba8(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        format: vk::Format,
+    ) -> Result<ImageHandle, String> {
+        self.image_list.new_image_from_rgba8(
+            name,
+            width,
+            height,
+            data,
+            format,
+            &self.gpu,
+            self.command_pool,
+            &self.debug_utils,
+        )
+    }
     pub fn new_image_from_file(&mut self, name: &str, path: &str) -> Result<ImageHandle, String> {
         self.image_list.new_image_from_file(
             name,
@@ -67,25 +67,9 @@ fn execute_pass(
 
         ctx.upload_data(uniform_buffer, &ubos);
     }
-    // Bind index and vertex buffers
-    unsafe {
-        {
-            let vertex_buffers = [mesh.vertex_buffer.vk_buffer];
-            let offsets = [0_u64];
-            ctx.gpu
-                .device
-                .cmd_bind_vertex_buffers(cmd_buf, 0, &vertex_buffers, &offsets);
-            ctx.gpu.device.cmd_bind_index_buffer(
-                cmd_buf,
-                mesh.index_buffer.vk_buffer,
-                0,
-                vk::IndexType::UINT32,
-            );
-        }
-
-        ctx.gpu
-            .device
-            .cmd_draw_indexed(cmd_buf, mesh.index_buffer.num_elements as u32, 1, 0, 0, 0);
+    // The mesh is uploaded with the first frames
+    if mesh.is_uploaded(&ctx.buffer_list) {
+        mesh.draw(cmd_buf, &ctx.buffer_list);
     }
 }
 
@@ -99,8 +83,11 @@ fn main() {
         "assets/meshes/suzanne.glb",
         &ctx.gpu,
         ctx.command_pool,
+        &mut ctx.image_list,
+        &mut ctx.buffer_list,
         &ctx.debug_utils,
-    );
+    )
+    .unwrap();
     let depth_image = ctx
         .new_image_relative_size(
             "image_depth",
@@ -156,18 +143,13 @@ fn main() {
         )
         .unwrap();
 
-    // TODO: Avoid having to create the vec. Automatically
-    // creating a unique uniform buffer per frame
-    let uniform_buffers: Vec<graphene::BufferHandle> = (0..ctx.facade.num_frames)
-        .map(|i| {
-            ctx.new_buffer(
-                &format!("buffer_uniform_{}", i),
-                std::mem::size_of::<UniformBuffer>(),
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
-            )
-            .unwrap()
-        })
-        .collect();
+    let uniform_buffer = ctx
+        .new_per_frame_buffer(
+            "buffer_uniform",
+            std::mem::size_of::<UniformBuffer>(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+        )
+        .unwrap();
 
     loop {
         if !ctx.begin_frame() {
@@ -177,19 +159,31 @@ fn main() {
         let elapsed_seconds = start_instant.elapsed().as_secs_f32();
         let cmd_buf = ctx.command_buffers[ctx.swapchain_idx];
 
-        let uniform_buffer = uniform_buffers[ctx.swapchain_idx];
-
         // Build and execute render graph
         let pass_lit = ctx
             .add_pass(
                 "lit",
                 shader_vertex,
                 shader_default,
-                &[temp_image],
+                graphene::PipelineState::default(),
+                Some(&mesh.vertex_layout),
+                &[graphene::ColorOutput {
+                    image: temp_image,
+                    opt_clear_color: Some([0.0, 0.0, 0.0, 1.0]),
+                    opt_blend_mode: None,
+                }],
                 Some(depth_image),
-                uniform_buffer,
-                environment_image,
-                &environment_sampler,
+                &[graphene::SampledImage {
+                    binding: 1,
+                    image: environment_image,
+                    sampler: environment_sampler.vk_sampler,
+                }],
+                &[graphene::UniformBuffer {
+                    binding: 0,
+                    buffer: uniform_buffer,
+                }],
+                &[],
+                &[],
             )
             .unwrap();
         let pass_post = ctx
@@ -197,28 +191,37 @@ fn main() {
                 "post",
                 shader_fullscreen_triangle_vertex,
                 shader_aberration,
-                &[ctx.facade.swapchain_images[ctx.swapchain_idx]],
+                graphene::PipelineState {
+                    depth_test: false,
+                    depth_write: false,
+                    ..Default::default()
+                },
+                None,
+                &[graphene::ColorOutput {
+                    image: ctx.facade.swapchain_images[ctx.swapchain_idx],
+                    opt_clear_color: None, // The full-screen triangle covers every pixel
+                    opt_blend_mode: None,
+                }],
                 Some(depth_image),
-                uniform_buffer,
-                temp_image,
-                &environment_sampler,
+                &[graphene::SampledImage {
+                    binding: 1,
+                    image: temp_image,
+                    sampler: environment_sampler.vk_sampler,
+                }],
+                &[graphene::UniformBuffer {
+                    binding: 0,
+                    buffer: uniform_buffer,
+                }],
+                &[],
+                &[],
             )
             .unwrap();
 
-        let graph = ctx.build_graph();
+        let graph = ctx.build_graph().unwrap();
         // Pass 0
         ctx.begin_pass(graph, pass_lit);
         execute_pass(&mut ctx, elapsed_seconds, uniform_buffer, cmd_buf, &mesh);
         ctx.end_pass(graph);
-        // Layout transition (TODO: Do this automatically in the render graph)
-        {
-            let img = ctx.image_list.get_image_from_handle(temp_image).unwrap();
-            img.image.transition_image_layout(
-                vk::ImageLayout::UNDEFINED,
-                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                cmd_buf,
-            );
-        }
         // Pass 1
         ctx.begin_pass(graph, pass_post);
         unsafe {
@@ -0,0 +1,227 @@
+use ash::version::DeviceV1_0;
+use ash::vk;
+use glam::*;
+use std::f32::consts::PI;
+
+const DEGREES_TO_RADIANS: f32 = PI / 180.0;
+
+#[allow(dead_code)]
+struct UniformBuffer {
+    mtx_obj_to_clip: Mat4,
+    mtx_norm_obj_to_world: Mat4,
+    elapsed_seconds: f32,
+    viewport_w: f32,
+    viewport_h: f32,
+}
+
+fn update_uniform_buffer(
+    ctx: &graphene::Context,
+    elapsed_seconds: f32,
+    uniform_buffer: graphene::BufferHandle,
+) {
+    let width = ctx.facade.swapchain_width as f32;
+    let height = ctx.facade.swapchain_height as f32;
+
+    let mtx_rot_scale = Mat4::from_rotation_z(elapsed_seconds * 0.3)
+        * Mat4::from_rotation_x(90.0 * DEGREES_TO_RADIANS);
+    let mtx_obj_to_world = Mat4::from_rotation_x(90.0 * DEGREES_TO_RADIANS) * mtx_rot_scale;
+    let mtx_world_to_view = Mat4::from_rotation_x(90.0 * DEGREES_TO_RADIANS)
+        * Mat4::from_translation(Vec3::new(0.0, 4.5, 0.0))
+        * Mat4::from_rotation_x(-90.0 * DEGREES_TO_RADIANS);
+    let mtx_view_to_clip =
+        Mat4::perspective_lh(60.0 * DEGREES_TO_RADIANS, width / height, 0.01, 100.0);
+
+    let ubos = [UniformBuffer {
+        mtx_obj_to_clip: mtx_view_to_clip * mtx_world_to_view * mtx_obj_to_world,
+        mtx_norm_obj_to_world: mtx_rot_scale.inverse().transpose(),
+        elapsed_seconds,
+        viewport_w: width,
+        viewport_h: height,
+    }];
+    ctx.upload_data(uniform_buffer, &ubos);
+}
+
+fn main() {
+    let mut ctx = graphene::Context::new();
+    let start_instant = std::time::Instant::now();
+
+    let mesh = graphene::Mesh::load(
+        "suzanne",
+        "assets/meshes/suzanne.glb",
+        &ctx.gpu,
+        ctx.command_pool,
+        &mut ctx.image_list,
+        &mut ctx.buffer_list,
+        &ctx.debug_utils,
+    )
+    .unwrap();
+    let depth_image = ctx
+        .new_image_relative_size(
+            "image_depth",
+            1.0,
+            vk::Format::D32_SFLOAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageAspectFlags::DEPTH,
+        )
+        .unwrap();
+    // G-buffer
+    let albedo_image = ctx
+        .new_image_relative_size(
+            "image_gbuffer_albedo",
+            1.0,
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            vk::ImageAspectFlags::COLOR,
+        )
+        .unwrap();
+    let normal_image = ctx
+        .new_image_relative_size(
+            "image_gbuffer_normal",
+            1.0,
+            vk::Format::A2B10G10R10_UNORM_PACK32,
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            vk::ImageAspectFlags::COLOR,
+        )
+        .unwrap();
+    let gbuffer_sampler = graphene::Sampler::new(&ctx.gpu);
+
+    let shader_vertex = ctx
+        .new_shader(
+            "shader_vertex",
+            graphene::ShaderStage::Vertex,
+            "default.vert",
+        )
+        .unwrap();
+    let shader_gbuffer = ctx
+        .new_shader(
+            "shader_gbuffer",
+            graphene::ShaderStage::Fragment,
+            "gbuffer.frag",
+        )
+        .unwrap();
+    let shader_fullscreen_triangle_vertex = ctx
+        .new_shader(
+            "fullscreen_triangle_vertex",
+            graphene::ShaderStage::Vertex,
+            "fullscreen_triangle.vert",
+        )
+        .unwrap();
+    let shader_lighting = ctx
+        .new_shader(
+            "shader_lighting",
+            graphene::ShaderStage::Fragment,
+            "deferred_lighting.frag",
+        )
+        .unwrap();
+
+    let uniform_buffer = ctx
+        .new_per_frame_buffer(
+            "buffer_uniform",
+            std::mem::size_of::<UniformBuffer>(),
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+        )
+        .unwrap();
+
+    loop {
+        if !ctx.begin_frame() {
+            break;
+        }
+
+        let elapsed_seconds = start_instant.elapsed().as_secs_f32();
+        let cmd_buf = ctx.command_buffers[ctx.swapchain_idx];
+        update_uniform_buffer(&ctx, elapsed_seconds, uniform_buffer);
+
+        // Build and execute render graph
+        let pass_gbuffer = ctx
+            .add_pass(
+                "gbuffer",
+                shader_vertex,
+                shader_gbuffer,
+                graphene::PipelineState::default(),
+                Some(&mesh.vertex_layout),
+                &[
+                    graphene::ColorOutput {
+                        image: albedo_image,
+                        opt_clear_color: Some([0.0, 0.0, 0.0, 0.0]),
+                        opt_blend_mode: None,
+                    },
+                    graphene::ColorOutput {
+                        image: normal_image,
+                        opt_clear_color: Some([0.0, 0.0, 0.0, 0.0]), // Zero alpha marks the background
+                        opt_blend_mode: None,
+                    },
+                ],
+                Some(depth_image),
+                &[],
+                &[graphene::UniformBuffer {
+                    binding: 0,
+                    buffer: uniform_buffer,
+                }],
+                &[],
+                &[],
+            )
+            .unwrap();
+        let pass_lighting = ctx
+            .add_pass(
+                "lighting",
+                shader_fullscreen_triangle_vertex,
+                shader_lighting,
+                graphene::PipelineState {
+                    depth_test: false,
+                    depth_write: false,
+                    ..Default::default()
+                },
+                None,
+                &[graphene::ColorOutput {
+                    image: ctx.facade.swapchain_images[ctx.swapchain_idx],
+                    opt_clear_color: None, // The full-screen triangle covers every pixel
+                    opt_blend_mode: None,
+                }],
+                None,
+                &[
+                    graphene::SampledImage {
+                        binding: 1,
+                        image: albedo_image,
+                        sampler: gbuffer_sampler.vk_sampler,
+                    },
+                    graphene::SampledImage {
+                        binding: 2,
+                        image: normal_image,
+                        sampler: gbuffer_sampler.vk_sampler,
+                    },
+                ],
+                &[graphene::UniformBuffer {
+                    binding: 0,
+                    buffer: uniform_buffer,
+                }],
+                &[],
+                &[],
+            )
+            .unwrap();
+
+        let graph = ctx.build_graph().unwrap();
+        // G-buffer pass
+        ctx.begin_pass(graph, pass_gbuffer);
+        // The mesh is uploaded with the first frames
+        if mesh.is_uploaded(&ctx.buffer_list) {
+            mesh.draw(cmd_buf, &ctx.buffer_list);
+        }
+        ctx.end_pass(graph);
+        // Lighting pass
+        ctx.begin_pass(graph, pass_lighting);
+        unsafe {
+            ctx.gpu.device.cmd_draw(cmd_buf, 3, 1, 0, 0);
+        }
+        ctx.end_pass(graph);
+
+        ctx.end_frame();
+    }
+
+    // TODO: Remove the necessity for this sync
+    unsafe {
+        ctx.gpu
+            .device
+            .device_wait_idle()
+            .expect("Failed to wait device idle!");
+    }
+}
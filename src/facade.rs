@@ -9,16 +9,17 @@ pub struct Facade {
     pub num_frames: usize,
     pub swapchain_width: u32,
     pub swapchain_height: u32,
-    pub swapchain: vk::SwapchainKHR,
-    pub swapchain_images: Vec<ImageHandle>, // Color images that are presented to the screen
+    pub swapchain: vk::SwapchainKHR,        // Null if headless
+    pub swapchain_images: Vec<ImageHandle>, // Color images that are presented to the screen, or rendered offscreen if headless
     // Synchronization primitives. These aren't really resolution-dependent
     // and could technically be moved outside the struct. They are kept here
     // because they're closely related to the rest of the members.
+    // The semaphores are empty if headless, since only the fences are needed.
     pub image_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
     pub command_buffer_complete_fences: Vec<vk::Fence>,
 
-    pub ext_swapchain: ash::extensions::khr::Swapchain,
+    pub opt_ext_swapchain: Option<ash::extensions::khr::Swapchain>, // None if headless
 }
 
 impl Facade {
@@ -31,19 +32,22 @@ impl Facade {
     ) -> Facade {
         let device = gpu.device.clone();
         let ext_swapchain = ash::extensions::khr::Swapchain::new(&basis.instance, &device);
+        let surface = basis
+            .opt_surface
+            .expect("A surface is needed to create a swapchain. Use `new_headless()` instead.");
 
         // # Get surface info
         let surface_caps = unsafe {
             basis
                 .ext_surface
-                .get_physical_device_surface_capabilities(gpu.physical_device, basis.surface)
+                .get_physical_device_surface_capabilities(gpu.physical_device, surface)
                 .expect("Failed to query for surface capabilities.")
         };
 
         let surface_formats = unsafe {
             basis
                 .ext_surface
-                .get_physical_device_surface_formats(gpu.physical_device, basis.surface)
+                .get_physical_device_surface_formats(gpu.physical_device, surface)
                 .expect("Failed to query for surface formats.")
         };
 
@@ -68,13 +72,15 @@ impl Facade {
 
             // Choose extent
             let extent = {
-                if surface_caps.current_extent.width == u32::max_value() {
+                if surface_caps.current_extent.width == u32::MAX {
                     let window_size = window.inner_size();
                     vk::Extent2D {
-                        width: (window_size.width as u32)
+                        width: window_size
+                            .width
                             .max(surface_caps.min_image_extent.width)
                             .min(surface_caps.max_image_extent.width),
-                        height: (window_size.height as u32)
+                        height: window_size
+                            .height
                             .max(surface_caps.min_image_extent.height)
                             .min(surface_caps.max_image_extent.height),
                     }
@@ -87,7 +93,7 @@ impl Facade {
             let present_mode: vk::PresentModeKHR = vk::PresentModeKHR::FIFO;
 
             let mut info = vk::SwapchainCreateInfoKHR::builder()
-                .surface(basis.surface)
+                .surface(surface)
                 .min_image_count(num_frames)
                 .image_format(swapchain_format)
                 .image_color_space(swapchain_color_space)
@@ -183,7 +189,7 @@ impl Facade {
                     aspect_flags: vk::ImageAspectFlags::empty(),
                     vk_image: swapchain_images[i as usize],
                     image_view: swapchain_imageviews[i as usize],
-                    opt_device_memory: None, // This memory is not allocated by us. It is part of the swapchain.
+                    opt_allocation: None, // This memory is not allocated by us. It is part of the swapchain.
                     device: device.clone(),
                     name,
                 };
@@ -200,43 +206,9 @@ impl Facade {
             .collect();
 
         // # Synchronization primitives
-        let (
-            image_available_semaphores,
-            render_finished_semaphores,
-            command_buffer_complete_fences,
-        ) = {
-            let mut image_available_semaphores = Vec::new();
-            let mut render_finished_semaphores = Vec::new();
-            let mut command_buffer_complete_fences = Vec::new();
-            let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
-            let fence_create_info =
-                vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
-
-            for _ in 0..num_frames {
-                unsafe {
-                    image_available_semaphores.push(
-                        device
-                            .create_semaphore(&semaphore_create_info, None)
-                            .expect("Failed to create Semaphore Object!"),
-                    );
-                    render_finished_semaphores.push(
-                        device
-                            .create_semaphore(&semaphore_create_info, None)
-                            .expect("Failed to create Semaphore Object!"),
-                    );
-                    command_buffer_complete_fences.push(
-                        device
-                            .create_fence(&fence_create_info, None)
-                            .expect("Failed to create Fence Object!"),
-                    );
-                }
-            }
-            (
-                image_available_semaphores,
-                render_finished_semaphores,
-                command_buffer_complete_fences,
-            )
-        };
+        let (image_available_semaphores, render_finished_semaphores) =
+            create_semaphores(&device, num_frames as usize);
+        let command_buffer_complete_fences = create_fences(&device, num_frames as usize);
 
         Facade {
             device,
@@ -250,22 +222,90 @@ impl Facade {
             image_available_semaphores,
             render_finished_semaphores,
             command_buffer_complete_fences,
-            ext_swapchain,
+            opt_ext_swapchain: Some(ext_swapchain),
+        }
+    }
+
+    /// Creates a facade without a swapchain. Frames are rendered into ordinary
+    /// images, which stand in for the swapchain images.
+    pub fn new_headless(
+        width: u32,
+        height: u32,
+        num_frames: usize,
+        gpu: &Gpu,
+        image_list: &mut ImageList,
+        debug_utils: &DebugUtils,
+    ) -> Facade {
+        let device = gpu.device.clone();
+
+        // Add offscreen images to the context's image list
+        let swapchain_images = (0..num_frames)
+            .map(|i| {
+                let name = String::from(&format!("image_swapchain_{}", i));
+                let hash: u64 = {
+                    let mut hasher = DefaultHasher::new();
+                    name.hash(&mut hasher);
+                    hasher.finish()
+                };
+                let handle = ImageHandle(hash);
+                let image = Image::new(
+                    &name,
+                    width,
+                    height,
+                    vk::Format::B8G8R8A8_SRGB,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                    vk::ImageAspectFlags::COLOR,
+                    gpu,
+                    debug_utils,
+                );
+                image_list.list.push((
+                    handle,
+                    InternalImage {
+                        image,
+                        kind: ImageKind::Swapchain,
+                    },
+                ));
+
+                handle
+            })
+            .collect();
+
+        // # Synchronization primitives. There is nothing to acquire or present,
+        // so the fences suffice.
+        let command_buffer_complete_fences = create_fences(&device, num_frames);
+
+        Facade {
+            device,
+            surface_caps: vk::SurfaceCapabilitiesKHR::default(),
+            surface_formats: Vec::new(),
+            num_frames,
+            swapchain_width: width,
+            swapchain_height: height,
+            swapchain: vk::SwapchainKHR::null(),
+            swapchain_images,
+            image_available_semaphores: Vec::new(),
+            render_finished_semaphores: Vec::new(),
+            command_buffer_complete_fences,
+            opt_ext_swapchain: None,
         }
     }
 
     pub fn destroy(&self, image_list: &mut ImageList) {
         unsafe {
-            for i in 0..self.num_frames {
-                self.device
-                    .destroy_semaphore(self.image_available_semaphores[i], None);
-                self.device
-                    .destroy_semaphore(self.render_finished_semaphores[i], None);
-                self.device
-                    .destroy_fence(self.command_buffer_complete_fences[i], None);
+            for &semaphore in self
+                .image_available_semaphores
+                .iter()
+                .chain(&self.render_finished_semaphores)
+            {
+                self.device.destroy_semaphore(semaphore, None);
+            }
+            for &fence in &self.command_buffer_complete_fences {
+                self.device.destroy_fence(fence, None);
             }
 
-            self.ext_swapchain.destroy_swapchain(self.swapchain, None);
+            if let Some(ext_swapchain) = &self.opt_ext_swapchain {
+                ext_swapchain.destroy_swapchain(self.swapchain, None);
+            }
         }
         // Delete swapchain images from image list
         image_list
@@ -273,3 +313,40 @@ impl Facade {
             .retain(|(_, internal_image)| internal_image.kind != ImageKind::Swapchain);
     }
 }
+
+fn create_semaphores(
+    device: &ash::Device,
+    num_frames: usize,
+) -> (Vec<vk::Semaphore>, Vec<vk::Semaphore>) {
+    let mut image_available_semaphores = Vec::new();
+    let mut render_finished_semaphores = Vec::new();
+    let semaphore_create_info = vk::SemaphoreCreateInfo::builder();
+
+    for _ in 0..num_frames {
+        unsafe {
+            image_available_semaphores.push(
+                device
+                    .create_semaphore(&semaphore_create_info, None)
+                    .expect("Failed to create Semaphore Object!"),
+            );
+            render_finished_semaphores.push(
+                device
+                    .create_semaphore(&semaphore_create_info, None)
+                    .expect("Failed to create Semaphore Object!"),
+            );
+        }
+    }
+    (image_available_semaphores, render_finished_semaphores)
+}
+
+fn create_fences(device: &ash::Device, num_frames: usize) -> Vec<vk::Fence> {
+    let fence_create_info = vk::FenceCreateInfo::builder().flags(vk::FenceCreateFlags::SIGNALED);
+
+    (0..num_frames)
+        .map(|_| unsafe {
+            device
+                .create_fence(&fence_create_info, None)
+                .expect("Failed to create Fence Object!")
+        })
+        .collect()
+}
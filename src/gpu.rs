@@ -7,17 +7,28 @@ pub struct Gpu {
     pub exts: Vec<vk::ExtensionProperties>,
     pub present_modes: Vec<vk::PresentModeKHR>,
     pub memory_properties: vk::PhysicalDeviceMemoryProperties,
-    pub _properties: vk::PhysicalDeviceProperties,
+    pub properties: vk::PhysicalDeviceProperties,
+    pub features: vk::PhysicalDeviceFeatures, // The optional features that were enabled
     pub graphics_queue_idx: u32,
     pub present_queue_idx: u32,
+    pub is_headless: bool, // True if there is no surface to present to
     // Logical device
     pub device: ash::Device,
     pub graphics_queue: vk::Queue,
     pub present_queue: vk::Queue,
+    pub allocator: MemoryAllocator,
 }
 
 impl Drop for Gpu {
     fn drop(&mut self) {
+        let leaked_allocation_count = self.allocator.destroy();
+        // Resources must be dropped before the GPU, unless we're unwinding
+        // from an earlier panic
+        debug_assert!(
+            leaked_allocation_count == 0 || std::thread::panicking(),
+            "{} allocations are still alive when the device is destroyed.",
+            leaked_allocation_count
+        );
         unsafe {
             self.device.destroy_device(None);
         }
@@ -26,7 +37,12 @@ impl Drop for Gpu {
 
 impl Gpu {
     pub fn new(basis: &Basis) -> Gpu {
-        let required_exts = vec![String::from("VK_KHR_swapchain")];
+        let is_headless = basis.opt_surface.is_none();
+        let required_exts = if is_headless {
+            Vec::new()
+        } else {
+            vec![String::from("VK_KHR_swapchain")]
+        };
 
         // # Enumerate eligible GPUs
         struct CandidateGpu {
@@ -72,21 +88,25 @@ impl Gpu {
                     continue;
                 }
 
-                let surface_formats = unsafe {
-                    basis
-                        .ext_surface
-                        .get_physical_device_surface_formats(physical_device, basis.surface)
-                        .expect("Failed to query for surface formats.")
-                };
-                let present_modes = unsafe {
-                    basis
-                        .ext_surface
-                        .get_physical_device_surface_present_modes(physical_device, basis.surface)
-                        .expect("Failed to query for surface present mode.")
-                };
-                // Are there any surface formats and present modes?
-                if surface_formats.is_empty() || present_modes.is_empty() {
-                    continue;
+                // Headless GPUs don't need any surface formats or present modes
+                let mut present_modes = Vec::new();
+                if let Some(surface) = basis.opt_surface {
+                    let surface_formats = unsafe {
+                        basis
+                            .ext_surface
+                            .get_physical_device_surface_formats(physical_device, surface)
+                            .expect("Failed to query for surface formats.")
+                    };
+                    present_modes = unsafe {
+                        basis
+                            .ext_surface
+                            .get_physical_device_surface_present_modes(physical_device, surface)
+                            .expect("Failed to query for surface present mode.")
+                    };
+                    // Are there any surface formats and present modes?
+                    if surface_formats.is_empty() || present_modes.is_empty() {
+                        continue;
+                    }
                 }
 
                 let memory_properties = unsafe {
@@ -109,17 +129,21 @@ impl Gpu {
                 let opt_graphics_queue_idx = queue_families.iter().position(|&fam| {
                     fam.queue_count > 0 && fam.queue_flags.contains(vk::QueueFlags::GRAPHICS)
                 });
-                let opt_present_queue_idx =
-                    queue_families.iter().enumerate().position(|(i, &fam)| {
+                // Headless GPUs never present, so the graphics queue stands in
+                // for the present queue.
+                let opt_present_queue_idx = match basis.opt_surface {
+                    Some(surface) => queue_families.iter().enumerate().position(|(i, &fam)| {
                         let is_present_supported = unsafe {
                             basis.ext_surface.get_physical_device_surface_support(
                                 physical_device,
                                 i as u32,
-                                basis.surface,
+                                surface,
                             )
                         };
                         fam.queue_count > 0 && is_present_supported
-                    });
+                    }),
+                    None => opt_graphics_queue_idx,
+                };
                 // Is there a graphics queue and a present queue?
                 if opt_graphics_queue_idx.is_none() || opt_present_queue_idx.is_none() {
                     continue;
@@ -172,8 +196,16 @@ impl Gpu {
                 queue_create_infos.push(queue_create_info);
             }
 
+            // Optional features are enabled only if they're supported
+            let supported_features = unsafe {
+                basis
+                    .instance
+                    .get_physical_device_features(cgpu.physical_device)
+            };
             let physical_device_features = vk::PhysicalDeviceFeatures {
                 sampler_anisotropy: vk::TRUE, // enable anisotropy device feature from Chapter-24.
+                fill_mode_non_solid: supported_features.fill_mode_non_solid, // Wireframe
+                wide_lines: supported_features.wide_lines,
                 ..Default::default()
             };
 
@@ -206,18 +238,26 @@ impl Gpu {
 
             let graphics_queue = unsafe { device.get_device_queue(cgpu.graphics_queue_idx, 0) };
             let present_queue = unsafe { device.get_device_queue(cgpu.present_queue_idx, 0) };
+            let allocator = MemoryAllocator::new(
+                &device,
+                cgpu.memory_properties,
+                cgpu.properties.limits.buffer_image_granularity,
+            );
 
             Gpu {
                 physical_device: cgpu.physical_device,
                 exts: cgpu.exts.clone(),
                 present_modes: cgpu.present_modes.clone(),
                 memory_properties: cgpu.memory_properties,
-                _properties: cgpu.properties,
+                properties: cgpu.properties,
+                features: physical_device_features,
                 graphics_queue_idx: cgpu.graphics_queue_idx,
                 present_queue_idx: cgpu.present_queue_idx,
+                is_headless,
                 device,
                 graphics_queue,
                 present_queue,
+                allocator,
             }
         };
 
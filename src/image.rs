@@ -8,7 +8,7 @@ pub struct Image {
     pub aspect_flags: vk::ImageAspectFlags,
     pub vk_image: vk::Image,
     pub image_view: vk::ImageView,
-    pub opt_device_memory: Option<vk::DeviceMemory>, // None if we didn't manually allocate memory, e.g. in the case of swapchain images
+    pub opt_allocation: Option<Allocation>, // None if we didn't manually allocate memory, e.g. in the case of swapchain images
     pub name: String,
     pub device: ash::Device,
 }
@@ -17,9 +17,8 @@ impl Drop for Image {
     fn drop(&mut self) {
         unsafe {
             self.device.destroy_image_view(self.image_view, None);
-            if let Some(mem) = self.opt_device_memory {
+            if self.opt_allocation.is_some() {
                 self.device.destroy_image(self.vk_image, None); // Only destroy the image if we allocated it in the first place
-                self.device.free_memory(mem, None);
             }
         }
     }
@@ -39,75 +38,25 @@ impl Image {
     ) -> Image {
         let device = gpu.device.clone();
 
-        let image_create_info = vk::ImageCreateInfo::builder()
-            .image_type(vk::ImageType::TYPE_2D)
-            .format(format)
-            .mip_levels(1)
-            .array_layers(1)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .tiling(vk::ImageTiling::OPTIMAL)
-            .usage(usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .extent(vk::Extent3D {
-                width,
-                height,
-                depth: 1,
-            });
-
-        let vk_image = unsafe {
-            device
-                .create_image(&image_create_info, None)
-                .expect("Failed to create image.")
-        };
+        let vk_image = create_vk_image(&device, width, height, format, usage);
 
         let image_memory_requirement = unsafe { device.get_image_memory_requirements(vk_image) };
-        let memory_type_index = gpu
-            .memory_properties
-            .memory_types
-            .iter()
-            .enumerate()
-            .position(|(i, &memory_type)| {
-                (image_memory_requirement.memory_type_bits & (1 << i)) > 0
-                    && memory_type
-                        .property_flags
-                        .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
-            })
-            .expect("Failed to find suitable memory type.") as u32;
-
-        let memory_allocate_info = vk::MemoryAllocateInfo::builder()
-            .allocation_size(image_memory_requirement.size)
-            .memory_type_index(memory_type_index);
-        let device_memory = unsafe {
-            device
-                .allocate_memory(&memory_allocate_info, None)
-                .expect("Failed to allocate image memory.")
-        };
+        let allocation = gpu
+            .allocator
+            .allocate(
+                image_memory_requirement,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                AllocationKind::Image,
+            )
+            .unwrap_or_else(|err| panic!("Failed to allocate image memory: {}", err));
 
         unsafe {
             device
-                .bind_image_memory(vk_image, device_memory, 0)
+                .bind_image_memory(vk_image, allocation.device_memory, allocation.offset)
                 .expect("Failed to bind image memory.");
         }
 
-        let image_view = {
-            let imageview_create_info = vk::ImageViewCreateInfo::builder()
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(format)
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: aspect_flags,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                })
-                .image(vk_image);
-
-            unsafe {
-                gpu.device
-                    .create_image_view(&imageview_create_info, None)
-                    .expect("Failed to create Image View!")
-            }
-        };
+        let image_view = create_image_view(&device, vk_image, format, aspect_flags);
 
         debug_utils.set_image_name(vk_image, name);
 
@@ -119,7 +68,7 @@ impl Image {
             aspect_flags,
             vk_image,
             image_view,
-            opt_device_memory: Some(device_memory),
+            opt_allocation: Some(allocation),
             device,
             name: String::from(name),
         }
@@ -199,19 +148,51 @@ impl Image {
         image_object = image_object.flipv();
 
         let (image_width, image_height) = (image_object.width(), image_object.height());
-        let image_size =
-            std::mem::size_of::<u8>() * image_width as usize * image_height as usize * 4;
-        let image_data = image_object.to_rgba().into_raw();
+        let image_data = image_object.to_rgba8().into_raw();
 
-        if image_size == 0 {
+        if image_data.is_empty() {
             panic!("Failed to load image.")
         }
 
-        let image = Image::new(
+        Image::new_from_rgba8(
             name,
             image_width,
             image_height,
+            &image_data,
             vk::Format::R8G8B8A8_UNORM, // TODO: Derive format from file or take as an argument
+            gpu,
+            command_pool,
+            debug_utils,
+        )
+    }
+
+    /// Create a sampled image from tightly packed 8-bit RGBA pixels. The
+    /// format decides whether they are interpreted as sRGB or linear.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_from_rgba8(
+        name: &str,
+        image_width: u32,
+        image_height: u32,
+        image_data: &[u8],
+        format: vk::Format,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        debug_utils: &DebugUtils,
+    ) -> Image {
+        let image_size =
+            std::mem::size_of::<u8>() * image_width as usize * image_height as usize * 4;
+        assert_eq!(
+            image_data.len(),
+            image_size,
+            "Pixel data of image `{}` doesn't match its size.",
+            name
+        );
+
+        let image = Image::new(
+            name,
+            image_width,
+            image_height,
+            format,
             vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
             vk::ImageAspectFlags::COLOR,
             gpu,
@@ -225,7 +206,7 @@ impl Image {
             gpu,
             debug_utils,
         );
-        staging_buffer.upload_data(&image_data, 0);
+        staging_buffer.upload_data(image_data, 0);
 
         let command_buffer = begin_single_use_command_buffer(&gpu.device, command_pool);
 
@@ -277,3 +258,59 @@ impl Image {
         image
     }
 }
+
+// A 2D image without memory bound to it yet
+pub(crate) fn create_vk_image(
+    device: &ash::Device,
+    width: u32,
+    height: u32,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+) -> vk::Image {
+    let image_create_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .extent(vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        });
+
+    unsafe {
+        device
+            .create_image(&image_create_info, None)
+            .expect("Failed to create image.")
+    }
+}
+
+// The image has to be bound to memory first
+pub(crate) fn create_image_view(
+    device: &ash::Device,
+    vk_image: vk::Image,
+    format: vk::Format,
+    aspect_flags: vk::ImageAspectFlags,
+) -> vk::ImageView {
+    let imageview_create_info = vk::ImageViewCreateInfo::builder()
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: aspect_flags,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+        .image(vk_image);
+
+    unsafe {
+        device
+            .create_image_view(&imageview_create_info, None)
+            .expect("Failed to create Image View!")
+    }
+}
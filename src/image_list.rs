@@ -12,13 +12,40 @@ pub struct InternalImage {
     pub kind: ImageKind,
 }
 
+/// An image that only lives within a frame's graph: written by one of its
+/// passes before any other pass reads it. Each graph creates its own image
+/// for it, sharing memory with the transient images that are never alive at
+/// the same time.
+#[derive(Clone)]
+pub struct TransientImage {
+    pub name: String,
+    pub scale: f32, // Scale relative to the swapchain size
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub aspect_flags: vk::ImageAspectFlags,
+}
+
+impl Hash for TransientImage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.scale.to_bits().hash(state);
+        self.format.hash(state);
+        self.usage.hash(state);
+        self.aspect_flags.hash(state);
+    }
+}
+
 pub struct ImageList {
     pub list: Vec<(ImageHandle, InternalImage)>,
+    pub transient_list: Vec<(ImageHandle, TransientImage)>,
 }
 
 impl ImageList {
     pub fn new() -> ImageList {
-        ImageList { list: Vec::new() }
+        ImageList {
+            list: Vec::new(),
+            transient_list: Vec::new(),
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -40,7 +67,7 @@ impl ImageList {
             ImageHandle(hasher.finish())
         };
         // Error if name already exists
-        if self.get_image_from_handle(handle).is_some() {
+        if self.contains(handle) {
             return Err(format!(
                 "An image with the same name `{}` already exists in the context.",
                 name
@@ -49,7 +76,7 @@ impl ImageList {
         // Create new image
         let w = (facade.swapchain_width as f32 * scale) as u32;
         let h = (facade.swapchain_height as f32 * scale) as u32;
-        let image = Image::new(name, w, h, format, usage, aspect_flags, gpu, &debug_utils);
+        let image = Image::new(name, w, h, format, usage, aspect_flags, gpu, debug_utils);
         self.list.push((
             handle,
             InternalImage {
@@ -76,7 +103,7 @@ impl ImageList {
             ImageHandle(hasher.finish())
         };
         // Error if name already exists
-        if self.get_image_from_handle(handle).is_some() {
+        if self.contains(handle) {
             return Err(format!(
                 "An image with the same name `{}` already exists in the context.",
                 name
@@ -88,7 +115,54 @@ impl ImageList {
             std::path::Path::new(&path),
             command_pool,
             name,
-            &debug_utils,
+            debug_utils,
+        );
+        self.list.push((
+            handle,
+            InternalImage {
+                image,
+                kind: ImageKind::AbsoluteSized,
+            },
+        ));
+
+        Ok(handle)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_image_from_rgba8(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        data: &[u8],
+        format: vk::Format,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        debug_utils: &DebugUtils,
+    ) -> Result<ImageHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            ImageHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.contains(handle) {
+            return Err(format!(
+                "An image with the same name `{}` already exists in the context.",
+                name
+            ));
+        }
+        // Create new image
+        let image = Image::new_from_rgba8(
+            name,
+            width,
+            height,
+            data,
+            format,
+            gpu,
+            command_pool,
+            debug_utils,
         );
         self.list.push((
             handle,
@@ -101,6 +175,42 @@ impl ImageList {
         Ok(handle)
     }
 
+    pub fn new_transient_image_relative_size(
+        &mut self,
+        name: &str,
+        scale: f32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_flags: vk::ImageAspectFlags,
+    ) -> Result<ImageHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            ImageHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.contains(handle) {
+            return Err(format!(
+                "An image with the same name `{}` already exists in the context.",
+                name
+            ));
+        }
+        // The image itself is created by the graphs that use it
+        self.transient_list.push((
+            handle,
+            TransientImage {
+                name: String::from(name),
+                scale,
+                format,
+                usage,
+                aspect_flags,
+            },
+        ));
+
+        Ok(handle)
+    }
+
     pub fn get_image_from_handle(&self, image_handle: ImageHandle) -> Option<&InternalImage> {
         for (handle, internal_image) in &self.list {
             if *handle == image_handle {
@@ -109,4 +219,21 @@ impl ImageList {
         }
         None
     }
+
+    pub fn get_transient_image_from_handle(
+        &self,
+        image_handle: ImageHandle,
+    ) -> Option<&TransientImage> {
+        for (handle, transient_image) in &self.transient_list {
+            if *handle == image_handle {
+                return Some(transient_image);
+            }
+        }
+        None
+    }
+
+    pub fn contains(&self, image_handle: ImageHandle) -> bool {
+        self.get_image_from_handle(image_handle).is_some()
+            || self.get_transient_image_from_handle(image_handle).is_some()
+    }
 }
@@ -1,9 +1,16 @@
 #![allow(clippy::new_without_default)]
 
+mod mesh_cache;
+mod mesh_import;
+mod mesh_primitives;
 mod platforms;
 
+pub mod animation;
+pub use animation::*;
 pub mod basis;
 pub use basis::*;
+pub mod bounds;
+pub use bounds::*;
 pub mod buffer;
 pub use buffer::*;
 pub mod buffer_list;
@@ -20,14 +27,24 @@ pub mod image;
 pub use crate::image::*;
 pub mod image_list;
 pub use image_list::*;
+pub mod material;
+pub use material::*;
+pub mod memory_allocator;
+pub use memory_allocator::*;
 pub mod mesh;
 pub use mesh::*;
+pub mod mesh_processing;
+pub use mesh_processing::*;
 pub mod rdg;
 pub use rdg::*;
 pub mod sampler;
 pub use sampler::*;
+pub mod scene;
+pub use scene::*;
 pub mod shader_list;
 pub use shader_list::*;
+pub mod shader_reflection;
+pub use shader_reflection::*;
 pub mod utils;
 pub use utils::*;
 
@@ -0,0 +1,534 @@
+use crate::*;
+use glam::*;
+use std::collections::HashMap;
+
+// Stand-ins for the textures a material doesn't have, so that every material
+// can be bound with the same pass layout
+const DEFAULT_WHITE_IMAGE_NAME: &str = "image_material_default_white";
+const DEFAULT_NORMAL_IMAGE_NAME: &str = "image_material_default_normal";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask { cutoff: f32 }, // Fragments with a lower alpha are discarded
+    Blend,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MaterialTexture {
+    pub image: ImageHandle,
+    pub tex_coord: u32, // Which TEXCOORD_n set the texture is mapped with
+}
+
+/// The scalar parameters of a material, laid out to match a std140 uniform
+/// block of two vec4s and four floats.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MaterialFactors {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 4], // w is unused
+    pub metallic: f32,
+    pub roughness: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+}
+
+/// A glTF metallic-roughness material. Each texture is multiplied by its
+/// factor; the samplers are left to the pass that binds the material.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    pub base_color_factor: Vec4,
+    pub opt_base_color_texture: Option<MaterialTexture>, // sRGB
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub opt_metallic_roughness_texture: Option<MaterialTexture>, // Linear, roughness in G, metallic in B
+    pub normal_scale: f32,
+    pub opt_normal_texture: Option<MaterialTexture>, // Linear, tangent space
+    pub occlusion_strength: f32,
+    pub opt_occlusion_texture: Option<MaterialTexture>, // Linear, occlusion in R
+    pub emissive_factor: Vec3,
+    pub opt_emissive_texture: Option<MaterialTexture>, // sRGB
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+// The pixels of a material texture, expanded to RGBA8 but not uploaded yet.
+// Images are named `image_{mesh name}_{suffix}`.
+pub(crate) struct MaterialImage {
+    pub(crate) suffix: String,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) is_srgb: bool,
+    pub(crate) pixels: Vec<u8>,
+}
+
+impl MaterialImage {
+    pub(crate) fn image_name(&self, mesh_name: &str) -> String {
+        format!("image_{}_{}", mesh_name, self.suffix)
+    }
+
+    pub(crate) fn handle(&self, mesh_name: &str) -> ImageHandle {
+        get_image_handle(&self.image_name(mesh_name))
+    }
+}
+
+impl Default for Material {
+    /// The glTF default material, used by primitives that don't have one
+    fn default() -> Material {
+        Material {
+            name: String::from("default"),
+            base_color_factor: Vec4::one(),
+            opt_base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            opt_metallic_roughness_texture: None,
+            normal_scale: 1.0,
+            opt_normal_texture: None,
+            occlusion_strength: 1.0,
+            opt_occlusion_texture: None,
+            emissive_factor: Vec3::zero(),
+            opt_emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+        }
+    }
+}
+
+impl Material {
+    /// Number of sampled images bound by `sampled_images()`
+    pub const TEXTURE_COUNT: u32 = 5;
+
+    /// Load every material of a glTF document, uploading the images they
+    /// use. Images used as both color and data are uploaded once per format.
+    pub fn load_all(
+        name: &str,
+        gltf: &gltf::Document,
+        images: &[gltf::image::Data],
+        image_list: &mut ImageList,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        debug_utils: &DebugUtils,
+    ) -> Result<Vec<Material>, String> {
+        let (materials, material_images) = Material::decode_all(name, gltf, images)?;
+        Material::upload_images(
+            name,
+            &material_images,
+            image_list,
+            gpu,
+            command_pool,
+            debug_utils,
+        )?;
+        Ok(materials)
+    }
+
+    /// Read every material of a glTF document, and expand the images they
+    /// use to RGBA8 without uploading them
+    pub(crate) fn decode_all(
+        name: &str,
+        gltf: &gltf::Document,
+        images: &[gltf::image::Data],
+    ) -> Result<(Vec<Material>, Vec<MaterialImage>), String> {
+        let mut material_images: Vec<MaterialImage> = Vec::new();
+        let mut decoded_images: HashMap<(usize, bool), ImageHandle> = HashMap::new();
+        let mut decode_texture = |texture: gltf::texture::Texture,
+                                  tex_coord: u32,
+                                  is_srgb: bool|
+         -> Result<MaterialTexture, String> {
+            let image_idx = texture.source().index();
+            let image = match decoded_images.get(&(image_idx, is_srgb)) {
+                Some(image) => *image,
+                None => {
+                    let data = images.get(image_idx).ok_or_else(|| {
+                        format!("Image {} of mesh `{}` wasn't loaded.", image_idx, name)
+                    })?;
+                    let material_image = MaterialImage {
+                        suffix: format!(
+                            "{}_{}",
+                            image_idx,
+                            if is_srgb { "srgb" } else { "linear" }
+                        ),
+                        width: data.width,
+                        height: data.height,
+                        is_srgb,
+                        pixels: get_rgba8_pixels(data),
+                    };
+                    let image = material_image.handle(name);
+                    material_images.push(material_image);
+                    decoded_images.insert((image_idx, is_srgb), image);
+                    image
+                }
+            };
+            Ok(MaterialTexture { image, tex_coord })
+        };
+
+        let mut materials = Vec::new();
+        for material in gltf.materials() {
+            let pbr = material.pbr_metallic_roughness();
+            let alpha_mode = match material.alpha_mode() {
+                gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+                    cutoff: material.alpha_cutoff(),
+                },
+                gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+            };
+            materials.push(Material {
+                name: material
+                    .name()
+                    .map(String::from)
+                    .unwrap_or_else(|| format!("{}_{}", name, materials.len())),
+                base_color_factor: Vec4::from(pbr.base_color_factor()),
+                opt_base_color_texture: match pbr.base_color_texture() {
+                    Some(info) => Some(decode_texture(info.texture(), info.tex_coord(), true)?),
+                    None => None,
+                },
+                metallic_factor: pbr.metallic_factor(),
+                roughness_factor: pbr.roughness_factor(),
+                opt_metallic_roughness_texture: match pbr.metallic_roughness_texture() {
+                    Some(info) => Some(decode_texture(info.texture(), info.tex_coord(), false)?),
+                    None => None,
+                },
+                normal_scale: material.normal_texture().map_or(1.0, |t| t.scale()),
+                opt_normal_texture: match material.normal_texture() {
+                    Some(info) => Some(decode_texture(info.texture(), info.tex_coord(), false)?),
+                    None => None,
+                },
+                occlusion_strength: material.occlusion_texture().map_or(1.0, |t| t.strength()),
+                opt_occlusion_texture: match material.occlusion_texture() {
+                    Some(info) => Some(decode_texture(info.texture(), info.tex_coord(), false)?),
+                    None => None,
+                },
+                emissive_factor: Vec3::from(material.emissive_factor()),
+                opt_emissive_texture: match material.emissive_texture() {
+                    Some(info) => Some(decode_texture(info.texture(), info.tex_coord(), true)?),
+                    None => None,
+                },
+                alpha_mode,
+                double_sided: material.double_sided(),
+            });
+        }
+
+        Ok((materials, material_images))
+    }
+
+    /// Upload decoded images under the names their materials refer to, and
+    /// create the stand-ins for missing textures if they don't exist yet
+    pub(crate) fn upload_images(
+        name: &str,
+        material_images: &[MaterialImage],
+        image_list: &mut ImageList,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        debug_utils: &DebugUtils,
+    ) -> Result<(), String> {
+        for (image_name, pixel) in &[
+            (DEFAULT_WHITE_IMAGE_NAME, [255, 255, 255, 255]),
+            (DEFAULT_NORMAL_IMAGE_NAME, [128, 128, 255, 255]),
+        ] {
+            if image_list
+                .get_image_from_handle(get_image_handle(image_name))
+                .is_none()
+            {
+                image_list.new_image_from_rgba8(
+                    image_name,
+                    1,
+                    1,
+                    pixel,
+                    vk::Format::R8G8B8A8_UNORM,
+                    gpu,
+                    command_pool,
+                    debug_utils,
+                )?;
+            }
+        }
+
+        for material_image in material_images {
+            image_list.new_image_from_rgba8(
+                &material_image.image_name(name),
+                material_image.width,
+                material_image.height,
+                &material_image.pixels,
+                if material_image.is_srgb {
+                    vk::Format::R8G8B8A8_SRGB
+                } else {
+                    vk::Format::R8G8B8A8_UNORM
+                },
+                gpu,
+                command_pool,
+                debug_utils,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn factors(&self) -> MaterialFactors {
+        MaterialFactors {
+            base_color: self.base_color_factor.into(),
+            emissive: self.emissive_factor.extend(0.0).into(),
+            metallic: self.metallic_factor,
+            roughness: self.roughness_factor,
+            normal_scale: self.normal_scale,
+            occlusion_strength: self.occlusion_strength,
+        }
+    }
+
+    /// The textures of the material, bound at consecutive bindings starting
+    /// at `first_binding`, in order: base color, metallic-roughness, normal,
+    /// occlusion and emissive. Missing textures are replaced by images that
+    /// leave the factors unchanged.
+    pub fn sampled_images(&self, first_binding: u32, sampler: vk::Sampler) -> Vec<SampledImage> {
+        let white = get_image_handle(DEFAULT_WHITE_IMAGE_NAME);
+        let flat_normal = get_image_handle(DEFAULT_NORMAL_IMAGE_NAME);
+        [
+            (self.opt_base_color_texture, white),
+            (self.opt_metallic_roughness_texture, white),
+            (self.opt_normal_texture, flat_normal),
+            (self.opt_occlusion_texture, white),
+            (self.opt_emissive_texture, white),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(idx, (opt_texture, default_image))| SampledImage {
+            binding: first_binding + idx as u32,
+            image: opt_texture.map_or(*default_image, |texture| texture.image),
+            sampler,
+        })
+        .collect()
+    }
+}
+
+fn get_image_handle(name: &str) -> ImageHandle {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    ImageHandle(hasher.finish())
+}
+
+/// Expand decoded glTF image data to 8-bit RGBA
+fn get_rgba8_pixels(data: &gltf::image::Data) -> Vec<u8> {
+    use gltf::image::Format;
+    let pixel_count = (data.width * data.height) as usize;
+    let mut pixels = Vec::with_capacity(pixel_count * 4);
+    for pixel_idx in 0..pixel_count {
+        let pixel = match data.format {
+            Format::R8 => {
+                let r = data.pixels[pixel_idx];
+                [r, r, r, 255]
+            }
+            Format::R8G8 => {
+                let p = &data.pixels[pixel_idx * 2..];
+                [p[0], p[1], 0, 255]
+            }
+            Format::R8G8B8 => {
+                let p = &data.pixels[pixel_idx * 3..];
+                [p[0], p[1], p[2], 255]
+            }
+            Format::R8G8B8A8 => {
+                let p = &data.pixels[pixel_idx * 4..];
+                [p[0], p[1], p[2], p[3]]
+            }
+            Format::B8G8R8 => {
+                let p = &data.pixels[pixel_idx * 3..];
+                [p[2], p[1], p[0], 255]
+            }
+            Format::B8G8R8A8 => {
+                let p = &data.pixels[pixel_idx * 4..];
+                [p[2], p[1], p[0], p[3]]
+            }
+            // 16-bit channels are little endian, so keep their high bytes
+            Format::R16 => {
+                let r = data.pixels[pixel_idx * 2 + 1];
+                [r, r, r, 255]
+            }
+            Format::R16G16 => {
+                let p = &data.pixels[pixel_idx * 4..];
+                [p[1], p[3], 0, 255]
+            }
+            Format::R16G16B16 => {
+                let p = &data.pixels[pixel_idx * 6..];
+                [p[1], p[3], p[5], 255]
+            }
+            Format::R16G16B16A16 => {
+                let p = &data.pixels[pixel_idx * 8..];
+                [p[1], p[3], p[5], p[7]]
+            }
+        };
+        pixels.extend_from_slice(&pixel);
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "mesh";
+
+    // Two materials that share an image between textures, once as sRGB and
+    // once as linear data
+    const GLTF: &str = r#"{
+        "asset": { "version": "2.0" },
+        "images": [{ "uri": "a.png" }, { "uri": "b.png" }],
+        "textures": [{ "source": 0 }, { "source": 1 }, { "source": 0 }],
+        "materials": [
+            {
+                "name": "painted",
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": [1.0, 0.5, 0.25, 1.0],
+                    "baseColorTexture": { "index": 0 },
+                    "metallicRoughnessTexture": { "index": 2, "texCoord": 1 },
+                    "metallicFactor": 0.0
+                },
+                "emissiveTexture": { "index": 2 },
+                "alphaMode": "MASK",
+                "alphaCutoff": 0.25,
+                "doubleSided": true
+            },
+            {
+                "normalTexture": { "index": 1, "scale": 0.5 },
+                "alphaMode": "BLEND"
+            }
+        ]
+    }"#;
+
+    fn image(width: u32, height: u32) -> gltf::image::Data {
+        gltf::image::Data {
+            pixels: vec![0; (width * height * 3) as usize],
+            format: gltf::image::Format::R8G8B8,
+            width,
+            height,
+        }
+    }
+
+    fn decode(images: &[gltf::image::Data]) -> Result<(Vec<Material>, Vec<MaterialImage>), String> {
+        let gltf = gltf::Gltf::from_slice(GLTF.as_bytes()).unwrap();
+        Material::decode_all(NAME, &gltf.document, images)
+    }
+
+    #[test]
+    fn images_are_decoded_once_per_color_space() {
+        let (materials, material_images) = decode(&[image(2, 2), image(1, 1)]).unwrap();
+
+        let suffixes: Vec<&str> = material_images
+            .iter()
+            .map(|material_image| material_image.suffix.as_str())
+            .collect();
+        assert_eq!(suffixes, vec!["0_srgb", "0_linear", "1_linear"]);
+        assert_eq!(material_images[0].pixels.len(), 2 * 2 * 4);
+        assert!(material_images[0].is_srgb && !material_images[1].is_srgb);
+        assert_eq!(material_images[0].image_name(NAME), "image_mesh_0_srgb");
+
+        let painted = &materials[0];
+        let srgb_image = material_images[0].handle(NAME);
+        assert_eq!(
+            painted.opt_base_color_texture,
+            Some(MaterialTexture {
+                image: srgb_image,
+                tex_coord: 0,
+            })
+        );
+        assert_eq!(
+            painted.opt_emissive_texture.map(|texture| texture.image),
+            Some(srgb_image)
+        );
+        assert_eq!(
+            painted.opt_metallic_roughness_texture,
+            Some(MaterialTexture {
+                image: material_images[1].handle(NAME),
+                tex_coord: 1,
+            })
+        );
+        assert_eq!(painted.base_color_factor, Vec4::new(1.0, 0.5, 0.25, 1.0));
+        assert_eq!(painted.metallic_factor, 0.0);
+        assert_eq!(painted.alpha_mode, AlphaMode::Mask { cutoff: 0.25 });
+        assert!(painted.double_sided);
+    }
+
+    #[test]
+    fn missing_properties_get_the_gltf_defaults() {
+        let (materials, material_images) = decode(&[image(2, 2), image(1, 1)]).unwrap();
+        let unnamed = &materials[1];
+        assert_eq!(unnamed.name, "mesh_1");
+        assert_eq!(unnamed.base_color_factor, Vec4::one());
+        assert_eq!(unnamed.opt_base_color_texture, None);
+        assert_eq!(unnamed.roughness_factor, 1.0);
+        assert_eq!(unnamed.normal_scale, 0.5);
+        assert_eq!(
+            unnamed.opt_normal_texture.map(|texture| texture.image),
+            Some(material_images[2].handle(NAME))
+        );
+        assert_eq!(unnamed.occlusion_strength, 1.0);
+        assert_eq!(unnamed.emissive_factor, Vec3::zero());
+        assert_eq!(unnamed.alpha_mode, AlphaMode::Blend);
+        assert!(!unnamed.double_sided);
+    }
+
+    #[test]
+    fn images_that_werent_loaded_are_an_error() {
+        let err = decode(&[image(2, 2)]).err().unwrap();
+        assert!(err.contains("Image 1 of mesh `mesh`"), "{}", err);
+    }
+
+    #[test]
+    fn factors_match_the_uniform_block() {
+        assert_eq!(std::mem::size_of::<MaterialFactors>(), 48);
+        let material = Material {
+            emissive_factor: Vec3::new(1.0, 2.0, 3.0),
+            roughness_factor: 0.5,
+            ..Default::default()
+        };
+        let factors = material.factors();
+        assert_eq!(factors.emissive, [1.0, 2.0, 3.0, 0.0]);
+        assert_eq!(factors.base_color, [1.0; 4]);
+        assert_eq!(factors.roughness, 0.5);
+    }
+
+    #[test]
+    fn missing_textures_are_bound_to_stand_ins() {
+        let normal_image = ImageHandle(7);
+        let material = Material {
+            opt_normal_texture: Some(MaterialTexture {
+                image: normal_image,
+                tex_coord: 0,
+            }),
+            ..Default::default()
+        };
+        let sampled_images = material.sampled_images(3, vk::Sampler::null());
+        assert_eq!(sampled_images.len(), Material::TEXTURE_COUNT as usize);
+
+        let white = get_image_handle(DEFAULT_WHITE_IMAGE_NAME);
+        let bound: Vec<(u32, ImageHandle)> = sampled_images
+            .iter()
+            .map(|sampled_image| (sampled_image.binding, sampled_image.image))
+            .collect();
+        assert_eq!(
+            bound,
+            vec![
+                (3, white),
+                (4, white),
+                (5, normal_image),
+                (6, white),
+                (7, white)
+            ]
+        );
+    }
+
+    #[test]
+    fn pixels_are_expanded_to_rgba8() {
+        let expand = |format, pixels: &[u8]| {
+            get_rgba8_pixels(&gltf::image::Data {
+                pixels: pixels.to_vec(),
+                format,
+                width: 1,
+                height: 1,
+            })
+        };
+        use gltf::image::Format;
+        assert_eq!(expand(Format::R8, &[9]), vec![9, 9, 9, 255]);
+        assert_eq!(expand(Format::R8G8B8, &[1, 2, 3]), vec![1, 2, 3, 255]);
+        assert_eq!(expand(Format::B8G8R8A8, &[1, 2, 3, 4]), vec![3, 2, 1, 4]);
+        assert_eq!(
+            expand(Format::R16G16, &[0x00, 0x12, 0xff, 0x34]),
+            vec![0x12, 0x34, 0, 255]
+        );
+    }
+}
@@ -0,0 +1,621 @@
+use crate::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Blocks are this big, unless the heap is small or an allocation needs more
+const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+// Images at least this big get their own device memory, since they would
+// leave a large hole in a block when freed
+const DEDICATED_IMAGE_SIZE: vk::DeviceSize = DEFAULT_BLOCK_SIZE / 4;
+
+/// What is bound to an allocation. Linear and optimal resources must be
+/// `bufferImageGranularity` apart when they share a block.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AllocationKind {
+    Buffer,
+    Image, // With optimal tiling
+}
+
+/// A range of device memory, returned to the allocator when dropped. Bind
+/// it at `offset` within `device_memory`.
+pub struct Allocation {
+    pub device_memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub opt_mapped_ptr: Option<*mut u8>, // Host-visible memory stays mapped, this points at `offset`
+    memory_type_index: u32,
+    is_dedicated: bool,
+    allocator: MemoryAllocator,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.allocator.free(self);
+    }
+}
+
+/// Usage of the allocator, for spotting leaks and fragmentation
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MemoryStats {
+    pub block_count: usize,
+    pub dedicated_allocation_count: usize,
+    pub allocation_count: usize, // Including dedicated allocations
+    pub reserved_bytes: u64,     // In blocks and dedicated allocations
+    pub used_bytes: u64,
+    pub free_range_count: usize, // Holes between allocations, and the ends of blocks
+    pub largest_free_range: u64,
+}
+
+impl MemoryStats {
+    /// Number of vkAllocateMemory allocations, which count towards
+    /// `maxMemoryAllocationCount`
+    pub fn device_memory_count(&self) -> usize {
+        self.block_count + self.dedicated_allocation_count
+    }
+
+    /// 0 if all free memory is in one range, approaching 1 as it gets split
+    /// into smaller ranges
+    pub fn fragmentation(&self) -> f32 {
+        let free_bytes = self.reserved_bytes - self.used_bytes;
+        if free_bytes == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_range as f32 / free_bytes as f32
+        }
+    }
+}
+
+/// Sub-allocates large blocks of device memory, one list of blocks per
+/// memory type. Clones share the same blocks.
+#[derive(Clone)]
+pub struct MemoryAllocator {
+    state: Rc<RefCell<AllocatorState>>,
+}
+
+struct AllocatorState {
+    device: ash::Device,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    buffer_image_granularity: vk::DeviceSize,
+    blocks: Vec<Vec<MemoryBlock>>, // Per memory type
+    dedicated_allocation_count: usize,
+    dedicated_bytes: u64,
+    is_destroyed: bool, // Allocations that outlive the device are leaked
+}
+
+struct MemoryBlock {
+    device_memory: vk::DeviceMemory,
+    size: vk::DeviceSize,
+    opt_mapped_ptr: Option<*mut u8>,
+    // Sorted by offset and covering the whole block. Adjacent free ranges
+    // are always merged.
+    ranges: Vec<MemoryRange>,
+}
+
+#[derive(Copy, Clone)]
+struct MemoryRange {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    opt_kind: Option<AllocationKind>, // None if free
+}
+
+impl MemoryAllocator {
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        buffer_image_granularity: vk::DeviceSize,
+    ) -> MemoryAllocator {
+        MemoryAllocator {
+            state: Rc::new(RefCell::new(AllocatorState {
+                device: device.clone(),
+                memory_properties,
+                buffer_image_granularity: buffer_image_granularity.max(1),
+                blocks: (0..memory_properties.memory_type_count)
+                    .map(|_| Vec::new())
+                    .collect(),
+                dedicated_allocation_count: 0,
+                dedicated_bytes: 0,
+                is_destroyed: false,
+            })),
+        }
+    }
+
+    /// Find room for a resource in the first memory type that has the
+    /// required properties, creating a new block if none has enough space
+    pub fn allocate(
+        &self,
+        requirements: vk::MemoryRequirements,
+        required_properties: vk::MemoryPropertyFlags,
+        kind: AllocationKind,
+    ) -> Result<Allocation, String> {
+        let mut state = self.state.borrow_mut();
+        let memory_type_index = state
+            .memory_properties
+            .memory_types
+            .iter()
+            .take(state.memory_properties.memory_type_count as usize)
+            .enumerate()
+            .position(|(i, memory_type)| {
+                (requirements.memory_type_bits & (1 << i)) > 0
+                    && memory_type.property_flags.contains(required_properties)
+            })
+            .ok_or("Failed to find suitable memory type.")?;
+        let block_size = state.get_block_size(memory_type_index);
+
+        // Big resources would waste most of a block
+        if requirements.size > block_size
+            || (kind == AllocationKind::Image && requirements.size >= DEDICATED_IMAGE_SIZE)
+        {
+            let (device_memory, opt_mapped_ptr) =
+                state.allocate_device_memory(memory_type_index, requirements.size)?;
+            state.dedicated_allocation_count += 1;
+            state.dedicated_bytes += requirements.size;
+            return Ok(Allocation {
+                device_memory,
+                offset: 0,
+                size: requirements.size,
+                opt_mapped_ptr,
+                memory_type_index: memory_type_index as u32,
+                is_dedicated: true,
+                allocator: self.clone(),
+            });
+        }
+
+        let granularity = state.buffer_image_granularity;
+        let opt_fit = state.blocks[memory_type_index]
+            .iter_mut()
+            .find_map(|block| block.allocate(requirements, kind, granularity));
+        let (device_memory, offset, opt_mapped_ptr) = match opt_fit {
+            Some(fit) => fit,
+            None => {
+                // ...No block has enough space. Make a new one.
+                let (device_memory, opt_mapped_ptr) =
+                    state.allocate_device_memory(memory_type_index, block_size)?;
+                let mut block = MemoryBlock {
+                    device_memory,
+                    size: block_size,
+                    opt_mapped_ptr,
+                    ranges: vec![MemoryRange {
+                        offset: 0,
+                        size: block_size,
+                        opt_kind: None,
+                    }],
+                };
+                let fit = block
+                    .allocate(requirements, kind, granularity)
+                    .ok_or("Failed to fit an allocation in an empty block.")?;
+                state.blocks[memory_type_index].push(block);
+                fit
+            }
+        };
+
+        Ok(Allocation {
+            device_memory,
+            offset,
+            size: requirements.size,
+            opt_mapped_ptr,
+            memory_type_index: memory_type_index as u32,
+            is_dedicated: false,
+            allocator: self.clone(),
+        })
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let state = self.state.borrow();
+        let mut stats = MemoryStats {
+            dedicated_allocation_count: state.dedicated_allocation_count,
+            allocation_count: state.dedicated_allocation_count,
+            reserved_bytes: state.dedicated_bytes,
+            used_bytes: state.dedicated_bytes,
+            ..Default::default()
+        };
+        for block in state.blocks.iter().flatten() {
+            stats.block_count += 1;
+            stats.reserved_bytes += block.size;
+            for range in &block.ranges {
+                match range.opt_kind {
+                    Some(_) => {
+                        stats.allocation_count += 1;
+                        stats.used_bytes += range.size;
+                    }
+                    None => {
+                        stats.free_range_count += 1;
+                        stats.largest_free_range = stats.largest_free_range.max(range.size);
+                    }
+                }
+            }
+        }
+        stats
+    }
+
+    /// Free every block, before the device is destroyed. Returns the number of
+    /// allocations that were still alive, whose memory is freed with it.
+    pub(crate) fn destroy(&self) -> usize {
+        let allocation_count = self.stats().allocation_count;
+        let mut state = self.state.borrow_mut();
+        state.is_destroyed = true;
+        let blocks: Vec<MemoryBlock> = state.blocks.iter_mut().flat_map(|b| b.drain(..)).collect();
+        for block in blocks {
+            state.free_device_memory(block.device_memory, block.opt_mapped_ptr);
+        }
+        allocation_count
+    }
+
+    fn free(&self, allocation: &Allocation) {
+        let mut state = self.state.borrow_mut();
+        if state.is_destroyed {
+            return;
+        }
+        if allocation.is_dedicated {
+            state.dedicated_allocation_count -= 1;
+            state.dedicated_bytes -= allocation.size;
+            state.free_device_memory(allocation.device_memory, allocation.opt_mapped_ptr);
+            return;
+        }
+
+        let blocks = &mut state.blocks[allocation.memory_type_index as usize];
+        let block_idx = match blocks
+            .iter()
+            .position(|block| block.device_memory == allocation.device_memory)
+        {
+            Some(block_idx) => block_idx,
+            None => return,
+        };
+        blocks[block_idx].free(allocation.offset);
+
+        // Keep one empty block around, so that freeing and allocating the
+        // same resource every frame doesn't hit the driver
+        let is_empty = blocks[block_idx].ranges.len() == 1;
+        let empty_block_count = blocks.iter().filter(|b| b.ranges.len() == 1).count();
+        if is_empty && empty_block_count > 1 {
+            let block = blocks.remove(block_idx);
+            state.free_device_memory(block.device_memory, block.opt_mapped_ptr);
+        }
+    }
+}
+
+impl AllocatorState {
+    fn get_block_size(&self, memory_type_index: usize) -> vk::DeviceSize {
+        let heap_index = self.memory_properties.memory_types[memory_type_index].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+        // Small heaps, such as the host-visible part of VRAM, get smaller blocks
+        DEFAULT_BLOCK_SIZE.min(heap_size / 8).max(1024 * 1024)
+    }
+
+    fn allocate_device_memory(
+        &self,
+        memory_type_index: usize,
+        size: vk::DeviceSize,
+    ) -> Result<(vk::DeviceMemory, Option<*mut u8>), String> {
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(size)
+            .memory_type_index(memory_type_index as u32);
+        let device_memory = unsafe {
+            self.device
+                .allocate_memory(&allocate_info, None)
+                .map_err(|err| format!("Failed to allocate device memory: {}", err))?
+        };
+
+        // Host-visible memory is mapped once, since a block can't be mapped
+        // again for each of the resources in it
+        let property_flags = self.memory_properties.memory_types[memory_type_index].property_flags;
+        let opt_mapped_ptr = if property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            let mapped_ptr = unsafe {
+                self.device
+                    .map_memory(device_memory, 0, size, vk::MemoryMapFlags::empty())
+                    .map_err(|err| format!("Failed to map memory: {}", err))?
+            };
+            Some(mapped_ptr as *mut u8)
+        } else {
+            None
+        };
+
+        Ok((device_memory, opt_mapped_ptr))
+    }
+
+    fn free_device_memory(&self, device_memory: vk::DeviceMemory, opt_mapped_ptr: Option<*mut u8>) {
+        unsafe {
+            if opt_mapped_ptr.is_some() {
+                self.device.unmap_memory(device_memory);
+            }
+            self.device.free_memory(device_memory, None);
+        }
+    }
+}
+
+impl MemoryBlock {
+    /// First fit. Returns the memory, offset and mapped pointer of the new
+    /// allocation.
+    fn allocate(
+        &mut self,
+        requirements: vk::MemoryRequirements,
+        kind: AllocationKind,
+        granularity: vk::DeviceSize,
+    ) -> Option<(vk::DeviceMemory, vk::DeviceSize, Option<*mut u8>)> {
+        // Whether the last byte of one resource and the first byte of the
+        // next are on the same granularity page
+        let is_same_page = |last_byte: vk::DeviceSize, first_byte: vk::DeviceSize| {
+            last_byte / granularity == first_byte / granularity
+        };
+
+        for range_idx in 0..self.ranges.len() {
+            let range = self.ranges[range_idx];
+            if range.opt_kind.is_some() {
+                continue;
+            }
+
+            let mut offset = align_up(range.offset, requirements.alignment.max(1));
+            if range_idx > 0 {
+                let previous = self.ranges[range_idx - 1];
+                if previous.opt_kind != Some(kind)
+                    && is_same_page(previous.offset + previous.size - 1, offset)
+                {
+                    offset = align_up(offset, granularity);
+                }
+            }
+            let end = offset + requirements.size;
+            if end > range.offset + range.size {
+                continue;
+            }
+            if let Some(next) = self.ranges.get(range_idx + 1) {
+                if next.opt_kind != Some(kind) && is_same_page(end - 1, next.offset) {
+                    continue;
+                }
+            }
+
+            // Split the free range into the padding in front, the allocation
+            // and the rest. The padding can hold other allocations later.
+            let mut replacement = Vec::new();
+            if offset > range.offset {
+                replacement.push(MemoryRange {
+                    offset: range.offset,
+                    size: offset - range.offset,
+                    opt_kind: None,
+                });
+            }
+            replacement.push(MemoryRange {
+                offset,
+                size: requirements.size,
+                opt_kind: Some(kind),
+            });
+            if end < range.offset + range.size {
+                replacement.push(MemoryRange {
+                    offset: end,
+                    size: range.offset + range.size - end,
+                    opt_kind: None,
+                });
+            }
+            self.ranges.splice(range_idx..=range_idx, replacement);
+
+            let opt_mapped_ptr = self
+                .opt_mapped_ptr
+                .map(|mapped_ptr| unsafe { mapped_ptr.add(offset as usize) });
+            return Some((self.device_memory, offset, opt_mapped_ptr));
+        }
+        None
+    }
+
+    /// Free the allocation at the offset, and merge it with its free
+    /// neighbours
+    fn free(&mut self, offset: vk::DeviceSize) {
+        let range_idx = match self
+            .ranges
+            .iter()
+            .position(|range| range.opt_kind.is_some() && range.offset == offset)
+        {
+            Some(range_idx) => range_idx,
+            None => return,
+        };
+        self.ranges[range_idx].opt_kind = None;
+        if range_idx + 1 < self.ranges.len() && self.ranges[range_idx + 1].opt_kind.is_none() {
+            self.ranges[range_idx].size += self.ranges[range_idx + 1].size;
+            self.ranges.remove(range_idx + 1);
+        }
+        if range_idx > 0 && self.ranges[range_idx - 1].opt_kind.is_none() {
+            self.ranges[range_idx - 1].size += self.ranges[range_idx].size;
+            self.ranges.remove(range_idx);
+        }
+    }
+}
+
+pub(crate) fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(size: vk::DeviceSize) -> MemoryBlock {
+        MemoryBlock {
+            device_memory: vk::DeviceMemory::null(),
+            size,
+            opt_mapped_ptr: None,
+            ranges: vec![MemoryRange {
+                offset: 0,
+                size,
+                opt_kind: None,
+            }],
+        }
+    }
+
+    fn allocate(
+        block: &mut MemoryBlock,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        kind: AllocationKind,
+        granularity: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let requirements = vk::MemoryRequirements {
+            size,
+            alignment,
+            memory_type_bits: 1,
+        };
+        let opt_offset = block
+            .allocate(requirements, kind, granularity)
+            .map(|(_, offset, _)| offset);
+        check_ranges(block);
+        opt_offset
+    }
+
+    fn allocate_buffer(block: &mut MemoryBlock, size: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        allocate(block, size, 1, AllocationKind::Buffer, 1)
+    }
+
+    fn free(block: &mut MemoryBlock, offset: vk::DeviceSize) {
+        block.free(offset);
+        check_ranges(block);
+    }
+
+    // The ranges are sorted, cover the whole block, and free ones are merged
+    fn check_ranges(block: &MemoryBlock) {
+        let mut offset = 0;
+        for (range_idx, range) in block.ranges.iter().enumerate() {
+            assert_eq!(range.offset, offset);
+            assert!(range.size > 0);
+            if range_idx > 0 {
+                let previous = block.ranges[range_idx - 1];
+                assert!(previous.opt_kind.is_some() || range.opt_kind.is_some());
+            }
+            offset += range.size;
+        }
+        assert_eq!(offset, block.size);
+    }
+
+    fn free_ranges(block: &MemoryBlock) -> Vec<(vk::DeviceSize, vk::DeviceSize)> {
+        block
+            .ranges
+            .iter()
+            .filter(|range| range.opt_kind.is_none())
+            .map(|range| (range.offset, range.size))
+            .collect()
+    }
+
+    #[test]
+    fn first_fit() {
+        let mut block = block(1000);
+        assert_eq!(allocate_buffer(&mut block, 100), Some(0));
+        assert_eq!(allocate_buffer(&mut block, 100), Some(100));
+        assert_eq!(allocate_buffer(&mut block, 100), Some(200));
+        free(&mut block, 100);
+
+        // The hole is the first range that fits
+        assert_eq!(allocate_buffer(&mut block, 50), Some(100));
+        // ...but not this one
+        assert_eq!(allocate_buffer(&mut block, 60), Some(300));
+        assert_eq!(allocate_buffer(&mut block, 50), Some(150));
+        assert_eq!(free_ranges(&block), vec![(360, 640)]);
+
+        // Exactly filling the block, and then running out of space
+        assert_eq!(allocate_buffer(&mut block, 640), Some(360));
+        assert!(free_ranges(&block).is_empty());
+        assert_eq!(allocate_buffer(&mut block, 1), None);
+    }
+
+    #[test]
+    fn alignment_padding_is_reused() {
+        let mut block = block(1024);
+        assert_eq!(allocate_buffer(&mut block, 10), Some(0));
+        assert_eq!(
+            allocate(&mut block, 100, 256, AllocationKind::Buffer, 1),
+            Some(256)
+        );
+        assert_eq!(free_ranges(&block), vec![(10, 246), (356, 668)]);
+        // Allocations that fit in the padding go there first
+        assert_eq!(
+            allocate(&mut block, 100, 16, AllocationKind::Buffer, 1),
+            Some(16)
+        );
+        assert_eq!(
+            allocate(&mut block, 1000, 1, AllocationKind::Buffer, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn free_ranges_are_coalesced() {
+        let mut block = block(400);
+        for offset in &[0, 100, 200, 300] {
+            assert_eq!(allocate_buffer(&mut block, 100), Some(*offset));
+        }
+
+        // With the next range
+        free(&mut block, 300);
+        free(&mut block, 200);
+        assert_eq!(free_ranges(&block), vec![(200, 200)]);
+        // With the previous range
+        free(&mut block, 0);
+        assert_eq!(free_ranges(&block), vec![(0, 100), (200, 200)]);
+        // With both
+        free(&mut block, 100);
+        assert_eq!(free_ranges(&block), vec![(0, 400)]);
+        assert_eq!(block.ranges.len(), 1);
+
+        // Offsets that aren't allocated are ignored
+        free(&mut block, 0);
+        free(&mut block, 50);
+        assert_eq!(block.ranges.len(), 1);
+
+        // The whole block can be reused
+        assert_eq!(allocate_buffer(&mut block, 400), Some(0));
+    }
+
+    #[test]
+    fn buffers_and_images_are_granularity_apart() {
+        // With 256 byte pages
+        let mut block = block(2048);
+        assert_eq!(
+            allocate(&mut block, 100, 1, AllocationKind::Buffer, 256),
+            Some(0)
+        );
+        // An image after a buffer goes to the next page
+        assert_eq!(
+            allocate(&mut block, 10, 1, AllocationKind::Image, 256),
+            Some(256)
+        );
+        // Resources of the same kind can share a page
+        assert_eq!(
+            allocate(&mut block, 10, 1, AllocationKind::Image, 256),
+            Some(266)
+        );
+        assert_eq!(
+            allocate(&mut block, 156, 1, AllocationKind::Buffer, 256),
+            Some(100)
+        );
+        // A buffer after an image goes to the next page too
+        assert_eq!(
+            allocate(&mut block, 10, 1, AllocationKind::Buffer, 256),
+            Some(512)
+        );
+
+        // A buffer in the hole would share a page with the image after it,
+        // and the padding after that image reaches the next buffer
+        free(&mut block, 256);
+        assert_eq!(
+            allocate(&mut block, 5, 1, AllocationKind::Buffer, 256),
+            Some(522)
+        );
+        // But another image fits
+        assert_eq!(
+            allocate(&mut block, 10, 1, AllocationKind::Image, 256),
+            Some(256)
+        );
+        assert_eq!(free_ranges(&block), vec![(276, 236), (527, 1521)]);
+    }
+
+    #[test]
+    fn fragmentation() {
+        let stats = MemoryStats {
+            reserved_bytes: 1000,
+            used_bytes: 600,
+            largest_free_range: 100,
+            ..Default::default()
+        };
+        assert!((stats.fragmentation() - 0.75).abs() < 1e-6);
+        let stats = MemoryStats {
+            reserved_bytes: 1000,
+            used_bytes: 1000,
+            ..Default::default()
+        };
+        assert_eq!(stats.fragmentation(), 0.0);
+    }
+}
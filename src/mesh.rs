@@ -1,85 +1,1183 @@
+use crate::mesh_cache::*;
+use crate::mesh_import::*;
 use crate::*;
+use glam::*;
 
 // TODO: This module is not a core part of the render graph. Make that clear from the hierarchy.
 
+/// The standard glTF vertex attributes. Each one always feeds the same shader
+/// input location, so that shaders can be written against any mesh.
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+pub enum MeshAttribute {
+    Position,
+    Normal,
+    TexCoord0,
+    TexCoord1,
+    Tangent, // xyz is the tangent, w is the handedness of the bitangent
+    Color0,
+    Joints0,
+    Weights0,
+}
+
+impl MeshAttribute {
+    pub const ALL: [MeshAttribute; 8] = [
+        MeshAttribute::Position,
+        MeshAttribute::Normal,
+        MeshAttribute::TexCoord0,
+        MeshAttribute::TexCoord1,
+        MeshAttribute::Tangent,
+        MeshAttribute::Color0,
+        MeshAttribute::Joints0,
+        MeshAttribute::Weights0,
+    ];
+
+    pub fn location(&self) -> u32 {
+        match self {
+            MeshAttribute::Position => 0,
+            MeshAttribute::Normal => 1,
+            MeshAttribute::TexCoord0 => 2,
+            MeshAttribute::TexCoord1 => 3,
+            MeshAttribute::Tangent => 4,
+            MeshAttribute::Color0 => 5,
+            MeshAttribute::Joints0 => 6,
+            MeshAttribute::Weights0 => 7,
+        }
+    }
+
+    pub fn format(&self) -> vk::Format {
+        match self {
+            MeshAttribute::Position | MeshAttribute::Normal => vk::Format::R32G32B32_SFLOAT,
+            MeshAttribute::TexCoord0 | MeshAttribute::TexCoord1 => vk::Format::R32G32_SFLOAT,
+            MeshAttribute::Tangent | MeshAttribute::Color0 | MeshAttribute::Weights0 => {
+                vk::Format::R32G32B32A32_SFLOAT
+            }
+            MeshAttribute::Joints0 => vk::Format::R32G32B32A32_UINT,
+        }
+    }
+
+    /// Number of 32-bit components
+    pub fn component_count(&self) -> usize {
+        match self {
+            MeshAttribute::Position | MeshAttribute::Normal => 3,
+            MeshAttribute::TexCoord0 | MeshAttribute::TexCoord1 => 2,
+            MeshAttribute::Tangent
+            | MeshAttribute::Color0
+            | MeshAttribute::Joints0
+            | MeshAttribute::Weights0 => 4,
+        }
+    }
+}
+
+/// A range of the index buffer that is drawn with a single material
+#[derive(Clone, Debug)]
+pub struct Submesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,            // Added to each index of the range
+    pub material_index: Option<usize>, // Index into Mesh::materials, None for the default material
+    // Simplified versions of the range, from finest to coarsest. They use the
+    // same vertices, so share the vertex offset. Empty unless the mesh was
+    // processed with LODs.
+    pub lods: Vec<SubmeshLod>,
+    // In the space of the mesh, without morph targets and skinning
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SubmeshLod {
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+impl Submesh {
+    /// The index range of a LOD, 0 being the full-detail one. LODs past the
+    /// coarsest one get the coarsest one.
+    pub fn get_lod(&self, lod: usize) -> SubmeshLod {
+        match lod.checked_sub(1) {
+            Some(lod_idx) if !self.lods.is_empty() => self.lods[lod_idx.min(self.lods.len() - 1)],
+            _ => SubmeshLod {
+                first_index: self.first_index,
+                index_count: self.index_count,
+            },
+        }
+    }
+}
+
 pub struct Mesh {
-    pub vertex_buffer: DeviceLocalBuffer,
-    pub index_buffer: DeviceLocalBuffer,
+    device: ash::Device,
+    // Device-local buffers in the buffer list. Their uploads take a frame or
+    // more, see `is_uploaded()`.
+    pub vertex_buffer: BufferHandle,
+    pub index_buffer: BufferHandle,
+    pub vertex_layout: VertexLayout, // How the vertex buffer is laid out
+    pub attributes: Vec<MeshAttribute>, // The attributes in the vertex buffer, in order
+    pub submeshes: Vec<Submesh>,     // One per glTF primitive, or OBJ material
+    pub materials: Vec<Material>,    // All the materials of the file
+    pub vertex_count: usize,
+    pub topology: vk::PrimitiveTopology, // Point list for point clouds, triangle list otherwise
+
+    // Morph target deltas, as three vec4s per vertex and target: position,
+    // normal and tangent, starting at `(target * vertex_count + vertex) * 3`.
+    // None if the mesh doesn't have morph targets.
+    pub opt_morph_target_buffer: Option<BufferHandle>,
+    pub morph_target_count: usize,
+    pub default_morph_weights: Vec<f32>, // One per morph target
+
+    // The simplification error of each LOD, as a fraction of the largest
+    // extent of the mesh. The first one is the full-detail mesh, at zero.
+    pub lod_errors: Vec<f32>,
+
+    // Around every submesh
+    pub aabb: Aabb,
+    pub bounding_sphere: BoundingSphere,
+
+    // The parts of the file that were skipped when it was imported, and why
+    // the baked mesh cache couldn't be read or written. None of them stop the
+    // mesh from loading.
+    pub warnings: Vec<String>,
 }
 
-impl Mesh {
-    pub fn load(
-        name: &str,
-        path: &str,
-        gpu: &Gpu,
-        command_pool: vk::CommandPool,
-        debug_utils: &DebugUtils,
-    ) -> Mesh {
-        // TODO: Benchmark and optimize
-        let (vertices_data, indices_data) = {
-            let mut vertices_data: Vec<f32> = Vec::new();
-            let mut indices_data: Vec<u32> = Vec::new();
-
-            let (gltf, buffers, _) = gltf::import(path).expect("Failed to open mesh.");
-            for mesh in gltf.meshes() {
-                for primitive in mesh.primitives() {
-                    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-                    if let Some(iter_pos) = reader.read_positions() {
-                        if let Some(iter_norm) = reader.read_normals() {
-                            for (pos, norm) in iter_pos.zip(iter_norm) {
-                                vertices_data.extend_from_slice(&pos);
-                                vertices_data.extend_from_slice(&norm);
+// The displacements of a morph target, zero for the ones the file doesn't have
+pub(crate) struct MorphTargetData {
+    pub(crate) position_deltas: Vec<[f32; 3]>,
+    pub(crate) normal_deltas: Vec<[f32; 3]>,
+    pub(crate) tangent_deltas: Vec<[f32; 3]>,
+}
+
+// The attributes of a single primitive, before interleaving. Every file
+// format is imported into these.
+#[derive(Default)]
+pub(crate) struct PrimitiveData {
+    pub(crate) positions: Vec<[f32; 3]>,
+    pub(crate) normals: Vec<[f32; 3]>, // Empty if the file doesn't have any
+    pub(crate) opt_tex_coords_0: Option<Vec<[f32; 2]>>,
+    pub(crate) opt_tex_coords_1: Option<Vec<[f32; 2]>>,
+    pub(crate) opt_tangents: Option<Vec<[f32; 4]>>,
+    pub(crate) opt_colors_0: Option<Vec<[f32; 4]>>,
+    pub(crate) opt_joints_0: Option<Vec<[u32; 4]>>,
+    pub(crate) opt_weights_0: Option<Vec<[f32; 4]>>,
+    pub(crate) indices: Vec<u32>, // Relative to the first vertex of the primitive
+    pub(crate) material_index: Option<usize>,
+    pub(crate) morph_targets: Vec<MorphTargetData>,
+    pub(crate) opt_aabb: Option<Aabb>, // From the file, if it has one
+}
+
+// A mesh ready to be uploaded, with its vertices interleaved and the images of
+// its materials decoded. This is what the mesh cache stores.
+pub(crate) struct BakedMesh {
+    pub(crate) attributes: Vec<MeshAttribute>,
+    pub(crate) topology: vk::PrimitiveTopology,
+    pub(crate) vertices: Vec<f32>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) submeshes: Vec<Submesh>,
+    pub(crate) vertex_count: usize,
+    pub(crate) morph_target_count: usize,
+    pub(crate) morph_target_deltas: Vec<[f32; 4]>, // Laid out like Mesh::opt_morph_target_buffer
+    pub(crate) default_morph_weights: Vec<f32>,
+    pub(crate) materials: Vec<Material>,
+    pub(crate) material_images: Vec<MaterialImage>,
+    pub(crate) lod_errors: Vec<f32>,
+    pub(crate) import_warnings: Vec<String>, // The parts of the file that were skipped
+}
+
+impl PrimitiveData {
+    fn has_attribute(&self, attribute: MeshAttribute) -> bool {
+        match attribute {
+            MeshAttribute::Position | MeshAttribute::Normal => true,
+            MeshAttribute::TexCoord0 => self.opt_tex_coords_0.is_some(),
+            MeshAttribute::TexCoord1 => self.opt_tex_coords_1.is_some(),
+            MeshAttribute::Tangent => self.opt_tangents.is_some(),
+            MeshAttribute::Color0 => self.opt_colors_0.is_some(),
+            MeshAttribute::Joints0 => self.opt_joints_0.is_some(),
+            MeshAttribute::Weights0 => self.opt_weights_0.is_some(),
+        }
+    }
+
+    /// Append the attribute of a vertex to the interleaved vertex data. If
+    /// the primitive doesn't have the attribute, a default value is written.
+    fn write_attribute(&self, attribute: MeshAttribute, vertex_idx: usize, out: &mut Vec<f32>) {
+        match attribute {
+            MeshAttribute::Position => out.extend_from_slice(&self.positions[vertex_idx]),
+            MeshAttribute::Normal => out.extend_from_slice(&self.normals[vertex_idx]),
+            MeshAttribute::TexCoord0 => match &self.opt_tex_coords_0 {
+                Some(tex_coords) => out.extend_from_slice(&tex_coords[vertex_idx]),
+                None => out.extend_from_slice(&[0.0, 0.0]),
+            },
+            MeshAttribute::TexCoord1 => match &self.opt_tex_coords_1 {
+                Some(tex_coords) => out.extend_from_slice(&tex_coords[vertex_idx]),
+                None => out.extend_from_slice(&[0.0, 0.0]),
+            },
+            MeshAttribute::Tangent => match &self.opt_tangents {
+                Some(tangents) => out.extend_from_slice(&tangents[vertex_idx]),
+                None => out.extend_from_slice(&[1.0, 0.0, 0.0, 1.0]),
+            },
+            MeshAttribute::Color0 => match &self.opt_colors_0 {
+                Some(colors) => out.extend_from_slice(&colors[vertex_idx]),
+                None => out.extend_from_slice(&[1.0, 1.0, 1.0, 1.0]),
+            },
+            // Joint indices are integers, so their bits are stored as is
+            MeshAttribute::Joints0 => match &self.opt_joints_0 {
+                Some(joints) => out.extend(joints[vertex_idx].iter().map(|j| f32::from_bits(*j))),
+                None => out.extend_from_slice(&[0.0; 4]),
+            },
+            MeshAttribute::Weights0 => match &self.opt_weights_0 {
+                Some(weights) => out.extend_from_slice(&weights[vertex_idx]),
+                None => out.extend_from_slice(&[0.0; 4]),
+            },
+        }
+    }
+
+    /// Generate the normals and tangents that the file didn't provide. Points
+    /// don't have faces, so they get placeholder normals instead.
+    pub(crate) fn generate_missing_attributes(&mut self, topology: vk::PrimitiveTopology) {
+        if topology == vk::PrimitiveTopology::POINT_LIST {
+            if self.normals.is_empty() {
+                self.normals = vec![[0.0, 0.0, 1.0]; self.positions.len()];
+            }
+            return;
+        }
+        if self.normals.is_empty() {
+            self.generate_flat_normals();
+        }
+        if self.opt_tangents.is_none() {
+            self.generate_tangents();
+        }
+    }
+
+    /// Split the triangles so that no vertices are shared, and give each
+    /// triangle its face normal. glTF requires flat normals when a primitive
+    /// doesn't have any.
+    fn generate_flat_normals(&mut self) {
+        fn unindex<T: Copy>(data: &[T], indices: &[u32]) -> Vec<T> {
+            indices.iter().map(|idx| data[*idx as usize]).collect()
+        }
+        self.positions = unindex(&self.positions, &self.indices);
+        if let Some(tex_coords) = &mut self.opt_tex_coords_0 {
+            *tex_coords = unindex(tex_coords, &self.indices);
+        }
+        if let Some(tex_coords) = &mut self.opt_tex_coords_1 {
+            *tex_coords = unindex(tex_coords, &self.indices);
+        }
+        if let Some(colors) = &mut self.opt_colors_0 {
+            *colors = unindex(colors, &self.indices);
+        }
+        if let Some(joints) = &mut self.opt_joints_0 {
+            *joints = unindex(joints, &self.indices);
+        }
+        if let Some(weights) = &mut self.opt_weights_0 {
+            *weights = unindex(weights, &self.indices);
+        }
+        for morph_target in &mut self.morph_targets {
+            morph_target.position_deltas = unindex(&morph_target.position_deltas, &self.indices);
+            morph_target.normal_deltas = unindex(&morph_target.normal_deltas, &self.indices);
+            morph_target.tangent_deltas = unindex(&morph_target.tangent_deltas, &self.indices);
+        }
+        // Provided tangents must be ignored if normals are generated
+        self.opt_tangents = None;
+        self.indices = (0..self.positions.len() as u32).collect();
+
+        self.normals = self
+            .positions
+            .chunks(3)
+            .flat_map(|triangle| {
+                let p0 = Vec3::from(triangle[0]);
+                let p1 = Vec3::from(triangle[1]);
+                let p2 = Vec3::from(triangle[2]);
+                let cross = (p1 - p0).cross(p2 - p0);
+                let normal = if cross.length_squared() > 0.0 {
+                    cross.normalize()
+                } else {
+                    Vec3::unit_z() // Degenerate triangle
+                };
+                let normal: [f32; 3] = normal.into();
+                vec![normal; 3]
+            })
+            .collect();
+    }
+
+    /// Tangents along the direction of increasing u, accumulated over the
+    /// triangles around each vertex and orthogonalized against the normal,
+    /// in the spirit of MikkTSpace.
+    fn generate_tangents(&mut self) {
+        let tex_coords = match &self.opt_tex_coords_0 {
+            Some(tex_coords) => tex_coords,
+            None => return, // Tangents can't be derived without UVs
+        };
+
+        let mut tangents = vec![Vec3::zero(); self.positions.len()];
+        let mut bitangents = vec![Vec3::zero(); self.positions.len()];
+        for triangle in self.indices.chunks(3) {
+            let [i0, i1, i2] = [
+                triangle[0] as usize,
+                triangle[1] as usize,
+                triangle[2] as usize,
+            ];
+            let edge_1 = Vec3::from(self.positions[i1]) - Vec3::from(self.positions[i0]);
+            let edge_2 = Vec3::from(self.positions[i2]) - Vec3::from(self.positions[i0]);
+            let delta_uv_1 = Vec2::from(tex_coords[i1]) - Vec2::from(tex_coords[i0]);
+            let delta_uv_2 = Vec2::from(tex_coords[i2]) - Vec2::from(tex_coords[i0]);
+
+            let determinant = delta_uv_1.x() * delta_uv_2.y() - delta_uv_2.x() * delta_uv_1.y();
+            if determinant.abs() < f32::EPSILON {
+                continue; // Degenerate UVs
+            }
+            let r = 1.0 / determinant;
+            let tangent = (edge_1 * delta_uv_2.y() - edge_2 * delta_uv_1.y()) * r;
+            let bitangent = (edge_2 * delta_uv_1.x() - edge_1 * delta_uv_2.x()) * r;
+            for idx in &[i0, i1, i2] {
+                tangents[*idx] += tangent;
+                bitangents[*idx] += bitangent;
+            }
+        }
+
+        let tangents = tangents
+            .iter()
+            .zip(&bitangents)
+            .zip(&self.normals)
+            .map(|((tangent, bitangent), normal)| {
+                let normal = Vec3::from(*normal);
+                // Gram-Schmidt orthogonalize
+                let mut t = *tangent - normal * normal.dot(*tangent);
+                if t.length_squared() < f32::EPSILON {
+                    // Pick any direction perpendicular to the normal
+                    let axis = if normal.x().abs() < 0.9 {
+                        Vec3::unit_x()
+                    } else {
+                        Vec3::unit_y()
+                    };
+                    t = normal.cross(axis);
+                }
+                let t = t.normalize();
+                let handedness = if normal.cross(t).dot(*bitangent) < 0.0 {
+                    -1.0
+                } else {
+                    1.0
+                };
+                [t.x(), t.y(), t.z(), handedness]
+            })
+            .collect();
+        self.opt_tangents = Some(tangents);
+    }
+}
+
+impl BakedMesh {
+    /// Read a glTF, OBJ or PLY file, depending on its extension. The parts of
+    /// the file that are skipped are kept in `import_warnings`.
+    pub(crate) fn import(name: &str, path: &str) -> Result<BakedMesh, String> {
+        let extension = std::path::Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let mut warnings = Vec::new();
+        let mut baked_mesh = match extension.as_str() {
+            "gltf" | "glb" => {
+                let (gltf, buffers, images) = gltf::import(path)
+                    .map_err(|err| format!("Failed to open mesh `{}`: {}", path, err))?;
+                let (materials, material_images) = Material::decode_all(name, &gltf, &images)
+                    .map_err(|err| {
+                        format!("Failed to load the materials of `{}`: {}", path, err)
+                    })?;
+                let (primitives, default_morph_weights) =
+                    read_gltf_primitives(gltf.meshes(), &buffers, &mut warnings);
+                BakedMesh::from_primitives(
+                    primitives,
+                    materials,
+                    material_images,
+                    default_morph_weights,
+                    vk::PrimitiveTopology::TRIANGLE_LIST,
+                )
+            }
+            "obj" => {
+                let (primitives, materials, material_images) = load_obj(name, path, &mut warnings)?;
+                BakedMesh::from_primitives(
+                    primitives,
+                    materials,
+                    material_images,
+                    Vec::new(),
+                    vk::PrimitiveTopology::TRIANGLE_LIST,
+                )
+            }
+            "ply" => {
+                let (primitive, topology) = load_ply(path, &mut warnings)?;
+                BakedMesh::from_primitives(
+                    vec![primitive],
+                    Vec::new(),
+                    Vec::new(),
+                    Vec::new(),
+                    topology,
+                )
+            }
+            _ => {
+                return Err(format!(
+                    "Mesh `{}` has an unsupported extension. Use .gltf, .glb, .obj or .ply.",
+                    path
+                ))
+            }
+        };
+        baked_mesh.import_warnings = warnings;
+        Ok(baked_mesh)
+    }
+
+    /// Interleave the vertices of the primitives
+    pub(crate) fn from_primitives(
+        primitives: Vec<PrimitiveData>,
+        materials: Vec<Material>,
+        material_images: Vec<MaterialImage>,
+        mut default_morph_weights: Vec<f32>,
+        topology: vk::PrimitiveTopology,
+    ) -> BakedMesh {
+        // Every vertex gets the attributes that any of the primitives have
+        let attributes: Vec<MeshAttribute> = MeshAttribute::ALL
+            .iter()
+            .copied()
+            .filter(|attribute| primitives.iter().any(|p| p.has_attribute(*attribute)))
+            .collect();
+
+        // Interleave the vertices, and concatenate the primitives into a
+        // single vertex/index buffer pair, with one submesh per primitive
+        let mut vertices: Vec<f32> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let mut submeshes = Vec::new();
+        let mut vertex_count = 0;
+        for primitive in &primitives {
+            let positions = primitive
+                .positions
+                .iter()
+                .map(|position| Vec3::from(*position));
+            let aabb = primitive
+                .opt_aabb
+                .unwrap_or_else(|| Aabb::from_points(positions.clone()));
+            submeshes.push(Submesh {
+                first_index: indices.len() as u32,
+                index_count: primitive.indices.len() as u32,
+                vertex_offset: vertex_count as i32,
+                material_index: primitive.material_index,
+                lods: Vec::new(),
+                aabb,
+                bounding_sphere: BoundingSphere::from_points(positions, &aabb),
+            });
+            for vertex_idx in 0..primitive.positions.len() {
+                for attribute in &attributes {
+                    primitive.write_attribute(*attribute, vertex_idx, &mut vertices);
+                }
+            }
+            indices.extend_from_slice(&primitive.indices);
+            vertex_count += primitive.positions.len();
+        }
+
+        // Lay out the morph target deltas target by target. Primitives with
+        // fewer targets than the others get zero deltas for the rest.
+        let morph_target_count = primitives
+            .iter()
+            .map(|primitive| primitive.morph_targets.len())
+            .max()
+            .unwrap_or(0);
+        let mut morph_target_deltas: Vec<[f32; 4]> =
+            Vec::with_capacity(morph_target_count * vertex_count * 3);
+        for target_idx in 0..morph_target_count {
+            for primitive in &primitives {
+                match primitive.morph_targets.get(target_idx) {
+                    Some(morph_target) => {
+                        for vertex_idx in 0..primitive.positions.len() {
+                            for deltas in &[
+                                &morph_target.position_deltas,
+                                &morph_target.normal_deltas,
+                                &morph_target.tangent_deltas,
+                            ] {
+                                let delta = deltas[vertex_idx];
+                                morph_target_deltas.push([delta[0], delta[1], delta[2], 0.0]);
                             }
                         }
                     }
-                    if let Some(iter) = reader.read_indices() {
-                        match iter {
-                            gltf::mesh::util::ReadIndices::U8(iter_2) => {
-                                for idx in iter_2 {
-                                    indices_data.push(idx as u32);
-                                }
-                            }
-                            gltf::mesh::util::ReadIndices::U16(iter_2) => {
-                                for idx in iter_2 {
-                                    indices_data.push(idx as u32);
-                                }
-                            }
-                            gltf::mesh::util::ReadIndices::U32(iter_2) => {
-                                for idx in iter_2 {
-                                    indices_data.push(idx as u32);
-                                }
-                            }
-                        }
+                    None => {
+                        let len = morph_target_deltas.len() + primitive.positions.len() * 3;
+                        morph_target_deltas.resize(len, [0.0; 4]);
                     }
                 }
             }
+        }
+        default_morph_weights.resize(morph_target_count, 0.0);
 
-            (vertices_data, indices_data)
-        };
+        BakedMesh {
+            attributes,
+            topology,
+            vertices,
+            indices,
+            submeshes,
+            vertex_count,
+            morph_target_count,
+            morph_target_deltas,
+            default_morph_weights,
+            materials,
+            material_images,
+            lod_errors: vec![0.0],
+            import_warnings: Vec::new(),
+        }
+    }
+}
 
-        // # Create and upload the vertex buffer
-        let vertex_buffer = DeviceLocalBuffer::new(
-            &format!("buffer_{}_mesh_vertex", name),
-            &vertices_data,
-            vk::BufferUsageFlags::VERTEX_BUFFER,
+impl Mesh {
+    /// Load a glTF, OBJ or PLY file, depending on its extension. The mesh is
+    /// baked into `_cache/meshes` the first time, and read from there as long
+    /// as the file doesn't change.
+    #[allow(clippy::too_many_arguments)]
+    pub fn load(
+        name: &str,
+        path: &str,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        image_list: &mut ImageList,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::load_processed(
+            name,
+            path,
+            &MeshProcessing::NONE,
+            gpu,
+            command_pool,
+            image_list,
+            buffer_list,
+            debug_utils,
+        )
+    }
+
+    /// Like `load()`, but weld, reorder and simplify the mesh before baking it
+    #[allow(clippy::too_many_arguments)]
+    pub fn load_processed(
+        name: &str,
+        path: &str,
+        processing: &MeshProcessing,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        image_list: &mut ImageList,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        let (baked_mesh, cache_warnings) = load_baked_mesh(name, path, processing)?;
+        Material::upload_images(
+            name,
+            &baked_mesh.material_images,
+            image_list,
             gpu,
             command_pool,
             debug_utils,
+        )
+        .map_err(|err| format!("Failed to load the materials of `{}`: {}", path, err))?;
+        let mut mesh = Mesh::from_baked(name, baked_mesh, gpu, buffer_list, debug_utils)?;
+        mesh.warnings.extend(cache_warnings);
+        Ok(mesh)
+    }
+
+    /// Flatten the primitives of the given glTF meshes into a single mesh
+    pub(crate) fn from_gltf_meshes<'a>(
+        name: &str,
+        gltf_meshes: impl Iterator<Item = gltf::Mesh<'a>>,
+        buffers: &[gltf::buffer::Data],
+        materials: Vec<Material>,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        let mut warnings = Vec::new();
+        let (primitives, default_morph_weights) =
+            read_gltf_primitives(gltf_meshes, buffers, &mut warnings);
+        let mut baked_mesh = BakedMesh::from_primitives(
+            primitives,
+            materials,
+            Vec::new(),
+            default_morph_weights,
+            vk::PrimitiveTopology::TRIANGLE_LIST,
         );
+        baked_mesh.import_warnings = warnings;
+        Mesh::from_baked(name, baked_mesh, gpu, buffer_list, debug_utils)
+    }
 
-        // # Create and upload index buffer
-        let index_buffer = DeviceLocalBuffer::new(
+    /// Create the buffers of a baked mesh in the buffer list, and queue the
+    /// uploads of its vertices. Its material images must have been uploaded
+    /// already.
+    pub(crate) fn from_baked(
+        name: &str,
+        baked_mesh: BakedMesh,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        let aabb = baked_mesh
+            .submeshes
+            .iter()
+            .map(|submesh| submesh.aabb)
+            .fold(None, |opt_aabb: Option<Aabb>, aabb| {
+                Some(opt_aabb.map_or(aabb, |opt_aabb| opt_aabb.union(&aabb)))
+            })
+            .unwrap_or_else(|| Aabb::from_points(std::iter::empty()));
+        let bounding_sphere = baked_mesh.submeshes.iter().fold(
+            BoundingSphere {
+                center: aabb.center(),
+                radius: 0.0,
+            },
+            |bounding_sphere, submesh| {
+                bounding_sphere.union(&submesh.bounding_sphere, aabb.center())
+            },
+        );
+
+        let opt_morph_target_buffer = if baked_mesh.morph_target_count > 0 {
+            Some(new_mesh_buffer(
+                &format!("buffer_{}_mesh_morph_targets", name),
+                &baked_mesh.morph_target_deltas,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                gpu,
+                buffer_list,
+                debug_utils,
+            )?)
+        } else {
+            None
+        };
+        let vertex_buffer = new_mesh_buffer(
+            &format!("buffer_{}_mesh_vertex", name),
+            &baked_mesh.vertices,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            gpu,
+            buffer_list,
+            debug_utils,
+        )?;
+        let index_buffer = new_mesh_buffer(
             &format!("buffer_{}_mesh_index", name),
-            &indices_data,
+            &baked_mesh.indices,
             vk::BufferUsageFlags::INDEX_BUFFER,
             gpu,
-            command_pool,
+            buffer_list,
             debug_utils,
-        );
+        )?;
 
-        Mesh {
+        Ok(Mesh {
+            device: gpu.device.clone(),
             vertex_buffer,
             index_buffer,
+            vertex_layout: get_vertex_layout(&baked_mesh.attributes),
+            attributes: baked_mesh.attributes,
+            submeshes: baked_mesh.submeshes,
+            materials: baked_mesh.materials,
+            vertex_count: baked_mesh.vertex_count,
+            topology: baked_mesh.topology,
+            opt_morph_target_buffer,
+            morph_target_count: baked_mesh.morph_target_count,
+            default_morph_weights: baked_mesh.default_morph_weights,
+            lod_errors: baked_mesh.lod_errors,
+            aabb,
+            bounding_sphere,
+            warnings: baked_mesh.import_warnings,
+        })
+    }
+
+    /// Whether the passes of the current frame can draw the mesh, i.e. all
+    /// of its buffers are uploaded
+    pub fn is_uploaded(&self, buffer_list: &BufferList) -> bool {
+        std::iter::once(self.vertex_buffer)
+            .chain(std::iter::once(self.index_buffer))
+            .chain(self.opt_morph_target_buffer)
+            .all(|buffer_handle| buffer_list.is_uploaded(buffer_handle))
+    }
+
+    /// Bind the vertex and index buffers, and draw every submesh
+    pub fn draw(&self, cmd_buf: vk::CommandBuffer, buffer_list: &BufferList) {
+        self.draw_submeshes(cmd_buf, buffer_list, 0, |_| true);
+    }
+
+    /// Draw every submesh at the given LOD, from `select_lod()`
+    pub fn draw_lod(&self, cmd_buf: vk::CommandBuffer, buffer_list: &BufferList, lod: usize) {
+        self.draw_submeshes(cmd_buf, buffer_list, lod, |_| true);
+    }
+
+    /// Draw only the submeshes that use the given material, so that a pass
+    /// can bind the textures of one material at a time
+    pub fn draw_material(
+        &self,
+        cmd_buf: vk::CommandBuffer,
+        buffer_list: &BufferList,
+        opt_material_index: Option<usize>,
+    ) {
+        self.draw_submeshes(cmd_buf, buffer_list, 0, |submesh| {
+            submesh.material_index == opt_material_index
+        });
+    }
+
+    /// The coarsest LOD whose error stays under `max_error` pixels, for a
+    /// mesh whose largest extent covers `screen_size` pixels on screen
+    pub fn select_lod(&self, screen_size: f32, max_error: f32) -> usize {
+        self.lod_errors
+            .iter()
+            .rposition(|error| error * screen_size <= max_error)
+            .unwrap_or(0)
+    }
+
+    /// The material of a submesh, or None if it uses the default material
+    pub fn get_material(&self, submesh: &Submesh) -> Option<&Material> {
+        submesh
+            .material_index
+            .and_then(|material_idx| self.materials.get(material_idx))
+    }
+
+    fn draw_submeshes(
+        &self,
+        cmd_buf: vk::CommandBuffer,
+        buffer_list: &BufferList,
+        lod: usize,
+        filter: impl Fn(&Submesh) -> bool,
+    ) {
+        let get_vk_buffer = |buffer_handle: BufferHandle| {
+            buffer_list
+                .get_device_local_buffer_from_handle(buffer_handle)
+                .map(|buffer| buffer.vk_buffer)
+                .expect("The buffers of the mesh are not in the buffer list.")
+        };
+        unsafe {
+            let vertex_buffers = [get_vk_buffer(self.vertex_buffer)];
+            let offsets = [0_u64];
+            self.device
+                .cmd_bind_vertex_buffers(cmd_buf, 0, &vertex_buffers, &offsets);
+            self.device.cmd_bind_index_buffer(
+                cmd_buf,
+                get_vk_buffer(self.index_buffer),
+                0,
+                vk::IndexType::UINT32,
+            );
+            for submesh in self.submeshes.iter().filter(|submesh| filter(submesh)) {
+                let range = submesh.get_lod(lod);
+                self.device.cmd_draw_indexed(
+                    cmd_buf,
+                    range.index_count,
+                    1,
+                    range.first_index,
+                    submesh.vertex_offset,
+                    0,
+                );
+            }
+        }
+    }
+
+    pub fn has_attribute(&self, attribute: MeshAttribute) -> bool {
+        self.attributes.contains(&attribute)
+    }
+}
+
+// A device-local buffer in the buffer list, with the upload of the data queued
+fn new_mesh_buffer<T>(
+    name: &str,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+    gpu: &Gpu,
+    buffer_list: &mut BufferList,
+    debug_utils: &DebugUtils,
+) -> Result<BufferHandle, String> {
+    let buffer_handle = buffer_list.new_device_local_buffer(
+        name,
+        std::mem::size_of_val(data),
+        usage,
+        gpu,
+        debug_utils,
+    )?;
+    // Device-local buffers don't have copies per frame
+    buffer_list.upload_data(buffer_handle, 0, data);
+    Ok(buffer_handle)
+}
+
+/// Read the triangle primitives of the given glTF meshes, and the default
+/// morph target weights of the first mesh that has them. The primitives that
+/// are skipped are added to the warnings.
+fn read_gltf_primitives<'a>(
+    gltf_meshes: impl Iterator<Item = gltf::Mesh<'a>>,
+    buffers: &[gltf::buffer::Data],
+    warnings: &mut Vec<String>,
+) -> (Vec<PrimitiveData>, Vec<f32>) {
+    let mut primitives: Vec<PrimitiveData> = Vec::new();
+    let mut opt_default_morph_weights: Option<Vec<f32>> = None;
+    for mesh in gltf_meshes {
+        if opt_default_morph_weights.is_none() {
+            opt_default_morph_weights = mesh.weights().map(|weights| weights.to_vec());
+        }
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warnings.push(format!(
+                    "Skipped primitive {} of mesh {}, only triangles are supported.",
+                    primitive.index(),
+                    mesh.index()
+                ));
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = match reader.read_positions() {
+                Some(iter) => iter.collect(),
+                None => continue, // Nothing to draw
+            };
+            let indices = match reader.read_indices() {
+                Some(iter) => iter.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let mut data = PrimitiveData {
+                indices,
+                opt_tex_coords_0: reader
+                    .read_tex_coords(0)
+                    .map(|iter| iter.into_f32().collect()),
+                opt_tex_coords_1: reader
+                    .read_tex_coords(1)
+                    .map(|iter| iter.into_f32().collect()),
+                opt_tangents: reader.read_tangents().map(|iter| iter.collect()),
+                opt_colors_0: reader
+                    .read_colors(0)
+                    .map(|iter| iter.into_rgba_f32().collect()),
+                opt_joints_0: reader.read_joints(0).map(|iter| {
+                    iter.into_u16()
+                        .map(|j| [j[0] as u32, j[1] as u32, j[2] as u32, j[3] as u32])
+                        .collect()
+                }),
+                opt_weights_0: reader.read_weights(0).map(|iter| iter.into_f32().collect()),
+                material_index: primitive.material().index(),
+                morph_targets: reader
+                    .read_morph_targets()
+                    .map(|(opt_positions, opt_normals, opt_tangents)| {
+                        let vertex_count = positions.len();
+                        MorphTargetData {
+                            position_deltas: opt_positions
+                                .map_or(vec![[0.0; 3]; vertex_count], |iter| iter.collect()),
+                            normal_deltas: opt_normals
+                                .map_or(vec![[0.0; 3]; vertex_count], |iter| iter.collect()),
+                            tangent_deltas: opt_tangents
+                                .map_or(vec![[0.0; 3]; vertex_count], |iter| iter.collect()),
+                        }
+                    })
+                    .collect(),
+                positions,
+                ..Default::default()
+            };
+            if let Some(iter) = reader.read_normals() {
+                data.normals = iter.collect();
+            }
+            // The position accessor usually has its bounds, which saves a pass
+            if let Some(accessor) = primitive.get(&gltf::Semantic::Positions) {
+                if let (Some(min), Some(max)) = (accessor.min(), accessor.max()) {
+                    if let (Some(min), Some(max)) = (get_json_vec3(&min), get_json_vec3(&max)) {
+                        data.opt_aabb = Some(Aabb { min, max });
+                    }
+                }
+            }
+            data.generate_missing_attributes(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+            primitives.push(data);
         }
     }
+    (primitives, opt_default_morph_weights.unwrap_or_default())
+}
+
+fn get_json_vec3(value: &gltf::json::Value) -> Option<Vec3> {
+    let array = value.as_array()?;
+    if array.len() != 3 {
+        return None;
+    }
+    Some(Vec3::new(
+        array[0].as_f64()? as f32,
+        array[1].as_f64()? as f32,
+        array[2].as_f64()? as f32,
+    ))
+}
+
+/// A single interleaved per-vertex binding
+fn get_vertex_layout(attributes: &[MeshAttribute]) -> VertexLayout {
+    let mut vertex_attributes = Vec::new();
+    let mut offset = 0;
+    for attribute in attributes {
+        vertex_attributes.push(VertexAttribute {
+            location: attribute.location(),
+            binding: 0,
+            format: attribute.format(),
+            offset,
+        });
+        offset += 4 * attribute.component_count() as u32;
+    }
+
+    VertexLayout {
+        bindings: vec![VertexBinding {
+            binding: 0,
+            stride: offset,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }],
+        attributes: vertex_attributes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit quad in the xy plane, made of two counter-clockwise triangles,
+    // with tex coords that follow x and y, or mirrored ones that go against x
+    fn quad(mirrored: bool) -> PrimitiveData {
+        let positions = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let tex_coords = positions
+            .iter()
+            .map(|p: &[f32; 3]| [if mirrored { 1.0 - p[0] } else { p[0] }, p[1]])
+            .collect();
+        PrimitiveData {
+            positions,
+            opt_tex_coords_0: Some(tex_coords),
+            indices: vec![0, 1, 2, 0, 2, 3],
+            ..Default::default()
+        }
+    }
+
+    // A triangle with normals, offset along x
+    fn triangle(x: f32, opt_material_index: Option<usize>) -> PrimitiveData {
+        PrimitiveData {
+            positions: vec![[x, 0.0, 0.0], [x + 1.0, 0.0, 0.0], [x, 1.0, 0.0]],
+            normals: vec![[0.0, 0.0, 1.0]; 3],
+            indices: vec![0, 1, 2],
+            material_index: opt_material_index,
+            ..Default::default()
+        }
+    }
+
+    fn bake(primitives: Vec<PrimitiveData>) -> BakedMesh {
+        BakedMesh::from_primitives(
+            primitives,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+        )
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn flat_normals_are_perpendicular_to_the_faces() {
+        let mut quad = quad(false);
+        quad.generate_flat_normals();
+
+        // Every triangle gets its own vertices
+        assert_eq!(quad.positions.len(), 6);
+        assert_eq!(quad.normals.len(), 6);
+        assert_eq!(quad.indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(quad.opt_tex_coords_0.as_ref().unwrap().len(), 6);
+        assert_eq!(quad.opt_tex_coords_0.as_ref().unwrap()[4], [1.0, 1.0]);
+
+        for (triangle, normals) in quad.positions.chunks(3).zip(quad.normals.chunks(3)) {
+            let edge_1 = Vec3::from(triangle[1]) - Vec3::from(triangle[0]);
+            let edge_2 = Vec3::from(triangle[2]) - Vec3::from(triangle[0]);
+            for normal in normals {
+                let normal = Vec3::from(*normal);
+                assert!(normal.dot(edge_1).abs() < 1e-5);
+                assert!(normal.dot(edge_2).abs() < 1e-5);
+                // Counter-clockwise triangles face the viewer
+                assert_near(normal, Vec3::unit_z());
+            }
+        }
+    }
+
+    #[test]
+    fn flat_normals_replace_the_tangents() {
+        let mut quad = quad(false);
+        quad.opt_tangents = Some(vec![[0.0, 1.0, 0.0, 1.0]; 4]);
+        quad.generate_flat_normals();
+        assert!(quad.opt_tangents.is_none());
+    }
+
+    #[test]
+    fn tangents_follow_the_tex_coords() {
+        let mut quad = quad(false);
+        quad.generate_missing_attributes(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let tangents = quad.opt_tangents.unwrap();
+        assert_eq!(tangents.len(), quad.positions.len());
+        for (tangent, normal) in tangents.iter().zip(&quad.normals) {
+            let t = Vec3::new(tangent[0], tangent[1], tangent[2]);
+            assert!(t.dot(Vec3::from(*normal)).abs() < 1e-5);
+            assert_near(t, Vec3::unit_x());
+            // The bitangent, normal x tangent, follows v
+            assert_eq!(tangent[3], 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_tex_coords_flip_the_handedness() {
+        let mut quad = quad(true);
+        quad.generate_missing_attributes(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        for tangent in quad.opt_tangents.unwrap() {
+            assert_near(
+                Vec3::new(tangent[0], tangent[1], tangent[2]),
+                -Vec3::unit_x(),
+            );
+            // v still follows y, but normal x tangent is now -y
+            assert_eq!(tangent[3], -1.0);
+        }
+    }
+
+    #[test]
+    fn tangents_need_tex_coords() {
+        let mut quad = quad(false);
+        quad.opt_tex_coords_0 = None;
+        quad.generate_missing_attributes(vk::PrimitiveTopology::TRIANGLE_LIST);
+        assert_eq!(quad.normals.len(), 6);
+        assert!(quad.opt_tangents.is_none());
+    }
+
+    #[test]
+    fn primitives_become_submeshes() {
+        let mut quad = quad(false);
+        quad.normals = vec![[0.0, 0.0, 1.0]; 4];
+        quad.material_index = Some(1);
+        let baked_mesh = bake(vec![triangle(0.0, None), quad, triangle(2.0, Some(0))]);
+
+        // Indices stay relative to the first vertex of their primitive
+        assert_eq!(baked_mesh.indices, vec![0, 1, 2, 0, 1, 2, 0, 2, 3, 0, 1, 2]);
+        let ranges: Vec<(u32, u32, i32, Option<usize>)> = baked_mesh
+            .submeshes
+            .iter()
+            .map(|s| {
+                (
+                    s.first_index,
+                    s.index_count,
+                    s.vertex_offset,
+                    s.material_index,
+                )
+            })
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(0, 3, 0, None), (3, 6, 3, Some(1)), (9, 3, 7, Some(0))]
+        );
+        assert_eq!(baked_mesh.vertex_count, 10);
+
+        let last = &baked_mesh.submeshes[2];
+        assert_eq!(last.aabb.min, Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(last.aabb.max, Vec3::new(3.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn vertices_get_every_attribute_of_the_mesh() {
+        // Only the quad has tex coords, so the triangle gets zero ones
+        let mut quad = quad(false);
+        quad.normals = vec![[0.0, 0.0, 1.0]; 4];
+        let baked_mesh = bake(vec![triangle(0.0, None), quad]);
+
+        assert_eq!(
+            baked_mesh.attributes,
+            vec![
+                MeshAttribute::Position,
+                MeshAttribute::Normal,
+                MeshAttribute::TexCoord0
+            ]
+        );
+        let floats_per_vertex = 3 + 3 + 2;
+        assert_eq!(baked_mesh.vertices.len(), 7 * floats_per_vertex);
+        assert_eq!(
+            baked_mesh.vertices[floats_per_vertex..2 * floats_per_vertex],
+            [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            baked_mesh.vertices[5 * floats_per_vertex..6 * floats_per_vertex],
+            [1.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn lods_fall_back_to_the_coarsest_one() {
+        let mut submesh = bake(vec![triangle(0.0, None)]).submeshes.remove(0);
+        let full = SubmeshLod {
+            first_index: 0,
+            index_count: 3,
+        };
+        assert_eq!(submesh.get_lod(0), full);
+        // Without LODs, every LOD is the full-detail one
+        assert_eq!(submesh.get_lod(2), full);
+
+        let lods = vec![
+            SubmeshLod {
+                first_index: 3,
+                index_count: 6,
+            },
+            SubmeshLod {
+                first_index: 9,
+                index_count: 3,
+            },
+        ];
+        submesh.lods = lods.clone();
+        assert_eq!(submesh.get_lod(0), full);
+        assert_eq!(submesh.get_lod(1), lods[0]);
+        assert_eq!(submesh.get_lod(2), lods[1]);
+        assert_eq!(submesh.get_lod(5), lods[1]);
+    }
+
+    #[test]
+    fn morph_targets_are_laid_out_target_by_target() {
+        // The first triangle has two targets, the second only one
+        let morph_target = |delta: f32| MorphTargetData {
+            position_deltas: vec![[delta, 0.0, 0.0]; 3],
+            normal_deltas: vec![[0.0, delta, 0.0]; 3],
+            tangent_deltas: vec![[0.0, 0.0, delta]; 3],
+        };
+        let mut first = triangle(0.0, None);
+        first.morph_targets = vec![morph_target(1.0), morph_target(2.0)];
+        let mut second = triangle(2.0, None);
+        second.morph_targets = vec![morph_target(3.0)];
+        let baked_mesh = BakedMesh::from_primitives(
+            vec![first, second],
+            Vec::new(),
+            Vec::new(),
+            vec![0.5],
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+        );
+
+        assert_eq!(baked_mesh.morph_target_count, 2);
+        // Weights the file doesn't have are 0
+        assert_eq!(baked_mesh.default_morph_weights, vec![0.5, 0.0]);
+        let vertex_count = baked_mesh.vertex_count;
+        assert_eq!(baked_mesh.morph_target_deltas.len(), 2 * vertex_count * 3);
+
+        let get_deltas = |target: usize, vertex: usize| {
+            let start = (target * vertex_count + vertex) * 3;
+            &baked_mesh.morph_target_deltas[start..start + 3]
+        };
+        assert_eq!(
+            get_deltas(0, 2),
+            [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0]
+            ]
+        );
+        assert_eq!(get_deltas(0, 3)[0], [3.0, 0.0, 0.0, 0.0]);
+        assert_eq!(get_deltas(1, 0)[1], [0.0, 2.0, 0.0, 0.0]);
+        // The second triangle doesn't move with the second target
+        assert_eq!(get_deltas(1, 5), [[0.0; 4]; 3]);
+    }
+
+    #[test]
+    fn flat_normals_split_the_morph_targets_too() {
+        let mut quad = quad(false);
+        quad.morph_targets = vec![MorphTargetData {
+            position_deltas: (0..4).map(|idx| [idx as f32, 0.0, 0.0]).collect(),
+            normal_deltas: vec![[0.0; 3]; 4],
+            tangent_deltas: vec![[0.0; 3]; 4],
+        }];
+        quad.generate_flat_normals();
+
+        let position_deltas: Vec<f32> = quad.morph_targets[0]
+            .position_deltas
+            .iter()
+            .map(|delta| delta[0])
+            .collect();
+        assert_eq!(position_deltas, vec![0.0, 1.0, 2.0, 0.0, 2.0, 3.0]);
+        assert_eq!(quad.morph_targets[0].normal_deltas.len(), 6);
+    }
 }
@@ -0,0 +1,843 @@
+use crate::*;
+use glam::*;
+use std::time::UNIX_EPOCH;
+
+// Baked meshes live next to the SPIR-V of the shaders
+const MESH_CACHE_PATH: &str = "_cache/meshes";
+const MESH_CACHE_MAGIC: &[u8; 8] = b"GRAPHENE";
+// Bump whenever the layout of the file, or the way meshes are baked, changes
+const MESH_CACHE_VERSION: u32 = 4;
+
+// What a baked mesh was built from. Only the file itself is tracked: the
+// buffers, textures and MTL files it references are not, so touch the mesh
+// file to rebuild after changing those.
+#[derive(PartialEq)]
+struct MeshCacheKey {
+    source_path: String,
+    modified: (u64, u32), // Seconds and nanoseconds since the Unix epoch
+    content_hash: u64,    // Only stable for a given build of the standard library
+    processing: MeshProcessing,
+}
+
+/// Read the baked version of a mesh file from the cache, or import the file
+/// and bake it if the cache is missing or stale. A cache with a different
+/// modification time is still used if the contents of the file didn't change.
+/// Also returns the reasons the cache couldn't be read or written, which don't
+/// stop the mesh from loading.
+pub(crate) fn load_baked_mesh(
+    name: &str,
+    path: &str,
+    processing: &MeshProcessing,
+) -> Result<(BakedMesh, Vec<String>), String> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|err| format!("Failed to open mesh `{}`: {}", path, err))?
+        .duration_since(UNIX_EPOCH)
+        .map_or((0, 0), |duration| {
+            (duration.as_secs(), duration.subsec_nanos())
+        });
+    let cache_path = get_cache_path(path, processing);
+
+    let mut cache_warnings = Vec::new();
+    let mut opt_content_hash = None;
+    if let Ok(bytes) = std::fs::read(&cache_path) {
+        let mut reader = CacheReader {
+            bytes: &bytes,
+            offset: 0,
+        };
+        if let Ok(cached_key) = reader.read_key() {
+            if cached_key.source_path == path && cached_key.processing == *processing {
+                let is_fresh = cached_key.modified == modified || {
+                    let content_hash = get_content_hash(path)?;
+                    opt_content_hash = Some(content_hash);
+                    content_hash == cached_key.content_hash
+                };
+                if is_fresh {
+                    match reader.read_baked_mesh(name) {
+                        Ok(baked_mesh) => {
+                            if cached_key.modified != modified {
+                                // Remember the new time, so the file isn't hashed again
+                                let key = MeshCacheKey {
+                                    modified,
+                                    ..cached_key
+                                };
+                                if let Err(err) =
+                                    write_baked_mesh(&cache_path, &key, &baked_mesh, name)
+                                {
+                                    cache_warnings.push(err);
+                                }
+                            }
+                            return Ok((baked_mesh, cache_warnings));
+                        }
+                        Err(err) => cache_warnings.push(format!(
+                            "Rebaking `{}` from `{}`: {}",
+                            path, cache_path, err
+                        )),
+                    }
+                }
+            }
+        }
+    }
+
+    // ...The cache is missing or stale. Hash the file before importing it, so
+    // that changes made in the meantime invalidate the cache.
+    let content_hash = match opt_content_hash {
+        Some(content_hash) => content_hash,
+        None => get_content_hash(path)?,
+    };
+    let mut baked_mesh = BakedMesh::import(name, path)?;
+    baked_mesh.process(processing);
+    let key = MeshCacheKey {
+        source_path: String::from(path),
+        modified,
+        content_hash,
+        processing: *processing,
+    };
+    if let Err(err) = write_baked_mesh(&cache_path, &key, &baked_mesh, name) {
+        cache_warnings.push(err);
+    }
+    Ok((baked_mesh, cache_warnings))
+}
+
+// One file per source path and processing, readable by name when browsing
+// the cache
+fn get_cache_path(path: &str, processing: &MeshProcessing) -> String {
+    let file_stem = std::path::Path::new(path)
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    processing.hash(&mut hasher);
+    format!(
+        "{}/{}_{:016x}.mesh",
+        MESH_CACHE_PATH,
+        file_stem,
+        hasher.finish()
+    )
+}
+
+fn get_content_hash(path: &str) -> Result<u64, String> {
+    let bytes =
+        std::fs::read(path).map_err(|err| format!("Failed to open mesh `{}`: {}", path, err))?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+// A failed write only costs the next launch a rebake, so callers don't treat
+// it as a failure to load the mesh
+fn write_baked_mesh(
+    cache_path: &str,
+    key: &MeshCacheKey,
+    baked_mesh: &BakedMesh,
+    name: &str,
+) -> Result<(), String> {
+    let mut writer = CacheWriter { bytes: Vec::new() };
+    writer.write_key(key);
+    writer
+        .write_baked_mesh(baked_mesh, name)
+        .and_then(|_| std::fs::create_dir_all(MESH_CACHE_PATH).map_err(|err| err.to_string()))
+        .and_then(|_| std::fs::write(cache_path, &writer.bytes).map_err(|err| err.to_string()))
+        .map_err(|err| {
+            format!(
+                "Failed to cache mesh `{}` in `{}`: {}",
+                key.source_path, cache_path, err
+            )
+        })
+}
+
+// Little endian, with lengths before every array
+struct CacheWriter {
+    bytes: Vec<u8>,
+}
+
+impl CacheWriter {
+    fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn write_f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.write_f32(*value);
+        }
+    }
+
+    fn write_len(&mut self, len: usize) {
+        self.write_u64(len as u64);
+    }
+
+    fn write_string(&mut self, value: &str) {
+        self.write_len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_key(&mut self, key: &MeshCacheKey) {
+        self.bytes.extend_from_slice(MESH_CACHE_MAGIC);
+        self.write_u32(MESH_CACHE_VERSION);
+        self.write_string(&key.source_path);
+        self.write_u64(key.modified.0);
+        self.write_u32(key.modified.1);
+        self.write_u64(key.content_hash);
+        self.write_u8(key.processing.weld_vertices as u8);
+        self.write_u8(key.processing.optimize_vertex_cache as u8);
+        self.write_u8(key.processing.optimize_vertex_fetch as u8);
+        self.write_len(key.processing.lod_count);
+        self.write_f32(key.processing.lod_triangle_ratio);
+    }
+
+    // Textures are stored as indices into the material images, since their
+    // handles depend on the name the mesh is loaded with
+    fn write_baked_mesh(&mut self, baked_mesh: &BakedMesh, name: &str) -> Result<(), String> {
+        self.write_len(baked_mesh.attributes.len());
+        for attribute in &baked_mesh.attributes {
+            self.write_u32(attribute.location());
+        }
+        self.write_u32(baked_mesh.topology.as_raw() as u32);
+
+        self.write_len(baked_mesh.vertex_count);
+        self.write_len(baked_mesh.vertices.len());
+        self.write_f32s(&baked_mesh.vertices);
+        self.write_len(baked_mesh.indices.len());
+        for index in &baked_mesh.indices {
+            self.write_u32(*index);
+        }
+        self.write_len(baked_mesh.submeshes.len());
+        for submesh in &baked_mesh.submeshes {
+            self.write_u32(submesh.first_index);
+            self.write_u32(submesh.index_count);
+            self.write_u32(submesh.vertex_offset as u32);
+            self.write_opt_index(submesh.material_index);
+            self.write_len(submesh.lods.len());
+            for lod in &submesh.lods {
+                self.write_u32(lod.first_index);
+                self.write_u32(lod.index_count);
+            }
+            self.write_f32s(submesh.aabb.min.as_ref());
+            self.write_f32s(submesh.aabb.max.as_ref());
+            self.write_f32s(submesh.bounding_sphere.center.as_ref());
+            self.write_f32(submesh.bounding_sphere.radius);
+        }
+        self.write_len(baked_mesh.lod_errors.len());
+        self.write_f32s(&baked_mesh.lod_errors);
+        self.write_len(baked_mesh.import_warnings.len());
+        for warning in &baked_mesh.import_warnings {
+            self.write_string(warning);
+        }
+
+        self.write_len(baked_mesh.morph_target_count);
+        self.write_len(baked_mesh.morph_target_deltas.len());
+        for delta in &baked_mesh.morph_target_deltas {
+            self.write_f32s(delta);
+        }
+        self.write_len(baked_mesh.default_morph_weights.len());
+        self.write_f32s(&baked_mesh.default_morph_weights);
+
+        self.write_len(baked_mesh.material_images.len());
+        for material_image in &baked_mesh.material_images {
+            self.write_string(&material_image.suffix);
+            self.write_u32(material_image.width);
+            self.write_u32(material_image.height);
+            self.write_u8(material_image.is_srgb as u8);
+            self.write_len(material_image.pixels.len());
+            self.bytes.extend_from_slice(&material_image.pixels);
+        }
+        let image_handles: Vec<ImageHandle> = baked_mesh
+            .material_images
+            .iter()
+            .map(|material_image| material_image.handle(name))
+            .collect();
+
+        self.write_len(baked_mesh.materials.len());
+        for material in &baked_mesh.materials {
+            self.write_string(&material.name);
+            self.write_f32s(&<[f32; 4]>::from(material.base_color_factor));
+            self.write_texture(material.opt_base_color_texture, &image_handles)?;
+            self.write_f32(material.metallic_factor);
+            self.write_f32(material.roughness_factor);
+            self.write_texture(material.opt_metallic_roughness_texture, &image_handles)?;
+            self.write_f32(material.normal_scale);
+            self.write_texture(material.opt_normal_texture, &image_handles)?;
+            self.write_f32(material.occlusion_strength);
+            self.write_texture(material.opt_occlusion_texture, &image_handles)?;
+            self.write_f32s(&<[f32; 3]>::from(material.emissive_factor));
+            self.write_texture(material.opt_emissive_texture, &image_handles)?;
+            match material.alpha_mode {
+                AlphaMode::Opaque => self.write_u8(0),
+                AlphaMode::Mask { cutoff } => {
+                    self.write_u8(1);
+                    self.write_f32(cutoff);
+                }
+                AlphaMode::Blend => self.write_u8(2),
+            }
+            self.write_u8(material.double_sided as u8);
+        }
+        Ok(())
+    }
+
+    fn write_opt_index(&mut self, opt_index: Option<usize>) {
+        match opt_index {
+            Some(index) => {
+                self.write_u8(1);
+                self.write_len(index);
+            }
+            None => self.write_u8(0),
+        }
+    }
+
+    fn write_texture(
+        &mut self,
+        opt_texture: Option<MaterialTexture>,
+        image_handles: &[ImageHandle],
+    ) -> Result<(), String> {
+        let opt_image_idx = match opt_texture {
+            Some(texture) => Some(
+                image_handles
+                    .iter()
+                    .position(|handle| *handle == texture.image)
+                    .ok_or("A material uses an image that isn't part of the mesh.")?,
+            ),
+            None => None,
+        };
+        self.write_opt_index(opt_image_idx);
+        if let Some(texture) = opt_texture {
+            self.write_u32(texture.tex_coord);
+        }
+        Ok(())
+    }
+}
+
+struct CacheReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.offset < len {
+            return Err(String::from("The cached mesh is truncated."));
+        }
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, String> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    fn read_f32s<T: Default + AsMut<[f32]>>(&mut self) -> Result<T, String> {
+        let mut values = T::default();
+        for value in values.as_mut() {
+            *value = self.read_f32()?;
+        }
+        Ok(values)
+    }
+
+    // Lengths are checked against the remaining bytes, so that a corrupt
+    // file can't make us allocate wildly
+    fn read_len(&mut self, element_size: usize) -> Result<usize, String> {
+        let len = self.read_u64()? as usize;
+        if len.saturating_mul(element_size) > self.bytes.len() - self.offset {
+            return Err(String::from("The cached mesh is truncated."));
+        }
+        Ok(len)
+    }
+
+    fn read_string(&mut self) -> Result<String, String> {
+        let len = self.read_len(1)?;
+        String::from_utf8(self.read_bytes(len)?.to_vec())
+            .map_err(|_| String::from("The cached mesh has an invalid string."))
+    }
+
+    fn read_key(&mut self) -> Result<MeshCacheKey, String> {
+        if self.read_bytes(MESH_CACHE_MAGIC.len())? != MESH_CACHE_MAGIC {
+            return Err(String::from("The file isn't a cached mesh."));
+        }
+        if self.read_u32()? != MESH_CACHE_VERSION {
+            return Err(String::from("The cached mesh is from another version."));
+        }
+        Ok(MeshCacheKey {
+            source_path: self.read_string()?,
+            modified: (self.read_u64()?, self.read_u32()?),
+            content_hash: self.read_u64()?,
+            processing: MeshProcessing {
+                weld_vertices: self.read_u8()? != 0,
+                optimize_vertex_cache: self.read_u8()? != 0,
+                optimize_vertex_fetch: self.read_u8()? != 0,
+                lod_count: self.read_u64()? as usize,
+                lod_triangle_ratio: self.read_f32()?,
+            },
+        })
+    }
+
+    fn read_baked_mesh(&mut self, name: &str) -> Result<BakedMesh, String> {
+        let attribute_count = self.read_len(4)?;
+        let mut attributes = Vec::with_capacity(attribute_count);
+        for _ in 0..attribute_count {
+            let location = self.read_u32()?;
+            attributes.push(
+                *MeshAttribute::ALL
+                    .iter()
+                    .find(|attribute| attribute.location() == location)
+                    .ok_or("The cached mesh has an unknown attribute.")?,
+            );
+        }
+        let topology = vk::PrimitiveTopology::from_raw(self.read_u32()? as i32);
+
+        let vertex_count = self.read_u64()? as usize;
+        let float_count = self.read_len(4)?;
+        let mut vertices = Vec::with_capacity(float_count);
+        for _ in 0..float_count {
+            vertices.push(self.read_f32()?);
+        }
+        let index_count = self.read_len(4)?;
+        let mut indices = Vec::with_capacity(index_count);
+        for _ in 0..index_count {
+            indices.push(self.read_u32()?);
+        }
+        let submesh_count = self.read_len(61)?;
+        let mut submeshes = Vec::with_capacity(submesh_count);
+        for _ in 0..submesh_count {
+            let first_index = self.read_u32()?;
+            let index_count = self.read_u32()?;
+            let vertex_offset = self.read_u32()? as i32;
+            let material_index = self.read_opt_index()?;
+            let lod_count = self.read_len(8)?;
+            let mut lods = Vec::with_capacity(lod_count);
+            for _ in 0..lod_count {
+                lods.push(SubmeshLod {
+                    first_index: self.read_u32()?,
+                    index_count: self.read_u32()?,
+                });
+            }
+            let aabb = Aabb {
+                min: Vec3::from(self.read_f32s::<[f32; 3]>()?),
+                max: Vec3::from(self.read_f32s::<[f32; 3]>()?),
+            };
+            let bounding_sphere = BoundingSphere {
+                center: Vec3::from(self.read_f32s::<[f32; 3]>()?),
+                radius: self.read_f32()?,
+            };
+            submeshes.push(Submesh {
+                first_index,
+                index_count,
+                vertex_offset,
+                material_index,
+                lods,
+                aabb,
+                bounding_sphere,
+            });
+        }
+        let lod_error_count = self.read_len(4)?;
+        let mut lod_errors = Vec::with_capacity(lod_error_count);
+        for _ in 0..lod_error_count {
+            lod_errors.push(self.read_f32()?);
+        }
+        let warning_count = self.read_len(8)?;
+        let mut import_warnings = Vec::with_capacity(warning_count);
+        for _ in 0..warning_count {
+            import_warnings.push(self.read_string()?);
+        }
+
+        let morph_target_count = self.read_u64()? as usize;
+        let delta_count = self.read_len(16)?;
+        let mut morph_target_deltas = Vec::with_capacity(delta_count);
+        for _ in 0..delta_count {
+            morph_target_deltas.push(self.read_f32s::<[f32; 4]>()?);
+        }
+        let weight_count = self.read_len(4)?;
+        let mut default_morph_weights = Vec::with_capacity(weight_count);
+        for _ in 0..weight_count {
+            default_morph_weights.push(self.read_f32()?);
+        }
+
+        let image_count = self.read_len(1)?;
+        let mut material_images = Vec::with_capacity(image_count);
+        for _ in 0..image_count {
+            let suffix = self.read_string()?;
+            let width = self.read_u32()?;
+            let height = self.read_u32()?;
+            let is_srgb = self.read_u8()? != 0;
+            let pixel_len = self.read_len(1)?;
+            material_images.push(MaterialImage {
+                suffix,
+                width,
+                height,
+                is_srgb,
+                pixels: self.read_bytes(pixel_len)?.to_vec(),
+            });
+        }
+        let image_handles: Vec<ImageHandle> = material_images
+            .iter()
+            .map(|material_image| material_image.handle(name))
+            .collect();
+
+        let material_count = self.read_len(1)?;
+        let mut materials = Vec::with_capacity(material_count);
+        for _ in 0..material_count {
+            materials.push(Material {
+                name: self.read_string()?,
+                base_color_factor: Vec4::from(self.read_f32s::<[f32; 4]>()?),
+                opt_base_color_texture: self.read_texture(&image_handles)?,
+                metallic_factor: self.read_f32()?,
+                roughness_factor: self.read_f32()?,
+                opt_metallic_roughness_texture: self.read_texture(&image_handles)?,
+                normal_scale: self.read_f32()?,
+                opt_normal_texture: self.read_texture(&image_handles)?,
+                occlusion_strength: self.read_f32()?,
+                opt_occlusion_texture: self.read_texture(&image_handles)?,
+                emissive_factor: Vec3::from(self.read_f32s::<[f32; 3]>()?),
+                opt_emissive_texture: self.read_texture(&image_handles)?,
+                alpha_mode: match self.read_u8()? {
+                    0 => AlphaMode::Opaque,
+                    1 => AlphaMode::Mask {
+                        cutoff: self.read_f32()?,
+                    },
+                    2 => AlphaMode::Blend,
+                    _ => return Err(String::from("The cached mesh has an unknown alpha mode.")),
+                },
+                double_sided: self.read_u8()? != 0,
+            });
+        }
+
+        if self.offset != self.bytes.len() {
+            return Err(String::from("The cached mesh has trailing bytes."));
+        }
+        Ok(BakedMesh {
+            attributes,
+            topology,
+            vertices,
+            indices,
+            submeshes,
+            vertex_count,
+            morph_target_count,
+            morph_target_deltas,
+            default_morph_weights,
+            materials,
+            material_images,
+            lod_errors,
+            import_warnings,
+        })
+    }
+
+    fn read_opt_index(&mut self) -> Result<Option<usize>, String> {
+        match self.read_u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.read_u64()? as usize)),
+        }
+    }
+
+    fn read_texture(
+        &mut self,
+        image_handles: &[ImageHandle],
+    ) -> Result<Option<MaterialTexture>, String> {
+        match self.read_opt_index()? {
+            Some(image_idx) => Ok(Some(MaterialTexture {
+                image: *image_handles
+                    .get(image_idx)
+                    .ok_or("A cached material uses an image that doesn't exist.")?,
+                tex_coord: self.read_u32()?,
+            })),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "cached";
+
+    fn key() -> MeshCacheKey {
+        MeshCacheKey {
+            source_path: String::from("assets/meshes/cached.glb"),
+            modified: (1_600_000_000, 123),
+            content_hash: 0x0123_4567_89ab_cdef,
+            processing: MeshProcessing {
+                weld_vertices: true,
+                optimize_vertex_cache: false,
+                optimize_vertex_fetch: true,
+                lod_count: 2,
+                lod_triangle_ratio: 0.5,
+            },
+        }
+    }
+
+    fn baked_mesh() -> BakedMesh {
+        let material_images = vec![MaterialImage {
+            suffix: String::from("material_0_base_color"),
+            width: 1,
+            height: 2,
+            is_srgb: true,
+            pixels: vec![1, 2, 3, 4, 5, 6, 7, 8],
+        }];
+        let image = material_images[0].handle(NAME);
+        BakedMesh {
+            attributes: vec![MeshAttribute::Position, MeshAttribute::TexCoord0],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            vertices: (0..15).map(|i| i as f32 * 0.5).collect(),
+            indices: vec![0, 1, 2, 2, 1, 0],
+            submeshes: vec![Submesh {
+                first_index: 0,
+                index_count: 3,
+                vertex_offset: 0,
+                material_index: Some(1),
+                lods: vec![SubmeshLod {
+                    first_index: 3,
+                    index_count: 3,
+                }],
+                aabb: Aabb {
+                    min: Vec3::new(-1.0, -2.0, -3.0),
+                    max: Vec3::new(1.0, 2.0, 3.0),
+                },
+                bounding_sphere: BoundingSphere {
+                    center: Vec3::new(0.0, 0.5, 0.0),
+                    radius: 4.0,
+                },
+            }],
+            vertex_count: 3,
+            morph_target_count: 1,
+            morph_target_deltas: vec![[0.25; 4]; 9],
+            default_morph_weights: vec![0.75],
+            materials: vec![
+                Material {
+                    name: String::from("masked"),
+                    alpha_mode: AlphaMode::Mask { cutoff: 0.25 },
+                    ..Default::default()
+                },
+                Material {
+                    name: String::from("textured"),
+                    base_color_factor: Vec4::new(0.1, 0.2, 0.3, 0.4),
+                    opt_base_color_texture: Some(MaterialTexture {
+                        image,
+                        tex_coord: 0,
+                    }),
+                    opt_emissive_texture: Some(MaterialTexture {
+                        image,
+                        tex_coord: 1,
+                    }),
+                    emissive_factor: Vec3::new(1.0, 0.5, 0.0),
+                    alpha_mode: AlphaMode::Blend,
+                    double_sided: true,
+                    ..Default::default()
+                },
+            ],
+            material_images,
+            lod_errors: vec![0.0, 0.125],
+            import_warnings: vec![String::from("Skipped a line.")],
+        }
+    }
+
+    fn write(key: &MeshCacheKey, baked_mesh: &BakedMesh) -> Vec<u8> {
+        let mut writer = CacheWriter { bytes: Vec::new() };
+        writer.write_key(key);
+        writer.write_baked_mesh(baked_mesh, NAME).unwrap();
+        writer.bytes
+    }
+
+    fn read(bytes: &[u8]) -> Result<(MeshCacheKey, BakedMesh), String> {
+        let mut reader = CacheReader { bytes, offset: 0 };
+        let key = reader.read_key()?;
+        let baked_mesh = reader.read_baked_mesh(NAME)?;
+        Ok((key, baked_mesh))
+    }
+
+    #[test]
+    fn round_trip() {
+        let original = baked_mesh();
+        let (read_key, read_mesh) = read(&write(&key(), &original)).unwrap();
+        assert!(read_key == key());
+
+        assert_eq!(read_mesh.attributes, original.attributes);
+        assert_eq!(read_mesh.topology, original.topology);
+        assert_eq!(read_mesh.vertices, original.vertices);
+        assert_eq!(read_mesh.indices, original.indices);
+        assert_eq!(read_mesh.vertex_count, original.vertex_count);
+        assert_eq!(read_mesh.morph_target_count, original.morph_target_count);
+        assert_eq!(read_mesh.morph_target_deltas, original.morph_target_deltas);
+        assert_eq!(
+            read_mesh.default_morph_weights,
+            original.default_morph_weights
+        );
+        assert_eq!(read_mesh.lod_errors, original.lod_errors);
+        assert_eq!(read_mesh.import_warnings, original.import_warnings);
+
+        assert_eq!(read_mesh.submeshes.len(), 1);
+        let (read_submesh, submesh) = (&read_mesh.submeshes[0], &original.submeshes[0]);
+        assert_eq!(read_submesh.first_index, submesh.first_index);
+        assert_eq!(read_submesh.index_count, submesh.index_count);
+        assert_eq!(read_submesh.vertex_offset, submesh.vertex_offset);
+        assert_eq!(read_submesh.material_index, submesh.material_index);
+        assert_eq!(read_submesh.lods, submesh.lods);
+        assert_eq!(read_submesh.aabb, submesh.aabb);
+        assert_eq!(read_submesh.bounding_sphere, submesh.bounding_sphere);
+
+        assert_eq!(read_mesh.material_images.len(), 1);
+        let (read_image, image) = (&read_mesh.material_images[0], &original.material_images[0]);
+        assert_eq!(read_image.suffix, image.suffix);
+        assert_eq!(
+            (read_image.width, read_image.height),
+            (image.width, image.height)
+        );
+        assert_eq!(read_image.is_srgb, image.is_srgb);
+        assert_eq!(read_image.pixels, image.pixels);
+
+        assert_eq!(read_mesh.materials.len(), 2);
+        for (read_material, material) in read_mesh.materials.iter().zip(&original.materials) {
+            assert_eq!(read_material.name, material.name);
+            assert_eq!(read_material.base_color_factor, material.base_color_factor);
+            assert_eq!(
+                read_material.opt_base_color_texture,
+                material.opt_base_color_texture
+            );
+            assert_eq!(read_material.metallic_factor, material.metallic_factor);
+            assert_eq!(read_material.roughness_factor, material.roughness_factor);
+            assert_eq!(
+                read_material.opt_normal_texture,
+                material.opt_normal_texture
+            );
+            assert_eq!(read_material.emissive_factor, material.emissive_factor);
+            assert_eq!(
+                read_material.opt_emissive_texture,
+                material.opt_emissive_texture
+            );
+            assert_eq!(read_material.alpha_mode, material.alpha_mode);
+            assert_eq!(read_material.double_sided, material.double_sided);
+        }
+    }
+
+    #[test]
+    fn truncation() {
+        let bytes = write(&key(), &baked_mesh());
+        for len in 0..bytes.len() {
+            match read(&bytes[..len]) {
+                Ok(_) => panic!("A cache cut at {} of {} bytes was read.", len, bytes.len()),
+                Err(err) => assert_eq!(err, "The cached mesh is truncated."),
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut bytes = write(&key(), &baked_mesh());
+        bytes.push(0);
+        assert_eq!(
+            read(&bytes).err().unwrap(),
+            "The cached mesh has trailing bytes."
+        );
+    }
+
+    #[test]
+    fn corrupt_lengths() {
+        // A vertex count far larger than the file is rejected before allocating
+        let mut bytes = write(&key(), &baked_mesh());
+        let key_len = {
+            let mut writer = CacheWriter { bytes: Vec::new() };
+            writer.write_key(&key());
+            writer.bytes.len()
+        };
+        // After the attributes and the topology
+        let float_count_offset = key_len + 8 + 2 * 4 + 4 + 8;
+        assert_eq!(
+            bytes[float_count_offset..float_count_offset + 8],
+            15_u64.to_le_bytes()
+        );
+        bytes[float_count_offset..float_count_offset + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(read(&bytes).err().unwrap(), "The cached mesh is truncated.");
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut bytes = write(&key(), &baked_mesh());
+        bytes[MESH_CACHE_MAGIC.len()] ^= 0xff;
+        assert!(read(&bytes).is_err());
+
+        let mut bytes = write(&key(), &baked_mesh());
+        bytes[0] = b'X';
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn textures_must_be_part_of_the_mesh() {
+        let mut mesh = baked_mesh();
+        mesh.material_images.clear();
+        let mut writer = CacheWriter { bytes: Vec::new() };
+        assert!(writer.write_baked_mesh(&mesh, NAME).is_err());
+    }
+
+    #[test]
+    fn cache_paths_depend_on_the_path_and_processing() {
+        let processing = key().processing;
+        let cache_path = get_cache_path("assets/meshes/cached.glb", &processing);
+        assert!(
+            cache_path.starts_with("_cache/meshes/cached_"),
+            "{}",
+            cache_path
+        );
+        assert!(cache_path.ends_with(".mesh"), "{}", cache_path);
+        assert_eq!(
+            cache_path,
+            get_cache_path("assets/meshes/cached.glb", &processing)
+        );
+
+        assert_ne!(
+            cache_path,
+            get_cache_path("assets/other/cached.glb", &processing)
+        );
+        let other_processing = MeshProcessing {
+            lod_count: 3,
+            ..processing
+        };
+        assert_ne!(
+            cache_path,
+            get_cache_path("assets/meshes/cached.glb", &other_processing)
+        );
+        assert_ne!(
+            cache_path,
+            get_cache_path("assets/meshes/cached.glb", &MeshProcessing::NONE)
+        );
+    }
+
+    #[test]
+    fn keys_compare_every_field() {
+        assert!(key() == key());
+        let mut other = key();
+        other.modified.1 += 1;
+        assert!(other != key());
+        let mut other = key();
+        other.content_hash += 1;
+        assert!(other != key());
+        let mut other = key();
+        other.processing.weld_vertices = false;
+        assert!(other != key());
+    }
+}
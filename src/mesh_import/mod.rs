@@ -0,0 +1,4 @@
+mod obj;
+pub(crate) use obj::*;
+mod ply;
+pub(crate) use ply::*;
@@ -0,0 +1,577 @@
+use crate::*;
+use glam::*;
+use std::collections::HashMap;
+
+// Statements that don't affect how the mesh is drawn
+const IGNORED_OBJ_STATEMENTS: [&str; 5] = ["o", "g", "s", "mg", "vp"];
+// Free-form geometry, which would need to be tessellated
+const FREE_FORM_OBJ_STATEMENTS: [&str; 12] = [
+    "cstype", "deg", "bmat", "step", "curv", "curv2", "surf", "parm", "trim", "hole", "scrv", "sp",
+];
+
+// The triangles that use one material, with their vertices deduplicated
+#[derive(Default)]
+struct ObjGroup {
+    primitive: PrimitiveData,
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), u32>, // (v, vt, vn) -> index
+    has_normals: bool,                                               // All vertices have a vn
+}
+
+/// Read a Wavefront OBJ file, with one primitive per material it uses. The
+/// materials come from the MTL files it references, along with the decoded
+/// images of their textures. The statements that are skipped are added to the
+/// warnings.
+#[allow(clippy::type_complexity)]
+pub(crate) fn load_obj(
+    name: &str,
+    path: &str,
+    warnings: &mut Vec<String>,
+) -> Result<(Vec<PrimitiveData>, Vec<Material>, Vec<MaterialImage>), String> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to open mesh `{}`: {}", path, err))?;
+    let directory = std::path::Path::new(path)
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new(""));
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut opt_colors: Option<Vec<[f32; 4]>> = None; // From the `v x y z r g b` extension
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut materials: Vec<Material> = Vec::new();
+    let mut material_images: Vec<MaterialImage> = Vec::new();
+    let mut groups: Vec<(Option<usize>, ObjGroup)> = Vec::new(); // (material, group)
+    let mut opt_material_index: Option<usize> = None;
+
+    for (line_idx, line) in source.lines().enumerate() {
+        let error = |message: &str| format!("`{}` line {}: {}", path, line_idx + 1, message);
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let statement = match tokens.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+        let parse_floats = |count: usize| -> Result<Vec<f32>, String> {
+            if args.len() < count {
+                return Err(error(&format!(
+                    "`{}` needs at least {} values.",
+                    statement, count
+                )));
+            }
+            args.iter()
+                .map(|arg| {
+                    arg.parse::<f32>()
+                        .map_err(|_| error(&format!("`{}` isn't a number.", arg)))
+                })
+                .collect()
+        };
+
+        match statement {
+            "v" => {
+                let values = parse_floats(3)?;
+                positions.push([values[0], values[1], values[2]]);
+                match values.len() {
+                    3 | 4 => {
+                        if let Some(colors) = &mut opt_colors {
+                            colors.push([1.0; 4]);
+                        }
+                    }
+                    6 => {
+                        let colors =
+                            opt_colors.get_or_insert_with(|| vec![[1.0; 4]; positions.len() - 1]);
+                        colors.push([values[3], values[4], values[5], 1.0]);
+                    }
+                    _ => return Err(error("Vertices need 3, 4 or 6 values.")),
+                }
+            }
+            "vt" => {
+                let values = parse_floats(1)?;
+                // OBJ puts the origin of the texture at the bottom left
+                let v = values.get(1).copied().unwrap_or(0.0);
+                tex_coords.push([values[0], 1.0 - v]);
+            }
+            "vn" => {
+                let values = parse_floats(3)?;
+                normals.push([values[0], values[1], values[2]]);
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error("Faces need at least 3 vertices."));
+                }
+                let group_idx = match groups
+                    .iter()
+                    .position(|(material_index, _)| *material_index == opt_material_index)
+                {
+                    Some(group_idx) => group_idx,
+                    None => {
+                        let mut group = ObjGroup {
+                            has_normals: true,
+                            ..Default::default()
+                        };
+                        group.primitive.material_index = opt_material_index;
+                        groups.push((opt_material_index, group));
+                        groups.len() - 1
+                    }
+                };
+                let group = &mut groups[group_idx].1;
+
+                let mut face_indices = Vec::new();
+                for arg in &args {
+                    let key =
+                        parse_face_vertex(arg, positions.len(), tex_coords.len(), normals.len())
+                            .map_err(|message| error(&message))?;
+                    let index = match group.vertex_map.get(&key) {
+                        Some(index) => *index,
+                        None => {
+                            let (v, opt_vt, opt_vn) = key;
+                            let primitive = &mut group.primitive;
+                            let index = primitive.positions.len() as u32;
+                            primitive.positions.push(positions[v]);
+                            if let Some(colors) = &opt_colors {
+                                primitive
+                                    .opt_colors_0
+                                    .get_or_insert_with(Vec::new)
+                                    .push(colors[v]);
+                            }
+                            if let Some(vt) = opt_vt {
+                                primitive
+                                    .opt_tex_coords_0
+                                    .get_or_insert_with(Vec::new)
+                                    .push(tex_coords[vt]);
+                            }
+                            match opt_vn {
+                                Some(vn) => primitive.normals.push(normals[vn]),
+                                None => group.has_normals = false,
+                            }
+                            group.vertex_map.insert(key, index);
+                            index
+                        }
+                    };
+                    face_indices.push(index);
+                }
+                // Triangulate polygons as fans
+                for idx in 1..face_indices.len() - 1 {
+                    group.primitive.indices.extend_from_slice(&[
+                        face_indices[0],
+                        face_indices[idx],
+                        face_indices[idx + 1],
+                    ]);
+                }
+            }
+            "l" | "p" => {
+                warnings.push(error(&format!(
+                    "Skipped a {}, only faces are supported.",
+                    if statement == "l" { "line" } else { "point" }
+                )));
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(error("`mtllib` needs a file name."));
+                }
+                // File names can contain spaces
+                let mtl_path = directory.join(args.join(" "));
+                materials.extend(load_mtl(
+                    name,
+                    &mtl_path,
+                    materials.len(),
+                    &mut material_images,
+                )?);
+            }
+            "usemtl" => {
+                let material_name = args.join(" ");
+                opt_material_index = materials
+                    .iter()
+                    .position(|material| material.name == material_name);
+                if opt_material_index.is_none() {
+                    return Err(error(&format!(
+                        "Material `{}` isn't defined by the MTL files before it.",
+                        material_name
+                    )));
+                }
+            }
+            _ if IGNORED_OBJ_STATEMENTS.contains(&statement) => {}
+            _ if FREE_FORM_OBJ_STATEMENTS.contains(&statement) => {
+                return Err(error(&format!(
+                    "Free-form geometry (`{}`) isn't supported, only polygons are.",
+                    statement
+                )));
+            }
+            _ => {
+                return Err(error(&format!(
+                    "`{}` isn't a supported OBJ statement.",
+                    statement
+                )))
+            }
+        }
+    }
+
+    let primitives: Vec<PrimitiveData> = groups
+        .into_iter()
+        .map(|(_, mut group)| {
+            let vertex_count = group.primitive.positions.len();
+            // Attributes that only some of the vertices have are dropped
+            if !group.has_normals {
+                group.primitive.normals.clear();
+            }
+            if let Some(tex_coords) = &group.primitive.opt_tex_coords_0 {
+                if tex_coords.len() != vertex_count {
+                    group.primitive.opt_tex_coords_0 = None;
+                }
+            }
+            if let Some(colors) = &group.primitive.opt_colors_0 {
+                if colors.len() != vertex_count {
+                    group.primitive.opt_colors_0 = None;
+                }
+            }
+            group
+                .primitive
+                .generate_missing_attributes(vk::PrimitiveTopology::TRIANGLE_LIST);
+            group.primitive
+        })
+        .collect();
+    if primitives.is_empty() {
+        return Err(format!("Mesh `{}` doesn't have any faces.", path));
+    }
+
+    Ok((primitives, materials, material_images))
+}
+
+/// Parse a `v`, `v/vt`, `v//vn` or `v/vt/vn` face vertex into zero-based
+/// indices. Negative indices count back from the latest element.
+fn parse_face_vertex(
+    arg: &str,
+    position_count: usize,
+    tex_coord_count: usize,
+    normal_count: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let resolve = |index: &str, count: usize, kind: &str| -> Result<usize, String> {
+        let index: i64 = index
+            .parse()
+            .map_err(|_| format!("Face vertex `{}` has an invalid {} index.", arg, kind))?;
+        let resolved = if index < 0 {
+            count as i64 + index
+        } else {
+            index - 1
+        };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(format!(
+                "Face vertex `{}` refers to {} {}, but only {} are defined before it.",
+                arg, kind, index, count
+            ));
+        }
+        Ok(resolved as usize)
+    };
+
+    let parts: Vec<&str> = arg.split('/').collect();
+    if parts.len() > 3 {
+        return Err(format!("Face vertex `{}` has too many indices.", arg));
+    }
+    let v = resolve(parts[0], position_count, "position")?;
+    let opt_vt = match parts.get(1) {
+        Some(vt) if !vt.is_empty() => Some(resolve(vt, tex_coord_count, "texture coordinate")?),
+        _ => None,
+    };
+    let opt_vn = match parts.get(2) {
+        Some(vn) if !vn.is_empty() => Some(resolve(vn, normal_count, "normal")?),
+        _ => None,
+    };
+    Ok((v, opt_vt, opt_vn))
+}
+
+/// Read the materials of an MTL file, mapped to the closest metallic-roughness
+/// parameters. The `Pr` and `Pm` values of the PBR extension are used as is.
+/// The textures are decoded into `material_images`.
+fn load_mtl(
+    name: &str,
+    mtl_path: &std::path::Path,
+    first_material_idx: usize,
+    material_images: &mut Vec<MaterialImage>,
+) -> Result<Vec<Material>, String> {
+    let mtl_path_str = mtl_path.to_string_lossy();
+    let source = std::fs::read_to_string(mtl_path).map_err(|err| {
+        format!(
+            "Failed to open material library `{}`: {}",
+            mtl_path_str, err
+        )
+    })?;
+    let directory = mtl_path
+        .parent()
+        .unwrap_or_else(|| std::path::Path::new(""));
+
+    let mut materials: Vec<Material> = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let error =
+            |message: &str| format!("`{}` line {}: {}", mtl_path_str, line_idx + 1, message);
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let statement = match tokens.next() {
+            Some(statement) => statement,
+            None => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if statement == "newmtl" {
+            materials.push(Material {
+                name: args.join(" "),
+                // OBJ materials are diffuse unless they say otherwise
+                metallic_factor: 0.0,
+                ..Default::default()
+            });
+            continue;
+        }
+        let material_idx = first_material_idx + materials.len();
+        let material = materials
+            .last_mut()
+            .ok_or_else(|| error(&format!("`{}` comes before any `newmtl`.", statement)))?;
+        let parse_floats = |count: usize| -> Result<Vec<f32>, String> {
+            if args.len() < count {
+                return Err(error(&format!(
+                    "`{}` needs at least {} values.",
+                    statement, count
+                )));
+            }
+            args[..count]
+                .iter()
+                .map(|arg| {
+                    arg.parse::<f32>()
+                        .map_err(|_| error(&format!("`{}` isn't a number.", arg)))
+                })
+                .collect()
+        };
+        // Texture options such as `-bm 1.0` come before the file name
+        let mut load_texture = |is_srgb: bool| -> Result<MaterialTexture, String> {
+            let file_name = args
+                .last()
+                .ok_or_else(|| error(&format!("`{}` needs a file name.", statement)))?;
+            let image_path = directory.join(file_name);
+            let image_object = ::image::open(&image_path).map_err(|err| {
+                error(&format!(
+                    "Failed to open texture `{}`: {}",
+                    image_path.to_string_lossy(),
+                    err
+                ))
+            })?;
+            let image_object = image_object.to_rgba8();
+            let material_image = MaterialImage {
+                suffix: format!("material_{}_{}", material_idx - 1, statement),
+                width: image_object.width(),
+                height: image_object.height(),
+                is_srgb,
+                pixels: image_object.into_raw(),
+            };
+            let image = material_image.handle(name);
+            material_images.push(material_image);
+            Ok(MaterialTexture {
+                image,
+                tex_coord: 0,
+            })
+        };
+
+        match statement {
+            "Kd" => {
+                let values = parse_floats(3)?;
+                material.base_color_factor = Vec4::new(
+                    values[0],
+                    values[1],
+                    values[2],
+                    material.base_color_factor.w(),
+                );
+            }
+            "d" => {
+                let values = parse_floats(1)?;
+                material.base_color_factor.set_w(values[0]);
+            }
+            "Tr" => {
+                let values = parse_floats(1)?;
+                material.base_color_factor.set_w(1.0 - values[0]);
+            }
+            "Ke" => {
+                let values = parse_floats(3)?;
+                material.emissive_factor = Vec3::new(values[0], values[1], values[2]);
+            }
+            "Ns" => {
+                // Blinn-Phong exponent to roughness
+                let values = parse_floats(1)?;
+                material.roughness_factor = (2.0 / (values[0].max(0.0) + 2.0)).sqrt();
+            }
+            "Pr" => material.roughness_factor = parse_floats(1)?[0],
+            "Pm" => material.metallic_factor = parse_floats(1)?[0],
+            "map_Kd" => material.opt_base_color_texture = Some(load_texture(true)?),
+            "map_Ke" => material.opt_emissive_texture = Some(load_texture(true)?),
+            "norm" | "map_Bump" | "map_bump" | "bump" => {
+                material.opt_normal_texture = Some(load_texture(false)?)
+            }
+            // Phong terms and options without a metallic-roughness equivalent
+            "Ka" | "Ks" | "Tf" | "Ni" | "illum" | "map_Ka" | "map_Ks" | "map_Ns" | "map_d" => {}
+            _ => {
+                return Err(error(&format!(
+                    "`{}` isn't a supported MTL statement.",
+                    statement
+                )))
+            }
+        }
+    }
+
+    // Blending is only needed for materials that aren't opaque
+    for material in &mut materials {
+        if material.base_color_factor.w() < 1.0 {
+            material.alpha_mode = AlphaMode::Blend;
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write the files of a test into a directory of their own
+    fn write_files(test_name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("graphene_obj_{}_{}", test_name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for (file_name, contents) in files {
+            std::fs::write(directory.join(file_name), contents).unwrap();
+        }
+        directory
+    }
+
+    fn load(test_name: &str, source: &str) -> Result<Vec<PrimitiveData>, String> {
+        load_with_warnings(test_name, source).map(|(primitives, _)| primitives)
+    }
+
+    fn load_with_warnings(
+        test_name: &str,
+        source: &str,
+    ) -> Result<(Vec<PrimitiveData>, Vec<String>), String> {
+        let directory = write_files(test_name, &[("mesh.obj", source)]);
+        let path = directory.join("mesh.obj");
+        let mut warnings = Vec::new();
+        let (primitives, _, _) = load_obj("mesh", &path.to_string_lossy(), &mut warnings)?;
+        Ok((primitives, warnings))
+    }
+
+    #[test]
+    fn face_vertices() {
+        assert_eq!(parse_face_vertex("2", 3, 0, 0), Ok((1, None, None)));
+        assert_eq!(parse_face_vertex("1/2", 3, 2, 0), Ok((0, Some(1), None)));
+        assert_eq!(parse_face_vertex("1//3", 3, 0, 3), Ok((0, None, Some(2))));
+        assert_eq!(
+            parse_face_vertex("3/1/1", 3, 1, 1),
+            Ok((2, Some(0), Some(0)))
+        );
+        // Negative indices count back from the latest element
+        assert_eq!(
+            parse_face_vertex("-1/-2/-3", 3, 2, 3),
+            Ok((2, Some(0), Some(0)))
+        );
+
+        assert!(parse_face_vertex("0", 3, 0, 0).is_err());
+        assert!(parse_face_vertex("4", 3, 0, 0).is_err());
+        assert!(parse_face_vertex("-4", 3, 0, 0).is_err());
+        assert!(parse_face_vertex("1/1", 3, 0, 0).is_err());
+        assert!(parse_face_vertex("x", 3, 0, 0).is_err());
+        assert!(parse_face_vertex("1/1/1/1", 3, 1, 1).is_err());
+    }
+
+    #[test]
+    fn quads_are_triangulated_and_vertices_shared() {
+        let primitives = load(
+            "quad",
+            "# A quad and a triangle sharing an edge\n\
+             v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 0 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvt 1 0\n\
+             vn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1 4/4/1\n\
+             f 2/2/1 5/5/1 -3/3/1\n",
+        )
+        .unwrap();
+        assert_eq!(primitives.len(), 1);
+        let primitive = &primitives[0];
+        assert_eq!(primitive.positions.len(), 5);
+        assert_eq!(primitive.indices, vec![0, 1, 2, 0, 2, 3, 1, 4, 2]);
+        assert_eq!(primitive.normals, vec![[0.0, 0.0, 1.0]; 5]);
+        // The origin of the texture moves to the top left
+        assert_eq!(primitive.opt_tex_coords_0.as_ref().unwrap()[2], [1.0, 0.0]);
+        assert!(primitive.opt_tangents.is_some());
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let primitives =
+            load("colors", "v 0 0 0 1 0 0\nv 1 0 0 0 1 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let colors = primitives[0].opt_colors_0.as_ref().unwrap();
+        // Flat normals unindex the vertices
+        assert_eq!(colors.len(), 3);
+        assert_eq!(colors[0], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(colors[2], [1.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn one_primitive_per_material() {
+        let directory = write_files(
+            "materials",
+            &[
+                (
+                    "mesh.obj",
+                    "mtllib mesh.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+                     usemtl red\nf 1 2 3\nusemtl glass\nf 3 2 1\nusemtl red\nf 1 3 2\n",
+                ),
+                (
+                    "mesh.mtl",
+                    "newmtl red\nKd 1 0 0\nNs 0\nnewmtl glass\nd 0.5\nPm 1\n",
+                ),
+            ],
+        );
+        let path = directory.join("mesh.obj");
+        let (primitives, materials, material_images) =
+            load_obj("mesh", &path.to_string_lossy(), &mut Vec::new()).unwrap();
+        assert_eq!(primitives.len(), 2);
+        assert_eq!(primitives[0].material_index, Some(0));
+        assert_eq!(primitives[0].indices.len(), 6);
+        assert_eq!(primitives[1].material_index, Some(1));
+        assert_eq!(primitives[1].indices.len(), 3);
+
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].name, "red");
+        assert_eq!(
+            materials[0].base_color_factor,
+            Vec4::new(1.0, 0.0, 0.0, 1.0)
+        );
+        assert_eq!(materials[0].roughness_factor, 1.0);
+        assert_eq!(materials[0].alpha_mode, AlphaMode::Opaque);
+        assert_eq!(materials[1].metallic_factor, 1.0);
+        assert_eq!(materials[1].alpha_mode, AlphaMode::Blend);
+        assert!(material_images.is_empty());
+    }
+
+    #[test]
+    fn lines_and_points_are_skipped() {
+        let (primitives, warnings) = load_with_warnings(
+            "lines_and_points",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nl 1 2\nf 1 2 3\np 3\n",
+        )
+        .unwrap();
+        assert_eq!(primitives[0].indices.len(), 3);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("line 4"), "{}", warnings[0]);
+        assert!(warnings[1].contains("point"), "{}", warnings[1]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = load("bad_index", "v 0 0 0\nv 1 0 0\n\nf 1 2 3\n")
+            .err()
+            .unwrap();
+        assert!(err.contains("line 4"), "{}", err);
+
+        let err = load("bad_number", "v 0 zero 0\n").err().unwrap();
+        assert!(err.contains("line 1"), "{}", err);
+
+        assert!(load("too_few_values", "v 0 0\n").is_err());
+        assert!(load("undefined_material", "usemtl missing\n").is_err());
+        assert!(load("free_form", "curv 0 1 1 2\n").is_err());
+        assert!(load("no_faces", "v 0 0 0\nv 1 0 0\nv 0 1 0\n").is_err());
+    }
+}
@@ -0,0 +1,614 @@
+use crate::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum PlyScalarType {
+    Int8,
+    Uint8,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Float32,
+    Float64,
+}
+
+enum PlyPropertyKind {
+    Scalar(PlyScalarType),
+    List {
+        count_type: PlyScalarType,
+        item_type: PlyScalarType,
+    },
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyPropertyKind,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+// The vertex properties that map to mesh attributes
+#[derive(Copy, Clone, PartialEq)]
+enum PlyVertexProperty {
+    Position(usize), // Component
+    Normal(usize),
+    TexCoord(usize),
+    Color(usize),
+}
+
+impl PlyScalarType {
+    fn from_name(name: &str) -> Option<PlyScalarType> {
+        let scalar_type = match name {
+            "char" | "int8" => PlyScalarType::Int8,
+            "uchar" | "uint8" => PlyScalarType::Uint8,
+            "short" | "int16" => PlyScalarType::Int16,
+            "ushort" | "uint16" => PlyScalarType::Uint16,
+            "int" | "int32" => PlyScalarType::Int32,
+            "uint" | "uint32" => PlyScalarType::Uint32,
+            "float" | "float32" => PlyScalarType::Float32,
+            "double" | "float64" => PlyScalarType::Float64,
+            _ => return None,
+        };
+        Some(scalar_type)
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            PlyScalarType::Int8 | PlyScalarType::Uint8 => 1,
+            PlyScalarType::Int16 | PlyScalarType::Uint16 => 2,
+            PlyScalarType::Int32 | PlyScalarType::Uint32 | PlyScalarType::Float32 => 4,
+            PlyScalarType::Float64 => 8,
+        }
+    }
+
+    /// The value that integer colors are divided by to normalize them
+    fn color_scale(&self) -> f64 {
+        match self {
+            PlyScalarType::Int8 => 127.0,
+            PlyScalarType::Uint8 => 255.0,
+            PlyScalarType::Int16 => 32767.0,
+            PlyScalarType::Uint16 => 65535.0,
+            PlyScalarType::Int32 => 2_147_483_647.0,
+            PlyScalarType::Uint32 => 4_294_967_295.0,
+            PlyScalarType::Float32 | PlyScalarType::Float64 => 1.0,
+        }
+    }
+}
+
+impl PlyVertexProperty {
+    fn from_name(name: &str) -> Option<PlyVertexProperty> {
+        let vertex_property = match name {
+            "x" => PlyVertexProperty::Position(0),
+            "y" => PlyVertexProperty::Position(1),
+            "z" => PlyVertexProperty::Position(2),
+            "nx" => PlyVertexProperty::Normal(0),
+            "ny" => PlyVertexProperty::Normal(1),
+            "nz" => PlyVertexProperty::Normal(2),
+            "u" | "s" | "texture_u" | "texture_s" => PlyVertexProperty::TexCoord(0),
+            "v" | "t" | "texture_v" | "texture_t" => PlyVertexProperty::TexCoord(1),
+            "red" | "r" => PlyVertexProperty::Color(0),
+            "green" | "g" => PlyVertexProperty::Color(1),
+            "blue" | "b" => PlyVertexProperty::Color(2),
+            "alpha" | "a" => PlyVertexProperty::Color(3),
+            _ => return None,
+        };
+        Some(vertex_property)
+    }
+}
+
+// Reads the values of the body, in the order they are declared
+struct PlyReader<'a> {
+    format: PlyFormat,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> PlyReader<'a> {
+    fn read(&mut self, scalar_type: PlyScalarType) -> Result<f64, String> {
+        if self.format == PlyFormat::Ascii {
+            // Skip whitespace, then read up to the next whitespace
+            while self.offset < self.data.len() && self.data[self.offset].is_ascii_whitespace() {
+                self.offset += 1;
+            }
+            let start = self.offset;
+            while self.offset < self.data.len() && !self.data[self.offset].is_ascii_whitespace() {
+                self.offset += 1;
+            }
+            if start == self.offset {
+                return Err(String::from("The file ends before all its elements."));
+            }
+            let token = String::from_utf8_lossy(&self.data[start..self.offset]);
+            return token
+                .parse::<f64>()
+                .map_err(|_| format!("`{}` isn't a number.", token));
+        }
+
+        let size = scalar_type.size();
+        if self.offset + size > self.data.len() {
+            return Err(String::from("The file ends before all its elements."));
+        }
+        let mut bytes = [0_u8; 8];
+        bytes[..size].copy_from_slice(&self.data[self.offset..self.offset + size]);
+        if self.format == PlyFormat::BinaryBigEndian {
+            bytes[..size].reverse();
+        }
+        self.offset += size;
+
+        let value = match scalar_type {
+            PlyScalarType::Int8 => bytes[0] as i8 as f64,
+            PlyScalarType::Uint8 => bytes[0] as f64,
+            PlyScalarType::Int16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyScalarType::Uint16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            PlyScalarType::Int32 => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            PlyScalarType::Uint32 => {
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            PlyScalarType::Float32 => {
+                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            PlyScalarType::Float64 => f64::from_le_bytes(bytes),
+        };
+        Ok(value)
+    }
+}
+
+/// Read a PLY mesh or point cloud, in any of the three PLY formats. Files
+/// without faces are imported as points. The elements that are skipped are
+/// added to the warnings.
+pub(crate) fn load_ply(
+    path: &str,
+    warnings: &mut Vec<String>,
+) -> Result<(PrimitiveData, vk::PrimitiveTopology), String> {
+    let error = |message: &str| format!("PLY file `{}`: {}", path, message);
+    let data =
+        std::fs::read(path).map_err(|err| format!("Failed to open mesh `{}`: {}", path, err))?;
+
+    // # Parse the header
+    let mut opt_format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    let mut offset = 0;
+    let mut is_first_line = true;
+    loop {
+        let line_end = data[offset..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .ok_or_else(|| error("The header doesn't end with `end_header`."))?;
+        let line = String::from_utf8_lossy(&data[offset..offset + line_end]).to_string();
+        offset += line_end + 1;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if is_first_line {
+            if tokens != ["ply"] {
+                return Err(error("The file doesn't start with `ply`."));
+            }
+            is_first_line = false;
+            continue;
+        }
+        match tokens.as_slice() {
+            ["format", format, "1.0"] => {
+                opt_format = Some(match *format {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(error(&format!("Unknown format `{}`.", format))),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(&format!("Element `{}` has an invalid count.", name)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_type, item_type, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("A property comes before any element."))?;
+                let parse_type = |type_name: &str| {
+                    PlyScalarType::from_name(type_name)
+                        .ok_or_else(|| error(&format!("Unknown property type `{}`.", type_name)))
+                };
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyPropertyKind::List {
+                        count_type: parse_type(count_type)?,
+                        item_type: parse_type(item_type)?,
+                    },
+                });
+            }
+            ["property", scalar_type, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("A property comes before any element."))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyPropertyKind::Scalar(
+                        PlyScalarType::from_name(scalar_type).ok_or_else(|| {
+                            error(&format!("Unknown property type `{}`.", scalar_type))
+                        })?,
+                    ),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["end_header"] => break,
+            _ => return Err(error(&format!("Unexpected header line `{}`.", line.trim()))),
+        }
+    }
+    let format = opt_format.ok_or_else(|| error("The header doesn't declare a format."))?;
+
+    // # Check that the vertex and face properties can be imported
+    let vertex_element = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .ok_or_else(|| error("The file doesn't have a `vertex` element."))?;
+    let mut vertex_properties = Vec::new();
+    for property in &vertex_element.properties {
+        match (&property.kind, PlyVertexProperty::from_name(&property.name)) {
+            (PlyPropertyKind::Scalar(scalar_type), Some(vertex_property)) => {
+                vertex_properties.push((vertex_property, *scalar_type))
+            }
+            (PlyPropertyKind::List { .. }, _) => {
+                return Err(error(&format!(
+                    "Vertex property `{}` is a list, which isn't supported.",
+                    property.name
+                )))
+            }
+            (_, None) => {
+                return Err(error(&format!(
+                    "Vertex property `{}` isn't supported. The supported ones are x, y, z, nx, ny, nz, u, v (or s, t) and red, green, blue, alpha.",
+                    property.name
+                )))
+            }
+        }
+    }
+    let has_property = |vertex_property: PlyVertexProperty| {
+        vertex_properties.iter().any(|(p, _)| *p == vertex_property)
+    };
+    if !(0..3).all(|i| has_property(PlyVertexProperty::Position(i))) {
+        return Err(error("Vertices need x, y and z properties."));
+    }
+    if let Some(face_element) = elements.iter().find(|element| element.name == "face") {
+        for property in &face_element.properties {
+            let is_index_list = matches!(property.kind, PlyPropertyKind::List { .. })
+                && (property.name == "vertex_indices" || property.name == "vertex_index");
+            if !is_index_list {
+                return Err(error(&format!(
+                    "Face property `{}` isn't supported, faces can only have a `vertex_indices` list.",
+                    property.name
+                )));
+            }
+        }
+    }
+
+    // # Read the body
+    let mut reader = PlyReader {
+        format,
+        data: &data[offset..],
+        offset: 0,
+    };
+    let mut primitive = PrimitiveData::default();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut face_count = 0;
+    for element in &elements {
+        match element.name.as_str() {
+            "vertex" => {
+                for _ in 0..element.count {
+                    let mut position = [0.0; 3];
+                    let mut normal = [0.0; 3];
+                    let mut tex_coord = [0.0; 2];
+                    let mut color = [1.0; 4];
+                    for (vertex_property, scalar_type) in &vertex_properties {
+                        let value = reader.read(*scalar_type).map_err(|err| error(&err))?;
+                        match vertex_property {
+                            PlyVertexProperty::Position(i) => position[*i] = value as f32,
+                            PlyVertexProperty::Normal(i) => normal[*i] = value as f32,
+                            PlyVertexProperty::TexCoord(i) => tex_coord[*i] = value as f32,
+                            PlyVertexProperty::Color(i) => {
+                                color[*i] = (value / scalar_type.color_scale()) as f32
+                            }
+                        }
+                    }
+                    primitive.positions.push(position);
+                    normals.push(normal);
+                    // PLY puts the origin of the texture at the bottom left
+                    tex_coords.push([tex_coord[0], 1.0 - tex_coord[1]]);
+                    colors.push(color);
+                }
+            }
+            "face" => {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        if let PlyPropertyKind::List {
+                            count_type,
+                            item_type,
+                        } = property.kind
+                        {
+                            let count =
+                                reader.read(count_type).map_err(|err| error(&err))? as usize;
+                            // The count isn't trusted to size the allocation
+                            let mut face_indices = Vec::new();
+                            for _ in 0..count {
+                                let index = reader.read(item_type).map_err(|err| error(&err))?;
+                                // Anything else would silently become another index
+                                let is_vertex_index = index >= 0.0
+                                    && index.fract() == 0.0
+                                    && index <= u32::MAX as f64;
+                                if !is_vertex_index {
+                                    return Err(error(&format!(
+                                        "A face has the vertex index {}.",
+                                        index
+                                    )));
+                                }
+                                face_indices.push(index as u32);
+                            }
+                            // Triangulate polygons as fans
+                            for idx in 1..count.saturating_sub(1) {
+                                primitive.indices.extend_from_slice(&[
+                                    face_indices[0],
+                                    face_indices[idx],
+                                    face_indices[idx + 1],
+                                ]);
+                            }
+                        }
+                    }
+                }
+                face_count += element.count;
+            }
+            _ => {
+                warnings.push(error(&format!(
+                    "Skipped the `{}` elements, only vertices and faces are supported.",
+                    element.name
+                )));
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        match property.kind {
+                            PlyPropertyKind::Scalar(scalar_type) => {
+                                reader.read(scalar_type).map_err(|err| error(&err))?;
+                            }
+                            PlyPropertyKind::List {
+                                count_type,
+                                item_type,
+                            } => {
+                                let count =
+                                    reader.read(count_type).map_err(|err| error(&err))? as usize;
+                                for _ in 0..count {
+                                    reader.read(item_type).map_err(|err| error(&err))?;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let vertex_count = primitive.positions.len();
+    if let Some(index) = primitive
+        .indices
+        .iter()
+        .find(|index| **index as usize >= vertex_count)
+    {
+        return Err(error(&format!(
+            "A face refers to vertex {}, but there are only {} vertices.",
+            index, vertex_count
+        )));
+    }
+    if (0..3).all(|i| has_property(PlyVertexProperty::Normal(i))) {
+        primitive.normals = normals;
+    }
+    if (0..2).all(|i| has_property(PlyVertexProperty::TexCoord(i))) {
+        primitive.opt_tex_coords_0 = Some(tex_coords);
+    }
+    if (0..3).any(|i| has_property(PlyVertexProperty::Color(i))) {
+        primitive.opt_colors_0 = Some(colors);
+    }
+
+    let topology = if face_count == 0 {
+        primitive.indices = (0..vertex_count as u32).collect();
+        vk::PrimitiveTopology::POINT_LIST
+    } else {
+        vk::PrimitiveTopology::TRIANGLE_LIST
+    };
+    primitive.generate_missing_attributes(topology);
+
+    Ok((primitive, topology))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(
+        test_name: &str,
+        contents: &[u8],
+    ) -> Result<(PrimitiveData, vk::PrimitiveTopology), String> {
+        load_with_warnings(test_name, contents)
+            .map(|(primitive, topology, _)| (primitive, topology))
+    }
+
+    fn load_with_warnings(
+        test_name: &str,
+        contents: &[u8],
+    ) -> Result<(PrimitiveData, vk::PrimitiveTopology, Vec<String>), String> {
+        let path = std::env::temp_dir().join(format!(
+            "graphene_ply_{}_{}.ply",
+            test_name,
+            std::process::id()
+        ));
+        std::fs::write(&path, contents).unwrap();
+        let mut warnings = Vec::new();
+        let (primitive, topology) = load_ply(&path.to_string_lossy(), &mut warnings)?;
+        Ok((primitive, topology, warnings))
+    }
+
+    // A unit quad with one color per vertex, as a single polygon
+    const QUAD_POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    const QUAD_HEADER: &str = "element vertex 4\n\
+        property float x\nproperty float y\nproperty float z\n\
+        property uchar red\nproperty uchar green\nproperty uchar blue\n\
+        element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    fn binary_quad(
+        format: &str,
+        to_bytes: fn(f32) -> [u8; 4],
+        index_to_bytes: fn(i32) -> [u8; 4],
+    ) -> Vec<u8> {
+        let mut contents = format!("ply\nformat {} 1.0\n{}", format, QUAD_HEADER).into_bytes();
+        for position in &QUAD_POSITIONS {
+            for component in position {
+                contents.extend_from_slice(&to_bytes(*component));
+            }
+            contents.extend_from_slice(&[255, 0, 51]);
+        }
+        contents.push(4);
+        for index in 0..4 {
+            contents.extend_from_slice(&index_to_bytes(index));
+        }
+        contents
+    }
+
+    fn check_quad(primitive: &PrimitiveData, topology: vk::PrimitiveTopology) {
+        assert_eq!(topology, vk::PrimitiveTopology::TRIANGLE_LIST);
+        // Flat normals unindex the two triangles of the quad
+        assert_eq!(primitive.positions.len(), 6);
+        assert_eq!(
+            primitive.positions[..3],
+            [QUAD_POSITIONS[0], QUAD_POSITIONS[1], QUAD_POSITIONS[2]]
+        );
+        assert_eq!(
+            primitive.positions[3..],
+            [QUAD_POSITIONS[0], QUAD_POSITIONS[2], QUAD_POSITIONS[3]]
+        );
+        assert_eq!(primitive.normals, vec![[0.0, 0.0, 1.0]; 6]);
+        assert_eq!(
+            primitive.opt_colors_0.as_ref().unwrap()[0],
+            [1.0, 0.0, 0.2, 1.0]
+        );
+    }
+
+    #[test]
+    fn ascii() {
+        let contents = format!(
+            "ply\nformat ascii 1.0\ncomment A quad\n{}0 0 0 255 0 51\n1 0 0 255 0 51\n1 1 0 255 0 51\n0 1 0 255 0 51\n4 0 1 2 3\n",
+            QUAD_HEADER
+        );
+        let (primitive, topology) = load("ascii", contents.as_bytes()).unwrap();
+        check_quad(&primitive, topology);
+    }
+
+    #[test]
+    fn binary() {
+        let contents = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        let (primitive, topology) = load("binary_little_endian", &contents).unwrap();
+        check_quad(&primitive, topology);
+
+        let contents = binary_quad("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+        let (primitive, topology) = load("binary_big_endian", &contents).unwrap();
+        check_quad(&primitive, topology);
+    }
+
+    #[test]
+    fn point_clouds() {
+        let contents = "ply\nformat ascii 1.0\nelement vertex 2\n\
+            property double x\nproperty double y\nproperty double z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property float u\nproperty float v\n\
+            end_header\n1 2 3 0 1 0 0.25 1\n4 5 6 0 1 0 0.5 0\n";
+        let (primitive, topology) = load("point_cloud", contents.as_bytes()).unwrap();
+        assert_eq!(topology, vk::PrimitiveTopology::POINT_LIST);
+        assert_eq!(primitive.positions, vec![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]);
+        assert_eq!(primitive.indices, vec![0, 1]);
+        assert_eq!(primitive.normals, vec![[0.0, 1.0, 0.0]; 2]);
+        // The origin of the texture moves to the top left
+        assert_eq!(
+            primitive.opt_tex_coords_0,
+            Some(vec![[0.25, 0.0], [0.5, 1.0]])
+        );
+        assert_eq!(primitive.opt_colors_0, None);
+    }
+
+    #[test]
+    fn other_elements_are_skipped() {
+        let contents = "ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element edge 1\nproperty int vertex1\nproperty list uchar int extra\n\
+            element face 1\nproperty list uchar uint vertex_index\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n0 2 7 8\n3 0 1 2\n";
+        let (primitive, topology, warnings) =
+            load_with_warnings("skipped", contents.as_bytes()).unwrap();
+        assert_eq!(topology, vk::PrimitiveTopology::TRIANGLE_LIST);
+        assert_eq!(primitive.positions.len(), 3);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("`edge`"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn errors() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+        // Truncated bodies
+        let contents = format!("{}0 0 0\n1 0 0\n", header);
+        assert!(load("truncated_ascii", contents.as_bytes()).is_err());
+        let mut contents = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        contents.truncate(contents.len() - 1);
+        assert!(load("truncated_binary", &contents).is_err());
+        // A face list whose count runs past the end of the file
+        let mut contents = binary_quad("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        let count_offset = contents.len() - 17;
+        contents[count_offset] = 255;
+        assert!(load("long_face", &contents).is_err());
+
+        let contents = format!("{}0 0 0\n1 0 0\n0 1 0\n3 0 1 3\n", header);
+        assert!(load("bad_index", contents.as_bytes()).is_err());
+        // Indices that aren't vertex numbers, which lists of floats can have
+        let float_header = header.replace("list uchar int", "list uchar float");
+        for (test_name, index) in &[
+            ("negative_index", "-1"),
+            ("fractional_index", "1.5"),
+            ("nan_index", "nan"),
+            ("huge_index", "1e10"),
+        ] {
+            let contents = format!("{}0 0 0\n1 0 0\n0 1 0\n3 0 1 {}\n", float_header, index);
+            let err = load(test_name, contents.as_bytes()).err().unwrap();
+            assert!(err.contains("vertex index"), "{}", err);
+        }
+        let contents = format!("{}0 0 0\n1 0 0\n0 1 0\n3 0 1 2.0\n", float_header);
+        assert!(load("float_index", contents.as_bytes()).is_ok());
+        let contents = format!("{}0 0 0\n1 zero 0\n0 1 0\n3 0 1 2\n", header);
+        assert!(load("bad_number", contents.as_bytes()).is_err());
+
+        // Invalid headers
+        assert!(load("no_magic", b"format ascii 1.0\nend_header\n").is_err());
+        assert!(load("no_end_header", b"ply\nformat ascii 1.0\n").is_err());
+        assert!(load("no_format", b"ply\nelement vertex 0\nend_header\n").is_err());
+        assert!(load("no_vertices", b"ply\nformat ascii 1.0\nend_header\n").is_err());
+        let contents = "ply\nformat ascii 1.0\nelement vertex 0\n\
+            property float x\nproperty float y\nproperty float w\nend_header\n";
+        assert!(load("unknown_property", contents.as_bytes()).is_err());
+        let contents = "ply\nformat ascii 1.0\nelement vertex 0\n\
+            property float x\nproperty float y\nend_header\n";
+        assert!(load("no_z", contents.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,561 @@
+use crate::*;
+use glam::*;
+use std::f32::consts::PI;
+
+// Shapes that fit in a unit cube centered on the origin, with Y up, and
+// counter-clockwise front faces like glTF. They have normals, UVs and
+// tangents, which is the layout `Mesh::load` gives textured glTF meshes. Like
+// loaded meshes, their buffers are in the buffer list, and uploaded with the
+// next frame.
+impl Mesh {
+    pub fn cube(
+        name: &str,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::from_primitive(name, get_cube_data(), gpu, buffer_list, debug_utils)
+    }
+
+    /// `segments` around the Y axis, and `rings` from pole to pole. UVs wrap
+    /// around once, with v = 0 at the top.
+    pub fn uv_sphere(
+        name: &str,
+        segments: u32,
+        rings: u32,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::from_primitive(
+            name,
+            get_uv_sphere_data(segments, rings),
+            gpu,
+            buffer_list,
+            debug_utils,
+        )
+    }
+
+    /// An icosahedron with each triangle split in four `subdivisions` times,
+    /// for evenly sized triangles
+    pub fn icosphere(
+        name: &str,
+        subdivisions: u32,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::from_primitive(
+            name,
+            get_icosphere_data(subdivisions),
+            gpu,
+            buffer_list,
+            debug_utils,
+        )
+    }
+
+    /// A square on the XZ plane, facing +Y
+    pub fn plane(
+        name: &str,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::from_primitive(name, get_plane_data(), gpu, buffer_list, debug_utils)
+    }
+
+    /// A capped cylinder along the Y axis
+    pub fn cylinder(
+        name: &str,
+        segments: u32,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::from_primitive(
+            name,
+            get_cylinder_data(segments),
+            gpu,
+            buffer_list,
+            debug_utils,
+        )
+    }
+
+    /// A quad that covers the screen when its positions are used as clip
+    /// coordinates as is. UVs have v = 0 at the top, like framebuffers.
+    pub fn fullscreen_quad(
+        name: &str,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        Mesh::from_primitive(
+            name,
+            get_fullscreen_quad_data(),
+            gpu,
+            buffer_list,
+            debug_utils,
+        )
+    }
+
+    fn from_primitive(
+        name: &str,
+        mut primitive: PrimitiveData,
+        gpu: &Gpu,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Mesh, String> {
+        primitive.generate_missing_attributes(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let baked_mesh = BakedMesh::from_primitives(
+            vec![primitive],
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            vk::PrimitiveTopology::TRIANGLE_LIST,
+        );
+        Mesh::from_baked(name, baked_mesh, gpu, buffer_list, debug_utils)
+    }
+}
+
+// Append a quad from its corners, counter-clockwise from the one at uv (0, 1)
+fn push_quad(data: &mut PrimitiveData, corners: [Vec3; 4], normal: Vec3) {
+    let first_vertex = data.positions.len() as u32;
+    let tex_coords = data.opt_tex_coords_0.get_or_insert_with(Vec::new);
+    for (corner, tex_coord) in corners
+        .iter()
+        .zip(&[[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]])
+    {
+        data.positions.push((*corner).into());
+        data.normals.push(normal.into());
+        tex_coords.push(*tex_coord);
+    }
+    data.indices.extend(
+        [0, 1, 2, 0, 2, 3]
+            .iter()
+            .map(|offset| first_vertex + offset),
+    );
+}
+
+pub(crate) fn get_cube_data() -> PrimitiveData {
+    let mut data = PrimitiveData::default();
+    // Each face as its normal, and the directions of increasing u and v
+    let faces = [
+        (Vec3::unit_x(), -Vec3::unit_z(), -Vec3::unit_y()),
+        (-Vec3::unit_x(), Vec3::unit_z(), -Vec3::unit_y()),
+        (Vec3::unit_y(), Vec3::unit_x(), Vec3::unit_z()),
+        (-Vec3::unit_y(), Vec3::unit_x(), -Vec3::unit_z()),
+        (Vec3::unit_z(), Vec3::unit_x(), -Vec3::unit_y()),
+        (-Vec3::unit_z(), -Vec3::unit_x(), -Vec3::unit_y()),
+    ];
+    for (normal, u, v) in &faces {
+        let center = *normal * 0.5;
+        let (u, v) = (*u * 0.5, *v * 0.5);
+        push_quad(
+            &mut data,
+            [
+                center - u + v,
+                center + u + v,
+                center + u - v,
+                center - u - v,
+            ],
+            *normal,
+        );
+    }
+    data
+}
+
+pub(crate) fn get_uv_sphere_data(segments: u32, rings: u32) -> PrimitiveData {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let mut data = PrimitiveData::default();
+    let mut tex_coords = Vec::new();
+    // The seam and the poles have one vertex per segment, for their UVs
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let (sin_theta, cos_theta) = (v * PI).sin_cos();
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+            let normal = Vec3::new(sin_theta * sin_phi, cos_theta, sin_theta * cos_phi);
+            data.positions.push((normal * 0.5).into());
+            data.normals.push(normal.into());
+            tex_coords.push([u, v]);
+        }
+    }
+    let row_len = segments + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let top_left = ring * row_len + segment;
+            let bottom_left = top_left + row_len;
+            // Rings at the poles are triangles, not quads
+            if ring != 0 {
+                data.indices
+                    .extend_from_slice(&[top_left, bottom_left, top_left + 1]);
+            }
+            if ring != rings - 1 {
+                data.indices
+                    .extend_from_slice(&[top_left + 1, bottom_left, bottom_left + 1]);
+            }
+        }
+    }
+    data.opt_tex_coords_0 = Some(tex_coords);
+    data
+}
+
+pub(crate) fn get_icosphere_data(subdivisions: u32) -> PrimitiveData {
+    // The 12 vertices of an icosahedron are on three golden rectangles
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut directions: Vec<Vec3> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(|position| Vec3::from(*position).normalize())
+    .collect();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges are shared, so each midpoint is only created once
+        let mut midpoints: std::collections::HashMap<(u32, u32), u32> =
+            std::collections::HashMap::new();
+        let mut get_midpoint = |a: u32, b: u32, directions: &mut Vec<Vec3>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let midpoint = (directions[a as usize] + directions[b as usize]).normalize();
+                directions.push(midpoint);
+                directions.len() as u32 - 1
+            })
+        };
+        let mut subdivided_triangles = Vec::with_capacity(triangles.len() * 4);
+        for [a, b, c] in &triangles {
+            let ab = get_midpoint(*a, *b, &mut directions);
+            let bc = get_midpoint(*b, *c, &mut directions);
+            let ca = get_midpoint(*c, *a, &mut directions);
+            subdivided_triangles.push([*a, ab, ca]);
+            subdivided_triangles.push([*b, bc, ab]);
+            subdivided_triangles.push([*c, ca, bc]);
+            subdivided_triangles.push([ab, bc, ca]);
+        }
+        triangles = subdivided_triangles;
+    }
+
+    // Equirectangular UVs, like the UV sphere's
+    let get_tex_coord = |direction: Vec3| {
+        let u = direction.x().atan2(direction.z()) / (2.0 * PI);
+        [
+            if u < 0.0 { u + 1.0 } else { u },
+            direction.y().clamp(-1.0, 1.0).acos() / PI,
+        ]
+    };
+    let mut tex_coords: Vec<[f32; 2]> = directions.iter().map(|d| get_tex_coord(*d)).collect();
+    // Triangles that cross the seam get their own copies of the vertices on
+    // the low side, with u past 1. The poles don't have a u of their own, so
+    // each triangle around them gets one at the middle of its other vertices.
+    let is_pole = |direction: Vec3| direction.x().abs() < 1e-6 && direction.z().abs() < 1e-6;
+    let mut seam_copies: std::collections::HashMap<u32, u32> = std::collections::HashMap::new();
+    let mut is_pole_used = vec![false; directions.len()];
+    for triangle in &mut triangles {
+        let us: Vec<f32> = triangle
+            .iter()
+            .filter(|idx| !is_pole(directions[**idx as usize]))
+            .map(|idx| tex_coords[*idx as usize][0])
+            .collect();
+        let max_u = us.iter().cloned().fold(f32::MIN, f32::max);
+        let min_u = us.iter().cloned().fold(f32::MAX, f32::min);
+        if max_u - min_u > 0.5 {
+            for idx in triangle.iter_mut() {
+                if !is_pole(directions[*idx as usize]) && tex_coords[*idx as usize][0] < 0.5 {
+                    *idx = *seam_copies.entry(*idx).or_insert_with(|| {
+                        let [u, v] = tex_coords[*idx as usize];
+                        directions.push(directions[*idx as usize]);
+                        tex_coords.push([u + 1.0, v]);
+                        directions.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        if let Some(pole_idx) = triangle
+            .iter()
+            .position(|idx| is_pole(directions[*idx as usize]))
+        {
+            let u = triangle
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| *idx != pole_idx)
+                .map(|(_, vertex_idx)| tex_coords[*vertex_idx as usize][0])
+                .sum::<f32>()
+                / 2.0;
+            let pole = triangle[pole_idx] as usize;
+            if is_pole_used[pole] {
+                directions.push(directions[pole]);
+                tex_coords.push([u, tex_coords[pole][1]]);
+                triangle[pole_idx] = directions.len() as u32 - 1;
+            } else {
+                is_pole_used[pole] = true;
+                tex_coords[pole][0] = u;
+            }
+        }
+    }
+
+    PrimitiveData {
+        positions: directions.iter().map(|d| (*d * 0.5).into()).collect(),
+        normals: directions.iter().map(|d| (*d).into()).collect(),
+        opt_tex_coords_0: Some(tex_coords),
+        indices: triangles.concat(),
+        ..Default::default()
+    }
+}
+
+pub(crate) fn get_plane_data() -> PrimitiveData {
+    let mut data = PrimitiveData::default();
+    push_quad(
+        &mut data,
+        [
+            Vec3::new(-0.5, 0.0, 0.5),
+            Vec3::new(0.5, 0.0, 0.5),
+            Vec3::new(0.5, 0.0, -0.5),
+            Vec3::new(-0.5, 0.0, -0.5),
+        ],
+        Vec3::unit_y(),
+    );
+    data
+}
+
+pub(crate) fn get_cylinder_data(segments: u32) -> PrimitiveData {
+    let segments = segments.max(3);
+    let mut data = PrimitiveData::default();
+    let mut tex_coords = Vec::new();
+
+    // The side, with a seam for the UVs
+    for segment in 0..=segments {
+        let u = segment as f32 / segments as f32;
+        let (sin_phi, cos_phi) = (u * 2.0 * PI).sin_cos();
+        let normal = Vec3::new(sin_phi, 0.0, cos_phi);
+        for (y, v) in &[(0.5, 0.0), (-0.5, 1.0)] {
+            data.positions
+                .push([normal.x() * 0.5, *y, normal.z() * 0.5]);
+            data.normals.push(normal.into());
+            tex_coords.push([u, *v]);
+        }
+    }
+    for segment in 0..segments {
+        let top = segment * 2;
+        data.indices
+            .extend_from_slice(&[top, top + 1, top + 2, top + 2, top + 1, top + 3]);
+    }
+
+    // The caps, as fans around their centers, with UVs mapped from above
+    for (y, normal) in &[(0.5_f32, Vec3::unit_y()), (-0.5, -Vec3::unit_y())] {
+        let center = data.positions.len() as u32;
+        data.positions.push([0.0, *y, 0.0]);
+        data.normals.push((*normal).into());
+        tex_coords.push([0.5, 0.5]);
+        for segment in 0..segments {
+            let (sin_phi, cos_phi) = (segment as f32 / segments as f32 * 2.0 * PI).sin_cos();
+            data.positions.push([sin_phi * 0.5, *y, cos_phi * 0.5]);
+            data.normals.push((*normal).into());
+            tex_coords.push([0.5 + sin_phi * 0.5, 0.5 + cos_phi * 0.5 * normal.y()]);
+        }
+        for segment in 0..segments {
+            let current = center + 1 + segment;
+            let next = center + 1 + (segment + 1) % segments;
+            if *y > 0.0 {
+                data.indices.extend_from_slice(&[center, current, next]);
+            } else {
+                data.indices.extend_from_slice(&[center, next, current]);
+            }
+        }
+    }
+
+    data.opt_tex_coords_0 = Some(tex_coords);
+    data
+}
+
+pub(crate) fn get_fullscreen_quad_data() -> PrimitiveData {
+    // Vulkan clip space has y pointing down, so the quad winds
+    // counter-clockwise on screen from the top left corner, and faces -Z
+    PrimitiveData {
+        positions: vec![
+            [-1.0, -1.0, 0.0],
+            [1.0, -1.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [-1.0, 1.0, 0.0],
+        ],
+        normals: vec![[0.0, 0.0, -1.0]; 4],
+        opt_tex_coords_0: Some(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]),
+        indices: vec![0, 1, 2, 0, 2, 3],
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangles(data: &PrimitiveData) -> Vec<[Vec3; 3]> {
+        data.indices
+            .chunks(3)
+            .map(|triangle| {
+                let get_position = |idx: u32| Vec3::from(data.positions[idx as usize]);
+                [
+                    get_position(triangle[0]),
+                    get_position(triangle[1]),
+                    get_position(triangle[2]),
+                ]
+            })
+            .collect()
+    }
+
+    // Checks what every primitive has in common, and returns the face normals
+    fn check_primitive(data: &PrimitiveData) -> Vec<Vec3> {
+        let vertex_count = data.positions.len();
+        assert_eq!(data.normals.len(), vertex_count);
+        assert_eq!(data.opt_tex_coords_0.as_ref().unwrap().len(), vertex_count);
+        assert_eq!(data.indices.len() % 3, 0);
+        assert!(data
+            .indices
+            .iter()
+            .all(|idx| (*idx as usize) < vertex_count));
+        for (position, normal) in data.positions.iter().zip(&data.normals) {
+            assert!(position.iter().all(|x| x.abs() <= 0.5 + 1e-6));
+            assert!((Vec3::from(*normal).length() - 1.0).abs() < 1e-5);
+        }
+
+        // Counter-clockwise triangles face the way of their vertex normals
+        triangles(data)
+            .iter()
+            .zip(data.indices.chunks(3))
+            .map(|([p0, p1, p2], triangle)| {
+                let cross = (*p1 - *p0).cross(*p2 - *p0);
+                assert!(cross.length() > 1e-7, "Degenerate triangle {:?}", triangle);
+                let face_normal = cross.normalize();
+                for idx in triangle {
+                    assert!(face_normal.dot(Vec3::from(data.normals[*idx as usize])) > 0.0);
+                }
+                face_normal
+            })
+            .collect()
+    }
+
+    // Closed shapes around the origin face away from it everywhere
+    fn check_outward(data: &PrimitiveData) {
+        let face_normals = check_primitive(data);
+        let mut volume = 0.0;
+        for ([p0, p1, p2], face_normal) in triangles(data).iter().zip(face_normals) {
+            let centroid = (*p0 + *p1 + *p2) / 3.0;
+            assert!(face_normal.dot(centroid) > 0.0);
+            volume += p0.dot(p1.cross(*p2)) / 6.0;
+        }
+        assert!(volume > 0.0);
+    }
+
+    #[test]
+    fn cube() {
+        let data = get_cube_data();
+        assert_eq!(data.positions.len(), 6 * 4);
+        assert_eq!(data.indices.len(), 6 * 2 * 3);
+        check_outward(&data);
+        let volume: f32 = triangles(&data)
+            .iter()
+            .map(|[p0, p1, p2]| p0.dot(p1.cross(*p2)) / 6.0)
+            .sum();
+        assert!((volume - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn uv_sphere() {
+        for (segments, rings) in &[(3, 2), (16, 8), (32, 17)] {
+            let data = get_uv_sphere_data(*segments, *rings);
+            assert_eq!(data.positions.len() as u32, (segments + 1) * (rings + 1));
+            // Quads, except for the triangles around the poles
+            assert_eq!(data.indices.len() as u32, segments * (rings - 1) * 2 * 3);
+            check_outward(&data);
+            for (position, normal) in data.positions.iter().zip(&data.normals) {
+                assert!((Vec3::from(*position) * 2.0 - Vec3::from(*normal)).length() < 1e-5);
+            }
+        }
+        // Too few segments and rings are clamped to the smallest sphere
+        assert_eq!(
+            get_uv_sphere_data(0, 0).indices,
+            get_uv_sphere_data(3, 2).indices
+        );
+    }
+
+    #[test]
+    fn icosphere() {
+        for subdivisions in 0..4 {
+            let data = get_icosphere_data(subdivisions);
+            let triangle_count = 20 * 4_usize.pow(subdivisions);
+            assert_eq!(data.indices.len(), triangle_count * 3);
+            // Every edge is split once, plus the copies of vertices on the seam
+            let vertex_count = 10 * 4_usize.pow(subdivisions) + 2;
+            assert!(data.positions.len() >= vertex_count);
+            assert!(data.positions.len() < vertex_count * 2);
+            check_outward(&data);
+            for (position, normal) in data.positions.iter().zip(&data.normals) {
+                assert!((Vec3::from(*position).length() - 0.5).abs() < 1e-5);
+                assert!((Vec3::from(*position) * 2.0 - Vec3::from(*normal)).length() < 1e-5);
+            }
+            // No triangle wraps all the way around the texture
+            let tex_coords = data.opt_tex_coords_0.as_ref().unwrap();
+            for triangle in data.indices.chunks(3) {
+                let us: Vec<f32> = triangle
+                    .iter()
+                    .map(|idx| tex_coords[*idx as usize][0])
+                    .collect();
+                let max_u = us.iter().cloned().fold(f32::MIN, f32::max);
+                let min_u = us.iter().cloned().fold(f32::MAX, f32::min);
+                assert!(max_u - min_u <= 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn cylinder() {
+        for segments in &[3, 8, 31] {
+            let data = get_cylinder_data(*segments);
+            // The side has a seam, and each cap a center vertex
+            assert_eq!(data.positions.len() as u32, 2 * (segments + 1) * 2);
+            // Two triangles per side segment, and one per cap segment
+            assert_eq!(data.indices.len() as u32, (2 + 2) * segments * 3);
+            check_outward(&data);
+            for (position, normal) in data.positions.iter().zip(&data.normals) {
+                if normal[1] == 0.0 {
+                    // The normals of the side point away from the axis
+                    let radial = Vec3::new(position[0], 0.0, position[2]) * 2.0;
+                    assert!((radial - Vec3::from(*normal)).length() < 1e-5);
+                } else {
+                    // The caps face away from the middle
+                    assert_eq!(normal[1], position[1] * 2.0);
+                }
+            }
+        }
+        assert_eq!(get_cylinder_data(0).indices, get_cylinder_data(3).indices);
+    }
+
+    #[test]
+    fn plane() {
+        let data = get_plane_data();
+        assert_eq!(data.positions.len(), 4);
+        assert_eq!(data.indices.len(), 6);
+        for face_normal in check_primitive(&data) {
+            assert!((face_normal - Vec3::unit_y()).length() < 1e-6);
+        }
+        assert!(data.positions.iter().all(|position| position[1] == 0.0));
+    }
+}
@@ -0,0 +1,869 @@
+use crate::*;
+use glam::*;
+use std::collections::HashMap;
+
+// Size of the simulated post-transform cache. Bigger than most hardware
+// caches, which the scoring tolerates well.
+const VERTEX_CACHE_SIZE: usize = 32;
+
+/// Optional clean-up of a mesh at load time. The result is cached with the
+/// baked mesh, so it's only paid for when the file changes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshProcessing {
+    pub weld_vertices: bool, // Merge vertices whose attributes are identical
+    pub optimize_vertex_cache: bool, // Reorder triangles for the post-transform cache
+    pub optimize_vertex_fetch: bool, // Reorder vertices by first use, dropping unused ones
+    pub lod_count: usize,    // Simplified LODs generated after the full-detail one
+    pub lod_triangle_ratio: f32, // Fraction of the triangles that each LOD keeps
+}
+
+impl MeshProcessing {
+    pub const NONE: MeshProcessing = MeshProcessing {
+        weld_vertices: false,
+        optimize_vertex_cache: false,
+        optimize_vertex_fetch: false,
+        lod_count: 0,
+        lod_triangle_ratio: 1.0,
+    };
+
+    pub const ALL: MeshProcessing = MeshProcessing {
+        weld_vertices: true,
+        optimize_vertex_cache: true,
+        optimize_vertex_fetch: true,
+        lod_count: 4,
+        lod_triangle_ratio: 0.5,
+    };
+}
+
+impl Hash for MeshProcessing {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.weld_vertices.hash(state);
+        self.optimize_vertex_cache.hash(state);
+        self.optimize_vertex_fetch.hash(state);
+        self.lod_count.hash(state);
+        self.lod_triangle_ratio.to_bits().hash(state);
+    }
+}
+
+impl BakedMesh {
+    /// Weld, reorder and simplify each submesh on its own. LODs are appended
+    /// to the index buffer, and reuse the vertices of the full-detail submesh.
+    /// Point clouds are only welded and reordered.
+    pub(crate) fn process(&mut self, processing: &MeshProcessing) {
+        if *processing == MeshProcessing::NONE {
+            return;
+        }
+        let is_triangle_list = self.topology == vk::PrimitiveTopology::TRIANGLE_LIST;
+        let lod_count = if is_triangle_list {
+            processing.lod_count
+        } else {
+            0
+        };
+        let stride: usize = self
+            .attributes
+            .iter()
+            .map(|attribute| attribute.component_count())
+            .sum();
+        // The position is always the first attribute
+        let get_position =
+            |vertex_idx: usize| Vec3::from_slice_unaligned(&self.vertices[vertex_idx * stride..]);
+
+        // LOD errors are relative to the largest extent of the whole mesh
+        let (min, max) = (0..self.vertex_count).fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vertex_idx| {
+                let position = get_position(vertex_idx);
+                (min.min(position), max.max(position))
+            },
+        );
+        let extent = (max - min).max_element();
+        let extent = if extent > 0.0 { extent } else { 1.0 };
+
+        let mut vertices: Vec<f32> = Vec::with_capacity(self.vertices.len());
+        let mut indices: Vec<u32> = Vec::with_capacity(self.indices.len());
+        let mut submeshes: Vec<Submesh> = Vec::with_capacity(self.submeshes.len());
+        // Deltas are laid out target by target, so gather them per target
+        let mut target_deltas: Vec<Vec<[f32; 4]>> = vec![Vec::new(); self.morph_target_count];
+        let mut lod_errors = vec![0.0; lod_count + 1];
+
+        for (submesh_idx, submesh) in self.submeshes.iter().enumerate() {
+            // Submeshes own consecutive vertex ranges
+            let first_vertex = submesh.vertex_offset as usize;
+            let end_vertex = self
+                .submeshes
+                .get(submesh_idx + 1)
+                .map_or(self.vertex_count, |next| next.vertex_offset as usize);
+            let vertex_count = end_vertex - first_vertex;
+            let first_index = submesh.first_index as usize;
+            let mut submesh_indices =
+                self.indices[first_index..first_index + submesh.index_count as usize].to_vec();
+
+            // Map every vertex to the first one with the same attributes and
+            // morph target deltas, or to itself
+            let remap: Vec<u32> = if processing.weld_vertices {
+                let mut first_vertices: HashMap<Vec<u32>, u32> = HashMap::new();
+                (0..vertex_count)
+                    .map(|local_idx| {
+                        let vertex_idx = first_vertex + local_idx;
+                        let mut key: Vec<u32> = self.vertices
+                            [vertex_idx * stride..(vertex_idx + 1) * stride]
+                            .iter()
+                            .map(|value| value.to_bits())
+                            .collect();
+                        for target_idx in 0..self.morph_target_count {
+                            let delta_idx = (target_idx * self.vertex_count + vertex_idx) * 3;
+                            for delta in &self.morph_target_deltas[delta_idx..delta_idx + 3] {
+                                key.extend(delta.iter().map(|value| value.to_bits()));
+                            }
+                        }
+                        *first_vertices.entry(key).or_insert(local_idx as u32)
+                    })
+                    .collect()
+            } else {
+                (0..vertex_count as u32).collect()
+            };
+            for index in &mut submesh_indices {
+                *index = remap[*index as usize];
+            }
+            if is_triangle_list && processing.optimize_vertex_cache {
+                optimize_vertex_cache(&mut submesh_indices, vertex_count);
+            }
+
+            // Each LOD simplifies the previous one
+            let positions: Vec<Vec3> = (first_vertex..end_vertex).map(get_position).collect();
+            let is_seam = get_seam_vertices(&positions, &submesh_indices);
+            let mut lod_indices: Vec<Vec<u32>> = vec![submesh_indices];
+            let mut error = 0.0;
+            for lod in 1..=lod_count {
+                let previous_indices = &lod_indices[lod - 1];
+                let target_triangle_count =
+                    ((previous_indices.len() / 3) as f32 * processing.lod_triangle_ratio) as usize;
+                let (mut simplified_indices, simplification_error) = simplify(
+                    &positions,
+                    &is_seam,
+                    previous_indices,
+                    target_triangle_count,
+                );
+                if processing.optimize_vertex_cache {
+                    optimize_vertex_cache(&mut simplified_indices, vertex_count);
+                }
+                error = f32::max(error, simplification_error);
+                lod_errors[lod] = f32::max(lod_errors[lod], error / extent);
+                lod_indices.push(simplified_indices);
+            }
+
+            // The vertices to keep, in their new order
+            let vertex_order: Vec<u32> = if processing.optimize_vertex_fetch {
+                let mut is_ordered = vec![false; vertex_count];
+                let mut vertex_order = Vec::with_capacity(vertex_count);
+                for index in &lod_indices[0] {
+                    if !is_ordered[*index as usize] {
+                        is_ordered[*index as usize] = true;
+                        vertex_order.push(*index);
+                    }
+                }
+                vertex_order
+            } else {
+                (0..vertex_count as u32)
+                    .filter(|local_idx| remap[*local_idx as usize] == *local_idx)
+                    .collect()
+            };
+            let mut new_indices = vec![u32::MAX; vertex_count];
+            for (new_idx, old_idx) in vertex_order.iter().enumerate() {
+                new_indices[*old_idx as usize] = new_idx as u32;
+            }
+
+            let vertex_offset = (vertices.len() / stride) as i32;
+            for old_idx in &vertex_order {
+                let vertex_idx = first_vertex + *old_idx as usize;
+                vertices.extend_from_slice(
+                    &self.vertices[vertex_idx * stride..(vertex_idx + 1) * stride],
+                );
+                for (target_idx, deltas) in target_deltas.iter_mut().enumerate() {
+                    let delta_idx = (target_idx * self.vertex_count + vertex_idx) * 3;
+                    deltas.extend_from_slice(&self.morph_target_deltas[delta_idx..delta_idx + 3]);
+                }
+            }
+            // LODs that couldn't be simplified further reuse the previous range
+            let mut ranges: Vec<SubmeshLod> = Vec::with_capacity(lod_indices.len());
+            for lod_indices in &lod_indices {
+                match ranges.last() {
+                    Some(previous) if previous.index_count as usize == lod_indices.len() => {
+                        ranges.push(*previous);
+                    }
+                    _ => {
+                        ranges.push(SubmeshLod {
+                            first_index: indices.len() as u32,
+                            index_count: lod_indices.len() as u32,
+                        });
+                        indices
+                            .extend(lod_indices.iter().map(|index| new_indices[*index as usize]));
+                    }
+                }
+            }
+            submeshes.push(Submesh {
+                first_index: ranges[0].first_index,
+                index_count: ranges[0].index_count,
+                vertex_offset,
+                material_index: submesh.material_index,
+                lods: ranges.split_off(1),
+                aabb: submesh.aabb,
+                bounding_sphere: submesh.bounding_sphere,
+            });
+        }
+
+        self.vertex_count = vertices.len() / stride.max(1);
+        self.vertices = vertices;
+        self.indices = indices;
+        self.submeshes = submeshes;
+        self.morph_target_deltas = target_deltas.concat();
+        self.lod_errors = lod_errors;
+    }
+}
+
+/// Reorder the triangles so that consecutive ones share vertices, following
+/// Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    fn get_vertex_score(opt_cache_position: Option<usize>, remaining_triangle_count: u32) -> f32 {
+        if remaining_triangle_count == 0 {
+            return -1.0; // No triangles left to draw
+        }
+        let cache_score = match opt_cache_position {
+            // The vertices of the last triangle are penalized a little, so
+            // that strips don't keep going back and forth
+            Some(position) if position < 3 => 0.75,
+            Some(position) => {
+                let scaled = (position - 3) as f32 / (VERTEX_CACHE_SIZE - 3) as f32;
+                (1.0 - scaled).powf(1.5)
+            }
+            None => 0.0,
+        };
+        // Vertices with few triangles left are finished off first
+        cache_score + 2.0 * (remaining_triangle_count as f32).powf(-0.5)
+    }
+
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    // The triangles that use each vertex, as slices of `vertex_triangles`
+    let mut remaining_triangle_counts = vec![0_u32; vertex_count];
+    for index in indices.iter() {
+        remaining_triangle_counts[*index as usize] += 1;
+    }
+    let mut first_vertex_triangles = vec![0_usize; vertex_count + 1];
+    for vertex_idx in 0..vertex_count {
+        first_vertex_triangles[vertex_idx + 1] =
+            first_vertex_triangles[vertex_idx] + remaining_triangle_counts[vertex_idx] as usize;
+    }
+    let mut vertex_triangles = vec![0_u32; indices.len()];
+    let mut filled_counts = vec![0_usize; vertex_count];
+    for (idx, index) in indices.iter().enumerate() {
+        let vertex_idx = *index as usize;
+        vertex_triangles[first_vertex_triangles[vertex_idx] + filled_counts[vertex_idx]] =
+            (idx / 3) as u32;
+        filled_counts[vertex_idx] += 1;
+    }
+
+    let mut vertex_scores: Vec<f32> = remaining_triangle_counts
+        .iter()
+        .map(|count| get_vertex_score(None, *count))
+        .collect();
+    let get_triangle_score = |triangle_idx: usize, vertex_scores: &[f32]| -> f32 {
+        indices[triangle_idx * 3..triangle_idx * 3 + 3]
+            .iter()
+            .map(|index| vertex_scores[*index as usize])
+            .sum()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+        .map(|triangle_idx| get_triangle_score(triangle_idx, &vertex_scores))
+        .collect();
+    let mut is_emitted = vec![false; triangle_count];
+    let mut output: Vec<u32> = Vec::with_capacity(indices.len());
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut next_unemitted = 0;
+
+    let mut opt_best_triangle = (0..triangle_count).max_by(|a, b| {
+        triangle_scores[*a]
+            .partial_cmp(&triangle_scores[*b])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    while let Some(best_triangle) = opt_best_triangle {
+        is_emitted[best_triangle] = true;
+        let triangle = [
+            indices[best_triangle * 3],
+            indices[best_triangle * 3 + 1],
+            indices[best_triangle * 3 + 2],
+        ];
+        output.extend_from_slice(&triangle);
+
+        // Remove the triangle from the lists of its vertices
+        for vertex_idx in &triangle {
+            let vertex_idx = *vertex_idx as usize;
+            let first = first_vertex_triangles[vertex_idx];
+            let count = remaining_triangle_counts[vertex_idx] as usize;
+            if let Some(position) = vertex_triangles[first..first + count]
+                .iter()
+                .position(|triangle_idx| *triangle_idx as usize == best_triangle)
+            {
+                vertex_triangles.swap(first + position, first + count - 1);
+                remaining_triangle_counts[vertex_idx] -= 1;
+            }
+        }
+
+        // Move the vertices of the triangle to the front of the cache
+        let mut new_cache: Vec<u32> = triangle.to_vec();
+        new_cache.extend(
+            cache
+                .iter()
+                .filter(|vertex_idx| !triangle.contains(vertex_idx)),
+        );
+        let evicted: Vec<u32> = new_cache.split_off(VERTEX_CACHE_SIZE.min(new_cache.len()));
+        cache = new_cache;
+
+        // Rescore the vertices whose cache position changed, and pick the
+        // best triangle among the ones that use the cached vertices
+        for (position, vertex_idx) in cache.iter().enumerate() {
+            let vertex_idx = *vertex_idx as usize;
+            vertex_scores[vertex_idx] =
+                get_vertex_score(Some(position), remaining_triangle_counts[vertex_idx]);
+        }
+        for vertex_idx in &evicted {
+            let vertex_idx = *vertex_idx as usize;
+            vertex_scores[vertex_idx] =
+                get_vertex_score(None, remaining_triangle_counts[vertex_idx]);
+        }
+        opt_best_triangle = None;
+        let mut best_score = f32::MIN;
+        for vertex_idx in cache.iter().chain(&evicted) {
+            let vertex_idx = *vertex_idx as usize;
+            let first = first_vertex_triangles[vertex_idx];
+            let count = remaining_triangle_counts[vertex_idx] as usize;
+            for triangle_idx in &vertex_triangles[first..first + count] {
+                let triangle_idx = *triangle_idx as usize;
+                triangle_scores[triangle_idx] = get_triangle_score(triangle_idx, &vertex_scores);
+                if triangle_scores[triangle_idx] > best_score {
+                    best_score = triangle_scores[triangle_idx];
+                    opt_best_triangle = Some(triangle_idx);
+                }
+            }
+        }
+
+        // ...The cache doesn't lead anywhere. Start over from any triangle.
+        if opt_best_triangle.is_none() {
+            while next_unemitted < triangle_count && is_emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            if next_unemitted < triangle_count {
+                opt_best_triangle = Some(next_unemitted);
+            }
+        }
+    }
+
+    indices.copy_from_slice(&output);
+}
+
+/// Vertices that share their position with another used vertex, because
+/// their other attributes differ. Moving them would tear the mesh apart.
+fn get_seam_vertices(positions: &[Vec3], indices: &[u32]) -> Vec<bool> {
+    let mut is_used = vec![false; positions.len()];
+    for index in indices {
+        is_used[*index as usize] = true;
+    }
+    let mut position_counts: HashMap<[u32; 3], u32> = HashMap::new();
+    let get_key = |position: &Vec3| {
+        [
+            position.x().to_bits(),
+            position.y().to_bits(),
+            position.z().to_bits(),
+        ]
+    };
+    for (position, is_used) in positions.iter().zip(&is_used) {
+        if *is_used {
+            *position_counts.entry(get_key(position)).or_insert(0) += 1;
+        }
+    }
+    positions
+        .iter()
+        .zip(&is_used)
+        .map(|(position, is_used)| *is_used && position_counts[&get_key(position)] > 1)
+        .collect()
+}
+
+// The weighted sum of squared distances to a set of planes, as the
+// coefficients of a symmetric 4x4 matrix
+#[derive(Copy, Clone, Default)]
+struct Quadric {
+    coefficients: [f64; 10],
+    weight: f64,
+}
+
+impl Quadric {
+    fn from_plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Quadric {
+        Quadric {
+            coefficients: [
+                a * a * weight,
+                a * b * weight,
+                a * c * weight,
+                a * d * weight,
+                b * b * weight,
+                b * c * weight,
+                b * d * weight,
+                c * c * weight,
+                c * d * weight,
+                d * d * weight,
+            ],
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (value, other_value) in self.coefficients.iter_mut().zip(&other.coefficients) {
+            *value += other_value;
+        }
+        self.weight += other.weight;
+    }
+
+    // The mean squared distance to the planes
+    fn get_error(&self, position: Vec3) -> f64 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        let q = &self.coefficients;
+        let (x, y, z) = (
+            position.x() as f64,
+            position.y() as f64,
+            position.z() as f64,
+        );
+        (q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9])
+            / self.weight
+    }
+}
+
+/// Collapse edges in order of increasing quadric error until the triangle
+/// count reaches the target, or no edge can be collapsed. Vertices only ever
+/// move onto one of their neighbours, so the vertex buffer can be shared
+/// with the full-detail mesh. Vertices on borders and attribute seams are
+/// locked. Returns the largest RMS distance from a collapsed vertex to the
+/// planes of its original triangles.
+fn simplify(
+    positions: &[Vec3],
+    is_seam: &[bool],
+    indices: &[u32],
+    target_triangle_count: usize,
+) -> (Vec<u32>, f32) {
+    let vertex_count = positions.len();
+    let mut indices = indices.to_vec();
+
+    // Edges that don't have exactly two triangles, once seams are welded,
+    // are borders or non-manifold
+    let get_position_key = |vertex_idx: u32| {
+        let position = positions[vertex_idx as usize];
+        [
+            position.x().to_bits(),
+            position.y().to_bits(),
+            position.z().to_bits(),
+        ]
+    };
+    let mut edge_counts: HashMap<([u32; 3], [u32; 3]), u32> = HashMap::new();
+    for triangle in indices.chunks(3) {
+        for edge_idx in 0..3 {
+            let a = get_position_key(triangle[edge_idx]);
+            let b = get_position_key(triangle[(edge_idx + 1) % 3]);
+            *edge_counts.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+    let mut is_locked = is_seam.to_vec();
+    for triangle in indices.chunks(3) {
+        for edge_idx in 0..3 {
+            let (a, b) = (triangle[edge_idx], triangle[(edge_idx + 1) % 3]);
+            let (key_a, key_b) = (get_position_key(a), get_position_key(b));
+            if edge_counts[&(key_a.min(key_b), key_a.max(key_b))] != 2 {
+                is_locked[a as usize] = true;
+                is_locked[b as usize] = true;
+            }
+        }
+    }
+
+    // Area-weighted planes of the triangles around each vertex
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    for triangle in indices.chunks(3) {
+        let p0 = positions[triangle[0] as usize];
+        let cross =
+            (positions[triangle[1] as usize] - p0).cross(positions[triangle[2] as usize] - p0);
+        let double_area = cross.length();
+        if double_area <= 0.0 {
+            continue;
+        }
+        let normal = cross / double_area;
+        let quadric = Quadric::from_plane(
+            normal.x() as f64,
+            normal.y() as f64,
+            normal.z() as f64,
+            -normal.dot(p0) as f64,
+            double_area as f64 * 0.5,
+        );
+        for index in triangle {
+            quadrics[*index as usize].add(&quadric);
+        }
+    }
+
+    let mut max_error: f64 = 0.0;
+    while indices.len() / 3 > target_triangle_count {
+        // The triangles around each vertex
+        let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+        for (triangle_idx, triangle) in indices.chunks(3).enumerate() {
+            for index in triangle {
+                vertex_triangles[*index as usize].push(triangle_idx);
+            }
+        }
+
+        // Moving `from` onto `to`, cheapest first
+        let mut collapses: Vec<(f64, u32, u32)> = Vec::new();
+        for triangle in indices.chunks(3) {
+            for edge_idx in 0..3 {
+                let a = triangle[edge_idx];
+                let b = triangle[(edge_idx + 1) % 3];
+                for (from, to) in &[(a, b), (b, a)] {
+                    if !is_locked[*from as usize] && !is_seam[*to as usize] {
+                        let error = quadrics[*from as usize].get_error(positions[*to as usize]);
+                        collapses.push((error.max(0.0), *from, *to));
+                    }
+                }
+            }
+        }
+        collapses.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        // Collapse the edges whose neighbourhoods don't overlap, so that the
+        // errors and flip checks stay valid within a pass
+        let mut remap: Vec<u32> = (0..vertex_count as u32).collect();
+        let mut is_touched = vec![false; vertex_count];
+        let mut removed_triangle_count = 0;
+        let triangle_count = indices.len() / 3;
+        for (error, from, to) in collapses {
+            if triangle_count - removed_triangle_count <= target_triangle_count {
+                break;
+            }
+            let (from_idx, to_idx) = (from as usize, to as usize);
+            if is_touched[from_idx] || is_touched[to_idx] {
+                continue;
+            }
+
+            // Reject collapses that would flip a triangle over
+            let mut shared_triangle_count = 0;
+            let mut is_flipping = false;
+            for triangle_idx in &vertex_triangles[from_idx] {
+                let triangle = &indices[triangle_idx * 3..triangle_idx * 3 + 3];
+                if triangle.contains(&to) {
+                    shared_triangle_count += 1;
+                    continue;
+                }
+                let get_normal = |moved_position: Vec3| {
+                    let p: Vec<Vec3> = triangle
+                        .iter()
+                        .map(|index| {
+                            if *index == from {
+                                moved_position
+                            } else {
+                                positions[*index as usize]
+                            }
+                        })
+                        .collect();
+                    (p[1] - p[0]).cross(p[2] - p[0])
+                };
+                let before = get_normal(positions[from_idx]);
+                let after = get_normal(positions[to_idx]);
+                if before.dot(after) <= 0.0 {
+                    is_flipping = true;
+                    break;
+                }
+            }
+            if is_flipping {
+                continue;
+            }
+
+            for triangle_idx in &vertex_triangles[from_idx] {
+                for index in &indices[triangle_idx * 3..triangle_idx * 3 + 3] {
+                    is_touched[*index as usize] = true;
+                }
+            }
+            remap[from_idx] = to;
+            let from_quadric = quadrics[from_idx];
+            quadrics[to_idx].add(&from_quadric);
+            max_error = max_error.max(error);
+            removed_triangle_count += shared_triangle_count;
+        }
+        if removed_triangle_count == 0 {
+            break; // Nothing left that can be collapsed
+        }
+
+        // Apply the collapses, and drop the triangles that became degenerate
+        let mut collapsed_indices = Vec::with_capacity(indices.len());
+        for triangle in indices.chunks(3) {
+            let triangle = [
+                remap[triangle[0] as usize],
+                remap[triangle[1] as usize],
+                remap[triangle[2] as usize],
+            ];
+            if triangle[0] != triangle[1]
+                && triangle[1] != triangle[2]
+                && triangle[2] != triangle[0]
+            {
+                collapsed_indices.extend_from_slice(&triangle);
+            }
+        }
+        indices = collapsed_indices;
+    }
+
+    (indices, max_error.sqrt() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A triangle list of vertices with a position and a normal each
+    fn baked_mesh(vertices: &[[f32; 6]], indices: &[u32]) -> BakedMesh {
+        BakedMesh {
+            attributes: vec![MeshAttribute::Position, MeshAttribute::Normal],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            vertices: vertices.concat(),
+            indices: indices.to_vec(),
+            submeshes: vec![Submesh {
+                first_index: 0,
+                index_count: indices.len() as u32,
+                vertex_offset: 0,
+                material_index: None,
+                lods: Vec::new(),
+                aabb: Aabb {
+                    min: Vec3::zero(),
+                    max: Vec3::one(),
+                },
+                bounding_sphere: BoundingSphere {
+                    center: Vec3::zero(),
+                    radius: 1.0,
+                },
+            }],
+            vertex_count: vertices.len(),
+            morph_target_count: 0,
+            morph_target_deltas: Vec::new(),
+            default_morph_weights: Vec::new(),
+            materials: Vec::new(),
+            material_images: Vec::new(),
+            lod_errors: vec![0.0],
+            import_warnings: Vec::new(),
+        }
+    }
+
+    // An n x n grid of quads in the XY plane, displaced along Z
+    fn grid(n: u32, height: impl Fn(f32, f32) -> f32) -> BakedMesh {
+        let mut vertices = Vec::new();
+        for y in 0..=n {
+            for x in 0..=n {
+                let (x, y) = (x as f32 / n as f32, y as f32 / n as f32);
+                vertices.push([x, y, height(x, y), 0.0, 0.0, 1.0]);
+            }
+        }
+        let mut indices = Vec::new();
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                indices.extend_from_slice(&[i, i + 1, i + n + 2, i, i + n + 2, i + n + 1]);
+            }
+        }
+        baked_mesh(&vertices, &indices)
+    }
+
+    // The vertices of each triangle of a range of the index buffer
+    fn triangles(mesh: &BakedMesh, first_index: u32, index_count: u32) -> Vec<Vec<u32>> {
+        let stride = 6;
+        let vertex_offset = mesh.submeshes[0].vertex_offset as usize;
+        let first_index = first_index as usize;
+        mesh.indices[first_index..first_index + index_count as usize]
+            .chunks(3)
+            .map(|triangle| {
+                triangle
+                    .iter()
+                    .flat_map(|index| {
+                        let vertex_idx = vertex_offset + *index as usize;
+                        assert!(vertex_idx < mesh.vertex_count);
+                        mesh.vertices[vertex_idx * stride..(vertex_idx + 1) * stride]
+                            .iter()
+                            .map(|value| value.to_bits())
+                            .collect::<Vec<u32>>()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn full_detail_triangles(mesh: &BakedMesh) -> Vec<Vec<u32>> {
+        let submesh = &mesh.submeshes[0];
+        let mut triangles = triangles(mesh, submesh.first_index, submesh.index_count);
+        triangles.sort();
+        triangles
+    }
+
+    const WELD: MeshProcessing = MeshProcessing {
+        weld_vertices: true,
+        ..MeshProcessing::NONE
+    };
+
+    #[test]
+    fn welding_merges_identical_vertices() {
+        // A quad as two triangles that don't share their vertices
+        let corners = [
+            [0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            [1.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        ];
+        let vertices = [
+            corners[0], corners[1], corners[2], corners[0], corners[2], corners[3],
+        ];
+        let mut mesh = baked_mesh(&vertices, &[0, 1, 2, 3, 4, 5]);
+        let triangles_before = full_detail_triangles(&mesh);
+        mesh.process(&WELD);
+        assert_eq!(mesh.vertex_count, 4);
+        assert_eq!(mesh.vertices.len(), 4 * 6);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(full_detail_triangles(&mesh), triangles_before);
+    }
+
+    #[test]
+    fn welding_keeps_seams() {
+        // The same position with two normals, like the corner of a cube
+        let vertices = [
+            [0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 1.0, 0.0, 0.0],
+        ];
+        let mut mesh = baked_mesh(&vertices, &[0, 1, 2, 3, 4, 5]);
+        mesh.process(&WELD);
+        assert_eq!(mesh.vertex_count, 6);
+
+        // Identical vertices with different morph target deltas
+        let vertices = [
+            [0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+        ];
+        let mut mesh = baked_mesh(&vertices, &[0, 1, 2, 3, 1, 2]);
+        mesh.morph_target_count = 1;
+        mesh.morph_target_deltas = vec![[0.0; 4]; 4 * 3];
+        mesh.morph_target_deltas[3 * 3] = [0.0, 0.0, 1.0, 0.0];
+        mesh.process(&WELD);
+        assert_eq!(mesh.vertex_count, 4);
+        assert_eq!(mesh.morph_target_deltas.len(), 4 * 3);
+        assert_eq!(mesh.morph_target_deltas[3 * 3], [0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn reordering_keeps_the_triangles() {
+        let mut mesh = grid(6, |_, _| 0.0);
+        let triangles_before = full_detail_triangles(&mesh);
+        // An unused vertex, dropped when the vertices are reordered
+        mesh.vertices
+            .extend_from_slice(&[2.0, 2.0, 2.0, 0.0, 0.0, 1.0]);
+        mesh.vertex_count += 1;
+        mesh.process(&MeshProcessing {
+            optimize_vertex_cache: true,
+            optimize_vertex_fetch: true,
+            ..MeshProcessing::NONE
+        });
+        assert_eq!(mesh.vertex_count, 7 * 7);
+        assert_eq!(full_detail_triangles(&mesh), triangles_before);
+        // Vertices are in the order of their first use
+        let mut next_new_vertex = 0;
+        for index in &mesh.indices {
+            assert!(*index <= next_new_vertex);
+            if *index == next_new_vertex {
+                next_new_vertex += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn lods_of_a_plane() {
+        let mut mesh = grid(8, |_, _| 0.0);
+        mesh.process(&MeshProcessing {
+            weld_vertices: true,
+            lod_count: 3,
+            lod_triangle_ratio: 0.5,
+            ..MeshProcessing::NONE
+        });
+        let submesh = &mesh.submeshes[0];
+        assert_eq!(submesh.index_count, 8 * 8 * 6);
+        assert_eq!(submesh.lods.len(), 3);
+        assert_eq!(mesh.lod_errors.len(), 4);
+
+        let mut previous_count = submesh.index_count;
+        for lod in &submesh.lods {
+            assert!(lod.index_count < previous_count);
+            assert!(lod.index_count > 0);
+            assert!(lod.first_index + lod.index_count <= mesh.indices.len() as u32);
+            // Every LOD still faces up, and covers the whole grid since its
+            // borders are locked
+            let mut area = 0.0;
+            for triangle in mesh.indices
+                [lod.first_index as usize..(lod.first_index + lod.index_count) as usize]
+                .chunks(3)
+            {
+                let p: Vec<Vec3> = triangle
+                    .iter()
+                    .map(|index| Vec3::from_slice_unaligned(&mesh.vertices[*index as usize * 6..]))
+                    .collect();
+                let double_area = (p[1] - p[0]).cross(p[2] - p[0]).z();
+                assert!(double_area > 0.0);
+                area += double_area * 0.5;
+            }
+            assert!((area - 1.0).abs() < 1e-5);
+            previous_count = lod.index_count;
+        }
+        assert!(submesh.lods[0].index_count <= submesh.index_count / 2);
+        assert_eq!(mesh.lod_errors, vec![0.0; 4]);
+    }
+
+    #[test]
+    fn lod_errors_grow() {
+        let mut mesh = grid(8, |x, y| (x * 6.0).sin() * (y * 6.0).cos() * 0.2);
+        mesh.process(&MeshProcessing {
+            weld_vertices: true,
+            lod_count: 3,
+            lod_triangle_ratio: 0.5,
+            ..MeshProcessing::NONE
+        });
+        assert_eq!(mesh.lod_errors[0], 0.0);
+        assert!(mesh.lod_errors[1] > 0.0);
+        for lod in 1..mesh.lod_errors.len() {
+            assert!(mesh.lod_errors[lod] >= mesh.lod_errors[lod - 1]);
+            assert!(mesh.lod_errors[lod] < 1.0);
+        }
+    }
+
+    #[test]
+    fn point_clouds_have_no_lods() {
+        let mut mesh = grid(2, |_, _| 0.0);
+        mesh.topology = vk::PrimitiveTopology::POINT_LIST;
+        mesh.indices = (0..mesh.vertex_count as u32).collect();
+        mesh.submeshes[0].index_count = mesh.vertex_count as u32;
+        mesh.process(&MeshProcessing::ALL);
+        assert!(mesh.submeshes[0].lods.is_empty());
+        assert_eq!(mesh.lod_errors, vec![0.0]);
+        assert_eq!(mesh.indices.len(), 9);
+    }
+}
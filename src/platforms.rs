@@ -30,7 +30,7 @@ pub fn required_extension_names() -> Vec<*const i8> {
     ]
 }
 
-#[cfg(all(windows))]
+#[cfg(windows)]
 pub fn required_extension_names() -> Vec<*const i8> {
     vec![
         Surface::name().as_ptr(),
@@ -47,6 +47,11 @@ pub fn required_extension_names() -> Vec<*const i8> {
         DebugUtils::name().as_ptr(),
     ]
 }
+
+/// Headless contexts don't present, so they don't need any surface extensions.
+pub fn required_headless_extension_names() -> Vec<*const i8> {
+    vec![DebugUtils::name().as_ptr()]
+}
 // ------------------------------------------------------------------------
 
 // create surface ---------------------------------------------------------
@@ -1,27 +1,135 @@
 use crate::*;
+use std::cell::Cell;
+
+#[derive(Debug, Hash)]
+pub enum PassKind {
+    Graphics {
+        vertex_shader: ShaderHandle,
+        fragment_shader: ShaderHandle,
+        pipeline_state: PipelineState,
+        opt_vertex_layout: Option<VertexLayout>, // Reflected from the vertex shader if None
+    },
+    Compute {
+        compute_shader: ShaderHandle,
+        group_count: [u32; 3], // Number of workgroups dispatched in each dimension
+    },
+}
+
+impl PassKind {
+    pub fn shader_handles(&self) -> Vec<ShaderHandle> {
+        match self {
+            PassKind::Graphics {
+                vertex_shader,
+                fragment_shader,
+                ..
+            } => vec![*vertex_shader, *fragment_shader],
+            PassKind::Compute { compute_shader, .. } => vec![*compute_shader],
+        }
+    }
+
+    pub fn shader_stage_mask(&self) -> vk::PipelineStageFlags {
+        match self {
+            PassKind::Graphics { .. } => {
+                vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER
+            }
+            PassKind::Compute { .. } => vk::PipelineStageFlags::COMPUTE_SHADER,
+        }
+    }
+
+    pub fn bind_point(&self) -> vk::PipelineBindPoint {
+        match self {
+            PassKind::Graphics { .. } => vk::PipelineBindPoint::GRAPHICS,
+            PassKind::Compute { .. } => vk::PipelineBindPoint::COMPUTE,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Hash)]
+pub struct SampledImage {
+    pub binding: u32,
+    pub image: ImageHandle,
+    pub sampler: vk::Sampler,
+}
+
+#[derive(Copy, Clone, Debug, Hash)]
+pub struct UniformBuffer {
+    pub binding: u32,
+    pub buffer: BufferHandle,
+}
+
+#[derive(Copy, Clone, Debug, Hash)]
+pub struct StorageImage {
+    pub binding: u32,
+    pub image: ImageHandle,
+    pub access: StorageAccess,
+}
+
+#[derive(Copy, Clone, Debug, Hash)]
+pub struct StorageBuffer {
+    pub binding: u32,
+    pub buffer: BufferHandle,
+    pub access: StorageAccess,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ColorOutput {
+    pub image: ImageHandle,
+    // Cleared to this color when it's first used in the graph, otherwise the
+    // first use starts from undefined contents, e.g. when a full-screen pass
+    // overwrites every pixel
+    pub opt_clear_color: Option<[f32; 4]>,
+    // Overrides the blend mode of the pipeline state for this output
+    pub opt_blend_mode: Option<BlendMode>,
+}
+
+impl Hash for ColorOutput {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.image.hash(state);
+        self.opt_clear_color.is_some().hash(state);
+        if let Some(clear_color) = &self.opt_clear_color {
+            for channel in clear_color {
+                channel.to_bits().hash(state);
+            }
+        }
+        self.opt_blend_mode.hash(state);
+    }
+}
 
 #[derive(Debug, Hash)]
 pub struct BuilderPass {
     pub name: String,
-    pub vertex_shader: ShaderHandle,
-    pub fragment_shader: ShaderHandle,
-    pub output_images: Vec<ImageHandle>,
-    pub input_image: (vk::ImageView, vk::Sampler), // TODO: Convert to image handle
+    pub kind: PassKind,
+    pub output_images: Vec<ColorOutput>, // Empty for compute passes
     pub opt_depth_image: Option<ImageHandle>,
+    // Shader resources, each sorted by binding so that the hash doesn't depend
+    // on the order they were declared in
+    pub sampled_images: Vec<SampledImage>,
+    pub uniform_buffers: Vec<UniformBuffer>,
+    pub storage_images: Vec<StorageImage>,
+    pub storage_buffers: Vec<StorageBuffer>,
     pub viewport_width: u32,
     pub viewport_height: u32,
-    pub uniform_buffer: BufferHandle,
 }
 
 pub struct BuiltPass {
     pub pass_handle: PassHandle,
     pub clear_values: Vec<vk::ClearValue>,
-    pub descriptor_set_layout: vk::DescriptorSetLayout,
-    pub descriptor_set: vk::DescriptorSet,
-    pub framebuffer: vk::Framebuffer,
-    pub render_pass: vk::RenderPass,
+    // Barriers for the resources this pass accesses from its shaders, recorded
+    // before the pass begins
+    pub image_barriers: Vec<vk::ImageMemoryBarrier>,
+    pub buffer_barriers: Vec<vk::BufferMemoryBarrier>,
+    pub barrier_src_stage_mask: vk::PipelineStageFlags,
+    pub barrier_dst_stage_mask: vk::PipelineStageFlags,
+    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>, // Of set 0, if the pass binds anything
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub dynamic_offset_count: usize, // One per uniform ring the pass binds
+    pub opt_push_constant_range: Option<vk::PushConstantRange>,
+    pub framebuffer: vk::Framebuffer, // Null for compute passes
+    pub render_pass: vk::RenderPass,  // Null for compute passes
     pub pipeline_layout: vk::PipelineLayout,
-    pub graphics_pipeline: vk::Pipeline,
+    pub pipeline: vk::Pipeline,
+    pub bind_point: vk::PipelineBindPoint,
+    pub opt_group_count: Option<[u32; 3]>, // Dispatched at the end of compute passes
     pub viewport_width: u32,
     pub viewport_height: u32,
 }
@@ -33,18 +141,24 @@ pub struct Graph {
     descriptor_pool: vk::DescriptorPool,
     pub built_passes: Vec<BuiltPass>,
     pub shader_handles: Vec<ShaderHandle>, // Needed for shader hot reloading
+    active_pass_idx: Cell<Option<usize>>,  // The pass between begin_pass() and end_pass()
+    // The resources declared by the graph builder, destroyed after the
+    // descriptors and framebuffers using them
+    buffers: Vec<(BufferHandle, vk::BufferUsageFlags, HostVisibleBuffer)>,
+    pub transient_resources: TransientResources,
 }
 
 impl Drop for Graph {
     fn drop(&mut self) {
         unsafe {
             for built_pass in &mut self.built_passes {
-                self.device
-                    .destroy_pipeline(built_pass.graphics_pipeline, None);
+                self.device.destroy_pipeline(built_pass.pipeline, None);
                 self.device
                     .destroy_pipeline_layout(built_pass.pipeline_layout, None);
-                self.device
-                    .destroy_descriptor_set_layout(built_pass.descriptor_set_layout, None);
+                for descriptor_set_layout in &built_pass.descriptor_set_layouts {
+                    self.device
+                        .destroy_descriptor_set_layout(*descriptor_set_layout, None);
+                }
                 self.device
                     .destroy_framebuffer(built_pass.framebuffer, None);
                 self.device
@@ -56,29 +170,83 @@ impl Drop for Graph {
     }
 }
 
+// A resource bound to a descriptor of a pass
+struct PassDescriptor {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    resource: DescriptorResource,
+}
+
+enum DescriptorResource {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
 impl Graph {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         gpu: &Gpu,
-        builder_passes: &Vec<(PassHandle, BuilderPass)>,
+        graph_builder: &GraphBuilder,
         shader_list: &ShaderList,
         buffer_list: &BufferList,
         image_list: &ImageList,
-    ) -> Graph {
+        facade: &Facade,
+        frame_idx: usize, // Selects the copies of the per-frame buffers
+        resource_pool: &mut ResourcePool,
+        debug_utils: &DebugUtils,
+    ) -> Result<Graph, String> {
+        let builder_passes = &graph_builder.passes;
+        // Merge the shader reflections of each pass into its layout
+        let pass_layouts: Vec<PassLayout> = builder_passes
+            .iter()
+            .map(|(_, pass)| PassLayout::new(pass, shader_list, buffer_list))
+            .collect::<Result<Vec<PassLayout>, String>>()?;
+
+        // Headless contexts can't present, so their frames are left ready to
+        // be copied out instead.
+        let present_layout = if gpu.is_headless {
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+        } else {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        };
+        // Derive the state of each resource around each of its uses
+        let mut usages = ResourceUsages::new(builder_passes, image_list, present_layout);
+        // Create the transient resources, which also adds the barriers between
+        // those that share memory
+        let transient_resources = TransientResources::new(
+            gpu,
+            graph_builder,
+            image_list,
+            buffer_list,
+            facade,
+            &mut usages,
+            resource_pool,
+            debug_utils,
+        )?;
+
         // Create descriptor pool
         let descriptor_pool = {
-            let pool_sizes = [
-                vk::DescriptorPoolSize {
-                    ty: vk::DescriptorType::UNIFORM_BUFFER,
-                    descriptor_count: 2, // TODO: Derive this number
-                },
-                vk::DescriptorPoolSize {
-                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                    descriptor_count: 2, // TODO: Derive this number
-                },
-            ];
+            let mut pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+            for (binding, _) in pass_layouts.iter().flat_map(|l| &l.bindings) {
+                match pool_sizes
+                    .iter_mut()
+                    .find(|size| size.ty == binding.descriptor_type)
+                {
+                    Some(size) => size.descriptor_count += binding.descriptor_count,
+                    None => pool_sizes.push(vk::DescriptorPoolSize {
+                        ty: binding.descriptor_type,
+                        descriptor_count: binding.descriptor_count,
+                    }),
+                }
+            }
+            // A set for each pass that binds resources
+            let max_sets = pass_layouts
+                .iter()
+                .filter(|l| !l.bindings.is_empty())
+                .count() as u32;
 
             let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
-                .max_sets(2) // TODO: Derive this number
+                .max_sets(max_sets.max(1))
                 .pool_sizes(&pool_sizes);
 
             unsafe {
@@ -88,376 +256,193 @@ impl Graph {
             }
         };
 
+        // Resolve the buffers declared by the graph builder
+        let buffers: Vec<(BufferHandle, vk::BufferUsageFlags, HostVisibleBuffer)> = graph_builder
+            .buffers
+            .iter()
+            .map(|(buffer_handle, graph_buffer)| {
+                let buffer = resource_pool.take_buffer(
+                    &graph_buffer.name,
+                    graph_buffer.size,
+                    graph_buffer.usage,
+                    gpu,
+                    debug_utils,
+                );
+                (*buffer_handle, graph_buffer.usage, buffer)
+            })
+            .collect();
+        let resources = GraphResources {
+            image_list,
+            buffer_list,
+            buffers: &buffers,
+            transient_resources: &transient_resources,
+            frame_idx,
+        };
+
+        // Gather the resources bound to the descriptors of each pass
+        let pass_descriptors: Vec<Vec<PassDescriptor>> = builder_passes
+            .iter()
+            .map(|(_, pass)| get_pass_descriptors(pass, &resources))
+            .collect();
+
         let mut shader_handles = Vec::new();
         let mut built_passes = Vec::new();
-        for (pass_handle, pass) in builder_passes {
+        for (pass_idx, (pass_handle, pass)) in builder_passes.iter().enumerate() {
             /* Record which shader handles have been used. This is needed for
             hot-reloading shaders. */
-            shader_handles.push(pass.vertex_shader);
-            shader_handles.push(pass.fragment_shader);
-
-            // Find depth image
-            let mut opt_depth_image = None;
-            if let Some(depth_handle) = pass.opt_depth_image {
-                opt_depth_image = Some(
-                    image_list
-                        .get_image_from_handle(depth_handle)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Image with handle `{:?}` not found in the context.",
-                                depth_handle
-                            )
-                        }),
-                );
-            }
-
-            // Find output images
-            let output_images: Vec<&InternalImage> = pass
-                .output_images
-                .iter()
-                .map(|output_handle| {
-                    image_list
-                        .get_image_from_handle(*output_handle)
-                        .unwrap_or_else(|| {
-                            panic!(
-                                "Image with handle `{:?}` not found in the context.",
-                                output_handle
-                            )
-                        })
-                })
-                .collect();
-
-            /* Create render pass */
-            let render_pass = {
-                let mut attachments: Vec<vk::AttachmentDescription> = Vec::new();
-                let mut attachment_idx = 0;
-                let mut depth_attachment_ptr = ptr::null();
-                let mut color_attachments = Vec::new();
-
-                // Depth attachment description and reference
-                let depth_attachment = vk::AttachmentReference {
-                    attachment: 0,
-                    layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                };
-                if let Some(depth_image) = opt_depth_image {
-                    attachments.push(vk::AttachmentDescription {
-                        format: depth_image.image.format,
-                        flags: vk::AttachmentDescriptionFlags::empty(),
-                        samples: vk::SampleCountFlags::TYPE_1,
-                        load_op: vk::AttachmentLoadOp::CLEAR,
-                        store_op: vk::AttachmentStoreOp::DONT_CARE, // TODO: Derive from graph
-                        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
-                        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-                        initial_layout: vk::ImageLayout::UNDEFINED,
-                        final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
-                    });
-
-                    depth_attachment_ptr = &depth_attachment;
-                    attachment_idx += 1;
-                }
+            shader_handles.extend(pass.kind.shader_handles());
 
-                // Color attachment descriptions and references
-                for output_image in &output_images {
-                    attachments.push(vk::AttachmentDescription {
-                        format: output_image.image.format,
-                        flags: vk::AttachmentDescriptionFlags::empty(),
-                        samples: vk::SampleCountFlags::TYPE_1,
-                        load_op: vk::AttachmentLoadOp::CLEAR,
-                        store_op: vk::AttachmentStoreOp::STORE, // TODO: Derive from graph
-                        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
-                        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-                        initial_layout: vk::ImageLayout::UNDEFINED,
-                        final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
-                    });
-                    color_attachments.push(vk::AttachmentReference {
-                        attachment: attachment_idx,
-                        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-                    });
-                    attachment_idx += 1;
+            /* Create render pass, framebuffer and clear values */
+            let (render_pass, framebuffer, clear_values) = match pass.kind {
+                PassKind::Graphics { .. } => {
+                    create_render_pass(gpu, pass, pass_idx, &usages, &resources)
                 }
-
-                let subpasses = [vk::SubpassDescription {
-                    pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
-                    color_attachment_count: 1,
-                    p_color_attachments: color_attachments.as_ptr(),
-                    p_depth_stencil_attachment: depth_attachment_ptr,
-                    ..Default::default()
-                }];
-
-                let renderpass_create_info = vk::RenderPassCreateInfo::builder()
-                    .attachments(&attachments)
-                    .subpasses(&subpasses);
-
-                unsafe {
-                    gpu.device
-                        .create_render_pass(&renderpass_create_info, None)
-                        .expect("Failed to create render pass.")
+                PassKind::Compute { .. } => {
+                    (vk::RenderPass::null(), vk::Framebuffer::null(), Vec::new())
                 }
             };
 
-            /* Create framebuffer */
-            let framebuffer: vk::Framebuffer = {
-                let mut attachments: Vec<vk::ImageView> = Vec::new();
-                if let Some(depth_image) = opt_depth_image {
-                    attachments.push(depth_image.image.image_view);
-                }
-                for output_image in &output_images {
-                    attachments.push(output_image.image.image_view);
+            /* Create barriers for the resources accessed from shaders.
+            Attachments are transitioned by the render pass, but everything
+            else needs an explicit barrier. */
+            let mut image_barriers = Vec::new();
+            let mut buffer_barriers = Vec::new();
+            let mut barrier_src_stage_mask = vk::PipelineStageFlags::empty();
+            let mut barrier_dst_stage_mask = vk::PipelineStageFlags::empty();
+            {
+                let mut image_handles: Vec<ImageHandle> =
+                    pass.storage_images.iter().map(|s| s.image).collect();
+                image_handles.extend(pass.sampled_images.iter().map(|s| s.image));
+                for image_handle in image_handles {
+                    let state = usages.get_image(pass_idx, image_handle);
+                    if !state.needs_barrier {
+                        continue;
+                    }
+                    let image = resources.get_image(image_handle);
+                    image_barriers.push(vk::ImageMemoryBarrier {
+                        src_access_mask: state.src_access_mask,
+                        dst_access_mask: state.access_mask,
+                        old_layout: state.initial_layout,
+                        new_layout: state.layout,
+                        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        image: image.vk_image,
+                        subresource_range: vk::ImageSubresourceRange {
+                            aspect_mask: image.aspect_flags,
+                            base_mip_level: 0,
+                            level_count: 1,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        },
+                        ..Default::default()
+                    });
+                    barrier_src_stage_mask |= state.src_stage_mask;
+                    barrier_dst_stage_mask |= state.stage_mask;
                 }
 
-                let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
-                    .render_pass(render_pass)
-                    .attachments(&attachments)
-                    .width(pass.viewport_width)
-                    .height(pass.viewport_height)
-                    .layers(1);
-
-                unsafe {
-                    gpu.device
-                        .create_framebuffer(&framebuffer_create_info, None)
-                        .expect("Failed to create framebuffer.")
+                let mut buffer_handles: Vec<BufferHandle> =
+                    pass.storage_buffers.iter().map(|s| s.buffer).collect();
+                buffer_handles.extend(pass.uniform_buffers.iter().map(|u| u.buffer));
+                for buffer_handle in buffer_handles {
+                    let state = usages.get_buffer(pass_idx, buffer_handle);
+                    if !state.needs_barrier {
+                        continue;
+                    }
+                    let (vk_buffer, _) = resources.get_buffer(buffer_handle);
+                    buffer_barriers.push(vk::BufferMemoryBarrier {
+                        src_access_mask: state.src_access_mask,
+                        dst_access_mask: state.access_mask,
+                        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        buffer: vk_buffer,
+                        offset: 0,
+                        size: vk::WHOLE_SIZE,
+                        ..Default::default()
+                    });
+                    barrier_src_stage_mask |= state.src_stage_mask;
+                    barrier_dst_stage_mask |= state.stage_mask;
                 }
-            };
-
-            /* Set clear values */
-            let mut clear_values = Vec::new();
-            if opt_depth_image.is_some() {
-                // Clear value for depth buffer
-                clear_values.push(vk::ClearValue {
-                    depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 1.0,
-                        stencil: 0,
-                    },
-                });
-            }
-            for _ in &output_images {
-                // Clear values for color buffer
-                clear_values.push(vk::ClearValue {
-                    color: vk::ClearColorValue {
-                        float32: [0.0, 0.0, 0.0, 1.0],
-                    },
-                })
             }
 
-            /* Create descriptor set layout */
-            let descriptor_set_layout = {
-                let bindings = [
-                    vk::DescriptorSetLayoutBinding {
-                        binding: 0,
-                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                        descriptor_count: 1,
-                        stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
-                        p_immutable_samplers: ptr::null(),
-                    },
-                    vk::DescriptorSetLayoutBinding {
-                        binding: 1,
-                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                        descriptor_count: 1,
-                        stage_flags: vk::ShaderStageFlags::FRAGMENT,
-                        p_immutable_samplers: ptr::null(),
-                    },
-                ];
-
-                let ubo_layout_create_info =
-                    vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+            let pass_layout = &pass_layouts[pass_idx];
 
-                unsafe {
-                    gpu.device
-                        .create_descriptor_set_layout(&ubo_layout_create_info, None)
-                        .expect("Failed to create Descriptor Set Layout!")
-                }
-            };
+            /* Create the descriptor set layout, of set 0 only */
+            let descriptor_set_layouts: Vec<vk::DescriptorSetLayout> =
+                if pass_layout.bindings.is_empty() {
+                    Vec::new()
+                } else {
+                    let bindings: Vec<vk::DescriptorSetLayoutBinding> = pass_layout
+                        .bindings
+                        .iter()
+                        .map(|(binding, stage_flags)| vk::DescriptorSetLayoutBinding {
+                            binding: binding.binding,
+                            descriptor_type: binding.descriptor_type,
+                            descriptor_count: binding.descriptor_count,
+                            stage_flags: *stage_flags,
+                            p_immutable_samplers: ptr::null(),
+                        })
+                        .collect();
+
+                    let layout_create_info =
+                        vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+
+                    vec![unsafe {
+                        gpu.device
+                            .create_descriptor_set_layout(&layout_create_info, None)
+                            .expect("Failed to create Descriptor Set Layout!")
+                    }]
+                };
 
-            /* Create descriptor set */
-            let descriptor_set = {
-                let layouts = [descriptor_set_layout];
+            /* Create descriptor sets */
+            let descriptor_sets = if descriptor_set_layouts.is_empty() {
+                Vec::new()
+            } else {
                 let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
                     .descriptor_pool(descriptor_pool)
-                    .set_layouts(&layouts);
+                    .set_layouts(&descriptor_set_layouts);
                 let descriptor_sets = unsafe {
                     gpu.device
                         .allocate_descriptor_sets(&descriptor_set_allocate_info)
                         .expect("Failed to allocate descriptor sets.")
                 };
 
-                let uniform_buffer = buffer_list
-                    .get_buffer_from_handle(pass.uniform_buffer)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "Uniform buffer with handle `{:?}` not found in the context.",
-                            pass.uniform_buffer
-                        )
-                    });
-                let descriptor_buffer_info = [vk::DescriptorBufferInfo {
-                    buffer: uniform_buffer.vk_buffer,
-                    offset: 0,
-                    range: uniform_buffer.size as u64,
-                }];
-
-                let (input_image_view, input_sampler) = pass.input_image;
-                let descriptor_image_info = [vk::DescriptorImageInfo {
-                    sampler: input_sampler,
-                    image_view: input_image_view,
-                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                }];
-
-                let descriptor_write_sets = [
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_sets[0],
-                        dst_binding: 0,
-                        dst_array_element: 0,
-                        descriptor_count: 1,
-                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                        p_buffer_info: descriptor_buffer_info.as_ptr(),
-                        ..Default::default()
-                    },
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_sets[0],
-                        dst_binding: 1,
-                        dst_array_element: 0,
-                        descriptor_count: 1,
-                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                        p_image_info: descriptor_image_info.as_ptr(),
-                        ..Default::default()
-                    },
-                ];
+                // All the resources declared by passes live in set 0
+                let descriptor_write_sets: Vec<vk::WriteDescriptorSet> = pass_descriptors[pass_idx]
+                    .iter()
+                    .map(|descriptor| {
+                        let mut write = vk::WriteDescriptorSet {
+                            dst_set: descriptor_sets[0],
+                            dst_binding: descriptor.binding,
+                            dst_array_element: 0,
+                            descriptor_count: 1,
+                            descriptor_type: descriptor.descriptor_type,
+                            ..Default::default()
+                        };
+                        match &descriptor.resource {
+                            DescriptorResource::Buffer(info) => write.p_buffer_info = info,
+                            DescriptorResource::Image(info) => write.p_image_info = info,
+                        }
+                        write
+                    })
+                    .collect();
 
                 unsafe {
                     gpu.device
                         .update_descriptor_sets(&descriptor_write_sets, &[]);
                 }
-                descriptor_sets[0]
+                descriptor_sets
             };
 
-            /* Create graphics pipeline and pipeline layout */
-            let (graphics_pipeline, pipeline_layout) = {
-                let main_function_name = CString::new("main").unwrap();
-                let vertex_shader = shader_list
-                    .get_shader_from_handle(pass.vertex_shader)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "Vertex shader with handle `{}` not found in the context.",
-                            pass.vertex_shader.0
-                        )
-                    });
-                let fragment_shader = shader_list
-                    .get_shader_from_handle(pass.fragment_shader)
-                    .unwrap_or_else(|| {
-                        panic!(
-                            "Fragment shader with handle `{}` not found in the context.",
-                            pass.fragment_shader.0
-                        )
-                    });
-                let shader_stages = [
-                    vk::PipelineShaderStageCreateInfo {
-                        stage: vk::ShaderStageFlags::VERTEX,
-                        module: vertex_shader.vk_shader_module,
-                        p_name: main_function_name.as_ptr(),
-                        ..Default::default()
-                    },
-                    vk::PipelineShaderStageCreateInfo {
-                        stage: vk::ShaderStageFlags::FRAGMENT,
-                        module: fragment_shader.vk_shader_module,
-                        p_name: main_function_name.as_ptr(),
-                        ..Default::default()
-                    },
-                ];
-
-                // (pos: vec3 + normal: vec3) = 6 floats * 4 bytes per float
-                const VERTEX_STRIDE: u32 = 24;
-                let binding_descriptions = [vk::VertexInputBindingDescription {
-                    binding: 0,
-                    stride: VERTEX_STRIDE,
-                    ..Default::default()
-                }];
-                let attribute_descriptions = [
-                    vk::VertexInputAttributeDescription {
-                        location: 0,
-                        binding: 0,
-                        format: vk::Format::R32G32B32_SFLOAT,
-                        offset: 0,
-                    },
-                    vk::VertexInputAttributeDescription {
-                        location: 1,
-                        binding: 0,
-                        format: vk::Format::R32G32B32_SFLOAT,
-                        offset: 12,
-                    },
-                ];
-                let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo {
-                    vertex_binding_description_count: binding_descriptions.len() as u32,
-                    p_vertex_binding_descriptions: binding_descriptions.as_ptr(),
-                    vertex_attribute_description_count: attribute_descriptions.len() as u32,
-                    p_vertex_attribute_descriptions: attribute_descriptions.as_ptr(),
-                    ..Default::default()
-                };
-
-                let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
-                    topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-                    ..Default::default()
-                };
-
-                // Initialize to defaults. It will be ignored because pipeline viewport is dynamic.
-                let viewports = [vk::Viewport {
-                    ..Default::default()
-                }];
-
-                // Initialize to defaults. It will be ignored because pipeline scissor is dynamic.
-                let scissors = [vk::Rect2D {
-                    ..Default::default()
-                }];
-
-                let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
-                    scissor_count: scissors.len() as u32,
-                    p_scissors: scissors.as_ptr(),
-                    viewport_count: viewports.len() as u32,
-                    p_viewports: viewports.as_ptr(),
-                    ..Default::default()
-                };
-
-                let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
-                    polygon_mode: vk::PolygonMode::FILL,
-                    cull_mode: vk::CullModeFlags::BACK,
-                    front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-                    line_width: 1.0,
-                    ..Default::default()
-                };
-
-                let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo {
-                    rasterization_samples: vk::SampleCountFlags::TYPE_1,
-                    ..Default::default()
-                };
-
-                let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo {
-                    depth_test_enable: vk::TRUE,
-                    depth_write_enable: vk::TRUE,
-                    depth_compare_op: vk::CompareOp::LESS,
-                    max_depth_bounds: 1.0,
-                    min_depth_bounds: 0.0,
-                    ..Default::default()
-                };
-
-                let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
-                    blend_enable: vk::FALSE,
-                    color_write_mask: vk::ColorComponentFlags::all(),
-                    src_color_blend_factor: vk::BlendFactor::ONE,
-                    dst_color_blend_factor: vk::BlendFactor::ZERO,
-                    color_blend_op: vk::BlendOp::ADD,
-                    src_alpha_blend_factor: vk::BlendFactor::ONE,
-                    dst_alpha_blend_factor: vk::BlendFactor::ZERO,
-                    alpha_blend_op: vk::BlendOp::ADD,
-                }];
-
-                let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
-                    attachment_count: color_blend_attachment_states.len() as u32,
-                    p_attachments: color_blend_attachment_states.as_ptr(),
-                    blend_constants: [0.0, 0.0, 0.0, 0.0],
-                    ..Default::default()
-                };
-
-                let set_layouts = [descriptor_set_layout];
-                let pipeline_layout_create_info =
-                    vk::PipelineLayoutCreateInfo::builder().set_layouts(&set_layouts);
+            /* Create pipeline and pipeline layout */
+            let (pipeline, pipeline_layout) = {
+                let push_constant_ranges: Vec<vk::PushConstantRange> = pass_layout
+                    .opt_push_constant_range
+                    .iter()
+                    .copied()
+                    .collect();
+                let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+                    .set_layouts(&descriptor_set_layouts)
+                    .push_constant_ranges(&push_constant_ranges);
 
                 let pipeline_layout = unsafe {
                     gpu.device
@@ -465,103 +450,143 @@ impl Graph {
                         .expect("Failed to create pipeline layout.")
                 };
 
-                let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
-                let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo {
-                    s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
-                    p_next: ptr::null(),
-                    flags: ash::vk::PipelineDynamicStateCreateFlags::empty(),
-                    dynamic_state_count: dynamic_states.len() as u32,
-                    p_dynamic_states: dynamic_states.as_ptr(),
+                let pipeline = match pass.kind {
+                    PassKind::Graphics {
+                        vertex_shader,
+                        fragment_shader,
+                        pipeline_state,
+                        ..
+                    } => create_graphics_pipeline(
+                        gpu,
+                        shader_list,
+                        vertex_shader,
+                        fragment_shader,
+                        &pipeline_state,
+                        &pass_layout.vertex_layout,
+                        &pass.output_images,
+                        pipeline_layout,
+                        render_pass,
+                    ),
+                    PassKind::Compute { compute_shader, .. } => {
+                        create_compute_pipeline(gpu, shader_list, compute_shader, pipeline_layout)
+                    }
                 };
 
-                let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo {
-                    stage_count: shader_stages.len() as u32,
-                    p_stages: shader_stages.as_ptr(),
-                    p_vertex_input_state: &vertex_input_state_create_info,
-                    p_input_assembly_state: &vertex_input_assembly_state_info,
-                    p_tessellation_state: ptr::null(),
-                    p_viewport_state: &viewport_state_create_info,
-                    p_rasterization_state: &rasterization_state_create_info,
-                    p_multisample_state: &multisample_state_create_info,
-                    p_depth_stencil_state: &depth_state_create_info,
-                    p_color_blend_state: &color_blend_state,
-                    p_dynamic_state: &dynamic_state_create_info, // No dynamic state
-                    layout: pipeline_layout,
-                    render_pass,
-                    subpass: 0,
-                    ..Default::default()
-                }];
-
-                let graphics_pipelines = unsafe {
-                    gpu.device
-                        .create_graphics_pipelines(
-                            vk::PipelineCache::null(),
-                            &graphic_pipeline_create_infos,
-                            None,
-                        )
-                        .expect("Failed to create Graphics Pipeline.")
-                };
+                (pipeline, pipeline_layout)
+            };
 
-                (graphics_pipelines[0], pipeline_layout)
+            let opt_group_count = match pass.kind {
+                PassKind::Graphics { .. } => None,
+                PassKind::Compute { group_count, .. } => Some(group_count),
             };
 
             built_passes.push(BuiltPass {
-                pass_handle: pass_handle.clone(),
+                pass_handle: *pass_handle,
                 clear_values,
-                descriptor_set_layout,
-                descriptor_set,
+                image_barriers,
+                buffer_barriers,
+                barrier_src_stage_mask,
+                barrier_dst_stage_mask,
+                descriptor_set_layouts,
+                descriptor_sets,
+                dynamic_offset_count: pass_layout.dynamic_offset_count(),
+                opt_push_constant_range: pass_layout.opt_push_constant_range,
                 framebuffer,
                 render_pass,
                 pipeline_layout,
-                graphics_pipeline,
+                pipeline,
+                bind_point: pass.kind.bind_point(),
+                opt_group_count,
                 viewport_width: pass.viewport_width,
                 viewport_height: pass.viewport_height,
             });
         }
 
-        Graph {
+        Ok(Graph {
             device: gpu.device.clone(),
             descriptor_pool,
             built_passes,
             shader_handles,
+            active_pass_idx: Cell::new(None),
+            buffers,
+            transient_resources,
+        })
+    }
+
+    /// A buffer declared by the graph builder
+    pub fn get_buffer(&self, buffer_handle: BufferHandle) -> Option<&HostVisibleBuffer> {
+        self.buffers
+            .iter()
+            .find(|(handle, _, _)| *handle == buffer_handle)
+            .map(|(_, _, buffer)| buffer)
+    }
+
+    /// Destroy the graph, but keep its buffers and the memory of its transient
+    /// resources in the pool for the graphs built after it
+    pub fn release_resources(mut self, resource_pool: &mut ResourcePool) {
+        let buffers = std::mem::take(&mut self.buffers);
+        let memories = self.transient_resources.take_memories();
+        drop(self);
+
+        for (_, usage, buffer) in buffers {
+            resource_pool.put_buffer(usage, buffer);
+        }
+        for (kind, memory_type_bits, allocation) in memories {
+            resource_pool.put_memory(kind, memory_type_bits, allocation);
         }
     }
 
     pub fn begin_pass(&self, pass_handle: PassHandle, command_buffer: vk::CommandBuffer) {
-        let built_pass = self
+        let pass_idx = self
             .built_passes
             .iter()
-            .find(|&p| p.pass_handle == pass_handle)
+            .position(|p| p.pass_handle == pass_handle)
             .unwrap_or_else(|| panic!("Pass with handle `{}` not found in graph.", pass_handle.0));
+        let built_pass = &self.built_passes[pass_idx];
+        self.active_pass_idx.set(Some(pass_idx));
 
         let extent = vk::Extent2D {
             width: built_pass.viewport_width,
             height: built_pass.viewport_height,
         };
 
-        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-            .render_pass(built_pass.render_pass)
-            .framebuffer(built_pass.framebuffer)
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D { x: 0, y: 0 },
-                extent,
-            })
-            .clear_values(&built_pass.clear_values);
-
         unsafe {
-            self.device.cmd_begin_render_pass(
-                command_buffer,
-                &render_pass_begin_info,
-                vk::SubpassContents::INLINE,
-            );
+            if !built_pass.image_barriers.is_empty() || !built_pass.buffer_barriers.is_empty() {
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    built_pass.barrier_src_stage_mask,
+                    built_pass.barrier_dst_stage_mask,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &built_pass.buffer_barriers,
+                    &built_pass.image_barriers,
+                );
+            }
+
+            if built_pass.bind_point == vk::PipelineBindPoint::GRAPHICS {
+                let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+                    .render_pass(built_pass.render_pass)
+                    .framebuffer(built_pass.framebuffer)
+                    .render_area(vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent,
+                    })
+                    .clear_values(&built_pass.clear_values);
+
+                self.device.cmd_begin_render_pass(
+                    command_buffer,
+                    &render_pass_begin_info,
+                    vk::SubpassContents::INLINE,
+                );
+            }
             self.device.cmd_bind_pipeline(
                 command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                built_pass.graphics_pipeline,
+                built_pass.bind_point,
+                built_pass.pipeline,
             );
 
             // Set viewport and scissor
-            {
+            if built_pass.bind_point == vk::PipelineBindPoint::GRAPHICS {
                 let viewports = [vk::Viewport {
                     x: 0.0,
                     y: 0.0,
@@ -578,24 +603,621 @@ impl Graph {
                 }];
                 self.device.cmd_set_scissor(command_buffer, 0, &scissors);
             }
-            // Bind descriptor sets
-            {
-                let sets = [built_pass.descriptor_set];
+            // Bind descriptor sets, with uniform rings at the start of the
+            // buffer until bind_dynamic_offsets() is called
+            if !built_pass.descriptor_sets.is_empty() {
                 self.device.cmd_bind_descriptor_sets(
                     command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
+                    built_pass.bind_point,
                     built_pass.pipeline_layout,
                     0,
-                    &sets,
-                    &[],
+                    &built_pass.descriptor_sets,
+                    &vec![0; built_pass.dynamic_offset_count],
                 );
             }
         }
     }
 
+    /// Rebind the descriptor sets of the active pass with the offsets returned
+    /// by `Context::push_uniform()`, one per uniform ring in binding order
+    pub fn bind_dynamic_offsets(&self, command_buffer: vk::CommandBuffer, offsets: &[u32]) {
+        let pass_idx = self
+            .active_pass_idx
+            .get()
+            .expect("bind_dynamic_offsets() called outside of a pass.");
+        let built_pass = &self.built_passes[pass_idx];
+        assert!(
+            offsets.len() == built_pass.dynamic_offset_count,
+            "The pass binds {} uniform rings, but {} dynamic offsets were given.",
+            built_pass.dynamic_offset_count,
+            offsets.len()
+        );
+
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                command_buffer,
+                built_pass.bind_point,
+                built_pass.pipeline_layout,
+                0,
+                &built_pass.descriptor_sets,
+                offsets,
+            );
+        }
+    }
+
     pub fn end_pass(&self, command_buffer: vk::CommandBuffer) {
+        let pass_idx = self
+            .active_pass_idx
+            .take()
+            .expect("end_pass() called without a matching begin_pass().");
+        let built_pass = &self.built_passes[pass_idx];
+
+        unsafe {
+            match built_pass.opt_group_count {
+                Some([x, y, z]) => self.device.cmd_dispatch(command_buffer, x, y, z),
+                None => self.device.cmd_end_render_pass(command_buffer),
+            }
+        }
+    }
+
+    pub fn push_constants<T>(&self, command_buffer: vk::CommandBuffer, data: &[T]) {
+        let pass_idx = self
+            .active_pass_idx
+            .get()
+            .expect("push_constants() called outside of a pass.");
+        let built_pass = &self.built_passes[pass_idx];
+        let range = built_pass
+            .opt_push_constant_range
+            .expect("The shaders of this pass don't declare push constants.");
+
+        let data_size = std::mem::size_of_val(data);
+        assert!(
+            data_size as u32 <= range.size,
+            "Pushed {} bytes, but the push constant range is {} bytes.",
+            data_size,
+            range.size
+        );
         unsafe {
-            self.device.cmd_end_render_pass(command_buffer);
+            let bytes = std::slice::from_raw_parts(data.as_ptr() as *const u8, data_size);
+            self.device.cmd_push_constants(
+                command_buffer,
+                built_pass.pipeline_layout,
+                range.stage_flags,
+                range.offset,
+                bytes,
+            );
         }
     }
 }
+
+// The images and buffers used by a graph, which are either in the context or
+// created by the graph for the resources of its builder and its transient
+// resources
+struct GraphResources<'a> {
+    image_list: &'a ImageList,
+    buffer_list: &'a BufferList,
+    buffers: &'a [(BufferHandle, vk::BufferUsageFlags, HostVisibleBuffer)],
+    transient_resources: &'a TransientResources,
+    frame_idx: usize,
+}
+
+impl GraphResources<'_> {
+    fn get_image(&self, image_handle: ImageHandle) -> &Image {
+        self.image_list
+            .get_image_from_handle(image_handle)
+            .map(|internal_image| &internal_image.image)
+            .or_else(|| self.transient_resources.get_image(image_handle))
+            .unwrap_or_else(|| {
+                panic!(
+                    "Image with handle `{:?}` not found in the context.",
+                    image_handle
+                )
+            })
+    }
+
+    // (buffer, size)
+    fn get_buffer(&self, buffer_handle: BufferHandle) -> (vk::Buffer, usize) {
+        self.buffer_list
+            .get_vk_buffer(buffer_handle, self.frame_idx)
+            .or_else(|| {
+                self.buffers
+                    .iter()
+                    .find(|(handle, _, _)| *handle == buffer_handle)
+                    .map(|(_, _, buffer)| (buffer.vk_buffer, buffer.size))
+            })
+            .or_else(|| self.transient_resources.get_buffer(buffer_handle))
+            .unwrap_or_else(|| {
+                panic!(
+                    "Buffer with handle `{:?}` not found in the context.",
+                    buffer_handle
+                )
+            })
+    }
+}
+
+fn get_pass_descriptors(pass: &BuilderPass, resources: &GraphResources) -> Vec<PassDescriptor> {
+    let mut descriptors = Vec::new();
+
+    for uniform_buffer in &pass.uniform_buffers {
+        let (vk_buffer, size) = resources.get_buffer(uniform_buffer.buffer);
+        // Uniform rings are offset to a single uniform when they are bound
+        let (descriptor_type, range) = match resources
+            .buffer_list
+            .get_uniform_ring_from_handle(uniform_buffer.buffer)
+        {
+            Some(uniform_ring) => (
+                vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                uniform_ring.get_max_uniform_size(),
+            ),
+            None => (vk::DescriptorType::UNIFORM_BUFFER, size),
+        };
+        descriptors.push(PassDescriptor {
+            binding: uniform_buffer.binding,
+            descriptor_type,
+            resource: DescriptorResource::Buffer(vk::DescriptorBufferInfo {
+                buffer: vk_buffer,
+                offset: 0,
+                range: range as u64,
+            }),
+        });
+    }
+    for sampled_image in &pass.sampled_images {
+        descriptors.push(PassDescriptor {
+            binding: sampled_image.binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            resource: DescriptorResource::Image(vk::DescriptorImageInfo {
+                sampler: sampled_image.sampler,
+                image_view: resources.get_image(sampled_image.image).image_view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }),
+        });
+    }
+
+    for storage_image in &pass.storage_images {
+        descriptors.push(PassDescriptor {
+            binding: storage_image.binding,
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            resource: DescriptorResource::Image(vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: resources.get_image(storage_image.image).image_view,
+                image_layout: vk::ImageLayout::GENERAL,
+            }),
+        });
+    }
+    for storage_buffer in &pass.storage_buffers {
+        let (vk_buffer, size) = resources.get_buffer(storage_buffer.buffer);
+        descriptors.push(PassDescriptor {
+            binding: storage_buffer.binding,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            resource: DescriptorResource::Buffer(vk::DescriptorBufferInfo {
+                buffer: vk_buffer,
+                offset: 0,
+                range: size as u64,
+            }),
+        });
+    }
+
+    descriptors
+}
+
+fn create_render_pass(
+    gpu: &Gpu,
+    pass: &BuilderPass,
+    pass_idx: usize,
+    usages: &ResourceUsages,
+    resources: &GraphResources,
+) -> (vk::RenderPass, vk::Framebuffer, Vec<vk::ClearValue>) {
+    // Find depth image
+    let opt_depth_image = pass
+        .opt_depth_image
+        .map(|depth_handle| resources.get_image(depth_handle));
+
+    // Find output images
+    let output_images: Vec<&Image> = pass
+        .output_images
+        .iter()
+        .map(|color_output| resources.get_image(color_output.image))
+        .collect();
+
+    /* Create render pass */
+    let render_pass = {
+        let mut attachments: Vec<vk::AttachmentDescription> = Vec::new();
+        let mut attachment_idx = 0;
+        let mut depth_attachment_ptr = ptr::null();
+        let mut color_attachments = Vec::new();
+        // Dependencies on the passes before and after this one
+        let mut src_dependency = vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            ..Default::default()
+        };
+        let mut dst_dependency = vk::SubpassDependency {
+            src_subpass: 0,
+            dst_subpass: vk::SUBPASS_EXTERNAL,
+            ..Default::default()
+        };
+        let mut add_dependencies = |state: UseState| {
+            src_dependency.src_stage_mask |= state.src_stage_mask;
+            src_dependency.src_access_mask |= state.src_access_mask;
+            src_dependency.dst_stage_mask |= state.stage_mask;
+            src_dependency.dst_access_mask |= state.access_mask;
+            dst_dependency.src_stage_mask |= state.stage_mask;
+            dst_dependency.src_access_mask |= state.access_mask;
+            dst_dependency.dst_stage_mask |= state.dst_stage_mask;
+            dst_dependency.dst_access_mask |= state.dst_access_mask;
+        };
+
+        // Depth attachment description and reference
+        let depth_attachment = vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+        };
+        if let (Some(depth_handle), Some(depth_image)) = (pass.opt_depth_image, opt_depth_image) {
+            let state = usages.get_image(pass_idx, depth_handle);
+            add_dependencies(state);
+            attachments.push(vk::AttachmentDescription {
+                format: depth_image.format,
+                flags: vk::AttachmentDescriptionFlags::empty(),
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: state.load_op,
+                store_op: state.store_op,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: state.initial_layout,
+                final_layout: state.final_layout,
+            });
+
+            depth_attachment_ptr = &depth_attachment;
+            attachment_idx += 1;
+        }
+
+        // Color attachment descriptions and references
+        for (color_output, output_image) in pass.output_images.iter().zip(&output_images) {
+            let state = usages.get_image(pass_idx, color_output.image);
+            add_dependencies(state);
+            attachments.push(vk::AttachmentDescription {
+                format: output_image.format,
+                flags: vk::AttachmentDescriptionFlags::empty(),
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: state.load_op,
+                store_op: state.store_op,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: state.initial_layout,
+                final_layout: state.final_layout,
+            });
+            color_attachments.push(vk::AttachmentReference {
+                attachment: attachment_idx,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            });
+            attachment_idx += 1;
+        }
+
+        let subpasses = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            p_depth_stencil_attachment: depth_attachment_ptr,
+            ..Default::default()
+        }];
+
+        let dependencies = [src_dependency, dst_dependency];
+
+        let renderpass_create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+
+        unsafe {
+            gpu.device
+                .create_render_pass(&renderpass_create_info, None)
+                .expect("Failed to create render pass.")
+        }
+    };
+
+    /* Create framebuffer */
+    let framebuffer: vk::Framebuffer = {
+        let mut attachments: Vec<vk::ImageView> = Vec::new();
+        if let Some(depth_image) = opt_depth_image {
+            attachments.push(depth_image.image_view);
+        }
+        for output_image in &output_images {
+            attachments.push(output_image.image_view);
+        }
+
+        let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
+            .render_pass(render_pass)
+            .attachments(&attachments)
+            .width(pass.viewport_width)
+            .height(pass.viewport_height)
+            .layers(1);
+
+        unsafe {
+            gpu.device
+                .create_framebuffer(&framebuffer_create_info, None)
+                .expect("Failed to create framebuffer.")
+        }
+    };
+
+    /* Set clear values */
+    let mut clear_values = Vec::new();
+    if opt_depth_image.is_some() {
+        // Clear value for depth buffer
+        clear_values.push(vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        });
+    }
+    for color_output in &pass.output_images {
+        // Clear values for color buffers
+        clear_values.push(vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: color_output.opt_clear_color.unwrap_or([0.0; 4]),
+            },
+        })
+    }
+
+    (render_pass, framebuffer, clear_values)
+}
+
+fn get_shader_module(shader_list: &ShaderList, shader_handle: ShaderHandle) -> vk::ShaderModule {
+    shader_list
+        .get_shader_from_handle(shader_handle)
+        .unwrap_or_else(|| {
+            panic!(
+                "Shader with handle `{}` not found in the context.",
+                shader_handle.0
+            )
+        })
+        .vk_shader_module
+}
+
+#[allow(clippy::too_many_arguments)]
+fn create_graphics_pipeline(
+    gpu: &Gpu,
+    shader_list: &ShaderList,
+    vertex_shader: ShaderHandle,
+    fragment_shader: ShaderHandle,
+    pipeline_state: &PipelineState,
+    vertex_layout: &VertexLayout,
+    color_outputs: &[ColorOutput],
+    pipeline_layout: vk::PipelineLayout,
+    render_pass: vk::RenderPass,
+) -> vk::Pipeline {
+    let main_function_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo {
+            stage: vk::ShaderStageFlags::VERTEX,
+            module: get_shader_module(shader_list, vertex_shader),
+            p_name: main_function_name.as_ptr(),
+            ..Default::default()
+        },
+        vk::PipelineShaderStageCreateInfo {
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            module: get_shader_module(shader_list, fragment_shader),
+            p_name: main_function_name.as_ptr(),
+            ..Default::default()
+        },
+    ];
+
+    let binding_descriptions = vertex_layout.vk_binding_descriptions();
+    let attribute_descriptions = vertex_layout.vk_attribute_descriptions();
+    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo {
+        vertex_binding_description_count: binding_descriptions.len() as u32,
+        p_vertex_binding_descriptions: binding_descriptions.as_ptr(),
+        vertex_attribute_description_count: attribute_descriptions.len() as u32,
+        p_vertex_attribute_descriptions: attribute_descriptions.as_ptr(),
+        ..Default::default()
+    };
+
+    let vertex_input_assembly_state_info = vk::PipelineInputAssemblyStateCreateInfo {
+        topology: pipeline_state.topology,
+        ..Default::default()
+    };
+
+    // Initialize to defaults. It will be ignored because pipeline viewport is dynamic.
+    let viewports = [vk::Viewport {
+        ..Default::default()
+    }];
+
+    // Initialize to defaults. It will be ignored because pipeline scissor is dynamic.
+    let scissors = [vk::Rect2D {
+        ..Default::default()
+    }];
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
+        scissor_count: scissors.len() as u32,
+        p_scissors: scissors.as_ptr(),
+        viewport_count: viewports.len() as u32,
+        p_viewports: viewports.as_ptr(),
+        ..Default::default()
+    };
+
+    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
+        polygon_mode: pipeline_state.polygon_mode,
+        cull_mode: pipeline_state.cull_mode,
+        front_face: pipeline_state.front_face,
+        line_width: pipeline_state.line_width,
+        depth_bias_enable: if pipeline_state.opt_depth_bias.is_some() {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
+        depth_bias_constant_factor: pipeline_state
+            .opt_depth_bias
+            .map_or(0.0, |bias| bias.constant_factor),
+        depth_bias_clamp: pipeline_state.opt_depth_bias.map_or(0.0, |bias| bias.clamp),
+        depth_bias_slope_factor: pipeline_state
+            .opt_depth_bias
+            .map_or(0.0, |bias| bias.slope_factor),
+        ..Default::default()
+    };
+
+    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo {
+        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        ..Default::default()
+    };
+
+    let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo {
+        depth_test_enable: if pipeline_state.depth_test {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
+        depth_write_enable: if pipeline_state.depth_write {
+            vk::TRUE
+        } else {
+            vk::FALSE
+        },
+        depth_compare_op: pipeline_state.depth_compare_op,
+        max_depth_bounds: 1.0,
+        min_depth_bounds: 0.0,
+        ..Default::default()
+    };
+
+    // One blend state per color attachment
+    let color_blend_attachment_states: Vec<vk::PipelineColorBlendAttachmentState> = color_outputs
+        .iter()
+        .map(|output| {
+            output
+                .opt_blend_mode
+                .unwrap_or(pipeline_state.blend_mode)
+                .attachment_state()
+        })
+        .collect();
+
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
+        attachment_count: color_blend_attachment_states.len() as u32,
+        p_attachments: color_blend_attachment_states.as_ptr(),
+        blend_constants: [0.0, 0.0, 0.0, 0.0],
+        ..Default::default()
+    };
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo {
+        s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+        p_next: ptr::null(),
+        flags: ash::vk::PipelineDynamicStateCreateFlags::empty(),
+        dynamic_state_count: dynamic_states.len() as u32,
+        p_dynamic_states: dynamic_states.as_ptr(),
+    };
+
+    let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo {
+        stage_count: shader_stages.len() as u32,
+        p_stages: shader_stages.as_ptr(),
+        p_vertex_input_state: &vertex_input_state_create_info,
+        p_input_assembly_state: &vertex_input_assembly_state_info,
+        p_tessellation_state: ptr::null(),
+        p_viewport_state: &viewport_state_create_info,
+        p_rasterization_state: &rasterization_state_create_info,
+        p_multisample_state: &multisample_state_create_info,
+        p_depth_stencil_state: &depth_state_create_info,
+        p_color_blend_state: &color_blend_state,
+        p_dynamic_state: &dynamic_state_create_info, // No dynamic state
+        layout: pipeline_layout,
+        render_pass,
+        subpass: 0,
+        ..Default::default()
+    }];
+
+    let graphics_pipelines = unsafe {
+        gpu.device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &graphic_pipeline_create_infos,
+                None,
+            )
+            .expect("Failed to create Graphics Pipeline.")
+    };
+
+    graphics_pipelines[0]
+}
+
+fn create_compute_pipeline(
+    gpu: &Gpu,
+    shader_list: &ShaderList,
+    compute_shader: ShaderHandle,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let main_function_name = CString::new("main").unwrap();
+    let compute_pipeline_create_infos = [vk::ComputePipelineCreateInfo {
+        stage: vk::PipelineShaderStageCreateInfo {
+            stage: vk::ShaderStageFlags::COMPUTE,
+            module: get_shader_module(shader_list, compute_shader),
+            p_name: main_function_name.as_ptr(),
+            ..Default::default()
+        },
+        layout: pipeline_layout,
+        ..Default::default()
+    }];
+
+    let compute_pipelines = unsafe {
+        gpu.device
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &compute_pipeline_create_infos,
+                None,
+            )
+            .expect("Failed to create Compute Pipeline.")
+    };
+
+    compute_pipelines[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn compute_kind(group_count: [u32; 3]) -> PassKind {
+        PassKind::Compute {
+            compute_shader: ShaderHandle(1),
+            group_count,
+        }
+    }
+
+    fn get_hash(kind: &PassKind) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        kind.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn compute_passes_run_in_the_compute_stage() {
+        let kind = compute_kind([8, 8, 1]);
+        assert_eq!(kind.shader_handles(), vec![ShaderHandle(1)]);
+        assert_eq!(
+            kind.shader_stage_mask(),
+            vk::PipelineStageFlags::COMPUTE_SHADER
+        );
+        assert_eq!(kind.bind_point(), vk::PipelineBindPoint::COMPUTE);
+
+        let graphics_kind = PassKind::Graphics {
+            vertex_shader: ShaderHandle(2),
+            fragment_shader: ShaderHandle(3),
+            pipeline_state: PipelineState::default(),
+            opt_vertex_layout: None,
+        };
+        assert_eq!(
+            graphics_kind.shader_handles(),
+            vec![ShaderHandle(2), ShaderHandle(3)]
+        );
+        assert_eq!(graphics_kind.bind_point(), vk::PipelineBindPoint::GRAPHICS);
+    }
+
+    #[test]
+    fn group_counts_change_the_hash() {
+        // Otherwise the graph cache would reuse a graph that dispatches the
+        // old number of workgroups
+        assert_eq!(
+            get_hash(&compute_kind([8, 8, 1])),
+            get_hash(&compute_kind([8, 8, 1]))
+        );
+        assert_ne!(
+            get_hash(&compute_kind([8, 8, 1])),
+            get_hash(&compute_kind([8, 4, 1]))
+        );
+    }
+}
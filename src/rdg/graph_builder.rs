@@ -0,0 +1,121 @@
+use crate::*;
+
+/// A host-visible buffer declared while building a graph, e.g. for the
+/// uniforms of its passes. Each built graph gets its own buffer for it, and
+/// graphs declaring buffers are built once per frame in flight.
+#[derive(Clone, Hash)]
+pub struct GraphBuffer {
+    pub name: String,
+    pub size: usize,
+    pub usage: vk::BufferUsageFlags,
+}
+
+/// The passes and resources of the graph declared in the current frame. The
+/// buffers and images declared here have virtual handles, which are resolved
+/// to the resources of a graph when it is built.
+#[derive(Hash)]
+pub struct GraphBuilder {
+    pub passes: Vec<(PassHandle, BuilderPass)>,
+    pub buffers: Vec<(BufferHandle, GraphBuffer)>,
+    pub images: Vec<(ImageHandle, TransientImage)>, // Aliased like the transient images of the context
+}
+
+impl GraphBuilder {
+    pub fn new() -> GraphBuilder {
+        GraphBuilder {
+            passes: Vec::new(),
+            buffers: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.passes.clear();
+        self.buffers.clear();
+        self.images.clear();
+    }
+
+    /// A buffer that can be uploaded to with `Context::upload_to_buffer()`
+    /// once the graph is built
+    pub fn new_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<BufferHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            BufferHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.get_buffer(handle).is_some() {
+            return Err(format!(
+                "A buffer with the same name `{}` already exists in the graph.",
+                name
+            ));
+        }
+        self.buffers.push((
+            handle,
+            GraphBuffer {
+                name: String::from(name),
+                size,
+                usage,
+            },
+        ));
+
+        Ok(handle)
+    }
+
+    /// An image sized relative to the swapchain, which has to be written by a
+    /// pass of the graph before it is read
+    pub fn new_image(
+        &mut self,
+        name: &str,
+        scale: f32,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        aspect_flags: vk::ImageAspectFlags,
+    ) -> Result<ImageHandle, String> {
+        // Hash
+        let handle = {
+            let mut hasher = DefaultHasher::new();
+            name.hash(&mut hasher);
+            ImageHandle(hasher.finish())
+        };
+        // Error if name already exists
+        if self.get_image(handle).is_some() {
+            return Err(format!(
+                "An image with the same name `{}` already exists in the graph.",
+                name
+            ));
+        }
+        self.images.push((
+            handle,
+            TransientImage {
+                name: String::from(name),
+                scale,
+                format,
+                usage,
+                aspect_flags,
+            },
+        ));
+
+        Ok(handle)
+    }
+
+    pub fn get_buffer(&self, buffer_handle: BufferHandle) -> Option<&GraphBuffer> {
+        self.buffers
+            .iter()
+            .find(|(handle, _)| *handle == buffer_handle)
+            .map(|(_, buffer)| buffer)
+    }
+
+    pub fn get_image(&self, image_handle: ImageHandle) -> Option<&TransientImage> {
+        self.images
+            .iter()
+            .find(|(handle, _)| *handle == image_handle)
+            .map(|(_, image)| image)
+    }
+}
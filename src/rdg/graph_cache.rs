@@ -0,0 +1,80 @@
+use crate::*;
+
+/// The graphs built in earlier frames, reused for as long as the graph builder
+/// declares the same graph. Graphs that are replaced or stop being built are
+/// evicted, and their resources are kept in the pool for the graphs built
+/// after them.
+pub struct GraphCache {
+    graphs: Vec<(Graph, GraphHandle, usize)>, // (graph, hash, frame it was last built in)
+    frame_count: usize,
+}
+
+impl GraphCache {
+    pub fn new() -> GraphCache {
+        GraphCache {
+            graphs: Vec::new(),
+            frame_count: 0,
+        }
+    }
+
+    pub fn get(&self, graph_handle: GraphHandle) -> Option<&Graph> {
+        self.graphs
+            .iter()
+            .find(|(_, handle, _)| handle.0 == graph_handle.0)
+            .map(|(graph, _, _)| graph)
+    }
+
+    /// Whether the graph is cached, marking it as used in the current frame
+    pub fn touch(&mut self, graph_handle: GraphHandle) -> bool {
+        let frame_count = self.frame_count;
+        match self
+            .graphs
+            .iter_mut()
+            .find(|(_, handle, _)| handle.0 == graph_handle.0)
+        {
+            Some((_, _, last_frame)) => {
+                *last_frame = frame_count;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn insert(&mut self, graph: Graph, graph_handle: GraphHandle) {
+        self.graphs.push((graph, graph_handle, self.frame_count));
+    }
+
+    /// Count a new frame, and evict the graphs that weren't built in the last
+    /// `max_unused_frames` frames. That has to be at least the number of
+    /// frames in flight, so that none of them still uses an evicted graph.
+    pub fn begin_frame(&mut self, max_unused_frames: usize, resource_pool: &mut ResourcePool) {
+        self.frame_count += 1;
+        let frame_count = self.frame_count;
+        self.evict(
+            |_, last_frame| frame_count - last_frame > max_unused_frames,
+            resource_pool,
+        );
+    }
+
+    /// Evict the graphs the predicate is true for, given the graph and the
+    /// frame it was last built in. The GPU mustn't be using them anymore.
+    pub fn evict<F: Fn(&Graph, usize) -> bool>(
+        &mut self,
+        predicate: F,
+        resource_pool: &mut ResourcePool,
+    ) {
+        let (evicted_graphs, kept_graphs): (Vec<_>, Vec<_>) = self
+            .graphs
+            .drain(..)
+            .partition(|(graph, _, last_frame)| predicate(graph, *last_frame));
+        self.graphs = kept_graphs;
+        for (graph, _, _) in evicted_graphs {
+            graph.release_resources(resource_pool);
+        }
+    }
+
+    /// Evict every graph, e.g. once the resources they use are recreated
+    pub fn clear(&mut self, resource_pool: &mut ResourcePool) {
+        self.evict(|_, _| true, resource_pool);
+    }
+}
@@ -1,2 +1,18 @@
 pub mod graph;
 pub use graph::*;
+pub mod graph_cache;
+pub use graph_cache::*;
+pub mod graph_builder;
+pub use graph_builder::*;
+pub mod usage;
+pub use usage::*;
+pub mod pass_layout;
+pub use pass_layout::*;
+pub mod pipeline_state;
+pub use pipeline_state::*;
+pub mod resource_pool;
+pub use resource_pool::*;
+pub mod transient_resources;
+pub use transient_resources::*;
+pub mod vertex_layout;
+pub use vertex_layout::*;
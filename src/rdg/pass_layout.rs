@@ -0,0 +1,428 @@
+use crate::*;
+
+/// The interface of a pass, merged from the reflections of its shaders
+pub struct PassLayout {
+    pub bindings: Vec<(ReflectedBinding, vk::ShaderStageFlags)>, // Sorted by binding, all in set 0
+    pub opt_push_constant_range: Option<vk::PushConstantRange>,
+    pub vertex_layout: VertexLayout, // Empty for compute passes
+}
+
+impl PassLayout {
+    /// Errors if the shaders of the pass disagree with each other, or with
+    /// the resources declared by the pass.
+    pub fn new(
+        pass: &BuilderPass,
+        shader_list: &ShaderList,
+        buffer_list: &BufferList,
+    ) -> Result<PassLayout, String> {
+        let shaders = pass
+            .kind
+            .shader_handles()
+            .into_iter()
+            .map(|shader_handle| {
+                shader_list
+                    .get_shader_from_handle(shader_handle)
+                    .ok_or_else(|| {
+                        format!(
+                            "Shader with handle `{}` not found in the context.",
+                            shader_handle.0
+                        )
+                    })
+            })
+            .collect::<Result<Vec<&InternalShader>, String>>()?;
+        PassLayout::from_shaders(pass, &shaders, &get_declared_bindings(pass, buffer_list))
+    }
+
+    /// Merge the reflections of the shaders, and check them against the
+    /// binding slots and descriptor types of the resources the pass declares
+    fn from_shaders(
+        pass: &BuilderPass,
+        shaders: &[&InternalShader],
+        declared_bindings: &[(u32, vk::DescriptorType)],
+    ) -> Result<PassLayout, String> {
+        let mut bindings: Vec<(ReflectedBinding, vk::ShaderStageFlags)> = Vec::new();
+        let mut opt_push_constant_range: Option<vk::PushConstantRange> = None;
+        let mut vertex_inputs = Vec::new(); // Only vertex shaders have these
+
+        for shader in shaders {
+            let stage_flags = shader.shader_stage.vk_shader_stage_flags();
+            let reflection = &shader.reflection;
+
+            // Merge descriptor bindings
+            for binding in &reflection.bindings {
+                match bindings
+                    .iter_mut()
+                    .find(|(b, _)| b.binding == binding.binding)
+                {
+                    Some((existing, existing_stage_flags)) => {
+                        if existing.descriptor_type != binding.descriptor_type
+                            || existing.descriptor_count != binding.descriptor_count
+                        {
+                            return Err(format!(
+                                "Pass `{}`: shader `{}` declares `{}` at binding {} as {} {:?}, but another shader of the pass declares `{}` there as {} {:?}.",
+                                pass.name,
+                                shader.name,
+                                binding.name,
+                                binding.binding,
+                                binding.descriptor_count,
+                                binding.descriptor_type,
+                                existing.name,
+                                existing.descriptor_count,
+                                existing.descriptor_type,
+                            ));
+                        }
+                        *existing_stage_flags |= stage_flags;
+                    }
+                    None => bindings.push((binding.clone(), stage_flags)),
+                }
+            }
+
+            // Merge push constant ranges into a single range visible to all
+            // the stages that use it
+            if let Some(push_constants) = &reflection.opt_push_constants {
+                opt_push_constant_range = Some(match opt_push_constant_range {
+                    Some(range) => {
+                        let start = range.offset.min(push_constants.offset);
+                        let end = (range.offset + range.size)
+                            .max(push_constants.offset + push_constants.size);
+                        vk::PushConstantRange {
+                            stage_flags: range.stage_flags | stage_flags,
+                            offset: start,
+                            size: end - start,
+                        }
+                    }
+                    None => vk::PushConstantRange {
+                        stage_flags,
+                        offset: push_constants.offset,
+                        size: push_constants.size,
+                    },
+                });
+            }
+
+            vertex_inputs.extend_from_slice(&reflection.vertex_inputs);
+        }
+        bindings.sort_by_key(|(b, _)| b.binding);
+
+        // Use the declared vertex layout, or fall back to one that matches
+        // the vertex shader
+        let vertex_layout = match &pass.kind {
+            PassKind::Graphics {
+                opt_vertex_layout: Some(vertex_layout),
+                ..
+            } => vertex_layout.clone(),
+            PassKind::Graphics { .. } => VertexLayout::from_reflection(&vertex_inputs),
+            PassKind::Compute { .. } => VertexLayout::new(),
+        };
+        vertex_layout
+            .validate(&vertex_inputs)
+            .map_err(|err| format!("Pass `{}`: {}", pass.name, err))?;
+
+        // Every resource the pass declares must be expected by its shaders
+        for (idx, (binding, _)) in declared_bindings.iter().enumerate() {
+            if declared_bindings[..idx].iter().any(|(b, _)| b == binding) {
+                return Err(format!(
+                    "Pass `{}` binds more than one resource at binding {}.",
+                    pass.name, binding
+                ));
+            }
+        }
+        for (binding, descriptor_type) in declared_bindings {
+            match bindings.iter_mut().find(|(b, _)| b.binding == *binding) {
+                Some((reflected, _)) => {
+                    // Shaders can't tell dynamic uniform buffers apart, and
+                    // passes don't bind arrays of resources
+                    let is_dynamic_uniform_buffer = *descriptor_type
+                        == vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                        && reflected.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER;
+                    if (reflected.descriptor_type != *descriptor_type && !is_dynamic_uniform_buffer)
+                        || reflected.descriptor_count != 1
+                    {
+                        return Err(format!(
+                            "Pass `{}` binds a {:?} at binding {}, but its shaders expect `{}` to be {} {:?}.",
+                            pass.name,
+                            descriptor_type,
+                            binding,
+                            reflected.name,
+                            reflected.descriptor_count,
+                            reflected.descriptor_type
+                        ));
+                    }
+                    reflected.descriptor_type = *descriptor_type;
+                }
+                None => {
+                    return Err(format!(
+                        "Pass `{}` binds a {:?} at binding {}, but none of its shaders declare it.",
+                        pass.name, descriptor_type, binding
+                    ))
+                }
+            }
+        }
+        // And every resource the shaders expect must be provided by the pass
+        for (reflected, _) in &bindings {
+            let is_declared = declared_bindings
+                .iter()
+                .any(|(binding, _)| *binding == reflected.binding);
+            if !is_declared {
+                return Err(format!(
+                    "Pass `{}`: shaders expect {:?} `{}` at binding {}, but the pass doesn't provide it.",
+                    pass.name,
+                    reflected.descriptor_type,
+                    reflected.name,
+                    reflected.binding
+                ));
+            }
+        }
+
+        Ok(PassLayout {
+            bindings,
+            opt_push_constant_range,
+            vertex_layout,
+        })
+    }
+
+    /// Number of dynamic offsets the descriptor sets are bound with
+    pub fn dynamic_offset_count(&self) -> usize {
+        self.bindings
+            .iter()
+            .filter(|(b, _)| b.descriptor_type == vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+            .count()
+    }
+}
+
+/// The binding slots and descriptor types of the resources declared by a pass.
+/// Uniform rings are bound as dynamic uniform buffers.
+fn get_declared_bindings(
+    pass: &BuilderPass,
+    buffer_list: &BufferList,
+) -> Vec<(u32, vk::DescriptorType)> {
+    let mut declared_bindings = Vec::new();
+    for sampled_image in &pass.sampled_images {
+        declared_bindings.push((
+            sampled_image.binding,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        ));
+    }
+    for uniform_buffer in &pass.uniform_buffers {
+        let descriptor_type = if buffer_list
+            .get_uniform_ring_from_handle(uniform_buffer.buffer)
+            .is_some()
+        {
+            vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+        } else {
+            vk::DescriptorType::UNIFORM_BUFFER
+        };
+        declared_bindings.push((uniform_buffer.binding, descriptor_type));
+    }
+    for storage_image in &pass.storage_images {
+        declared_bindings.push((storage_image.binding, vk::DescriptorType::STORAGE_IMAGE));
+    }
+    for storage_buffer in &pass.storage_buffers {
+        declared_bindings.push((storage_buffer.binding, vk::DescriptorType::STORAGE_BUFFER));
+    }
+    declared_bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute_pass() -> BuilderPass {
+        BuilderPass {
+            name: String::from("pass"),
+            kind: PassKind::Compute {
+                compute_shader: ShaderHandle(0),
+                group_count: [1, 1, 1],
+            },
+            output_images: Vec::new(),
+            opt_depth_image: None,
+            sampled_images: Vec::new(),
+            uniform_buffers: Vec::new(),
+            storage_images: Vec::new(),
+            storage_buffers: Vec::new(),
+            viewport_width: 0,
+            viewport_height: 0,
+        }
+    }
+
+    fn graphics_pass() -> BuilderPass {
+        BuilderPass {
+            kind: PassKind::Graphics {
+                vertex_shader: ShaderHandle(0),
+                fragment_shader: ShaderHandle(1),
+                pipeline_state: PipelineState::default(),
+                opt_vertex_layout: None,
+            },
+            ..compute_pass()
+        }
+    }
+
+    fn shader(shader_stage: ShaderStage, bindings: Vec<ReflectedBinding>) -> InternalShader {
+        InternalShader {
+            name: String::from("shader"),
+            shader_stage,
+            source_path: String::new(),
+            spirv_path: String::new(),
+            vk_shader_module: vk::ShaderModule::null(),
+            reflection: ShaderReflection {
+                opt_shader_stage: Some(shader_stage),
+                bindings,
+                opt_push_constants: None,
+                vertex_inputs: Vec::new(),
+            },
+        }
+    }
+
+    fn binding(
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+    ) -> ReflectedBinding {
+        ReflectedBinding {
+            set: 0,
+            binding,
+            descriptor_type,
+            descriptor_count,
+            name: format!("resource_{}", binding),
+        }
+    }
+
+    #[test]
+    fn arrays_of_resources_are_rejected() {
+        let declared_bindings = [(0, vk::DescriptorType::STORAGE_BUFFER)];
+
+        let single = shader(
+            ShaderStage::Compute,
+            vec![binding(0, vk::DescriptorType::STORAGE_BUFFER, 1)],
+        );
+        let pass_layout =
+            PassLayout::from_shaders(&compute_pass(), &[&single], &declared_bindings).unwrap();
+        assert_eq!(pass_layout.bindings.len(), 1);
+
+        let array = shader(
+            ShaderStage::Compute,
+            vec![binding(0, vk::DescriptorType::STORAGE_BUFFER, 4)],
+        );
+        let err = PassLayout::from_shaders(&compute_pass(), &[&array], &declared_bindings)
+            .err()
+            .unwrap();
+        assert!(err.contains("expect `resource_0` to be 4"), "{}", err);
+    }
+
+    #[test]
+    fn shaders_share_bindings_and_push_constants() {
+        let mut vertex_shader = shader(
+            ShaderStage::Vertex,
+            vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1)],
+        );
+        vertex_shader.reflection.opt_push_constants = Some(ReflectedPushConstants {
+            offset: 0,
+            size: 64,
+        });
+        let mut fragment_shader = shader(
+            ShaderStage::Fragment,
+            vec![
+                binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1),
+                binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1),
+            ],
+        );
+        fragment_shader.reflection.opt_push_constants = Some(ReflectedPushConstants {
+            offset: 64,
+            size: 16,
+        });
+        // A uniform ring at binding 0
+        let declared_bindings = [
+            (0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC),
+            (1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+        ];
+
+        let pass_layout = PassLayout::from_shaders(
+            &graphics_pass(),
+            &[&vertex_shader, &fragment_shader],
+            &declared_bindings,
+        )
+        .unwrap();
+        let bindings: Vec<(u32, vk::DescriptorType, vk::ShaderStageFlags)> = pass_layout
+            .bindings
+            .iter()
+            .map(|(b, stage_flags)| (b.binding, b.descriptor_type, *stage_flags))
+            .collect();
+        assert_eq!(
+            bindings,
+            vec![
+                (
+                    0,
+                    vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC,
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+                ),
+                (
+                    1,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    vk::ShaderStageFlags::FRAGMENT
+                ),
+            ]
+        );
+        assert_eq!(pass_layout.dynamic_offset_count(), 1);
+        let range = pass_layout.opt_push_constant_range.unwrap();
+        assert_eq!(
+            range.stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!((range.offset, range.size), (0, 80));
+        assert_eq!(pass_layout.vertex_layout, VertexLayout::new());
+    }
+
+    #[test]
+    fn shaders_that_disagree_are_rejected() {
+        let vertex_shader = shader(
+            ShaderStage::Vertex,
+            vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1)],
+        );
+        let fragment_shader = shader(
+            ShaderStage::Fragment,
+            vec![binding(0, vk::DescriptorType::STORAGE_BUFFER, 1)],
+        );
+        let err = PassLayout::from_shaders(
+            &graphics_pass(),
+            &[&vertex_shader, &fragment_shader],
+            &[(0, vk::DescriptorType::UNIFORM_BUFFER)],
+        )
+        .err()
+        .unwrap();
+        assert!(err.contains("another shader of the pass"), "{}", err);
+    }
+
+    #[test]
+    fn declared_resources_have_to_match_the_shaders() {
+        let compute_shader = shader(
+            ShaderStage::Compute,
+            vec![binding(0, vk::DescriptorType::STORAGE_BUFFER, 1)],
+        );
+        let assert_err = |declared_bindings: &[(u32, vk::DescriptorType)], message: &str| {
+            let err =
+                PassLayout::from_shaders(&compute_pass(), &[&compute_shader], declared_bindings)
+                    .err()
+                    .unwrap();
+            assert!(err.contains(message), "{}", err);
+        };
+
+        assert_err(&[], "the pass doesn't provide it");
+        assert_err(
+            &[
+                (0, vk::DescriptorType::STORAGE_BUFFER),
+                (1, vk::DescriptorType::STORAGE_IMAGE),
+            ],
+            "none of its shaders declare it",
+        );
+        assert_err(
+            &[(0, vk::DescriptorType::STORAGE_IMAGE)],
+            "binds a STORAGE_IMAGE at binding 0",
+        );
+        assert_err(
+            &[
+                (0, vk::DescriptorType::STORAGE_BUFFER),
+                (0, vk::DescriptorType::STORAGE_BUFFER),
+            ],
+            "more than one resource at binding 0",
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::*;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+pub enum BlendMode {
+    None,
+    Alpha,              // src * src_alpha + dst * (1 - src_alpha)
+    Additive,           // src + dst
+    PremultipliedAlpha, // src + dst * (1 - src_alpha)
+}
+
+impl BlendMode {
+    pub fn attachment_state(&self) -> vk::PipelineColorBlendAttachmentState {
+        let (src_color_blend_factor, dst_color_blend_factor) = match self {
+            BlendMode::None => (vk::BlendFactor::ONE, vk::BlendFactor::ZERO),
+            BlendMode::Alpha => (
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (vk::BlendFactor::ONE, vk::BlendFactor::ONE),
+            BlendMode::PremultipliedAlpha => {
+                (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            }
+        };
+        // Alpha is composited with the "over" operator, unless blending is
+        // additive
+        let dst_alpha_blend_factor = match self {
+            BlendMode::None => vk::BlendFactor::ZERO,
+            BlendMode::Additive => vk::BlendFactor::ONE,
+            _ => vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        };
+
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: if *self == BlendMode::None {
+                vk::FALSE
+            } else {
+                vk::TRUE
+            },
+            color_write_mask: vk::ColorComponentFlags::all(),
+            src_color_blend_factor,
+            dst_color_blend_factor,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ONE,
+            dst_alpha_blend_factor,
+            alpha_blend_op: vk::BlendOp::ADD,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+/// Fixed-function state of the graphics pipeline of a pass
+#[derive(Copy, Clone, Debug)]
+pub struct PipelineState {
+    pub blend_mode: BlendMode, // Unless a color output has its own
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub depth_compare_op: vk::CompareOp,
+    pub opt_depth_bias: Option<DepthBias>,
+    pub polygon_mode: vk::PolygonMode, // Needs the fillModeNonSolid feature if not FILL
+    pub line_width: f32,               // Needs the wideLines feature if not 1.0
+    pub topology: vk::PrimitiveTopology,
+}
+
+impl Default for PipelineState {
+    fn default() -> PipelineState {
+        PipelineState {
+            blend_mode: BlendMode::None,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_test: true,
+            depth_write: true,
+            depth_compare_op: vk::CompareOp::LESS,
+            opt_depth_bias: None,
+            polygon_mode: vk::PolygonMode::FILL,
+            line_width: 1.0,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        }
+    }
+}
+
+// Manual implementation, because floats don't implement Hash
+impl Hash for PipelineState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.blend_mode.hash(state);
+        self.cull_mode.hash(state);
+        self.front_face.hash(state);
+        self.depth_test.hash(state);
+        self.depth_write.hash(state);
+        self.depth_compare_op.hash(state);
+        self.opt_depth_bias
+            .map(|bias| {
+                [
+                    bias.constant_factor.to_bits(),
+                    bias.clamp.to_bits(),
+                    bias.slope_factor.to_bits(),
+                ]
+            })
+            .hash(state);
+        self.polygon_mode.hash(state);
+        self.line_width.to_bits().hash(state);
+        self.topology.hash(state);
+    }
+}
+
+impl PipelineState {
+    /// Errors if the state needs device features that aren't enabled
+    pub fn validate(&self, gpu: &Gpu) -> Result<(), String> {
+        if self.polygon_mode != vk::PolygonMode::FILL
+            && gpu.features.fill_mode_non_solid == vk::FALSE
+        {
+            return Err(format!(
+                "Polygon mode {:?} isn't supported by the GPU.",
+                self.polygon_mode
+            ));
+        }
+        if self.line_width != 1.0 && gpu.features.wide_lines == vk::FALSE {
+            return Err(format!(
+                "Line width {} isn't supported by the GPU. Only 1.0 is.",
+                self.line_width
+            ));
+        }
+        Ok(())
+    }
+}
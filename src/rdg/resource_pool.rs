@@ -0,0 +1,93 @@
+use crate::*;
+
+/// The resources of graphs that were evicted from the cache, reused by the
+/// graphs built after them instead of allocating new ones. Resources that no
+/// graph takes for a while, e.g. those sized for an old resolution, are
+/// freed.
+pub struct ResourcePool {
+    buffers: Vec<(vk::BufferUsageFlags, HostVisibleBuffer, usize)>, // (usage, buffer, frame it was pooled in)
+    memories: Vec<(AllocationKind, u32, Allocation, usize)>, // (kind, memory type bits, allocation, frame it was pooled in)
+    frame_count: usize,
+}
+
+impl ResourcePool {
+    pub fn new() -> ResourcePool {
+        ResourcePool {
+            buffers: Vec::new(),
+            memories: Vec::new(),
+            frame_count: 0,
+        }
+    }
+
+    /// Count a new frame, and free the resources that were pooled more than
+    /// `max_unused_frames` frames ago
+    pub fn begin_frame(&mut self, max_unused_frames: usize) {
+        self.frame_count += 1;
+        let frame_count = self.frame_count;
+        self.buffers
+            .retain(|(_, _, pooled_frame)| frame_count - pooled_frame <= max_unused_frames);
+        self.memories
+            .retain(|(_, _, _, pooled_frame)| frame_count - pooled_frame <= max_unused_frames);
+    }
+
+    /// A pooled buffer of the same size and usage, or a new one
+    pub fn take_buffer(
+        &mut self,
+        name: &str,
+        size: usize,
+        usage: vk::BufferUsageFlags,
+        gpu: &Gpu,
+        debug_utils: &DebugUtils,
+    ) -> HostVisibleBuffer {
+        match self
+            .buffers
+            .iter()
+            .position(|(buffer_usage, buffer, _)| *buffer_usage == usage && buffer.size == size)
+        {
+            Some(idx) => {
+                let (_, mut buffer, _) = self.buffers.swap_remove(idx);
+                buffer.name = String::from(name);
+                debug_utils.set_buffer_name(buffer.vk_buffer, name);
+                buffer
+            }
+            None => HostVisibleBuffer::new(name, size, usage, gpu, debug_utils),
+        }
+    }
+
+    pub fn put_buffer(&mut self, usage: vk::BufferUsageFlags, buffer: HostVisibleBuffer) {
+        self.buffers.push((usage, buffer, self.frame_count));
+    }
+
+    /// The smallest pooled memory that satisfies the requirements, if any
+    pub fn take_memory(
+        &mut self,
+        kind: AllocationKind,
+        requirements: vk::MemoryRequirements,
+    ) -> Option<Allocation> {
+        let idx = self
+            .memories
+            .iter()
+            .enumerate()
+            .filter(|(_, (memory_kind, memory_type_bits, allocation, _))| {
+                *memory_kind == kind
+                    && *memory_type_bits == requirements.memory_type_bits
+                    && allocation.size >= requirements.size
+                    && allocation.offset % requirements.alignment.max(1) == 0
+            })
+            .min_by_key(|(_, (_, _, allocation, _))| allocation.size)
+            .map(|(idx, _)| idx)?;
+        let (_, _, allocation, _) = self.memories.swap_remove(idx);
+        Some(allocation)
+    }
+
+    // Nothing may be bound to the memory anymore
+    pub fn put_memory(
+        &mut self,
+        kind: AllocationKind,
+        memory_type_bits: u32,
+        allocation: Allocation,
+    ) {
+        self.memories
+            .push((kind, memory_type_bits, allocation, self.frame_count));
+    }
+}
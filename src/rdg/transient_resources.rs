@@ -0,0 +1,584 @@
+use crate::*;
+
+#[derive(Copy, Clone)]
+enum TransientHandle {
+    Image(ImageHandle),
+    Buffer(BufferHandle),
+}
+
+// Where a transient resource lives in the memory of its graph
+struct Placement {
+    handle: TransientHandle,
+    name: String,
+    lifetime: Lifetime,
+    requirements: vk::MemoryRequirements,
+    memory_idx: usize,
+    offset: vk::DeviceSize, // Within the memory
+}
+
+// Memory shared by the transient resources of the same kind that can live in
+// the same memory types
+struct TransientMemory {
+    kind: AllocationKind,
+    memory_type_bits: u32,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+}
+
+/// The images and buffers a graph creates for the transient resources it
+/// uses. Each of them lives from the first pass that writes it to the last
+/// pass that uses it, and resources that are never alive at the same time
+/// share memory.
+pub struct TransientResources {
+    device: ash::Device,
+    pub images: Vec<(ImageHandle, Image)>, // Their memory is owned by `allocations`
+    pub buffers: Vec<(BufferHandle, vk::Buffer, usize)>, // (handle, buffer, size)
+    placements: Vec<Placement>,
+    memories: Vec<TransientMemory>,
+    allocations: Vec<Allocation>, // One per memory, freed after the resources are destroyed
+}
+
+impl Drop for TransientResources {
+    fn drop(&mut self) {
+        // Image only destroys the views of images that don't own their memory
+        let vk_images: Vec<vk::Image> = self
+            .images
+            .drain(..)
+            .map(|(_, image)| image.vk_image)
+            .collect();
+        unsafe {
+            for vk_image in vk_images {
+                self.device.destroy_image(vk_image, None);
+            }
+            for (_, vk_buffer, _) in &self.buffers {
+                self.device.destroy_buffer(*vk_buffer, None);
+            }
+        }
+    }
+}
+
+impl TransientResources {
+    /// Creates the transient resources used by a graph, from both the context
+    /// and the graph builder, and makes the first use of each wait for the
+    /// resources that were in its memory before it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        gpu: &Gpu,
+        graph_builder: &GraphBuilder,
+        image_list: &ImageList,
+        buffer_list: &BufferList,
+        facade: &Facade,
+        usages: &mut ResourceUsages,
+        resource_pool: &mut ResourcePool,
+        debug_utils: &DebugUtils,
+    ) -> Result<TransientResources, String> {
+        let device = gpu.device.clone();
+        let mut placements = Vec::new();
+
+        // Transient resources have no contents before a pass writes them
+        for (image_handle, transient_image) in image_list
+            .transient_list
+            .iter()
+            .chain(&graph_builder.images)
+        {
+            if let Some(lifetime) = usages.get_image_lifetime(*image_handle) {
+                if lifetime.is_read_first {
+                    return Err(format!(
+                        "Transient image `{}` is read before it is written in the graph.",
+                        transient_image.name
+                    ));
+                }
+            }
+        }
+        for (buffer_handle, transient_buffer) in &buffer_list.transient_list {
+            if let Some(lifetime) = usages.get_buffer_lifetime(*buffer_handle) {
+                if lifetime.is_read_first {
+                    return Err(format!(
+                        "Transient buffer `{}` is read before it is written in the graph.",
+                        transient_buffer.name
+                    ));
+                }
+            }
+        }
+
+        // Create the resources without memory, to find out how much they need
+        let mut vk_images = Vec::new();
+        for (image_handle, transient_image) in image_list
+            .transient_list
+            .iter()
+            .chain(&graph_builder.images)
+        {
+            let lifetime = match usages.get_image_lifetime(*image_handle) {
+                Some(lifetime) => lifetime,
+                None => continue,
+            };
+            let w = (facade.swapchain_width as f32 * transient_image.scale) as u32;
+            let h = (facade.swapchain_height as f32 * transient_image.scale) as u32;
+            let vk_image =
+                create_vk_image(&device, w, h, transient_image.format, transient_image.usage);
+            debug_utils.set_image_name(vk_image, &transient_image.name);
+
+            placements.push(Placement {
+                handle: TransientHandle::Image(*image_handle),
+                name: transient_image.name.clone(),
+                lifetime,
+                requirements: unsafe { device.get_image_memory_requirements(vk_image) },
+                memory_idx: 0,
+                offset: 0,
+            });
+            vk_images.push((vk_image, w, h, transient_image));
+        }
+        let mut buffers = Vec::new();
+        for (buffer_handle, transient_buffer) in &buffer_list.transient_list {
+            let lifetime = match usages.get_buffer_lifetime(*buffer_handle) {
+                Some(lifetime) => lifetime,
+                None => continue,
+            };
+            let buffer_create_info = vk::BufferCreateInfo::builder()
+                .size(transient_buffer.size as vk::DeviceSize)
+                .usage(transient_buffer.usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+            let vk_buffer = unsafe {
+                device
+                    .create_buffer(&buffer_create_info, None)
+                    .expect("Failed to create buffer.")
+            };
+            debug_utils.set_buffer_name(vk_buffer, &transient_buffer.name);
+
+            placements.push(Placement {
+                handle: TransientHandle::Buffer(*buffer_handle),
+                name: transient_buffer.name.clone(),
+                lifetime,
+                requirements: unsafe { device.get_buffer_memory_requirements(vk_buffer) },
+                memory_idx: 0,
+                offset: 0,
+            });
+            buffers.push((*buffer_handle, vk_buffer, transient_buffer.size));
+        }
+
+        // Allocate the shared memory and bind the resources to it
+        let memories = place_resources(&mut placements);
+        let allocations = match memories
+            .iter()
+            .map(|memory| {
+                let requirements = vk::MemoryRequirements {
+                    size: memory.size,
+                    alignment: memory.alignment,
+                    memory_type_bits: memory.memory_type_bits,
+                };
+                match resource_pool.take_memory(memory.kind, requirements) {
+                    Some(allocation) => Ok(allocation),
+                    None => gpu.allocator.allocate(
+                        requirements,
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                        memory.kind,
+                    ),
+                }
+            })
+            .collect::<Result<Vec<Allocation>, String>>()
+        {
+            Ok(allocations) => allocations,
+            Err(err) => {
+                // The allocations that succeeded are freed when dropped
+                unsafe {
+                    for (vk_image, _, _, _) in vk_images {
+                        device.destroy_image(vk_image, None);
+                    }
+                    for (_, vk_buffer, _) in buffers {
+                        device.destroy_buffer(vk_buffer, None);
+                    }
+                }
+                return Err(format!("Failed to allocate transient memory: {}", err));
+            }
+        };
+
+        let mut images = Vec::new();
+        for (placement, (vk_image, width, height, transient_image)) in
+            placements.iter().zip(vk_images)
+        {
+            let allocation = &allocations[placement.memory_idx];
+            let image_handle = match placement.handle {
+                TransientHandle::Image(image_handle) => image_handle,
+                TransientHandle::Buffer(_) => unreachable!(),
+            };
+            unsafe {
+                device
+                    .bind_image_memory(
+                        vk_image,
+                        allocation.device_memory,
+                        allocation.offset + placement.offset,
+                    )
+                    .expect("Failed to bind image memory.");
+            }
+            let image_view = create_image_view(
+                &device,
+                vk_image,
+                transient_image.format,
+                transient_image.aspect_flags,
+            );
+            images.push((
+                image_handle,
+                Image {
+                    width,
+                    height,
+                    format: transient_image.format,
+                    usage: transient_image.usage,
+                    aspect_flags: transient_image.aspect_flags,
+                    vk_image,
+                    image_view,
+                    opt_allocation: None,
+                    name: transient_image.name.clone(),
+                    device: device.clone(),
+                },
+            ));
+        }
+        for (placement, (_, vk_buffer, _)) in placements[images.len()..].iter().zip(&buffers) {
+            let allocation = &allocations[placement.memory_idx];
+            unsafe {
+                device
+                    .bind_buffer_memory(
+                        *vk_buffer,
+                        allocation.device_memory,
+                        allocation.offset + placement.offset,
+                    )
+                    .expect("Failed to bind buffer.");
+            }
+        }
+
+        // The first use of each resource has to wait for the last uses of the
+        // resources that overlap it in memory. Those used later in the graph
+        // are from the previous frame.
+        for placement in &placements {
+            let mut src_stage_mask = vk::PipelineStageFlags::empty();
+            let mut src_access_mask = vk::AccessFlags::empty();
+            for other in &placements {
+                let is_overlapping = other.memory_idx == placement.memory_idx
+                    && other.offset < placement.offset + placement.requirements.size
+                    && placement.offset < other.offset + other.requirements.size;
+                if std::ptr::eq(other, placement) || !is_overlapping {
+                    continue;
+                }
+                let state = match other.handle {
+                    TransientHandle::Image(image_handle) => usages.get_last_image(image_handle),
+                    TransientHandle::Buffer(buffer_handle) => usages.get_last_buffer(buffer_handle),
+                };
+                src_stage_mask |= state.stage_mask;
+                src_access_mask |= state.access_mask;
+            }
+            if src_stage_mask.is_empty() {
+                continue;
+            }
+            match placement.handle {
+                TransientHandle::Image(image_handle) => {
+                    usages.add_image_aliasing(image_handle, src_stage_mask, src_access_mask)
+                }
+                TransientHandle::Buffer(buffer_handle) => {
+                    usages.add_buffer_aliasing(buffer_handle, src_stage_mask, src_access_mask)
+                }
+            }
+        }
+
+        Ok(TransientResources {
+            device,
+            images,
+            buffers,
+            placements,
+            memories,
+            allocations,
+        })
+    }
+
+    pub fn get_image(&self, image_handle: ImageHandle) -> Option<&Image> {
+        self.images
+            .iter()
+            .find(|(handle, _)| *handle == image_handle)
+            .map(|(_, image)| image)
+    }
+
+    pub fn get_buffer(&self, buffer_handle: BufferHandle) -> Option<(vk::Buffer, usize)> {
+        self.buffers
+            .iter()
+            .find(|(handle, _, _)| *handle == buffer_handle)
+            .map(|(_, vk_buffer, size)| (*vk_buffer, *size))
+    }
+
+    /// Take the memory out of the resources, which are destroyed once the
+    /// graph is dropped, to pool it. (kind, memory type bits, allocation)
+    pub fn take_memories(&mut self) -> Vec<(AllocationKind, u32, Allocation)> {
+        self.memories
+            .iter()
+            .zip(self.allocations.drain(..))
+            .map(|(memory, allocation)| (memory.kind, memory.memory_type_bits, allocation))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.placements.is_empty()
+    }
+
+    /// The memory the resources would need without sharing any
+    pub fn unaliased_size(&self) -> vk::DeviceSize {
+        self.placements.iter().map(|p| p.requirements.size).sum()
+    }
+
+    /// The memory the resources actually use
+    pub fn aliased_size(&self) -> vk::DeviceSize {
+        self.memories.iter().map(|m| m.size).sum()
+    }
+
+    /// A table of where each resource was placed, and how much memory sharing
+    /// saves
+    pub fn report(&self) -> String {
+        let to_mib = |size: vk::DeviceSize| size as f32 / (1024.0 * 1024.0);
+        let mut report = String::from("Transient resources:\n");
+        for placement in &self.placements {
+            report += &format!(
+                "  {:<32} passes {:>2}..={:<2} memory {} offset {:>8.2} MiB size {:>8.2} MiB\n",
+                placement.name,
+                placement.lifetime.first_pass_idx,
+                placement.lifetime.last_pass_idx,
+                placement.memory_idx,
+                to_mib(placement.offset),
+                to_mib(placement.requirements.size),
+            );
+        }
+        let unaliased_size = self.unaliased_size();
+        let aliased_size = self.aliased_size();
+        report += &format!(
+            "  {:.2} MiB in {} memory blocks instead of {:.2} MiB, saving {:.2} MiB ({:.0}%)\n",
+            to_mib(aliased_size),
+            self.memories.len(),
+            to_mib(unaliased_size),
+            to_mib(unaliased_size - aliased_size),
+            100.0 * (unaliased_size - aliased_size) as f32 / unaliased_size.max(1) as f32,
+        );
+        report
+    }
+}
+
+/* Give each resource a memory and an offset in it, so that resources which
+are alive at the same time don't overlap. Largest first, each at the lowest
+offset that fits, which is simple and close to optimal for the chains of
+similar render targets that are common in graphs. */
+fn place_resources(placements: &mut [Placement]) -> Vec<TransientMemory> {
+    let mut memories: Vec<TransientMemory> = Vec::new();
+    let mut order: Vec<usize> = (0..placements.len()).collect();
+    order.sort_by_key(|&idx| std::cmp::Reverse(placements[idx].requirements.size));
+
+    for (order_idx, &idx) in order.iter().enumerate() {
+        // Images and buffers are kept apart to avoid bufferImageGranularity
+        let kind = match placements[idx].handle {
+            TransientHandle::Image(_) => AllocationKind::Image,
+            TransientHandle::Buffer(_) => AllocationKind::Buffer,
+        };
+        let requirements = placements[idx].requirements;
+        let memory_idx = match memories.iter().position(|memory| {
+            memory.kind == kind && memory.memory_type_bits == requirements.memory_type_bits
+        }) {
+            Some(memory_idx) => memory_idx,
+            None => {
+                memories.push(TransientMemory {
+                    kind,
+                    memory_type_bits: requirements.memory_type_bits,
+                    size: 0,
+                    alignment: 1,
+                });
+                memories.len() - 1
+            }
+        };
+
+        // The ranges of the resources placed so far that this one can't overlap
+        let lifetime = placements[idx].lifetime;
+        let taken_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)> = order[..order_idx]
+            .iter()
+            .map(|&other_idx| &placements[other_idx])
+            .filter(|other| other.memory_idx == memory_idx && other.lifetime.overlaps(&lifetime))
+            .map(|other| (other.offset, other.offset + other.requirements.size))
+            .collect();
+        // Either the start of the memory or the end of a taken range fits
+        let alignment = requirements.alignment.max(1);
+        let offset = std::iter::once(0)
+            .chain(taken_ranges.iter().map(|(_, end)| *end))
+            .map(|offset| align_up(offset, alignment))
+            .filter(|&offset| {
+                taken_ranges
+                    .iter()
+                    .all(|&(start, end)| offset + requirements.size <= start || end <= offset)
+            })
+            .min()
+            .unwrap();
+
+        let memory = &mut memories[memory_idx];
+        memory.size = memory.size.max(offset + requirements.size);
+        memory.alignment = memory.alignment.max(alignment);
+        placements[idx].memory_idx = memory_idx;
+        placements[idx].offset = offset;
+    }
+
+    memories
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(
+        handle: TransientHandle,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        passes: std::ops::RangeInclusive<usize>,
+    ) -> Placement {
+        Placement {
+            handle,
+            name: String::new(),
+            lifetime: Lifetime {
+                first_pass_idx: *passes.start(),
+                last_pass_idx: *passes.end(),
+                is_read_first: false,
+            },
+            requirements: vk::MemoryRequirements {
+                size,
+                alignment,
+                memory_type_bits: 1,
+            },
+            memory_idx: 0,
+            offset: 0,
+        }
+    }
+
+    fn image(size: vk::DeviceSize, passes: std::ops::RangeInclusive<usize>) -> Placement {
+        placement(TransientHandle::Image(ImageHandle(0)), size, 1, passes)
+    }
+
+    fn buffer(size: vk::DeviceSize, passes: std::ops::RangeInclusive<usize>) -> Placement {
+        placement(TransientHandle::Buffer(BufferHandle(0)), size, 1, passes)
+    }
+
+    // Resources that are alive at the same time never overlap, and all of
+    // them are aligned and inside memory of their kind
+    fn place_and_check(placements: &mut [Placement]) -> Vec<TransientMemory> {
+        let memories = place_resources(placements);
+        for (idx, placement) in placements.iter().enumerate() {
+            let memory = &memories[placement.memory_idx];
+            let kind = match placement.handle {
+                TransientHandle::Image(_) => AllocationKind::Image,
+                TransientHandle::Buffer(_) => AllocationKind::Buffer,
+            };
+            assert!(memory.kind == kind);
+            assert_eq!(
+                memory.memory_type_bits,
+                placement.requirements.memory_type_bits
+            );
+            assert_eq!(placement.offset % placement.requirements.alignment, 0);
+            assert_eq!(memory.alignment % placement.requirements.alignment, 0);
+            assert!(placement.offset + placement.requirements.size <= memory.size);
+
+            for other in &placements[idx + 1..] {
+                if other.memory_idx == placement.memory_idx
+                    && other.lifetime.overlaps(&placement.lifetime)
+                {
+                    assert!(
+                        other.offset + other.requirements.size <= placement.offset
+                            || placement.offset + placement.requirements.size <= other.offset
+                    );
+                }
+            }
+        }
+        memories
+    }
+
+    #[test]
+    fn chains_alias() {
+        // Like a chain of post-processing passes, each reading the previous
+        let mut placements = vec![image(100, 0..=1), image(100, 1..=2), image(100, 2..=3)];
+        let memories = place_and_check(&mut placements);
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].size, 200);
+        assert_eq!(placements[0].offset, placements[2].offset);
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share_memory() {
+        let mut placements = vec![image(50, 0..=0), image(200, 1..=1), image(100, 2..=2)];
+        let memories = place_and_check(&mut placements);
+        assert_eq!(memories.len(), 1);
+        assert_eq!(memories[0].size, 200);
+        assert!(placements.iter().all(|placement| placement.offset == 0));
+    }
+
+    #[test]
+    fn overlapping_lifetimes_dont_share_memory() {
+        let mut placements = vec![image(50, 0..=2), image(200, 1..=1), image(100, 1..=3)];
+        let memories = place_and_check(&mut placements);
+        assert_eq!(memories[0].size, 350);
+        // Largest first
+        assert_eq!(placements[1].offset, 0);
+    }
+
+    #[test]
+    fn holes_are_reused() {
+        // The last image fits where the second was, between two that are
+        // alive with it
+        let mut placements = vec![
+            image(100, 0..=3),
+            image(100, 0..=1),
+            image(100, 0..=3),
+            image(50, 2..=3),
+        ];
+        let memories = place_and_check(&mut placements);
+        assert_eq!(memories[0].size, 300);
+        assert_eq!(placements[1].offset, 100);
+        assert_eq!(placements[3].offset, 100);
+    }
+
+    #[test]
+    fn kinds_and_memory_types_are_kept_apart() {
+        let mut placements = vec![image(100, 0..=0), buffer(100, 1..=1), image(100, 2..=2)];
+        placements[2].requirements.memory_type_bits = 2;
+        let memories = place_and_check(&mut placements);
+        assert_eq!(memories.len(), 3);
+        assert!(placements.iter().all(|placement| placement.offset == 0));
+    }
+
+    #[test]
+    fn alignment() {
+        let mut placements = vec![
+            image(100, 0..=1),
+            placement(TransientHandle::Image(ImageHandle(0)), 100, 256, 0..=1),
+        ];
+        let memories = place_and_check(&mut placements);
+        assert_eq!(placements[1].offset, 256);
+        assert_eq!(memories[0].size, 356);
+        assert_eq!(memories[0].alignment, 256);
+    }
+
+    #[test]
+    fn many_resources() {
+        // A deterministic pseudo-random mix of sizes, alignments and lifetimes
+        let mut state = 12345u64;
+        let mut random = |range: u64| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) % range
+        };
+        let mut placements: Vec<Placement> = (0..200)
+            .map(|_| {
+                let handle = if random(4) == 0 {
+                    TransientHandle::Buffer(BufferHandle(0))
+                } else {
+                    TransientHandle::Image(ImageHandle(0))
+                };
+                let size = 1 + random(1000);
+                let alignment = 1 << random(9);
+                let first_pass_idx = random(20) as usize;
+                let last_pass_idx = first_pass_idx + random(5) as usize;
+                placement(handle, size, alignment, first_pass_idx..=last_pass_idx)
+            })
+            .collect();
+        let memories = place_and_check(&mut placements);
+        let unaliased_size: vk::DeviceSize = placements.iter().map(|p| p.requirements.size).sum();
+        let aliased_size: vk::DeviceSize = memories.iter().map(|m| m.size).sum();
+        assert!(aliased_size < unaliased_size);
+    }
+}
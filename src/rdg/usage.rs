@@ -0,0 +1,799 @@
+use crate::*;
+
+/// Whether a shader reads, writes, or does both to a storage resource.
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+pub enum StorageAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl StorageAccess {
+    pub fn is_read(self) -> bool {
+        self != StorageAccess::Write
+    }
+
+    pub fn is_write(self) -> bool {
+        self != StorageAccess::Read
+    }
+
+    pub fn access_mask(self) -> vk::AccessFlags {
+        match self {
+            StorageAccess::Read => vk::AccessFlags::SHADER_READ,
+            StorageAccess::Write => vk::AccessFlags::SHADER_WRITE,
+            StorageAccess::ReadWrite => {
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE
+            }
+        }
+    }
+}
+
+/// How a single pass uses an image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageUsage {
+    ColorAttachment { is_cleared: bool }, // Cleared if it's the first use in the graph
+    DepthAttachment { is_tested: bool, is_written: bool },
+    Sampled,
+    Storage(StorageAccess),
+}
+
+impl ImageUsage {
+    pub fn layout(self) -> vk::ImageLayout {
+        match self {
+            ImageUsage::ColorAttachment { .. } => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageUsage::DepthAttachment { .. } => vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ImageUsage::Sampled => vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageUsage::Storage(_) => vk::ImageLayout::GENERAL,
+        }
+    }
+
+    /// Attachments are used by fixed-function stages. Everything else is used
+    /// by the shader stages of the pass.
+    fn stage_mask(self, shader_stage_mask: vk::PipelineStageFlags) -> vk::PipelineStageFlags {
+        match self {
+            ImageUsage::ColorAttachment { .. } => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ImageUsage::DepthAttachment { .. } => {
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            ImageUsage::Sampled | ImageUsage::Storage(_) => shader_stage_mask,
+        }
+    }
+
+    fn access_mask(self) -> vk::AccessFlags {
+        match self {
+            ImageUsage::ColorAttachment { .. } => {
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            ImageUsage::DepthAttachment { .. } => {
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            ImageUsage::Sampled => vk::AccessFlags::SHADER_READ,
+            ImageUsage::Storage(access) => access.access_mask(),
+        }
+    }
+
+    /// Attachments are transitioned by the render pass itself. Everything else
+    /// needs an explicit pipeline barrier.
+    pub fn is_attachment(self) -> bool {
+        match self {
+            ImageUsage::ColorAttachment { .. } | ImageUsage::DepthAttachment { .. } => true,
+            ImageUsage::Sampled | ImageUsage::Storage(_) => false,
+        }
+    }
+
+    fn is_read(self) -> bool {
+        match self {
+            ImageUsage::ColorAttachment { .. } | ImageUsage::DepthAttachment { .. } => false,
+            ImageUsage::Sampled => true,
+            ImageUsage::Storage(access) => access.is_read(),
+        }
+    }
+
+    fn is_write(self) -> bool {
+        match self {
+            ImageUsage::ColorAttachment { .. } => true,
+            // Depth is only written where it's tested
+            ImageUsage::DepthAttachment {
+                is_tested,
+                is_written,
+            } => is_tested && is_written,
+            ImageUsage::Sampled => false,
+            ImageUsage::Storage(access) => access.is_write(),
+        }
+    }
+
+    /// Whether the use depends on the contents left by earlier uses. Color
+    /// attachments always do, since a pass may not draw over every pixel.
+    fn needs_contents(self) -> bool {
+        match self {
+            ImageUsage::ColorAttachment { .. } => true,
+            ImageUsage::DepthAttachment { is_tested, .. } => is_tested,
+            ImageUsage::Sampled => true,
+            ImageUsage::Storage(access) => access.is_read(),
+        }
+    }
+
+    /// Whether the use starts from a cleared attachment when nothing earlier
+    /// in the graph wrote it
+    fn needs_clear(self) -> bool {
+        match self {
+            ImageUsage::ColorAttachment { is_cleared } => is_cleared,
+            ImageUsage::DepthAttachment { is_tested, .. } => is_tested,
+            ImageUsage::Sampled | ImageUsage::Storage(_) => false,
+        }
+    }
+}
+
+/// How a single pass uses a buffer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BufferUsage {
+    Uniform,
+    Storage(StorageAccess),
+}
+
+impl BufferUsage {
+    fn access_mask(self) -> vk::AccessFlags {
+        match self {
+            BufferUsage::Uniform => vk::AccessFlags::UNIFORM_READ,
+            BufferUsage::Storage(access) => access.access_mask(),
+        }
+    }
+
+    fn is_read(self) -> bool {
+        match self {
+            BufferUsage::Uniform => true,
+            BufferUsage::Storage(access) => access.is_read(),
+        }
+    }
+
+    fn is_write(self) -> bool {
+        match self {
+            BufferUsage::Uniform => false,
+            BufferUsage::Storage(access) => access.is_write(),
+        }
+    }
+}
+
+/// The state a resource needs to be in around one of its uses, derived from
+/// the uses before and after it in the graph.
+#[derive(Copy, Clone, Debug)]
+pub struct UseState {
+    pub stage_mask: vk::PipelineStageFlags,
+    pub access_mask: vk::AccessFlags,
+    // Only meaningful for images
+    pub layout: vk::ImageLayout,
+    pub initial_layout: vk::ImageLayout, // Layout the image is in when the pass begins
+    pub final_layout: vk::ImageLayout,   // Layout the pass has to leave the image in
+    // Only meaningful for attachments
+    pub load_op: vk::AttachmentLoadOp,
+    pub store_op: vk::AttachmentStoreOp,
+    // Stages and accesses of the previous use, which this use has to wait for
+    pub src_stage_mask: vk::PipelineStageFlags,
+    pub src_access_mask: vk::AccessFlags,
+    // Stages and accesses of the next use, which have to wait for this use
+    pub dst_stage_mask: vk::PipelineStageFlags,
+    pub dst_access_mask: vk::AccessFlags,
+    /* Attachments are synchronized by the dependencies of their render pass.
+    Other uses need an explicit pipeline barrier before the pass if this is
+    set. */
+    pub needs_barrier: bool,
+}
+
+// A single use of any kind of resource, as far as synchronization is concerned
+#[derive(Copy, Clone)]
+struct Use {
+    pass_idx: usize,
+    stage_mask: vk::PipelineStageFlags,
+    access_mask: vk::AccessFlags,
+    layout: vk::ImageLayout,
+    is_attachment: bool,
+    is_read: bool,
+    is_write: bool,
+    needs_contents: bool, // See ImageUsage::needs_contents()
+    needs_clear: bool,    // See ImageUsage::needs_clear()
+}
+
+/// The passes between the first and the last use of a resource in a graph.
+#[derive(Copy, Clone, Debug)]
+pub struct Lifetime {
+    pub first_pass_idx: usize,
+    pub last_pass_idx: usize,
+    pub is_read_first: bool, // If so, the contents come from before the graph
+}
+
+impl Lifetime {
+    pub fn overlaps(&self, other: &Lifetime) -> bool {
+        self.first_pass_idx <= other.last_pass_idx && other.first_pass_idx <= self.last_pass_idx
+    }
+}
+
+/// Tracks the uses of every image and buffer across the passes of a graph.
+pub struct ResourceUsages {
+    images: Vec<(ImageHandle, Vec<(usize, UseState)>)>, // (image, [(pass index, state)])
+    buffers: Vec<(BufferHandle, Vec<(usize, UseState)>)>, // (buffer, [(pass index, state)])
+    image_lifetimes: Vec<(ImageHandle, Lifetime)>,
+    buffer_lifetimes: Vec<(BufferHandle, Lifetime)>,
+}
+
+impl ResourceUsages {
+    pub fn new(
+        builder_passes: &[(PassHandle, BuilderPass)],
+        image_list: &ImageList,
+        present_layout: vk::ImageLayout,
+    ) -> ResourceUsages {
+        // Gather the uses of each resource, in pass order
+        let mut image_uses: Vec<(ImageHandle, Vec<Use>)> = Vec::new();
+        let mut buffer_uses: Vec<(BufferHandle, Vec<Use>)> = Vec::new();
+        for (pass_idx, (_, pass)) in builder_passes.iter().enumerate() {
+            let shader_stage_mask = pass.kind.shader_stage_mask();
+            let mut add_image_use = |image_handle: ImageHandle, usage: ImageUsage| {
+                let image_use = Use {
+                    pass_idx,
+                    stage_mask: usage.stage_mask(shader_stage_mask),
+                    access_mask: usage.access_mask(),
+                    layout: usage.layout(),
+                    is_attachment: usage.is_attachment(),
+                    is_read: usage.is_read(),
+                    is_write: usage.is_write(),
+                    needs_contents: usage.needs_contents(),
+                    needs_clear: usage.needs_clear(),
+                };
+                add_use(&mut image_uses, image_handle, image_use);
+            };
+            for sampled_image in &pass.sampled_images {
+                add_image_use(sampled_image.image, ImageUsage::Sampled);
+            }
+            for storage_image in &pass.storage_images {
+                add_image_use(
+                    storage_image.image,
+                    ImageUsage::Storage(storage_image.access),
+                );
+            }
+            if let Some(depth_handle) = pass.opt_depth_image {
+                let (is_tested, is_written) = match &pass.kind {
+                    PassKind::Graphics { pipeline_state, .. } => {
+                        (pipeline_state.depth_test, pipeline_state.depth_write)
+                    }
+                    PassKind::Compute { .. } => (false, false),
+                };
+                add_image_use(
+                    depth_handle,
+                    ImageUsage::DepthAttachment {
+                        is_tested,
+                        is_written,
+                    },
+                );
+            }
+            for color_output in &pass.output_images {
+                add_image_use(
+                    color_output.image,
+                    ImageUsage::ColorAttachment {
+                        is_cleared: color_output.opt_clear_color.is_some(),
+                    },
+                );
+            }
+
+            let mut add_buffer_use = |buffer_handle: BufferHandle, usage: BufferUsage| {
+                let buffer_use = Use {
+                    pass_idx,
+                    stage_mask: shader_stage_mask,
+                    access_mask: usage.access_mask(),
+                    layout: vk::ImageLayout::UNDEFINED,
+                    is_attachment: false,
+                    is_read: usage.is_read(),
+                    is_write: usage.is_write(),
+                    needs_contents: usage.is_read(),
+                    needs_clear: false,
+                };
+                add_use(&mut buffer_uses, buffer_handle, buffer_use);
+            };
+            for uniform_buffer in &pass.uniform_buffers {
+                add_buffer_use(uniform_buffer.buffer, BufferUsage::Uniform);
+            }
+            for storage_buffer in &pass.storage_buffers {
+                add_buffer_use(
+                    storage_buffer.buffer,
+                    BufferUsage::Storage(storage_buffer.access),
+                );
+            }
+        }
+
+        let image_lifetimes = image_uses
+            .iter()
+            .map(|(image_handle, uses)| (*image_handle, get_lifetime(uses)))
+            .collect();
+        let buffer_lifetimes = buffer_uses
+            .iter()
+            .map(|(buffer_handle, uses)| (*buffer_handle, get_lifetime(uses)))
+            .collect();
+
+        // Resolve the state around each use
+        let images = image_uses
+            .into_iter()
+            .map(|(image_handle, uses)| {
+                let is_swapchain = image_list
+                    .get_image_from_handle(image_handle)
+                    .is_some_and(|image| image.kind == ImageKind::Swapchain);
+                (
+                    image_handle,
+                    resolve_uses(&uses, is_swapchain, present_layout),
+                )
+            })
+            .collect();
+        let buffers = buffer_uses
+            .into_iter()
+            .map(|(buffer_handle, uses)| {
+                (buffer_handle, resolve_uses(&uses, false, present_layout))
+            })
+            .collect();
+
+        ResourceUsages {
+            images,
+            buffers,
+            image_lifetimes,
+            buffer_lifetimes,
+        }
+    }
+
+    pub fn get_image(&self, pass_idx: usize, image_handle: ImageHandle) -> UseState {
+        find_state(&self.images, pass_idx, image_handle).unwrap_or_else(|| {
+            panic!(
+                "Image with handle `{:?}` is not used by pass {}.",
+                image_handle, pass_idx
+            )
+        })
+    }
+
+    pub fn get_buffer(&self, pass_idx: usize, buffer_handle: BufferHandle) -> UseState {
+        find_state(&self.buffers, pass_idx, buffer_handle).unwrap_or_else(|| {
+            panic!(
+                "Buffer with handle `{:?}` is not used by pass {}.",
+                buffer_handle, pass_idx
+            )
+        })
+    }
+
+    /// None if the image isn't used by the graph
+    pub fn get_image_lifetime(&self, image_handle: ImageHandle) -> Option<Lifetime> {
+        find_lifetime(&self.image_lifetimes, image_handle)
+    }
+
+    /// None if the buffer isn't used by the graph
+    pub fn get_buffer_lifetime(&self, buffer_handle: BufferHandle) -> Option<Lifetime> {
+        find_lifetime(&self.buffer_lifetimes, buffer_handle)
+    }
+
+    /// The state of the last use of an image that is used by the graph
+    pub fn get_last_image(&self, image_handle: ImageHandle) -> UseState {
+        let lifetime = self
+            .get_image_lifetime(image_handle)
+            .unwrap_or_else(|| panic!("Image with handle `{:?}` is not used.", image_handle));
+        self.get_image(lifetime.last_pass_idx, image_handle)
+    }
+
+    /// The state of the last use of a buffer that is used by the graph
+    pub fn get_last_buffer(&self, buffer_handle: BufferHandle) -> UseState {
+        let lifetime = self
+            .get_buffer_lifetime(buffer_handle)
+            .unwrap_or_else(|| panic!("Buffer with handle `{:?}` is not used.", buffer_handle));
+        self.get_buffer(lifetime.last_pass_idx, buffer_handle)
+    }
+
+    /* An image that shares memory with other images has to wait for their
+    last uses before its first use overwrites them. Its first use discards the
+    contents and starts from an undefined layout, so it already has a barrier,
+    or a render pass dependency for attachments. */
+    pub fn add_image_aliasing(
+        &mut self,
+        image_handle: ImageHandle,
+        src_stage_mask: vk::PipelineStageFlags,
+        src_access_mask: vk::AccessFlags,
+    ) {
+        if let Some(state) = find_first_state_mut(&mut self.images, image_handle) {
+            state.src_stage_mask |= src_stage_mask;
+            state.src_access_mask |= src_access_mask;
+        }
+    }
+
+    // Same for buffers, whose first use doesn't have a barrier otherwise
+    pub fn add_buffer_aliasing(
+        &mut self,
+        buffer_handle: BufferHandle,
+        src_stage_mask: vk::PipelineStageFlags,
+        src_access_mask: vk::AccessFlags,
+    ) {
+        if let Some(state) = find_first_state_mut(&mut self.buffers, buffer_handle) {
+            state.src_stage_mask |= src_stage_mask;
+            state.src_access_mask |= src_access_mask;
+            state.needs_barrier = true;
+        }
+    }
+}
+
+fn get_lifetime(uses: &[Use]) -> Lifetime {
+    Lifetime {
+        first_pass_idx: uses[0].pass_idx,
+        last_pass_idx: uses[uses.len() - 1].pass_idx,
+        is_read_first: uses[0].is_read,
+    }
+}
+
+fn find_lifetime<H: PartialEq>(list: &[(H, Lifetime)], handle: H) -> Option<Lifetime> {
+    list.iter()
+        .find(|(h, _)| *h == handle)
+        .map(|(_, lifetime)| *lifetime)
+}
+
+fn find_first_state_mut<H: PartialEq>(
+    list: &mut [(H, Vec<(usize, UseState)>)],
+    handle: H,
+) -> Option<&mut UseState> {
+    list.iter_mut()
+        .find(|(h, _)| *h == handle)
+        .and_then(|(_, states)| states.first_mut())
+        .map(|(_, state)| state)
+}
+
+fn add_use<H: PartialEq>(list: &mut Vec<(H, Vec<Use>)>, handle: H, new_use: Use) {
+    match list.iter_mut().find(|(h, _)| *h == handle) {
+        Some((_, uses)) => uses.push(new_use),
+        None => list.push((handle, vec![new_use])),
+    }
+}
+
+fn find_state<H: PartialEq>(
+    list: &[(H, Vec<(usize, UseState)>)],
+    pass_idx: usize,
+    handle: H,
+) -> Option<UseState> {
+    list.iter()
+        .find(|(h, _)| *h == handle)
+        .and_then(|(_, states)| states.iter().find(|(idx, _)| *idx == pass_idx))
+        .map(|(_, state)| *state)
+}
+
+fn resolve_uses(
+    uses: &[Use],
+    is_swapchain: bool,
+    present_layout: vk::ImageLayout,
+) -> Vec<(usize, UseState)> {
+    /* A resource that is read before it is written in the graph reads the
+    contents written by the previous frame. In that case the uses wrap around,
+    so that the last use of a frame hands the resource over to the first use of
+    the next one. */
+    let is_wrapping = uses[0].is_read && uses.iter().any(|u| u.is_write);
+
+    (0..uses.len())
+        .map(|i| {
+            let cur = uses[i];
+            let opt_prev = if i > 0 {
+                Some(uses[i - 1])
+            } else if is_wrapping {
+                Some(uses[uses.len() - 1])
+            } else {
+                None
+            };
+            let opt_next = if i + 1 < uses.len() {
+                Some(uses[i + 1])
+            } else if is_wrapping {
+                Some(uses[0])
+            } else {
+                None
+            };
+
+            // Attachments are left in the layout of the next use by the render pass
+            let initial_layout = match opt_prev {
+                Some(prev) if prev.is_attachment => cur.layout,
+                Some(prev) => prev.layout,
+                // Nothing to preserve
+                None if !cur.is_read => vk::ImageLayout::UNDEFINED,
+                // Images from outside the graph, e.g. loaded from a file
+                None => cur.layout,
+            };
+            let final_layout = match opt_next {
+                Some(next) if cur.is_attachment => next.layout,
+                None if is_swapchain => present_layout,
+                _ => cur.layout,
+            };
+
+            /* An attachment continues from the contents left by the earlier
+            uses in the same frame, e.g. when drawing into it across several
+            passes, if this or a later use needs them. Otherwise it starts out
+            cleared if the pass needs that, and undefined if not. The contents
+            only need to be stored if a later use needs them, or if they are
+            presented. */
+            let is_needed_later = is_wrapping || uses[i + 1..].iter().any(|u| u.needs_contents);
+            let load_op = if i > 0 && (cur.needs_contents || is_needed_later) {
+                vk::AttachmentLoadOp::LOAD
+            } else if i == 0 && cur.needs_clear {
+                vk::AttachmentLoadOp::CLEAR
+            } else {
+                vk::AttachmentLoadOp::DONT_CARE
+            };
+            let store_op = if is_needed_later || is_swapchain {
+                vk::AttachmentStoreOp::STORE
+            } else {
+                vk::AttachmentStoreOp::DONT_CARE
+            };
+
+            let (src_stage_mask, src_access_mask) = match opt_prev {
+                Some(prev) => (prev.stage_mask, prev.access_mask),
+                // Only wait for earlier submissions of the same stage, e.g. the
+                // acquisition of a swapchain image.
+                None => (cur.stage_mask, vk::AccessFlags::empty()),
+            };
+            let (dst_stage_mask, dst_access_mask) = match opt_next {
+                Some(next) => (next.stage_mask, next.access_mask),
+                None => (
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::AccessFlags::empty(),
+                ),
+            };
+
+            /* Layout changes always need a barrier. Otherwise, a barrier is only
+            needed if either side writes. Uses that follow an attachment are
+            covered by the dependency at the end of its render pass. */
+            let needs_barrier = !cur.is_attachment
+                && (initial_layout != cur.layout
+                    || opt_prev.is_some_and(|prev| {
+                        !prev.is_attachment && (prev.is_write || cur.is_write)
+                    }));
+
+            let state = UseState {
+                stage_mask: cur.stage_mask,
+                access_mask: cur.access_mask,
+                layout: cur.layout,
+                initial_layout,
+                final_layout,
+                load_op,
+                store_op,
+                src_stage_mask,
+                src_access_mask,
+                dst_stage_mask,
+                dst_access_mask,
+                needs_barrier,
+            };
+            (cur.pass_idx, state)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_use(pass_idx: usize, usage: ImageUsage) -> Use {
+        Use {
+            pass_idx,
+            stage_mask: usage.stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER),
+            access_mask: usage.access_mask(),
+            layout: usage.layout(),
+            is_attachment: usage.is_attachment(),
+            is_read: usage.is_read(),
+            is_write: usage.is_write(),
+            needs_contents: usage.needs_contents(),
+            needs_clear: usage.needs_clear(),
+        }
+    }
+
+    fn compute_image_use(pass_idx: usize, usage: ImageUsage) -> Use {
+        Use {
+            stage_mask: usage.stage_mask(vk::PipelineStageFlags::COMPUTE_SHADER),
+            ..image_use(pass_idx, usage)
+        }
+    }
+
+    // Like the buffer uses of a compute pass
+    fn buffer_use(pass_idx: usize, usage: BufferUsage) -> Use {
+        Use {
+            pass_idx,
+            stage_mask: vk::PipelineStageFlags::COMPUTE_SHADER,
+            access_mask: usage.access_mask(),
+            layout: vk::ImageLayout::UNDEFINED,
+            is_attachment: false,
+            is_read: usage.is_read(),
+            is_write: usage.is_write(),
+            needs_contents: usage.is_read(),
+            needs_clear: false,
+        }
+    }
+
+    fn ops(states: &[(usize, UseState)]) -> Vec<(vk::AttachmentLoadOp, vk::AttachmentStoreOp)> {
+        states
+            .iter()
+            .map(|(_, state)| (state.load_op, state.store_op))
+            .collect()
+    }
+
+    const TESTED_DEPTH: ImageUsage = ImageUsage::DepthAttachment {
+        is_tested: true,
+        is_written: true,
+    };
+    const UNTESTED_DEPTH: ImageUsage = ImageUsage::DepthAttachment {
+        is_tested: false,
+        is_written: false,
+    };
+
+    #[test]
+    fn depth_shared_with_an_untested_pass_is_neither_loaded_nor_stored() {
+        let uses = [image_use(0, TESTED_DEPTH), image_use(1, UNTESTED_DEPTH)];
+        let states = resolve_uses(&uses, false, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(
+            ops(&states),
+            vec![
+                (
+                    vk::AttachmentLoadOp::CLEAR,
+                    vk::AttachmentStoreOp::DONT_CARE
+                ),
+                (
+                    vk::AttachmentLoadOp::DONT_CARE,
+                    vk::AttachmentStoreOp::DONT_CARE
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn depth_tested_by_a_later_pass_is_kept() {
+        let uses = [
+            image_use(0, TESTED_DEPTH),
+            image_use(1, UNTESTED_DEPTH),
+            image_use(2, TESTED_DEPTH),
+        ];
+        let states = resolve_uses(&uses, false, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(
+            ops(&states),
+            vec![
+                (vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE),
+                (vk::AttachmentLoadOp::LOAD, vk::AttachmentStoreOp::STORE),
+                (vk::AttachmentLoadOp::LOAD, vk::AttachmentStoreOp::DONT_CARE),
+            ]
+        );
+    }
+
+    #[test]
+    fn first_color_use_without_a_clear_color_is_not_cleared() {
+        let uses = [
+            image_use(0, ImageUsage::ColorAttachment { is_cleared: false }),
+            image_use(1, ImageUsage::ColorAttachment { is_cleared: false }),
+        ];
+        let states = resolve_uses(&uses, true, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(
+            ops(&states),
+            vec![
+                (
+                    vk::AttachmentLoadOp::DONT_CARE,
+                    vk::AttachmentStoreOp::STORE
+                ),
+                (vk::AttachmentLoadOp::LOAD, vk::AttachmentStoreOp::STORE),
+            ]
+        );
+        assert_eq!(states[1].1.final_layout, vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+
+    #[test]
+    fn color_sampled_later_is_cleared_and_stored() {
+        let uses = [
+            image_use(0, ImageUsage::ColorAttachment { is_cleared: true }),
+            image_use(1, ImageUsage::Sampled),
+        ];
+        let states = resolve_uses(&uses, false, vk::ImageLayout::PRESENT_SRC_KHR);
+        assert_eq!(
+            (states[0].1.load_op, states[0].1.store_op),
+            (vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE)
+        );
+        assert_eq!(
+            states[0].1.final_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+    }
+
+    #[test]
+    fn storage_access_masks() {
+        assert_eq!(
+            StorageAccess::Read.access_mask(),
+            vk::AccessFlags::SHADER_READ
+        );
+        assert_eq!(
+            StorageAccess::Write.access_mask(),
+            vk::AccessFlags::SHADER_WRITE
+        );
+        assert_eq!(
+            StorageAccess::ReadWrite.access_mask(),
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE
+        );
+        assert!(StorageAccess::ReadWrite.is_read() && StorageAccess::ReadWrite.is_write());
+        assert!(!StorageAccess::Write.is_read());
+        assert!(!StorageAccess::Read.is_write());
+    }
+
+    #[test]
+    fn storage_image_written_by_compute_and_sampled_later() {
+        let uses = [
+            compute_image_use(0, ImageUsage::Storage(StorageAccess::Write)),
+            image_use(1, ImageUsage::Sampled),
+        ];
+        let states = resolve_uses(&uses, false, vk::ImageLayout::PRESENT_SRC_KHR);
+
+        // Nothing to preserve before the write
+        let write = states[0].1;
+        assert_eq!(write.initial_layout, vk::ImageLayout::UNDEFINED);
+        assert_eq!(write.layout, vk::ImageLayout::GENERAL);
+        assert!(write.needs_barrier);
+        assert_eq!(
+            write.dst_stage_mask,
+            vk::PipelineStageFlags::FRAGMENT_SHADER
+        );
+
+        // The sampling waits for the write, and changes the layout
+        let read = states[1].1;
+        assert_eq!(read.initial_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(read.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        assert!(read.needs_barrier);
+        assert_eq!(read.src_stage_mask, vk::PipelineStageFlags::COMPUTE_SHADER);
+        assert_eq!(read.src_access_mask, vk::AccessFlags::SHADER_WRITE);
+    }
+
+    #[test]
+    fn buffer_written_then_read_by_compute_passes() {
+        let uses = [
+            buffer_use(0, BufferUsage::Storage(StorageAccess::Write)),
+            buffer_use(1, BufferUsage::Storage(StorageAccess::Read)),
+            buffer_use(2, BufferUsage::Uniform),
+        ];
+        let states = resolve_uses(&uses, false, vk::ImageLayout::PRESENT_SRC_KHR);
+
+        // The first use waits for nothing but earlier compute work
+        assert!(!states[0].1.needs_barrier);
+        assert_eq!(states[0].1.src_access_mask, vk::AccessFlags::empty());
+        // The first read waits for the write
+        assert!(states[1].1.needs_barrier);
+        assert_eq!(states[1].1.src_access_mask, vk::AccessFlags::SHADER_WRITE);
+        // Reads after reads don't need a barrier
+        assert!(!states[2].1.needs_barrier);
+        assert_eq!(
+            states[2].1.dst_stage_mask,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE
+        );
+
+        let lifetime = get_lifetime(&uses);
+        assert_eq!(lifetime.first_pass_idx, 0);
+        assert_eq!(lifetime.last_pass_idx, 2);
+        assert!(!lifetime.is_read_first);
+    }
+
+    #[test]
+    fn buffer_read_before_it_is_written_wraps_around() {
+        // E.g. a simulation that updates its state in place every frame
+        let uses = [
+            buffer_use(0, BufferUsage::Storage(StorageAccess::Read)),
+            buffer_use(1, BufferUsage::Storage(StorageAccess::ReadWrite)),
+        ];
+        let states = resolve_uses(&uses, false, vk::ImageLayout::PRESENT_SRC_KHR);
+
+        // The first read waits for the write of the previous frame
+        assert!(states[0].1.needs_barrier);
+        assert_eq!(
+            states[0].1.src_access_mask,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE
+        );
+        assert_eq!(states[1].1.dst_access_mask, vk::AccessFlags::SHADER_READ);
+        assert!(get_lifetime(&uses).is_read_first);
+    }
+
+    #[test]
+    fn lifetimes_overlap_when_they_share_a_pass() {
+        let lifetime = |first_pass_idx, last_pass_idx| Lifetime {
+            first_pass_idx,
+            last_pass_idx,
+            is_read_first: false,
+        };
+        assert!(lifetime(0, 2).overlaps(&lifetime(2, 3)));
+        assert!(lifetime(2, 3).overlaps(&lifetime(0, 2)));
+        assert!(lifetime(0, 5).overlaps(&lifetime(1, 2)));
+        assert!(!lifetime(0, 1).overlaps(&lifetime(2, 3)));
+        assert!(!lifetime(2, 3).overlaps(&lifetime(0, 1)));
+    }
+}
@@ -0,0 +1,395 @@
+use crate::*;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+pub struct VertexBinding {
+    pub binding: u32,
+    pub stride: u32,
+    pub input_rate: vk::VertexInputRate, // Per-vertex or per-instance
+}
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub binding: u32,
+    pub format: vk::Format,
+    pub offset: u32, // In bytes, from the start of the element in the binding
+}
+
+/// Describes how vertex buffers are laid out, and which shader input
+/// locations their attributes feed.
+#[derive(Clone, Debug, Default, Hash, PartialEq)]
+pub struct VertexLayout {
+    pub bindings: Vec<VertexBinding>,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+// How the shader interprets the components of a vertex attribute format
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FormatNumericType {
+    Float, // Including normalized and scaled integers
+    Sint,
+    Uint,
+}
+
+impl VertexLayout {
+    pub fn new() -> VertexLayout {
+        VertexLayout {
+            bindings: Vec::new(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// A single per-vertex binding with the attributes tightly packed in
+    /// order of location. This is what passes use if they don't declare a
+    /// layout.
+    pub fn from_reflection(vertex_inputs: &[ReflectedVertexInput]) -> VertexLayout {
+        let mut vertex_inputs = vertex_inputs.to_vec();
+        vertex_inputs.sort_by_key(|input| input.location);
+
+        let mut attributes = Vec::new();
+        let mut offset = 0;
+        for input in &vertex_inputs {
+            attributes.push(VertexAttribute {
+                location: input.location,
+                binding: 0,
+                format: input.format,
+                offset,
+            });
+            // Reflected formats are always 32-bit per component
+            offset += format_size(input.format).unwrap_or(0);
+        }
+
+        let bindings = if attributes.is_empty() {
+            Vec::new()
+        } else {
+            vec![VertexBinding {
+                binding: 0,
+                stride: offset,
+                input_rate: vk::VertexInputRate::VERTEX,
+            }]
+        };
+
+        VertexLayout {
+            bindings,
+            attributes,
+        }
+    }
+
+    /// Errors if the layout is inconsistent, or can't feed the given inputs
+    pub fn validate(&self, vertex_inputs: &[ReflectedVertexInput]) -> Result<(), String> {
+        for (idx, binding) in self.bindings.iter().enumerate() {
+            if self.bindings[..idx]
+                .iter()
+                .any(|b| b.binding == binding.binding)
+            {
+                return Err(format!(
+                    "Vertex binding {} is declared more than once.",
+                    binding.binding
+                ));
+            }
+        }
+        for (idx, attribute) in self.attributes.iter().enumerate() {
+            if self.attributes[..idx]
+                .iter()
+                .any(|a| a.location == attribute.location)
+            {
+                return Err(format!(
+                    "Vertex location {} is declared more than once.",
+                    attribute.location
+                ));
+            }
+            let binding = self
+                .bindings
+                .iter()
+                .find(|b| b.binding == attribute.binding)
+                .ok_or_else(|| {
+                    format!(
+                        "Vertex location {} uses binding {}, which isn't declared.",
+                        attribute.location, attribute.binding
+                    )
+                })?;
+            let size = format_size(attribute.format).ok_or_else(|| {
+                format!(
+                    "Vertex location {} has unsupported format {:?}.",
+                    attribute.location, attribute.format
+                )
+            })?;
+            if attribute.offset + size > binding.stride {
+                return Err(format!(
+                    "Vertex location {} doesn't fit within the {} byte stride of binding {}.",
+                    attribute.location, binding.stride, binding.binding
+                ));
+            }
+        }
+
+        for input in vertex_inputs {
+            let attribute = self
+                .attributes
+                .iter()
+                .find(|a| a.location == input.location)
+                .ok_or_else(|| {
+                    format!(
+                        "The vertex shader expects `{}` at location {}, but the vertex layout doesn't provide it.",
+                        input.name, input.location
+                    )
+                })?;
+            if format_numeric_type(attribute.format) != format_numeric_type(input.format) {
+                return Err(format!(
+                    "The vertex shader expects `{}` at location {} to be {:?}, but the vertex layout provides {:?}.",
+                    input.name, input.location, input.format, attribute.format
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn vk_binding_descriptions(&self) -> Vec<vk::VertexInputBindingDescription> {
+        self.bindings
+            .iter()
+            .map(|binding| vk::VertexInputBindingDescription {
+                binding: binding.binding,
+                stride: binding.stride,
+                input_rate: binding.input_rate,
+            })
+            .collect()
+    }
+
+    pub fn vk_attribute_descriptions(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .map(|attribute| vk::VertexInputAttributeDescription {
+                location: attribute.location,
+                binding: attribute.binding,
+                format: attribute.format,
+                offset: attribute.offset,
+            })
+            .collect()
+    }
+}
+
+/// Size in bytes of the vertex attribute formats that are supported
+pub fn format_size(format: vk::Format) -> Option<u32> {
+    let size = match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT | vk::Format::R8_SINT => {
+            1
+        }
+        vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8_SINT
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_SNORM
+        | vk::Format::R16_UINT
+        | vk::Format::R16_SINT
+        | vk::Format::R16_SFLOAT => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::A2B10G10R10_SNORM_PACK32
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SNORM
+        | vk::Format::R16G16_UINT
+        | vk::Format::R16G16_SINT
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R16G16B16A16_SINT
+        | vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32_SINT
+        | vk::Format::R32G32_SFLOAT => 8,
+        vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT | vk::Format::R32G32B32_SFLOAT => {
+            12
+        }
+        vk::Format::R32G32B32A32_UINT
+        | vk::Format::R32G32B32A32_SINT
+        | vk::Format::R32G32B32A32_SFLOAT => 16,
+        _ => return None,
+    };
+    Some(size)
+}
+
+pub fn format_numeric_type(format: vk::Format) -> FormatNumericType {
+    match format {
+        vk::Format::R8_UINT
+        | vk::Format::R8G8_UINT
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::R16_UINT
+        | vk::Format::R16G16_UINT
+        | vk::Format::R16G16B16A16_UINT
+        | vk::Format::R32_UINT
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32B32_UINT
+        | vk::Format::R32G32B32A32_UINT => FormatNumericType::Uint,
+        vk::Format::R8_SINT
+        | vk::Format::R8G8_SINT
+        | vk::Format::R8G8B8A8_SINT
+        | vk::Format::R16_SINT
+        | vk::Format::R16G16_SINT
+        | vk::Format::R16G16B16A16_SINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32G32_SINT
+        | vk::Format::R32G32B32_SINT
+        | vk::Format::R32G32B32A32_SINT => FormatNumericType::Sint,
+        _ => FormatNumericType::Float,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(location: u32, format: vk::Format) -> ReflectedVertexInput {
+        ReflectedVertexInput {
+            location,
+            format,
+            name: format!("input_{}", location),
+        }
+    }
+
+    // Positions and normals from one buffer, and per-instance colors from another
+    fn layout() -> VertexLayout {
+        VertexLayout {
+            bindings: vec![
+                VertexBinding {
+                    binding: 0,
+                    stride: 24,
+                    input_rate: vk::VertexInputRate::VERTEX,
+                },
+                VertexBinding {
+                    binding: 1,
+                    stride: 4,
+                    input_rate: vk::VertexInputRate::INSTANCE,
+                },
+            ],
+            attributes: vec![
+                VertexAttribute {
+                    location: 0,
+                    binding: 0,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: 0,
+                },
+                VertexAttribute {
+                    location: 1,
+                    binding: 0,
+                    format: vk::Format::R32G32B32_SFLOAT,
+                    offset: 12,
+                },
+                VertexAttribute {
+                    location: 2,
+                    binding: 1,
+                    format: vk::Format::R8G8B8A8_UNORM,
+                    offset: 0,
+                },
+            ],
+        }
+    }
+
+    fn inputs() -> Vec<ReflectedVertexInput> {
+        vec![
+            input(0, vk::Format::R32G32B32_SFLOAT),
+            input(1, vk::Format::R32G32B32_SFLOAT),
+            input(2, vk::Format::R32G32B32A32_SFLOAT),
+        ]
+    }
+
+    #[test]
+    fn reflected_layouts_are_packed_by_location() {
+        let layout = VertexLayout::from_reflection(&[
+            input(2, vk::Format::R32G32_SFLOAT),
+            input(0, vk::Format::R32G32B32_SFLOAT),
+            input(1, vk::Format::R32_UINT),
+        ]);
+        assert_eq!(
+            layout.bindings,
+            vec![VertexBinding {
+                binding: 0,
+                stride: 24,
+                input_rate: vk::VertexInputRate::VERTEX,
+            }]
+        );
+        let offsets: Vec<(u32, u32)> = layout
+            .attributes
+            .iter()
+            .map(|a| (a.location, a.offset))
+            .collect();
+        assert_eq!(offsets, vec![(0, 0), (1, 12), (2, 16)]);
+
+        assert_eq!(VertexLayout::from_reflection(&[]), VertexLayout::new());
+    }
+
+    #[test]
+    fn declared_layouts_can_feed_the_shader() {
+        // Normalized colors are read as floats
+        layout().validate(&inputs()).unwrap();
+        // Attributes the shader doesn't use are fine
+        layout().validate(&inputs()[..1]).unwrap();
+    }
+
+    #[test]
+    fn inconsistent_layouts_are_rejected() {
+        let assert_err = |layout: VertexLayout, message: &str| {
+            let err = layout.validate(&[]).unwrap_err();
+            assert!(err.contains(message), "{}", err);
+        };
+
+        let mut duplicate_binding = layout();
+        duplicate_binding.bindings[1].binding = 0;
+        assert_err(duplicate_binding, "binding 0 is declared more than once");
+
+        let mut duplicate_location = layout();
+        duplicate_location.attributes[1].location = 0;
+        assert_err(duplicate_location, "location 0 is declared more than once");
+
+        let mut undeclared_binding = layout();
+        undeclared_binding.attributes[2].binding = 2;
+        assert_err(undeclared_binding, "binding 2, which isn't declared");
+
+        let mut past_the_stride = layout();
+        past_the_stride.attributes[1].offset = 16;
+        assert_err(past_the_stride, "doesn't fit within the 24 byte stride");
+
+        let mut unsupported_format = layout();
+        unsupported_format.attributes[0].format = vk::Format::D32_SFLOAT;
+        assert_err(unsupported_format, "unsupported format");
+    }
+
+    #[test]
+    fn layouts_that_dont_match_the_shader_are_rejected() {
+        let mut inputs = inputs();
+        inputs.push(input(3, vk::Format::R32G32_SFLOAT));
+        let err = layout().validate(&inputs).unwrap_err();
+        assert!(err.contains("`input_3` at location 3"), "{}", err);
+
+        let inputs = [input(2, vk::Format::R32_UINT)];
+        let err = layout().validate(&inputs).unwrap_err();
+        assert!(err.contains("to be R32_UINT"), "{}", err);
+    }
+
+    #[test]
+    fn format_sizes_and_numeric_types() {
+        assert_eq!(format_size(vk::Format::R8G8B8A8_UNORM), Some(4));
+        assert_eq!(format_size(vk::Format::R16G16B16A16_SFLOAT), Some(8));
+        assert_eq!(format_size(vk::Format::R32G32B32_SINT), Some(12));
+        assert_eq!(format_size(vk::Format::B8G8R8A8_SRGB), None);
+        assert_eq!(
+            format_numeric_type(vk::Format::R16G16_SNORM),
+            FormatNumericType::Float
+        );
+        assert_eq!(
+            format_numeric_type(vk::Format::R8G8B8A8_UINT),
+            FormatNumericType::Uint
+        );
+        assert_eq!(
+            format_numeric_type(vk::Format::R32_SINT),
+            FormatNumericType::Sint
+        );
+    }
+}
@@ -0,0 +1,403 @@
+use crate::*;
+use glam::*;
+
+/// A node of the glTF node tree
+pub struct SceneNode {
+    pub name: String,
+    pub opt_parent: Option<usize>, // Index into Scene::nodes
+    pub children: Vec<usize>,      // Indices into Scene::nodes
+    // The local transform, decomposed so that animations can replace parts of it
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    pub local_transform: Mat4, // Relative to the parent
+    pub world_transform: Mat4,
+    pub morph_weights: Vec<f32>, // One per morph target of the node's mesh, if any
+}
+
+/// A node that draws a mesh
+pub struct MeshInstance {
+    pub node_index: usize,
+    pub mesh_index: usize,                 // Index into Scene::meshes
+    pub opt_skeleton_index: Option<usize>, // Index into Scene::skeletons
+    pub world_transform: Mat4,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Projection {
+    Perspective {
+        opt_aspect_ratio: Option<f32>, // None means use the viewport's
+        y_fov: f32,                    // In radians
+        z_near: f32,
+        opt_z_far: Option<f32>, // None means an infinite projection
+    },
+    Orthographic {
+        x_mag: f32, // Half the width of the view volume
+        y_mag: f32, // Half the height of the view volume
+        z_near: f32,
+        z_far: f32,
+    },
+}
+
+/// A camera placed in the scene. Like all glTF cameras, it looks down its
+/// local -Z axis, with +Y up.
+pub struct SceneCamera {
+    pub name: String,
+    pub node_index: usize,
+    pub projection: Projection,
+    pub world_transform: Mat4,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f32, // In radians
+        outer_cone_angle: f32, // In radians
+    },
+}
+
+/// A KHR_lights_punctual light placed in the scene. Directional and spot
+/// lights shine down their local -Z axis.
+pub struct SceneLight {
+    pub name: String,
+    pub node_index: usize,
+    pub kind: LightKind,
+    pub color: Vec3,            // Linear RGB
+    pub intensity: f32,         // Candela for point and spot lights, lux for directional ones
+    pub opt_range: Option<f32>, // None means infinite
+    pub world_transform: Mat4,
+}
+
+/// The default scene of a glTF file, with the transforms of the node tree
+/// resolved to world space.
+pub struct Scene {
+    pub nodes: Vec<SceneNode>,    // Indexed like the glTF nodes
+    pub root_nodes: Vec<usize>,   // The nodes of the scene without a parent
+    pub meshes: Vec<Mesh>,        // Indexed like the glTF meshes
+    pub materials: Vec<Material>, // Indexed like the glTF materials
+    pub mesh_instances: Vec<MeshInstance>,
+    pub cameras: Vec<SceneCamera>,
+    pub lights: Vec<SceneLight>,
+    pub skeletons: Vec<Skeleton>, // Indexed like the glTF skins
+    pub animations: Vec<Animation>,
+}
+
+impl Scene {
+    pub fn load(
+        name: &str,
+        path: &str,
+        gpu: &Gpu,
+        command_pool: vk::CommandPool,
+        image_list: &mut ImageList,
+        buffer_list: &mut BufferList,
+        debug_utils: &DebugUtils,
+    ) -> Result<Scene, String> {
+        let (gltf, buffers, images) = gltf::import(path)
+            .map_err(|err| format!("Failed to open scene `{}`: {}", path, err))?;
+        let materials = Material::load_all(
+            name,
+            &gltf,
+            &images,
+            image_list,
+            gpu,
+            command_pool,
+            debug_utils,
+        )
+        .map_err(|err| format!("Failed to load the materials of `{}`: {}", path, err))?;
+
+        let meshes = gltf
+            .meshes()
+            .map(|mesh| {
+                Mesh::from_gltf_meshes(
+                    &format!(
+                        "{}_{}",
+                        name,
+                        mesh.name().unwrap_or(&mesh.index().to_string())
+                    ),
+                    std::iter::once(mesh),
+                    &buffers,
+                    materials.clone(),
+                    gpu,
+                    buffer_list,
+                    debug_utils,
+                )
+                .map_err(|err| format!("Failed to load a mesh of `{}`: {}", path, err))
+            })
+            .collect::<Result<Vec<Mesh>, String>>()?;
+
+        let mut nodes: Vec<SceneNode> = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                SceneNode {
+                    name: node.name().unwrap_or("").to_string(),
+                    opt_parent: None,
+                    children: node.children().map(|child| child.index()).collect(),
+                    translation: Vec3::from(translation),
+                    rotation: Quat::from(rotation),
+                    scale: Vec3::from(scale),
+                    local_transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
+                    world_transform: Mat4::identity(),
+                    // Nodes can override the default weights of their mesh
+                    morph_weights: match (node.weights(), node.mesh()) {
+                        (Some(weights), _) => weights.to_vec(),
+                        (None, Some(mesh)) => meshes[mesh.index()].default_morph_weights.clone(),
+                        (None, None) => Vec::new(),
+                    },
+                }
+            })
+            .collect();
+        for node_idx in 0..nodes.len() {
+            for child_idx in nodes[node_idx].children.clone() {
+                nodes[child_idx].opt_parent = Some(node_idx);
+            }
+        }
+
+        // Files without a default scene show their first one
+        let opt_gltf_scene = gltf.default_scene().or_else(|| gltf.scenes().next());
+
+        let root_nodes: Vec<usize> = match &opt_gltf_scene {
+            Some(gltf_scene) => gltf_scene.nodes().map(|node| node.index()).collect(),
+            None => Vec::new(),
+        };
+
+        // Nodes that aren't reachable from the roots aren't part of the scene
+        let mut scene_node_indices = Vec::new();
+        let mut stack = root_nodes.clone();
+        while let Some(node_idx) = stack.pop() {
+            scene_node_indices.push(node_idx);
+            stack.extend_from_slice(&nodes[node_idx].children);
+        }
+        scene_node_indices.sort();
+
+        let gltf_nodes: Vec<gltf::Node> = gltf.nodes().collect();
+        let mut mesh_instances = Vec::new();
+        let mut cameras = Vec::new();
+        let mut lights = Vec::new();
+        for node_idx in scene_node_indices {
+            let gltf_node = &gltf_nodes[node_idx];
+            let node = &nodes[node_idx];
+
+            if let Some(mesh) = gltf_node.mesh() {
+                mesh_instances.push(MeshInstance {
+                    node_index: node_idx,
+                    mesh_index: mesh.index(),
+                    opt_skeleton_index: gltf_node.skin().map(|skin| skin.index()),
+                    world_transform: node.world_transform,
+                });
+            }
+
+            if let Some(camera) = gltf_node.camera() {
+                let projection = match camera.projection() {
+                    gltf::camera::Projection::Perspective(perspective) => Projection::Perspective {
+                        opt_aspect_ratio: perspective.aspect_ratio(),
+                        y_fov: perspective.yfov(),
+                        z_near: perspective.znear(),
+                        opt_z_far: perspective.zfar(),
+                    },
+                    gltf::camera::Projection::Orthographic(orthographic) => {
+                        Projection::Orthographic {
+                            x_mag: orthographic.xmag(),
+                            y_mag: orthographic.ymag(),
+                            z_near: orthographic.znear(),
+                            z_far: orthographic.zfar(),
+                        }
+                    }
+                };
+                cameras.push(SceneCamera {
+                    name: camera.name().unwrap_or(&node.name).to_string(),
+                    node_index: node_idx,
+                    projection,
+                    world_transform: node.world_transform,
+                });
+            }
+
+            if let Some(light) = gltf_node.light() {
+                let kind = match light.kind() {
+                    gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+                    gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+                    gltf::khr_lights_punctual::Kind::Spot {
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    } => LightKind::Spot {
+                        inner_cone_angle,
+                        outer_cone_angle,
+                    },
+                };
+                lights.push(SceneLight {
+                    name: light.name().unwrap_or(&node.name).to_string(),
+                    node_index: node_idx,
+                    kind,
+                    color: Vec3::from(light.color()),
+                    intensity: light.intensity(),
+                    opt_range: light.range(),
+                    world_transform: node.world_transform,
+                });
+            }
+        }
+
+        let animations = Animation::load_all(&gltf, &buffers)
+            .map_err(|err| format!("Failed to load the animations of `{}`: {}", path, err))?;
+        let mut scene = Scene {
+            nodes,
+            root_nodes,
+            meshes,
+            materials,
+            mesh_instances,
+            cameras,
+            lights,
+            skeletons: Skeleton::load_all(&gltf, &buffers),
+            animations,
+        };
+        scene.update_world_transforms();
+        Ok(scene)
+    }
+
+    /// Pose the scene with an animation, at the given time in seconds
+    pub fn animate(&mut self, animation_idx: usize, time: f32) {
+        self.animations[animation_idx].apply(time, &mut self.nodes);
+        self.update_world_transforms();
+    }
+
+    /// Propagate the local transforms of the nodes down the node tree, and
+    /// into the instances, cameras and lights that are attached to them
+    pub fn update_world_transforms(&mut self) {
+        let mut stack: Vec<(usize, Mat4)> = self
+            .root_nodes
+            .iter()
+            .map(|node_idx| (*node_idx, Mat4::identity()))
+            .collect();
+        while let Some((node_idx, parent_transform)) = stack.pop() {
+            let node = &mut self.nodes[node_idx];
+            node.world_transform = parent_transform * node.local_transform;
+            for child_idx in &node.children {
+                stack.push((*child_idx, node.world_transform));
+            }
+        }
+
+        for mesh_instance in &mut self.mesh_instances {
+            mesh_instance.world_transform = self.nodes[mesh_instance.node_index].world_transform;
+        }
+        for camera in &mut self.cameras {
+            camera.world_transform = self.nodes[camera.node_index].world_transform;
+        }
+        for light in &mut self.lights {
+            light.world_transform = self.nodes[light.node_index].world_transform;
+        }
+    }
+
+    /// The morph target weights of a mesh instance, to be uploaded to a
+    /// buffer for the shader that applies the mesh's morph target deltas
+    pub fn get_morph_weights(&self, mesh_instance: &MeshInstance) -> &[f32] {
+        &self.nodes[mesh_instance.node_index].morph_weights
+    }
+
+    /// Override the morph target weights of a mesh instance, until an
+    /// animation changes them again
+    pub fn set_morph_weights(&mut self, mesh_instance_idx: usize, weights: &[f32]) {
+        let node_idx = self.mesh_instances[mesh_instance_idx].node_index;
+        self.nodes[node_idx].morph_weights = weights.to_vec();
+    }
+
+    /// The joint matrices of a skinned mesh instance, in its current pose
+    pub fn get_joint_matrices(&self, mesh_instance: &MeshInstance) -> Option<Vec<Mat4>> {
+        mesh_instance.opt_skeleton_index.map(|skeleton_idx| {
+            self.skeletons[skeleton_idx].joint_matrices(&self.nodes, mesh_instance.world_transform)
+        })
+    }
+
+    /// Draw every mesh instance. The callback is called before each draw, to
+    /// set per-instance state such as the world transform.
+    pub fn draw(
+        &self,
+        cmd_buf: vk::CommandBuffer,
+        buffer_list: &BufferList,
+        mut set_instance: impl FnMut(&MeshInstance),
+    ) {
+        for mesh_instance in &self.mesh_instances {
+            set_instance(mesh_instance);
+            self.meshes[mesh_instance.mesh_index].draw(cmd_buf, buffer_list);
+        }
+    }
+
+    /// Like `draw()`, but skip the mesh instances whose bounding spheres are
+    /// outside the frustum, built from the view-projection matrix of the
+    /// camera. Skinned and morphed meshes are tested in their rest pose.
+    pub fn draw_visible(
+        &self,
+        cmd_buf: vk::CommandBuffer,
+        buffer_list: &BufferList,
+        frustum: &Frustum,
+        mut set_instance: impl FnMut(&MeshInstance),
+    ) {
+        for mesh_instance in &self.mesh_instances {
+            let mesh = &self.meshes[mesh_instance.mesh_index];
+            let bounding_sphere = mesh
+                .bounding_sphere
+                .transform(mesh_instance.world_transform);
+            if frustum.intersects_sphere(&bounding_sphere) {
+                set_instance(mesh_instance);
+                mesh.draw(cmd_buf, buffer_list);
+            }
+        }
+    }
+}
+
+impl SceneCamera {
+    pub fn position(&self) -> Vec3 {
+        self.world_transform.w_axis().truncate()
+    }
+
+    pub fn world_to_view(&self) -> Mat4 {
+        self.world_transform.inverse()
+    }
+
+    /// Right handed, with depth in [0, 1]. The aspect ratio is only used if
+    /// the camera doesn't specify one.
+    pub fn view_to_clip(&self, aspect_ratio: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective {
+                opt_aspect_ratio,
+                y_fov,
+                z_near,
+                opt_z_far,
+            } => {
+                let aspect_ratio = opt_aspect_ratio.unwrap_or(aspect_ratio);
+                match opt_z_far {
+                    Some(z_far) => {
+                        // glam doesn't have a finite right handed perspective
+                        // with depth in [0, 1]
+                        let f = 1.0 / (0.5 * y_fov).tan();
+                        let range = z_far / (z_near - z_far);
+                        Mat4::from_cols(
+                            Vec4::new(f / aspect_ratio, 0.0, 0.0, 0.0),
+                            Vec4::new(0.0, f, 0.0, 0.0),
+                            Vec4::new(0.0, 0.0, range, -1.0),
+                            Vec4::new(0.0, 0.0, range * z_near, 0.0),
+                        )
+                    }
+                    None => Mat4::perspective_infinite_rh(y_fov, aspect_ratio, z_near),
+                }
+            }
+            Projection::Orthographic {
+                x_mag,
+                y_mag,
+                z_near,
+                z_far,
+            } => Mat4::orthographic_rh(-x_mag, x_mag, -y_mag, y_mag, z_near, z_far),
+        }
+    }
+}
+
+impl SceneLight {
+    pub fn position(&self) -> Vec3 {
+        self.world_transform.w_axis().truncate()
+    }
+
+    /// The direction the light shines in. Meaningless for point lights.
+    pub fn direction(&self) -> Vec3 {
+        (-self.world_transform.z_axis().truncate()).normalize()
+    }
+}
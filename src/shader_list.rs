@@ -1,9 +1,21 @@
 use crate::*;
 use std::path::Path;
 
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ShaderStage {
     Vertex,
     Fragment,
+    Compute,
+}
+
+impl ShaderStage {
+    pub fn vk_shader_stage_flags(&self) -> vk::ShaderStageFlags {
+        match self {
+            ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
+            ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+        }
+    }
 }
 
 pub struct InternalShader {
@@ -12,6 +24,7 @@ pub struct InternalShader {
     pub source_path: String,
     pub spirv_path: String,
     pub vk_shader_module: vk::ShaderModule,
+    pub reflection: ShaderReflection,
 }
 
 pub struct ShaderList {
@@ -63,12 +76,22 @@ impl ShaderList {
         let source_path = String::from(&format!("assets/shaders/{}", path));
         let spirv_path = String::from(&format!("{}/{}.spv", SHADER_CACHE_PATH, path));
         let is_compilation_needed = is_compilation_needed(&source_path, &spirv_path);
-        let vk_shader_module = get_shader_module(
+        let (vk_shader_module, reflection) = get_shader_module(
             &self.device,
             &source_path,
             &spirv_path,
             is_compilation_needed,
         )?;
+        // Error if the shader was declared with the wrong stage
+        if reflection.opt_shader_stage != Some(shader_stage) {
+            unsafe {
+                self.device.destroy_shader_module(vk_shader_module, None);
+            }
+            return Err(format!(
+                "Shader `{}` was declared as {:?}, but `{}` has a {:?} entry point.",
+                name, shader_stage, path, reflection.opt_shader_stage
+            ));
+        }
         // Insert
         self.list.push((
             handle,
@@ -78,6 +101,7 @@ impl ShaderList {
                 source_path,
                 spirv_path,
                 vk_shader_module,
+                reflection,
             },
         ));
         Ok(handle)
@@ -92,22 +116,27 @@ impl ShaderList {
         None
     }
 
-    pub fn hot_reload(&mut self, graph_cache: &mut Vec<(Graph, GraphHandle)>) {
+    pub fn hot_reload(&mut self, graph_cache: &mut GraphCache, resource_pool: &mut ResourcePool) {
         for (shader_handle, shader) in &mut self.list {
             if !is_compilation_needed(&shader.source_path, &shader.spirv_path) {
                 continue;
             }
 
-            if let Ok(vk_shader_module) =
+            if let Ok((vk_shader_module, reflection)) =
                 get_shader_module(&self.device, &shader.source_path, &shader.spirv_path, true)
             {
-                // Evict any graphs that contain the shaders that need to be updated
-                graph_cache.retain(|(graph, _)| !graph.shader_handles.contains(shader_handle));
+                // Evict any graphs that contain the shaders that need to be
+                // updated, keeping their resources for the rebuilt graphs
+                graph_cache.evict(
+                    |graph, _| graph.shader_handles.contains(shader_handle),
+                    resource_pool,
+                );
 
                 unsafe {
                     self.device
                         .destroy_shader_module(shader.vk_shader_module, None);
                     shader.vk_shader_module = vk_shader_module;
+                    shader.reflection = reflection;
                 }
             }
         }
@@ -176,7 +205,7 @@ fn get_shader_module(
     source_path: &str,
     spirv_path: &str,
     is_compilation_needed: bool,
-) -> Result<vk::ShaderModule, String> {
+) -> Result<(vk::ShaderModule, ShaderReflection), String> {
     // If spirv path doesn't exist, compile the shader
     if is_compilation_needed {
         compile_shader(source_path, spirv_path)?;
@@ -198,6 +227,8 @@ fn get_shader_module(
         assert_eq!(suffix_u8.len(), 0);
         middle_u32
     };
+    let reflection = ShaderReflection::new(spirv_u32)
+        .map_err(|err| format!("Failed to reflect `{}`: {}", spirv_path, err))?;
     let create_info = vk::ShaderModuleCreateInfo::builder().code(spirv_u32);
 
     let vk_shader_module = unsafe {
@@ -206,5 +237,5 @@ fn get_shader_module(
             .expect("Failed to create shader module.")
     };
 
-    Ok(vk_shader_module)
+    Ok((vk_shader_module, reflection))
 }
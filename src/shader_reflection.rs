@@ -0,0 +1,803 @@
+use crate::*;
+use std::collections::HashMap;
+
+/* A small SPIR-V reflector. It walks the instructions of a module once, and
+extracts just what's needed to build descriptor set layouts, pipeline layouts
+and vertex input state. The opcodes and enumerants are from the SPIR-V spec:
+https://www.khronos.org/registry/spir-v/specs/unified1/SPIRV.html
+
+Passes bind all their resources in descriptor set 0, one descriptor per
+binding. Resources in other sets are rejected here, and arrays of resources are
+reflected but rejected when the layout of the pass is built. */
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const SPIRV_HEADER_LEN: usize = 5;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_INPUT: u32 = 1;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+
+// Execution models
+const EXECUTION_MODEL_VERTEX: u32 = 0;
+const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+
+// Image dimensions
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub descriptor_count: u32, // Array size, 1 if not an array
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectedPushConstants {
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReflectedVertexInput {
+    pub location: u32,
+    pub format: vk::Format,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShaderReflection {
+    pub opt_shader_stage: Option<ShaderStage>, // From the entry point
+    pub bindings: Vec<ReflectedBinding>,
+    pub opt_push_constants: Option<ReflectedPushConstants>,
+    pub vertex_inputs: Vec<ReflectedVertexInput>, // Empty unless it's a vertex shader
+}
+
+enum Type {
+    Scalar {
+        is_float: bool,
+        is_signed: bool,
+        width: u32,
+    },
+    Vector {
+        component: u32,
+        count: u32,
+    },
+    Matrix {
+        column: u32,
+        count: u32,
+    },
+    Image {
+        dim: u32,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage,
+    Array {
+        element: u32,
+        length: u32,
+    },
+    RuntimeArray,
+    Struct {
+        members: Vec<u32>,
+    },
+    Pointer {
+        pointee: u32,
+    },
+}
+
+#[derive(Default)]
+struct Decorations {
+    opt_set: Option<u32>,
+    opt_binding: Option<u32>,
+    opt_location: Option<u32>,
+    opt_array_stride: Option<u32>,
+    is_built_in: bool,
+    is_buffer_block: bool,
+    member_offsets: HashMap<u32, u32>,
+    member_matrix_strides: HashMap<u32, u32>,
+}
+
+impl ShaderReflection {
+    pub fn new(spirv: &[u32]) -> Result<ShaderReflection, String> {
+        if spirv.len() < SPIRV_HEADER_LEN || spirv[0] != SPIRV_MAGIC {
+            return Err(String::from("Not a valid SPIR-V module."));
+        }
+
+        let mut opt_shader_stage = None;
+        let mut names: HashMap<u32, String> = HashMap::new();
+        let mut decorations: HashMap<u32, Decorations> = HashMap::new();
+        let mut types: HashMap<u32, Type> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut variables: Vec<(u32, u32, u32)> = Vec::new(); // (id, type, storage class)
+
+        // Collect everything we need in a single pass
+        let mut word_idx = SPIRV_HEADER_LEN;
+        while word_idx < spirv.len() {
+            let word_count = (spirv[word_idx] >> 16) as usize;
+            let opcode = spirv[word_idx] & 0xffff;
+            if word_count == 0 || word_idx + word_count > spirv.len() {
+                return Err(String::from("Malformed SPIR-V instruction stream."));
+            }
+            let ops = &spirv[word_idx + 1..word_idx + word_count];
+            word_idx += word_count;
+            let op = |idx: usize| {
+                ops.get(idx).copied().ok_or_else(|| {
+                    format!(
+                        "SPIR-V instruction with opcode {} is missing operand {}.",
+                        opcode, idx
+                    )
+                })
+            };
+            // Composite types may only refer to types declared before them,
+            // which also rules out cycles
+            let declared = |types: &HashMap<u32, Type>, type_id: u32| {
+                if types.contains_key(&type_id) {
+                    Ok(type_id)
+                } else {
+                    Err(format!(
+                        "Type `%{}` is used before it is declared in the SPIR-V module.",
+                        type_id
+                    ))
+                }
+            };
+            if (OP_TYPE_BOOL..=OP_TYPE_POINTER).contains(&opcode) && types.contains_key(&op(0)?) {
+                return Err(format!(
+                    "Type `%{}` is declared more than once in the SPIR-V module.",
+                    op(0)?
+                ));
+            }
+
+            match opcode {
+                OP_NAME => {
+                    names.insert(op(0)?, parse_string(&ops[1..]));
+                }
+                OP_ENTRY_POINT => {
+                    opt_shader_stage = match op(0)? {
+                        EXECUTION_MODEL_VERTEX => Some(ShaderStage::Vertex),
+                        EXECUTION_MODEL_FRAGMENT => Some(ShaderStage::Fragment),
+                        EXECUTION_MODEL_GL_COMPUTE => Some(ShaderStage::Compute),
+                        _ => None,
+                    };
+                }
+                OP_DECORATE => {
+                    let decoration = decorations.entry(op(0)?).or_default();
+                    match op(1)? {
+                        DECORATION_BUFFER_BLOCK => decoration.is_buffer_block = true,
+                        DECORATION_ARRAY_STRIDE => decoration.opt_array_stride = Some(op(2)?),
+                        DECORATION_BUILT_IN => decoration.is_built_in = true,
+                        DECORATION_LOCATION => decoration.opt_location = Some(op(2)?),
+                        DECORATION_BINDING => decoration.opt_binding = Some(op(2)?),
+                        DECORATION_DESCRIPTOR_SET => decoration.opt_set = Some(op(2)?),
+                        _ => {}
+                    }
+                }
+                OP_MEMBER_DECORATE => {
+                    let decoration = decorations.entry(op(0)?).or_default();
+                    match op(2)? {
+                        DECORATION_OFFSET => {
+                            decoration.member_offsets.insert(op(1)?, op(3)?);
+                        }
+                        DECORATION_MATRIX_STRIDE => {
+                            decoration.member_matrix_strides.insert(op(1)?, op(3)?);
+                        }
+                        DECORATION_BUILT_IN => decoration.is_built_in = true,
+                        _ => {}
+                    }
+                }
+                OP_TYPE_BOOL => {
+                    types.insert(
+                        op(0)?,
+                        Type::Scalar {
+                            is_float: false,
+                            is_signed: false,
+                            width: 32,
+                        },
+                    );
+                }
+                OP_TYPE_INT => {
+                    types.insert(
+                        op(0)?,
+                        Type::Scalar {
+                            is_float: false,
+                            is_signed: op(2)? != 0,
+                            width: op(1)?,
+                        },
+                    );
+                }
+                OP_TYPE_FLOAT => {
+                    types.insert(
+                        op(0)?,
+                        Type::Scalar {
+                            is_float: true,
+                            is_signed: true,
+                            width: op(1)?,
+                        },
+                    );
+                }
+                OP_TYPE_VECTOR => {
+                    types.insert(
+                        op(0)?,
+                        Type::Vector {
+                            component: declared(&types, op(1)?)?,
+                            count: op(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_MATRIX => {
+                    types.insert(
+                        op(0)?,
+                        Type::Matrix {
+                            column: declared(&types, op(1)?)?,
+                            count: op(2)?,
+                        },
+                    );
+                }
+                OP_TYPE_IMAGE => {
+                    types.insert(
+                        op(0)?,
+                        Type::Image {
+                            dim: op(2)?,
+                            sampled: op(6)?,
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER => {
+                    types.insert(op(0)?, Type::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    types.insert(op(0)?, Type::SampledImage);
+                }
+                OP_TYPE_ARRAY => {
+                    types.insert(
+                        op(0)?,
+                        Type::Array {
+                            element: declared(&types, op(1)?)?,
+                            length: op(2)?, // Id of a constant, resolved later
+                        },
+                    );
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    types.insert(op(0)?, Type::RuntimeArray);
+                }
+                OP_TYPE_STRUCT => {
+                    let struct_id = op(0)?;
+                    let members = ops[1..]
+                        .iter()
+                        .map(|member| declared(&types, *member))
+                        .collect::<Result<Vec<u32>, String>>()?;
+                    types.insert(struct_id, Type::Struct { members });
+                }
+                OP_TYPE_POINTER => {
+                    types.insert(op(0)?, Type::Pointer { pointee: op(2)? });
+                }
+                OP_CONSTANT => {
+                    // Only the low word matters for array lengths
+                    constants.insert(op(1)?, op(2)?);
+                }
+                OP_VARIABLE => {
+                    variables.push((op(1)?, op(0)?, op(2)?));
+                }
+                _ => {}
+            }
+        }
+
+        let module = Module {
+            names,
+            decorations,
+            types,
+            constants,
+        };
+
+        let mut bindings = Vec::new();
+        let mut opt_push_constants: Option<ReflectedPushConstants> = None;
+        let mut vertex_inputs = Vec::new();
+        for (id, pointer_type, storage_class) in variables {
+            let type_id = match module.types.get(&pointer_type) {
+                Some(Type::Pointer { pointee }) => *pointee,
+                _ => return Err(format!("Variable `{}` is not a pointer.", module.name(id))),
+            };
+            let decoration = module.decorations.get(&id);
+
+            match storage_class {
+                STORAGE_CLASS_UNIFORM_CONSTANT
+                | STORAGE_CLASS_UNIFORM
+                | STORAGE_CLASS_STORAGE_BUFFER => {
+                    let (set, binding) = match decoration {
+                        Some(Decorations {
+                            opt_set,
+                            opt_binding: Some(binding),
+                            ..
+                        }) => (opt_set.unwrap_or(0), *binding),
+                        _ => {
+                            return Err(format!(
+                                "Resource `{}` doesn't have a binding decoration.",
+                                module.name(id)
+                            ))
+                        }
+                    };
+                    // Passes bind all their resources in a single set
+                    if set != 0 {
+                        return Err(format!(
+                            "Resource `{}` is in descriptor set {}, but only set 0 is supported.",
+                            module.name(id),
+                            set
+                        ));
+                    }
+                    let (element_type, descriptor_count) = module.strip_arrays(type_id)?;
+                    let descriptor_type =
+                        module.descriptor_type(element_type, storage_class, id)?;
+                    bindings.push(ReflectedBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        descriptor_count,
+                        name: module.name(id),
+                    });
+                }
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    let (offset, end) = module.struct_extent(type_id)?;
+                    opt_push_constants = Some(ReflectedPushConstants {
+                        offset,
+                        size: end - offset,
+                    });
+                }
+                STORAGE_CLASS_INPUT if opt_shader_stage == Some(ShaderStage::Vertex) => {
+                    let location = match decoration {
+                        Some(Decorations {
+                            is_built_in: false,
+                            opt_location: Some(location),
+                            ..
+                        }) => *location,
+                        _ => continue, // Built-ins like gl_VertexIndex
+                    };
+                    vertex_inputs.push(ReflectedVertexInput {
+                        location,
+                        format: module.vertex_format(type_id, id)?,
+                        name: module.name(id),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // Sort for deterministic layouts
+        bindings.sort_by_key(|b| (b.set, b.binding));
+        vertex_inputs.sort_by_key(|v| v.location);
+
+        Ok(ShaderReflection {
+            opt_shader_stage,
+            bindings,
+            opt_push_constants,
+            vertex_inputs,
+        })
+    }
+}
+
+struct Module {
+    names: HashMap<u32, String>,
+    decorations: HashMap<u32, Decorations>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+}
+
+impl Module {
+    fn name(&self, id: u32) -> String {
+        match self.names.get(&id) {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => format!("%{}", id),
+        }
+    }
+
+    fn get_type(&self, type_id: u32) -> Result<&Type, String> {
+        self.types
+            .get(&type_id)
+            .ok_or_else(|| format!("Type `%{}` not found in the SPIR-V module.", type_id))
+    }
+
+    /// Returns the innermost element type, and the total number of elements
+    fn strip_arrays(&self, type_id: u32) -> Result<(u32, u32), String> {
+        match self.get_type(type_id)? {
+            Type::Array { element, length } => {
+                let length = *self.constants.get(length).ok_or_else(|| {
+                    String::from("Arrays sized by specialization constants are not supported.")
+                })?;
+                let (element_type, count) = self.strip_arrays(*element)?;
+                let count = count
+                    .checked_mul(length)
+                    .ok_or_else(|| String::from("Descriptor array is too large."))?;
+                Ok((element_type, count))
+            }
+            Type::RuntimeArray => Err(String::from("Unsized descriptor arrays are not supported.")),
+            _ => Ok((type_id, 1)),
+        }
+    }
+
+    fn descriptor_type(
+        &self,
+        type_id: u32,
+        storage_class: u32,
+        variable_id: u32,
+    ) -> Result<vk::DescriptorType, String> {
+        let is_buffer_block = self
+            .decorations
+            .get(&type_id)
+            .is_some_and(|d| d.is_buffer_block);
+        let descriptor_type = match (self.get_type(type_id)?, storage_class) {
+            (Type::SampledImage, _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (Type::Sampler, _) => vk::DescriptorType::SAMPLER,
+            (Type::Image { dim, .. }, _) if *dim == DIM_SUBPASS_DATA => {
+                vk::DescriptorType::INPUT_ATTACHMENT
+            }
+            (Type::Image { dim, sampled }, _) if *dim == DIM_BUFFER => {
+                if *sampled == 2 {
+                    vk::DescriptorType::STORAGE_TEXEL_BUFFER
+                } else {
+                    vk::DescriptorType::UNIFORM_TEXEL_BUFFER
+                }
+            }
+            (Type::Image { sampled, .. }, _) => {
+                if *sampled == 2 {
+                    vk::DescriptorType::STORAGE_IMAGE
+                } else {
+                    vk::DescriptorType::SAMPLED_IMAGE
+                }
+            }
+            (Type::Struct { .. }, STORAGE_CLASS_STORAGE_BUFFER) => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            // Older GLSL compilers mark storage buffers as BufferBlock
+            (Type::Struct { .. }, STORAGE_CLASS_UNIFORM) if is_buffer_block => {
+                vk::DescriptorType::STORAGE_BUFFER
+            }
+            (Type::Struct { .. }, STORAGE_CLASS_UNIFORM) => vk::DescriptorType::UNIFORM_BUFFER,
+            _ => {
+                return Err(format!(
+                    "Resource `{}` has a type that can't be bound to a descriptor.",
+                    self.name(variable_id)
+                ))
+            }
+        };
+        Ok(descriptor_type)
+    }
+
+    /// Returns the (start, end) byte range covered by the members of a struct
+    fn struct_extent(&self, type_id: u32) -> Result<(u32, u32), String> {
+        let members = match self.get_type(type_id)? {
+            Type::Struct { members } => members,
+            _ => return Err(String::from("Push constants must be a block.")),
+        };
+        let decoration = self.decorations.get(&type_id);
+        let mut start = u32::MAX;
+        let mut end = 0;
+        for (member_idx, member_type) in members.iter().enumerate() {
+            let member_idx = member_idx as u32;
+            let offset = decoration
+                .and_then(|d| d.member_offsets.get(&member_idx))
+                .copied()
+                .ok_or_else(|| String::from("Push constant member is missing an offset."))?;
+            let opt_matrix_stride =
+                decoration.and_then(|d| d.member_matrix_strides.get(&member_idx).copied());
+            start = start.min(offset);
+            let member_end = offset
+                .checked_add(self.size_of(*member_type, opt_matrix_stride)?)
+                .ok_or_else(|| String::from("Push constant member is too large."))?;
+            end = end.max(member_end);
+        }
+        if members.is_empty() {
+            start = 0;
+        }
+        Ok((start, end))
+    }
+
+    /// Size in bytes of a type laid out with explicit offsets and strides
+    fn size_of(&self, type_id: u32, opt_matrix_stride: Option<u32>) -> Result<u32, String> {
+        let size = match self.get_type(type_id)? {
+            Type::Scalar { width, .. } => Some(width / 8),
+            Type::Vector { component, count } => {
+                self.size_of(*component, None)?.checked_mul(*count)
+            }
+            Type::Matrix { column, count } => match opt_matrix_stride {
+                Some(stride) => stride.checked_mul(*count),
+                None => self.size_of(*column, None)?.checked_mul(*count),
+            },
+            Type::Array { element, length } => {
+                let length = self.constants.get(length).copied().unwrap_or(0);
+                let stride = match self
+                    .decorations
+                    .get(&type_id)
+                    .and_then(|d| d.opt_array_stride)
+                {
+                    Some(stride) => stride,
+                    None => self.size_of(*element, opt_matrix_stride)?,
+                };
+                stride.checked_mul(length)
+            }
+            Type::Struct { .. } => Some(self.struct_extent(type_id)?.1),
+            _ => {
+                return Err(String::from(
+                    "Opaque types can't be used in push constants.",
+                ))
+            }
+        };
+        size.ok_or_else(|| String::from("Push constant member is too large."))
+    }
+
+    fn vertex_format(&self, type_id: u32, variable_id: u32) -> Result<vk::Format, String> {
+        let (component, count) = match self.get_type(type_id)? {
+            Type::Vector { component, count } => (*component, *count),
+            Type::Scalar { .. } => (type_id, 1),
+            _ => {
+                return Err(format!(
+                    "Vertex input `{}` must be a scalar or a vector.",
+                    self.name(variable_id)
+                ))
+            }
+        };
+        if count == 0 || count > 4 {
+            return Err(format!(
+                "Vertex input `{}` has an unsupported number of components.",
+                self.name(variable_id)
+            ));
+        }
+        let format = match (self.get_type(component)?, count) {
+            (
+                Type::Scalar {
+                    is_float: true,
+                    width: 32,
+                    ..
+                },
+                _,
+            ) => [
+                vk::Format::R32_SFLOAT,
+                vk::Format::R32G32_SFLOAT,
+                vk::Format::R32G32B32_SFLOAT,
+                vk::Format::R32G32B32A32_SFLOAT,
+            ][count as usize - 1],
+            (
+                Type::Scalar {
+                    is_float: false,
+                    is_signed: true,
+                    width: 32,
+                },
+                _,
+            ) => [
+                vk::Format::R32_SINT,
+                vk::Format::R32G32_SINT,
+                vk::Format::R32G32B32_SINT,
+                vk::Format::R32G32B32A32_SINT,
+            ][count as usize - 1],
+            (
+                Type::Scalar {
+                    is_float: false,
+                    is_signed: false,
+                    width: 32,
+                },
+                _,
+            ) => [
+                vk::Format::R32_UINT,
+                vk::Format::R32G32_UINT,
+                vk::Format::R32G32B32_UINT,
+                vk::Format::R32G32B32A32_UINT,
+            ][count as usize - 1],
+            _ => {
+                return Err(format!(
+                    "Vertex input `{}` has an unsupported component type.",
+                    self.name(variable_id)
+                ))
+            }
+        };
+        Ok(format)
+    }
+}
+
+/// Decode a nul-terminated UTF-8 literal string packed into words
+fn parse_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inst(opcode: u32, ops: &[u32]) -> Vec<u32> {
+        let mut words = vec![((ops.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(ops);
+        words
+    }
+
+    fn module(insts: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 100, 0];
+        for inst in insts {
+            words.extend_from_slice(inst);
+        }
+        words
+    }
+
+    // layout(set = 0, binding = 0) uniform Block { vec4 color; };
+    // layout(set = 0, binding = 1) uniform sampler2D tex[2];
+    fn fragment_insts(set: u32) -> Vec<Vec<u32>> {
+        vec![
+            inst(
+                OP_ENTRY_POINT,
+                &[EXECUTION_MODEL_FRAGMENT, 1, 0x6e69_616d, 0],
+            ),
+            inst(OP_NAME, &[10, 0x0078_6574]), // "tex"
+            inst(OP_DECORATE, &[5, DECORATION_DESCRIPTOR_SET, set]),
+            inst(OP_DECORATE, &[5, DECORATION_BINDING, 0]),
+            inst(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 0]),
+            inst(OP_DECORATE, &[10, DECORATION_BINDING, 1]),
+            inst(OP_MEMBER_DECORATE, &[3, 0, DECORATION_OFFSET, 0]),
+            inst(OP_TYPE_FLOAT, &[2, 32]),
+            inst(OP_TYPE_VECTOR, &[20, 2, 4]),
+            inst(OP_TYPE_STRUCT, &[3, 20]),
+            inst(OP_TYPE_POINTER, &[4, STORAGE_CLASS_UNIFORM, 3]),
+            inst(OP_VARIABLE, &[4, 5, STORAGE_CLASS_UNIFORM]),
+            inst(OP_TYPE_INT, &[6, 32, 0]),
+            inst(OP_CONSTANT, &[6, 7, 2]),
+            inst(OP_TYPE_IMAGE, &[11, 2, 1, 0, 0, 0, 1, 0]),
+            inst(OP_TYPE_SAMPLED_IMAGE, &[12, 11]),
+            inst(OP_TYPE_ARRAY, &[8, 12, 7]),
+            inst(OP_TYPE_POINTER, &[9, STORAGE_CLASS_UNIFORM_CONSTANT, 8]),
+            inst(OP_VARIABLE, &[9, 10, STORAGE_CLASS_UNIFORM_CONSTANT]),
+        ]
+    }
+
+    #[test]
+    fn reflects_bindings() {
+        let reflection = ShaderReflection::new(&module(&fragment_insts(0))).unwrap();
+        assert_eq!(reflection.opt_shader_stage, Some(ShaderStage::Fragment));
+        assert_eq!(
+            reflection.bindings,
+            vec![
+                ReflectedBinding {
+                    set: 0,
+                    binding: 0,
+                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                    descriptor_count: 1,
+                    name: String::from("%5"),
+                },
+                ReflectedBinding {
+                    set: 0,
+                    binding: 1,
+                    descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    descriptor_count: 2,
+                    name: String::from("tex"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reflects_vertex_inputs_and_push_constants() {
+        // layout(location = 0) in vec3 position;
+        // layout(push_constant) uniform Push { mat4 model; };
+        let spirv = module(&[
+            inst(OP_ENTRY_POINT, &[EXECUTION_MODEL_VERTEX, 1, 0x6e69_616d, 0]),
+            inst(OP_DECORATE, &[4, DECORATION_LOCATION, 0]),
+            inst(OP_MEMBER_DECORATE, &[6, 0, DECORATION_OFFSET, 16]),
+            inst(OP_MEMBER_DECORATE, &[6, 0, DECORATION_MATRIX_STRIDE, 16]),
+            inst(OP_TYPE_FLOAT, &[2, 32]),
+            inst(OP_TYPE_VECTOR, &[3, 2, 3]),
+            inst(OP_TYPE_POINTER, &[5, STORAGE_CLASS_INPUT, 3]),
+            inst(OP_VARIABLE, &[5, 4, STORAGE_CLASS_INPUT]),
+            inst(OP_TYPE_VECTOR, &[9, 2, 4]),
+            inst(OP_TYPE_MATRIX, &[10, 9, 4]),
+            inst(OP_TYPE_STRUCT, &[6, 10]),
+            inst(OP_TYPE_POINTER, &[7, STORAGE_CLASS_PUSH_CONSTANT, 6]),
+            inst(OP_VARIABLE, &[7, 8, STORAGE_CLASS_PUSH_CONSTANT]),
+        ]);
+        let reflection = ShaderReflection::new(&spirv).unwrap();
+        assert_eq!(reflection.opt_shader_stage, Some(ShaderStage::Vertex));
+        assert_eq!(reflection.vertex_inputs.len(), 1);
+        assert_eq!(
+            reflection.vertex_inputs[0].format,
+            vk::Format::R32G32B32_SFLOAT
+        );
+        assert_eq!(
+            reflection.opt_push_constants,
+            Some(ReflectedPushConstants {
+                offset: 16,
+                size: 64,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_sets_other_than_0() {
+        let err = ShaderReflection::new(&module(&fragment_insts(1))).unwrap_err();
+        assert!(err.contains("only set 0 is supported"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        assert!(ShaderReflection::new(&[]).is_err());
+        assert!(ShaderReflection::new(&[0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_instructions() {
+        // Every instruction of the module, cut short by one operand, must be
+        // handled without panicking
+        for insts in fragment_insts(0).iter() {
+            let truncated = inst(insts[0] & 0xffff, &insts[1..insts.len() - 1]);
+            let _ = ShaderReflection::new(&module(&[truncated]));
+        }
+        assert!(ShaderReflection::new(&module(&[inst(OP_DECORATE, &[5])])).is_err());
+        assert!(ShaderReflection::new(&module(&[inst(OP_TYPE_IMAGE, &[11, 2])])).is_err());
+
+        // An instruction whose word count runs past the end of the module
+        let mut spirv = module(&fragment_insts(0));
+        spirv.truncate(spirv.len() - 1);
+        assert!(ShaderReflection::new(&spirv).is_err());
+
+        // A zero word count
+        assert!(ShaderReflection::new(&module(&[vec![OP_NAME]])).is_err());
+    }
+
+    #[test]
+    fn rejects_cyclic_types() {
+        let spirv = module(&[inst(OP_TYPE_VECTOR, &[3, 3, 4])]);
+        assert!(ShaderReflection::new(&spirv).is_err());
+
+        let spirv = module(&[
+            inst(OP_TYPE_FLOAT, &[2, 32]),
+            inst(OP_TYPE_STRUCT, &[3, 2]),
+            inst(OP_TYPE_STRUCT, &[3, 3]),
+        ]);
+        assert!(ShaderReflection::new(&spirv).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_push_constants() {
+        let spirv = module(&[
+            inst(OP_MEMBER_DECORATE, &[6, 0, DECORATION_OFFSET, u32::MAX]),
+            inst(OP_TYPE_FLOAT, &[2, 32]),
+            inst(OP_TYPE_STRUCT, &[6, 2]),
+            inst(OP_TYPE_POINTER, &[7, STORAGE_CLASS_PUSH_CONSTANT, 6]),
+            inst(OP_VARIABLE, &[7, 8, STORAGE_CLASS_PUSH_CONSTANT]),
+        ]);
+        assert!(ShaderReflection::new(&spirv).is_err());
+    }
+}